@@ -0,0 +1,155 @@
+//! Microbenchmarks of the hot paths and a small end-to-end render.
+//!
+//! Run with `cargo bench`; `cargo bench -- bvh` picks groups by name. Criterion keeps the
+//! previous results under `target/criterion` and reports the change against them.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use ray_tracing_001::bvh::Bvh;
+use ray_tracing_001::hitable::{HitRecord, Hitable, HitableList, Sphere};
+use ray_tracing_001::material::{Checker, Dielectric, Lambertian, Material, Metal};
+use ray_tracing_001::misc::{self, random};
+use ray_tracing_001::ray::Ray;
+use ray_tracing_001::render::Renderer;
+use ray_tracing_001::scene::Scene;
+use ray_tracing_001::vec3::Vec3;
+use std::hint::black_box;
+use std::rc::Rc;
+
+/// Primary rays through random points of the default camera's image.
+fn camera_rays(scene: &Scene, n: usize) -> Vec<Ray> {
+    let camera = scene.camera.build(16.0 / 9.0).unwrap();
+    (0..n)
+        .filter_map(|_| camera.get_ray(random(), random()))
+        .collect()
+}
+
+fn vec3_ops(c: &mut Criterion) {
+    let a = Vec3::new(0.3, -1.2, 2.5);
+    let b = Vec3::new(-0.7, 0.4, 1.1);
+    let mut group = c.benchmark_group("vec3");
+    group.bench_function("add", |bench| bench.iter(|| black_box(a) + black_box(b)));
+    group.bench_function("mul", |bench| bench.iter(|| black_box(a) * black_box(b)));
+    group.bench_function("scale", |bench| {
+        bench.iter(|| black_box(a) * black_box(1.5))
+    });
+    group.bench_function("dot", |bench| bench.iter(|| black_box(a).dot(&b)));
+    group.bench_function("cross", |bench| bench.iter(|| black_box(a).cross(&b)));
+    group.bench_function("unit_vector", |bench| {
+        bench.iter(|| black_box(a).unit_vector())
+    });
+    group.finish();
+}
+
+fn sphere_hit(c: &mut Criterion) {
+    let sphere = Sphere {
+        center: (0, 0, -2).into(),
+        radius: 0.5,
+        material: Rc::new(Box::new(Lambertian::new((0.5, 0.5, 0.5).into()))),
+    };
+    let hit = Ray::new((0, 0, 0).into(), (0.1, 0.1, -1.0).into());
+    let miss = Ray::new((0, 0, 0).into(), (1, 0, -1).into());
+    let mut group = c.benchmark_group("sphere");
+    group.bench_function("hit", |bench| {
+        bench.iter(|| sphere.hit(black_box(&hit), 0.0001, f32::MAX))
+    });
+    group.bench_function("miss", |bench| {
+        bench.iter(|| sphere.hit(black_box(&miss), 0.0001, f32::MAX))
+    });
+    group.finish();
+}
+
+/// The same random spheres traversed as a flat list and through a BVH.
+fn list_against_bvh(c: &mut Criterion) {
+    // the layout comes from the random sequence, so both worlds get the same spheres
+    misc::seed(1);
+    let list = HitableList::new(Scene::random().objects);
+    misc::seed(1);
+    let scene = Scene::random();
+    let rays = camera_rays(&scene, 1024);
+    let bvh = Bvh::new(scene.objects);
+
+    let mut group = c.benchmark_group("traversal");
+    group.throughput(Throughput::Elements(rays.len() as u64));
+    let worlds: [(&str, &dyn Hitable); 2] = [("list", &list), ("bvh", &bvh)];
+    for (name, world) in worlds {
+        group.bench_function(name, |bench| {
+            bench.iter(|| {
+                rays.iter()
+                    .filter(|r| world.hit(r, 0.0001, f32::MAX).is_some())
+                    .count()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn scatter(c: &mut Criterion) {
+    let materials: Vec<(&str, Box<dyn Material>)> = vec![
+        (
+            "lambertian",
+            Box::new(Lambertian::new((0.5, 0.5, 0.5).into())),
+        ),
+        (
+            "checker",
+            Box::new(Checker::new(
+                (0.9, 0.9, 0.9).into(),
+                (0.1, 0.1, 0.1).into(),
+                0.25,
+            )),
+        ),
+        ("metal", Box::new(Metal::new((0.7, 0.6, 0.5).into(), 0.0))),
+        (
+            "fuzzy_metal",
+            Box::new(Metal::new((0.7, 0.6, 0.5).into(), 0.3)),
+        ),
+        ("dielectric", Box::new(Dielectric { ref_idx: 1.5 })),
+    ];
+    let incoming = Ray::new((0, 0, 2).into(), (0.2, 0.1, -1.0).into());
+    let record = HitRecord {
+        t: 1.0,
+        p: (0.2, 0.1, 1.0).into(),
+        normal: (0, 0, 1).into(),
+        ..HitRecord::null()
+    };
+    let mut group = c.benchmark_group("scatter");
+    for (name, material) in &materials {
+        group.bench_function(*name, |bench| {
+            bench.iter(|| material.scatter(black_box(&incoming), black_box(&record)))
+        });
+    }
+    group.finish();
+}
+
+/// `scenes/random.scene` at a fixed size, sample count and seed, single-threaded.
+fn render(c: &mut Criterion) {
+    const SIZE: (usize, usize) = (64, 36);
+    const SPP: u32 = 4;
+    let path = format!("{}/scenes/random.scene", env!("CARGO_MANIFEST_DIR"));
+    let renderer = Renderer::new(|| {
+        misc::seed(1);
+        Scene::load(&path).map_err(|e| e.to_string())
+    })
+    .size(SIZE.0, SIZE.1)
+    .spp(SPP)
+    .seed(7)
+    .threads(1);
+
+    let mut group = c.benchmark_group("render");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements((SIZE.0 * SIZE.1) as u64 * SPP as u64));
+    group.bench_function(
+        BenchmarkId::new("random", format!("{}x{}x{}", SIZE.0, SIZE.1, SPP)),
+        |bench| bench.iter(|| renderer.render().expect("the bundled scene renders")),
+    );
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    vec3_ops,
+    sphere_hit,
+    list_against_bvh,
+    scatter,
+    render
+);
+criterion_main!(benches);
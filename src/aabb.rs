@@ -0,0 +1,112 @@
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+
+/// Axis-aligned bounding box.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Aabb {
+        Aabb { min, max }
+    }
+
+    /// Builds a box from two arbitrary corners.
+    pub fn from_points(a: Vec3, b: Vec3) -> Aabb {
+        Aabb::new(
+            Vec3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)),
+            Vec3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)),
+        )
+    }
+
+    pub fn surrounding(&self, other: &Aabb) -> Aabb {
+        Aabb::new(
+            Vec3::new(
+                self.min.x.min(other.min.x),
+                self.min.y.min(other.min.y),
+                self.min.z.min(other.min.z),
+            ),
+            Vec3::new(
+                self.max.x.max(other.max.x),
+                self.max.y.max(other.max.y),
+                self.max.z.max(other.max.z),
+            ),
+        )
+    }
+
+    /// Grows every degenerate axis to at least `delta` so that flat shapes still get a volume.
+    pub fn padded(&self, delta: f32) -> Aabb {
+        fn pad(lo: f32, hi: f32, delta: f32) -> (f32, f32) {
+            if hi - lo < delta {
+                (lo - delta / 2.0, hi + delta / 2.0)
+            } else {
+                (lo, hi)
+            }
+        }
+        let (x0, x1) = pad(self.min.x, self.max.x, delta);
+        let (y0, y1) = pad(self.min.y, self.max.y, delta);
+        let (z0, z1) = pad(self.min.z, self.max.z, delta);
+        Aabb::new(Vec3::new(x0, y0, z0), Vec3::new(x1, y1, z1))
+    }
+
+    pub fn centroid(&self) -> Vec3 {
+        0.5 * (self.min + self.max)
+    }
+
+    /// Slab test.
+    pub fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> bool {
+        let (mut t_min, mut t_max) = (t_min, t_max);
+        for axis in 0..3 {
+            let inv_d = 1.0 / r.direction()[axis];
+            let mut t0 = (self.min[axis] - r.origin()[axis]) * inv_d;
+            let mut t1 = (self.max[axis] - r.origin()[axis]) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            // NaN (0 * inf) must not shrink the interval
+            if t0 > t_min {
+                t_min = t0;
+            }
+            if t1 < t_max {
+                t_max = t1;
+            }
+            if t_max < t_min {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slab_hit_and_miss() {
+        let b = Aabb::new((-1, -1, -1).into(), (1, 1, 1).into());
+        assert!(b.hit(
+            &Ray::new((0, 0, -5).into(), (0, 0, 1).into()),
+            0.0,
+            f32::MAX
+        ));
+        assert!(b.hit(&Ray::new((0, 0, 0).into(), (1, 0, 0).into()), 0.0, f32::MAX));
+        assert!(!b.hit(
+            &Ray::new((0, 2, -5).into(), (0, 0, 1).into()),
+            0.0,
+            f32::MAX
+        ));
+        assert!(!b.hit(&Ray::new((0, 0, -5).into(), (0, 0, 1).into()), 0.0, 3.0));
+    }
+
+    #[test]
+    fn surrounding_box() {
+        let a = Aabb::from_points((1, 0, 0).into(), (0, 1, 1).into());
+        let b = Aabb::new((-1, 0, 0).into(), (0, 3, 0).into());
+        let s = a.surrounding(&b);
+        assert_eq!(s.min, Vec3::from_i(-1, 0, 0));
+        assert_eq!(s.max, Vec3::from_i(1, 3, 1));
+    }
+}
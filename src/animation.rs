@@ -0,0 +1,360 @@
+use crate::transform::Quaternion;
+use crate::vec3::Vec3;
+use std::str::FromStr;
+
+/// How a track moves from one keyframe to the next.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Interpolation {
+    Linear,
+    /// Cubic Bezier through the keys with Catmull-Rom tangents, so motion stays smooth across
+    /// keyframes.
+    Bezier,
+    /// Along the great circle: rotations at constant angular speed, vectors around the origin.
+    Slerp,
+}
+
+impl FromStr for Interpolation {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Interpolation, String> {
+        match s {
+            "linear" => Ok(Interpolation::Linear),
+            "bezier" => Ok(Interpolation::Bezier),
+            "slerp" => Ok(Interpolation::Slerp),
+            _ => Err(format!(
+                "unknown interpolation `{}` (expected linear, bezier or slerp)",
+                s
+            )),
+        }
+    }
+}
+
+/// Values that keyframe tracks can interpolate.
+pub trait Animatable: Copy {
+    fn lerp(&self, other: &Self, t: f32) -> Self;
+    fn add(&self, other: &Self) -> Self;
+    fn scale(&self, f: f32) -> Self;
+
+    fn slerp(&self, other: &Self, t: f32) -> Self {
+        self.lerp(other, t)
+    }
+
+    /// Representation of the same value closest to `previous`, for values with several.
+    fn align_to(&self, _previous: &Self) -> Self {
+        *self
+    }
+
+    fn finish(&self) -> Self {
+        *self
+    }
+}
+
+impl Animatable for f32 {
+    fn lerp(&self, other: &f32, t: f32) -> f32 {
+        self + (other - self) * t
+    }
+
+    fn add(&self, other: &f32) -> f32 {
+        self + other
+    }
+
+    fn scale(&self, f: f32) -> f32 {
+        self * f
+    }
+}
+
+impl Animatable for Vec3 {
+    fn lerp(&self, other: &Vec3, t: f32) -> Vec3 {
+        (1.0 - t) * *self + t * *other
+    }
+
+    fn add(&self, other: &Vec3) -> Vec3 {
+        *self + *other
+    }
+
+    fn scale(&self, f: f32) -> Vec3 {
+        *self * f
+    }
+
+    /// Turns the direction about the origin while the length changes linearly; orbits a
+    /// camera around a subject at the origin.
+    fn slerp(&self, other: &Vec3, t: f32) -> Vec3 {
+        let (la, lb) = (self.length(), other.length());
+        if la == 0.0 || lb == 0.0 {
+            return self.lerp(other, t);
+        }
+        let (a, b) = (*self / la, *other / lb);
+        let theta = a.dot(&b).clamp(-1.0, 1.0).acos();
+        if theta < 1e-4 {
+            return self.lerp(other, t);
+        }
+        let dir = (((1.0 - t) * theta).sin() * a + (t * theta).sin() * b) / theta.sin();
+        dir * la.lerp(&lb, t)
+    }
+}
+
+impl Animatable for Quaternion {
+    fn lerp(&self, other: &Quaternion, t: f32) -> Quaternion {
+        self.scale(1.0 - t)
+            .add(&other.align_to(self).scale(t))
+            .normalized()
+    }
+
+    fn add(&self, other: &Quaternion) -> Quaternion {
+        Quaternion::add(self, other)
+    }
+
+    fn scale(&self, f: f32) -> Quaternion {
+        Quaternion::scale(self, f)
+    }
+
+    fn slerp(&self, other: &Quaternion, t: f32) -> Quaternion {
+        Quaternion::slerp(self, other, t)
+    }
+
+    fn align_to(&self, previous: &Quaternion) -> Quaternion {
+        if self.dot(previous) < 0.0 {
+            self.scale(-1.0)
+        } else {
+            *self
+        }
+    }
+
+    fn finish(&self) -> Quaternion {
+        self.normalized()
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Keyframe<T> {
+    pub frame: f32,
+    pub value: T,
+    /// Used from this key up to the next one.
+    pub interpolation: Interpolation,
+}
+
+/// Keyframes of one animated value, held constant before the first and after the last.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Track<T> {
+    keys: Vec<Keyframe<T>>,
+}
+
+impl<T: Animatable> Track<T> {
+    pub fn new() -> Track<T> {
+        Track { keys: vec![] }
+    }
+
+    /// Adds a keyframe, replacing any other at the same frame.
+    pub fn insert(&mut self, key: Keyframe<T>) {
+        self.keys.retain(|k| k.frame != key.frame);
+        let at = self.keys.partition_point(|k| k.frame < key.frame);
+        self.keys.insert(at, key);
+        for i in 1..self.keys.len() {
+            let previous = self.keys[i - 1].value;
+            self.keys[i].value = self.keys[i].value.align_to(&previous);
+        }
+    }
+
+    pub fn keys(&self) -> &[Keyframe<T>] {
+        &self.keys
+    }
+
+    pub fn sample(&self, frame: f32) -> Option<T> {
+        let last = self.keys.len().checked_sub(1)?;
+        let i = self.keys.partition_point(|k| k.frame <= frame);
+        if i == 0 {
+            return Some(self.keys[0].value);
+        }
+        if i > last {
+            return Some(self.keys[last].value);
+        }
+        let (k0, k1) = (&self.keys[i - 1], &self.keys[i]);
+        let t = (frame - k0.frame) / (k1.frame - k0.frame);
+        let value = match k0.interpolation {
+            Interpolation::Linear => k0.value.lerp(&k1.value, t),
+            Interpolation::Slerp => k0.value.slerp(&k1.value, t),
+            Interpolation::Bezier => {
+                let before = if i >= 2 { &self.keys[i - 2] } else { k0 };
+                let after = self.keys.get(i + 1).unwrap_or(k1);
+                // Catmull-Rom tangents turned into the inner Bezier control points
+                let c0 = k0
+                    .value
+                    .add(&k1.value.add(&before.value.scale(-1.0)).scale(1.0 / 6.0));
+                let c1 = k1
+                    .value
+                    .add(&after.value.add(&k0.value.scale(-1.0)).scale(-1.0 / 6.0));
+                let (a, b, c) = (
+                    k0.value.lerp(&c0, t),
+                    c0.lerp(&c1, t),
+                    c1.lerp(&k1.value, t),
+                );
+                let (d, e) = (a.lerp(&b, t), b.lerp(&c, t));
+                d.lerp(&e, t)
+            }
+        };
+        Some(value.finish())
+    }
+}
+
+impl<T: Animatable> Default for Track<T> {
+    fn default() -> Track<T> {
+        Track::new()
+    }
+}
+
+/// Inclusive range of frames to render.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct FrameRange {
+    pub start: u32,
+    pub end: u32,
+    pub fps: f32,
+}
+
+impl FrameRange {
+    pub fn frames(&self) -> std::ops::RangeInclusive<u32> {
+        self.start..=self.end
+    }
+}
+
+/// Seed of the random sequence used to render `frame`: the base is mixed before the frame
+/// is, so neighbouring frames get unrelated noise, as do neighbouring bases, while
+/// rerendering a frame reproduces it exactly.
+pub fn frame_seed(base: u64, frame: u32) -> u64 {
+    splitmix(splitmix(base) ^ frame as u64)
+}
+
+/// One SplitMix64 step.
+fn splitmix(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Replaces the last run of `#` in `pattern` with the zero-padded frame number, or appends
+/// the number before the extension when there is none.
+pub fn frame_path(pattern: &str, frame: u32) -> String {
+    match pattern.rfind('#') {
+        Some(end) => {
+            let start = pattern[..end].trim_end_matches('#').len();
+            let width = end + 1 - start;
+            format!(
+                "{}{:0width$}{}",
+                &pattern[..start],
+                frame,
+                &pattern[end + 1..],
+                width = width
+            )
+        }
+        None => match pattern.rfind('.') {
+            Some(dot) if !pattern[dot..].contains('/') => {
+                format!("{}_{:04}{}", &pattern[..dot], frame, &pattern[dot..])
+            }
+            _ => format!("{}_{:04}", pattern, frame),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track<T: Animatable>(keys: &[(f32, T)], interpolation: Interpolation) -> Track<T> {
+        let mut track = Track::new();
+        for &(frame, value) in keys {
+            track.insert(Keyframe {
+                frame,
+                value,
+                interpolation,
+            });
+        }
+        track
+    }
+
+    #[test]
+    fn linear_holds_outside_the_keys() {
+        let t = track(
+            &[(10.0, 2.0), (0.0, 0.0), (20.0, 2.0)],
+            Interpolation::Linear,
+        );
+        assert_eq!(t.keys()[1].frame, 10.0);
+        assert_eq!(t.sample(-5.0), Some(0.0));
+        assert_eq!(t.sample(5.0), Some(1.0));
+        assert_eq!(t.sample(15.0), Some(2.0));
+        assert_eq!(t.sample(30.0), Some(2.0));
+        assert_eq!(Track::<f32>::new().sample(0.0), None);
+    }
+
+    #[test]
+    fn bezier_passes_through_keys_smoothly() {
+        let t = track(
+            &[(0.0, 0.0), (10.0, 10.0), (20.0, 0.0), (30.0, 10.0)],
+            Interpolation::Bezier,
+        );
+        for &(frame, value) in &[(0.0, 0.0), (10.0, 10.0), (20.0, 0.0)] {
+            assert!((t.sample(frame).unwrap() - value).abs() < 1e-5);
+        }
+        // the slopes on either side of a key agree
+        let slope = |a: f32, b: f32| (t.sample(b).unwrap() - t.sample(a).unwrap()) / (b - a);
+        let (left, right) = (slope(19.99, 20.0), slope(20.0, 20.01));
+        assert!((left - right).abs() < 0.02);
+        // evenly spaced collinear keys move at constant speed
+        let even = track(
+            &[(0.0, 0.0), (1.0, 1.0), (2.0, 2.0), (3.0, 3.0)],
+            Interpolation::Bezier,
+        );
+        assert!((even.sample(1.25).unwrap() - 1.25).abs() < 1e-5);
+        assert!((even.sample(1.5).unwrap() - 1.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn slerp_orbits() {
+        let t = track(
+            &[
+                (0.0, Vec3::new(10.0, 0.0, 0.0)),
+                (4.0, Vec3::new(0.0, 0.0, 10.0)),
+            ],
+            Interpolation::Slerp,
+        );
+        let mid = t.sample(2.0).unwrap();
+        assert!((mid.length() - 10.0).abs() < 1e-4);
+        assert!((mid.x - mid.z).abs() < 1e-4);
+
+        let axis = Vec3::new(0.0, 1.0, 0.0);
+        let spin = track(
+            &[
+                (0.0, Quaternion::from_axis_angle(axis, 0.0)),
+                (10.0, Quaternion::from_axis_angle(axis, 90.0)),
+            ],
+            Interpolation::Slerp,
+        );
+        let q = spin.sample(5.0).unwrap();
+        assert!((q.dot(&Quaternion::from_axis_angle(axis, 45.0)) - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn seeds_are_deterministic_and_distinct() {
+        assert_eq!(frame_seed(1234, 7), frame_seed(1234, 7));
+        assert_ne!(frame_seed(1234, 7), frame_seed(1234, 8));
+        assert_ne!(frame_seed(1234, 7), frame_seed(1235, 7));
+    }
+
+    #[test]
+    fn neighbouring_bases_do_not_replay_each_other() {
+        let seeds: Vec<u64> = (0..64)
+            .flat_map(|base| (0..64).map(move |frame| frame_seed(base, frame)))
+            .collect();
+        let mut distinct = seeds.clone();
+        distinct.sort();
+        distinct.dedup();
+        assert_eq!(distinct.len(), seeds.len());
+        assert_ne!(frame_seed(1234, 8), frame_seed(1235, 7));
+    }
+
+    #[test]
+    fn numbers_frame_paths() {
+        assert_eq!(frame_path("out/frame_####.ppm", 12), "out/frame_0012.ppm");
+        assert_eq!(frame_path("f#.ppm", 123), "f123.ppm");
+        assert_eq!(frame_path("render.ppm", 3), "render_0003.ppm");
+        assert_eq!(frame_path("dir.v2/render", 3), "dir.v2/render_0003");
+    }
+}
@@ -0,0 +1,206 @@
+use crate::image::{luminance, Image, ImageError};
+use crate::misc::{degree_to_radian, random};
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+/// Outline of the diaphragm, spanning `[-1, 1]` on both axes of the lens.
+#[derive(Clone, Debug)]
+pub enum ApertureShape {
+    Circle,
+    /// Regular polygon formed by `blades` straight blades; `rotation` is in degrees.
+    Polygon {
+        blades: u32,
+        rotation: f32,
+    },
+    /// Behind an `Arc` so camera settings can be sent to other threads, as the preview does.
+    Mask(Arc<ApertureMask>),
+}
+
+/// Transmission of the aperture sampled from a grayscale image, white being fully open.
+#[derive(Debug)]
+pub struct ApertureMask {
+    width: usize,
+    height: usize,
+    /// Running sum of pixel transmission, row by row from the top.
+    cdf: Vec<f32>,
+}
+
+impl ApertureMask {
+    pub fn load(path: &str) -> Result<ApertureMask, ImageError> {
+        ApertureMask::from_image(&Image::load(path)?)
+    }
+
+    pub fn from_image(image: &Image) -> Result<ApertureMask, ImageError> {
+        let mut total = 0.0;
+        let cdf: Vec<f32> = image
+            .pixels
+            .iter()
+            .map(|p| {
+                total += luminance(p).max(0.0);
+                total
+            })
+            .collect();
+        if total <= 0.0 {
+            return Err(ImageError::Format(
+                "aperture mask is completely black".to_string(),
+            ));
+        }
+        Ok(ApertureMask {
+            width: image.width,
+            height: image.height,
+            cdf,
+        })
+    }
+
+    fn sample(&self) -> (f32, f32) {
+        let target = random() * self.cdf[self.cdf.len() - 1];
+        let index = self
+            .cdf
+            .partition_point(|&c| c <= target)
+            .min(self.cdf.len() - 1);
+        let x = ((index % self.width) as f32 + random()) / self.width as f32;
+        let y = ((index / self.width) as f32 + random()) / self.height as f32;
+        (2.0 * x - 1.0, 1.0 - 2.0 * y)
+    }
+}
+
+/// Shape of a thin lens's aperture and the optical effects around it.
+#[derive(Clone, Debug)]
+pub struct Aperture {
+    pub shape: ApertureShape,
+    /// Anamorphic squeeze; the lens is narrowed horizontally by this factor.
+    pub squeeze: f32,
+    /// Cat's-eye vignetting strength. The lens barrel is modeled as a second unit circle that
+    /// slides `cats_eye * offset from the image center` off axis; rays outside it are blocked.
+    pub cats_eye: f32,
+}
+
+impl Default for Aperture {
+    fn default() -> Aperture {
+        Aperture {
+            shape: ApertureShape::Circle,
+            squeeze: 1.0,
+            cats_eye: 0.0,
+        }
+    }
+}
+
+impl Aperture {
+    /// Point on the lens in units of the aperture radius, or `None` when the barrel blocks it.
+    /// `s` and `t` locate the pixel as in `Camera::get_ray`.
+    pub fn sample(&self, s: f32, t: f32) -> Option<(f32, f32)> {
+        let (x, y) = match &self.shape {
+            ApertureShape::Circle => random_in_unit_disk(),
+            ApertureShape::Polygon { blades, rotation } => {
+                sample_polygon((*blades).max(3), degree_to_radian(*rotation))
+            }
+            ApertureShape::Mask(mask) => mask.sample(),
+        };
+        if self.cats_eye > 0.0 {
+            let cx = self.cats_eye * (2.0 * s - 1.0);
+            let cy = self.cats_eye * (2.0 * t - 1.0);
+            if (x - cx) * (x - cx) + (y - cy) * (y - cy) > 1.0 {
+                return None;
+            }
+        }
+        Some((x / self.squeeze, y))
+    }
+}
+
+fn random_in_unit_disk() -> (f32, f32) {
+    loop {
+        let (x, y) = (2.0 * random() - 1.0, 2.0 * random() - 1.0);
+        if x * x + y * y <= 1.0 {
+            return (x, y);
+        }
+    }
+}
+
+/// Uniform point in a regular polygon with unit circumradius.
+fn sample_polygon(sides: u32, rotation: f32) -> (f32, f32) {
+    let step = 2.0 * PI / sides as f32;
+    let i = ((random() * sides as f32) as u32).min(sides - 1);
+    let a0 = rotation + PI / 2.0 + step * i as f32;
+    let a1 = a0 + step;
+    // uniform in the triangle (center, v0, v1)
+    let (mut u, mut v) = (random(), random());
+    if u + v > 1.0 {
+        u = 1.0 - u;
+        v = 1.0 - v;
+    }
+    (u * a0.cos() + v * a1.cos(), u * a0.sin() + v * a1.sin())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::Vec3;
+
+    fn samples(aperture: &Aperture, s: f32, t: f32, n: usize) -> Vec<(f32, f32)> {
+        (0..n).filter_map(|_| aperture.sample(s, t)).collect()
+    }
+
+    #[test]
+    fn polygon_stays_inside_its_edges() {
+        let aperture = Aperture {
+            shape: ApertureShape::Polygon {
+                blades: 6,
+                rotation: 0.0,
+            },
+            ..Aperture::default()
+        };
+        // apothem of a hexagon with unit circumradius
+        let apothem = (PI / 6.0).cos();
+        let mut reaches_corner = false;
+        for (x, y) in samples(&aperture, 0.5, 0.5, 5000) {
+            for k in 0..6 {
+                let a = PI / 2.0 + PI / 6.0 + k as f32 * PI / 3.0;
+                assert!(x * a.cos() + y * a.sin() <= apothem + 1e-5);
+            }
+            reaches_corner |= y > 0.95;
+        }
+        assert!(reaches_corner);
+    }
+
+    #[test]
+    fn squeeze_narrows_horizontally() {
+        let aperture = Aperture {
+            squeeze: 2.0,
+            ..Aperture::default()
+        };
+        let points = samples(&aperture, 0.5, 0.5, 2000);
+        assert!(points.iter().all(|&(x, _)| x.abs() <= 0.5));
+        assert!(points.iter().any(|&(_, y)| y.abs() > 0.9));
+    }
+
+    #[test]
+    fn cats_eye_vignettes_edges() {
+        let aperture = Aperture {
+            cats_eye: 0.8,
+            ..Aperture::default()
+        };
+        let center = samples(&aperture, 0.5, 0.5, 4000).len();
+        let corner = samples(&aperture, 1.0, 1.0, 4000);
+        assert_eq!(center, 4000);
+        assert!(corner.len() < 3000);
+        // the surviving part of the lens is pushed toward the corner
+        let mean_x: f32 = corner.iter().map(|p| p.0).sum::<f32>() / corner.len() as f32;
+        assert!(mean_x > 0.1);
+    }
+
+    #[test]
+    fn mask_follows_image() {
+        // only the right column of a 2x2 mask is open
+        let mut image = Image::new(2, 2);
+        image.set(1, 0, Vec3::new(1.0, 1.0, 1.0));
+        image.set(1, 1, Vec3::new(1.0, 1.0, 1.0));
+        let aperture = Aperture {
+            shape: ApertureShape::Mask(Arc::new(ApertureMask::from_image(&image).unwrap())),
+            ..Aperture::default()
+        };
+        assert!(samples(&aperture, 0.5, 0.5, 1000)
+            .iter()
+            .all(|&(x, _)| x >= 0.0));
+        assert!(ApertureMask::from_image(&Image::new(2, 2)).is_err());
+    }
+}
@@ -0,0 +1,795 @@
+use crate::hitable::HitRecord;
+use crate::integrator::{facing, trace, Integrator, SceneView, Splat};
+use crate::light::{bounding_sphere, emit, Background};
+use crate::material::Lobe;
+use crate::misc::random;
+use crate::ray::Ray;
+use crate::stats::{count, Counter};
+use crate::vec3::{random_unit_vector, Vec3};
+use std::f32::consts::PI;
+use std::rc::Rc;
+
+/// Bidirectional path tracing: for every camera ray a second path starts at a point on a
+/// light, and every vertex of one path is joined to every vertex of the other. The
+/// strategies are weighted against each other with the balance heuristic, so light that
+/// only reaches the camera through glass is found from the light's side while what the
+/// camera sees directly stays as clean as with the path tracer.
+///
+/// Light paths start on the sampled lights and, when it shines, the background. Joins made
+/// straight to the camera land elsewhere on the image and come back as splats; without a
+/// camera that supports `Camera::we` those strategies are left out of the weights instead.
+/// Mirrors and glass scatter as they do for the path tracer and cannot be joined at.
+pub struct BidirectionalPathTracer {
+    /// Bounces of the longest path, counted the same way as for the path tracer.
+    pub max_depth: u32,
+    /// Bounces either path takes before Russian roulette starts; `u32::MAX` turns it off.
+    pub roulette_depth: u32,
+}
+
+impl BidirectionalPathTracer {
+    pub fn new(max_depth: u32) -> BidirectionalPathTracer {
+        BidirectionalPathTracer {
+            max_depth,
+            roulette_depth: 3,
+        }
+    }
+
+    pub fn with_roulette_depth(mut self, roulette_depth: u32) -> BidirectionalPathTracer {
+        self.roulette_depth = roulette_depth;
+        self
+    }
+}
+
+impl Integrator for BidirectionalPathTracer {
+    fn radiance(&self, r: &Ray, scene: &SceneView) -> Vec3 {
+        self.paths(r, scene, None)
+    }
+
+    fn radiance_and_splats(&self, r: &Ray, scene: &SceneView, splats: &mut Vec<Splat>) -> Vec3 {
+        let splats = scene.camera.pdf_we(r).map(|_| splats);
+        self.paths(r, scene, splats)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum Kind {
+    /// A point on the lens.
+    Camera,
+    Surface,
+    /// A point on `lights[i]` where a light path starts or a join ends.
+    Light(usize),
+    /// The background, in the direction `n`.
+    Sky,
+}
+
+#[derive(Clone)]
+struct Vertex {
+    kind: Kind,
+    p: Vec3,
+    /// Outward normal of surfaces and lights, the unit direction of the sky, zero at the
+    /// camera.
+    n: Vec3,
+    /// What the path carries up to here over the density of sampling it.
+    beta: Vec3,
+    record: Option<HitRecord>,
+    /// The light a surface hit by a camera path gives off light as.
+    light: Option<usize>,
+    /// Scattered by a material other than a diffuse one, which is taken to be a mirror.
+    delta: bool,
+    /// Density of the vertex per unit area, or per unit solid angle for the sky, as its own
+    /// path sampled it.
+    pdf_fwd: f32,
+    /// The same as the other path would have sampled it.
+    pdf_rev: f32,
+}
+
+impl Vertex {
+    fn new(kind: Kind, p: Vec3, n: Vec3, beta: Vec3, pdf_fwd: f32) -> Vertex {
+        Vertex {
+            kind,
+            p,
+            n,
+            beta,
+            record: None,
+            light: None,
+            delta: false,
+            pdf_fwd,
+            pdf_rev: 0.0,
+        }
+    }
+
+    fn on_surface(&self) -> bool {
+        matches!(self.kind, Kind::Surface | Kind::Light(_))
+    }
+
+    /// Unit vector from here to `other`.
+    fn towards(&self, other: &Vertex) -> Vec3 {
+        match (self.kind, other.kind) {
+            (_, Kind::Sky) => other.n,
+            (Kind::Sky, _) => -self.n,
+            _ => (other.p - self.p).unit_vector(),
+        }
+    }
+
+    /// Turns a density per unit solid angle around this vertex into one per unit area at
+    /// `next`.
+    fn convert(&self, pdf: f32, next: &Vertex) -> f32 {
+        if next.kind == Kind::Sky {
+            return pdf;
+        }
+        let w = next.p - self.p;
+        let distance_squared = w.squared_length();
+        if distance_squared == 0.0 {
+            return 0.0;
+        }
+        let cosine = if next.on_surface() {
+            next.n.dot(&w).abs() / distance_squared.sqrt()
+        } else {
+            1.0
+        };
+        pdf * cosine / distance_squared
+    }
+
+    /// Reflectance of diffuse surfaces, the only ones paths are joined at.
+    fn albedo(&self) -> Option<Vec3> {
+        let record = self.record.as_ref()?;
+        record.material.lambert(record)
+    }
+
+    /// The BSDF from `wo` to `wi`, both unit vectors pointing away from the surface.
+    fn f(&self, wo: &Vec3, wi: &Vec3) -> Vec3 {
+        match self.albedo() {
+            Some(albedo) if wo.dot(&self.n) * wi.dot(&self.n) > 0.0 => albedo / PI,
+            _ => Vec3::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    fn emission(&self, background: &Background) -> Vec3 {
+        match (&self.kind, &self.record) {
+            (Kind::Sky, _) => background.radiance(&self.n),
+            (_, Some(record)) => record.material.emitted(record),
+            _ => Vec3::new(0.0, 0.0, 0.0),
+        }
+    }
+}
+
+fn is_black(v: &Vec3) -> bool {
+    *v == Vec3::new(0.0, 0.0, 0.0)
+}
+
+/// The scene as the sources light paths start from: the lights, then the background if
+/// it shines.
+struct Sources<'s, 'a> {
+    scene: &'s SceneView<'a>,
+    count: usize,
+    /// Bounding sphere of `scene.bounds`, across which light from the background enters.
+    sphere: Option<(Vec3, f32)>,
+}
+
+impl<'s, 'a> Sources<'s, 'a> {
+    fn new(scene: &'s SceneView<'a>) -> Sources<'s, 'a> {
+        Sources {
+            scene,
+            count: scene.lights.len() + scene.background.shines() as usize,
+            sphere: scene.bounds.as_ref().map(bounding_sphere),
+        }
+    }
+
+    fn pick(&self) -> usize {
+        ((random() * self.count as f32) as usize).min(self.count - 1)
+    }
+
+    fn choice(&self) -> f32 {
+        1.0 / self.count as f32
+    }
+
+    /// The sampled light that `record`, found by a camera path, lies on.
+    fn light_at(&self, record: &HitRecord) -> Option<usize> {
+        let probe = Ray::new(record.p + 1e-3 * record.normal, -record.normal);
+        self.scene.lights.iter().position(|light| {
+            light
+                .hit(&probe, 0.0, 2e-3)
+                .is_some_and(|hit| Rc::ptr_eq(&hit.material, &record.material))
+        })
+    }
+
+    /// Density per unit area across the sky's beam of starting a light path that first
+    /// meets `p`, coming from `towards`: uniform over a disk as wide as the bounding sphere,
+    /// one radius behind its center.
+    fn sky_pdf(&self, towards: &Vec3, p: &Vec3) -> f32 {
+        let (center, radius) = match self.sphere {
+            Some(sphere) => sphere,
+            None => return 0.0,
+        };
+        let offset = *p - center;
+        let along = -offset.dot(towards);
+        if along < -radius || (offset + along * *towards).squared_length() > radius * radius {
+            0.0
+        } else {
+            1.0 / (PI * radius * radius)
+        }
+    }
+
+    /// Density of `v` as the start of a light path, the choice of source included.
+    fn pdf_origin(&self, v: &Vertex) -> f32 {
+        match (v.kind, v.light) {
+            (Kind::Sky, _) => self.choice() / (4.0 * PI),
+            (Kind::Light(i), _) | (_, Some(i)) => {
+                self.choice() / self.scene.lights[i].area().unwrap_or(f32::INFINITY)
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// Density per unit area of `next` as the vertex after a light path starting at `v`.
+    fn pdf_light(&self, v: &Vertex, next: &Vertex) -> f32 {
+        let w = v.towards(next);
+        let pdf = match v.kind {
+            Kind::Sky => self.sky_pdf(&v.n, &next.p),
+            _ => v.n.dot(&w).abs() / (2.0 * PI) / (next.p - v.p).squared_length(),
+        };
+        if next.on_surface() {
+            pdf * next.n.dot(&w).abs()
+        } else {
+            pdf
+        }
+    }
+
+    /// Density per unit area of `next` as sampled from `v`, which was reached from `prev`.
+    fn pdf(&self, v: &Vertex, prev: Option<&Vertex>, next: &Vertex) -> f32 {
+        let wn = v.towards(next);
+        let pdf = match v.kind {
+            Kind::Light(_) | Kind::Sky => return self.pdf_light(v, next),
+            Kind::Camera => {
+                let r = Ray::new(v.p, wn);
+                self.scene.camera.pdf_we(&r).map_or(0.0, |(_, dir)| dir)
+            }
+            Kind::Surface => match (v.albedo(), prev) {
+                (Some(_), Some(prev)) if v.towards(prev).dot(&v.n) * wn.dot(&v.n) > 0.0 => {
+                    wn.dot(&v.n).abs() / PI
+                }
+                _ => 0.0,
+            },
+        };
+        v.convert(pdf, next)
+    }
+
+    /// A point on a source as seen from `p`, with what it sends there over the density of
+    /// choosing it; `None` when it is blocked.
+    fn sample(&self, p: &Vec3) -> Option<Vertex> {
+        if self.count == 0 {
+            return None;
+        }
+        let i = self.pick();
+        let lights = self.scene.lights;
+        if i == lights.len() {
+            let direction = random_unit_vector();
+            count(Counter::ShadowRays);
+            let escape = Ray::new(*p, direction);
+            if self.scene.world.hit(&escape, 0.0001, f32::MAX).is_some() {
+                return None;
+            }
+            let pdf = 1.0 / (4.0 * PI);
+            let radiance = self.scene.background.radiance(&direction);
+            let beta = radiance / (pdf * self.choice());
+            return Some(Vertex::new(
+                Kind::Sky,
+                *p + direction,
+                direction,
+                beta,
+                self.choice() * pdf,
+            ));
+        }
+        let light = &lights[i];
+        let (area, (q, normal)) = (light.area()?, light.sample_surface()?);
+        let to_light = q - p;
+        let distance_squared = to_light.squared_length();
+        let cosine = normal.dot(&to_light.unit_vector()).abs();
+        if cosine < 1e-6 || distance_squared < 1e-12 {
+            return None;
+        }
+        let shadow = Ray::new(*p, to_light);
+        count(Counter::ShadowRays);
+        if self.scene.world.hit(&shadow, 0.0001, 1.0 - 1e-3).is_some() {
+            return None;
+        }
+        let record = light.hit(&shadow, 1.0 - 1e-3, 1.0 + 1e-3)?;
+        let pdf = distance_squared / (cosine * area);
+        let beta = record.material.emitted(&record) / (pdf * self.choice());
+        let mut v = Vertex::new(Kind::Light(i), q, normal, beta, self.choice() / area);
+        v.record = Some(record);
+        Some(v)
+    }
+
+    /// Whether nothing stands between `a` and `b`.
+    fn visible(&self, a: &Vec3, b: &Vec3) -> bool {
+        count(Counter::ShadowRays);
+        let r = Ray::new(*a, b - a);
+        self.scene.world.hit(&r, 0.0001, 1.0 - 1e-3).is_none()
+    }
+}
+
+impl BidirectionalPathTracer {
+    fn paths(&self, r: &Ray, scene: &SceneView, mut splats: Option<&mut Vec<Splat>>) -> Vec3 {
+        let sources = Sources::new(scene);
+        let white = Vec3::new(1.0, 1.0, 1.0);
+        let zero = Vec3::new(0.0, 0.0, 0.0);
+        let mut camera = vec![Vertex::new(Kind::Camera, *r.origin(), zero, white, 1.0)];
+        let pdf_dir = scene.camera.pdf_we(r).map_or(0.0, |(_, dir)| dir);
+        let ray = Ray::new(*r.origin(), *r.direction());
+        let vertices = self.max_depth.saturating_add(1);
+        self.walk(&sources, ray, white, pdf_dir, vertices, true, &mut camera);
+        let light = self.light_path(&sources);
+
+        let splatting = splats.is_some();
+        // joins to a point sampled on a light need no light path
+        let longest = light.len().max((sources.count > 0) as usize);
+        let mut radiance = zero;
+        for t in 1..=camera.len() {
+            for s in 0..=longest {
+                if s + t < 2
+                    || s + t - 2 > self.max_depth as usize
+                    || (s == 1 && t == 1)
+                    || (t == 1 && !splatting)
+                {
+                    continue;
+                }
+                let (contribution, raster) =
+                    self.connect(&sources, &light, &camera, s, t, splatting);
+                if is_black(&contribution) {
+                    continue;
+                }
+                match (raster, splats.as_mut()) {
+                    (Some((s, t)), Some(splats)) => splats.push(Splat {
+                        s,
+                        t,
+                        radiance: contribution,
+                    }),
+                    _ => radiance = radiance + contribution,
+                }
+            }
+        }
+        radiance
+    }
+
+    /// A path starting at a point on one of the sources, empty when there are none.
+    fn light_path(&self, sources: &Sources) -> Vec<Vertex> {
+        let scene = sources.scene;
+        let emission = match emit(scene.lights, &scene.background, scene.bounds) {
+            Some(emission) => emission,
+            None => return vec![],
+        };
+        let origin = *emission.ray.origin();
+        let direction = *emission.ray.direction();
+        let mut start = match emission.light {
+            Some(i) => Vertex::new(
+                Kind::Light(i),
+                origin,
+                emission.normal,
+                emission.radiance,
+                emission.pdf_choice * emission.pdf_pos,
+            ),
+            None => Vertex::new(
+                Kind::Sky,
+                origin,
+                emission.normal,
+                emission.radiance,
+                emission.pdf_choice * emission.pdf_dir,
+            ),
+        };
+        start.record = emission.record.clone();
+        let mut path = vec![start];
+        let beta = emission.power();
+        self.walk(
+            sources,
+            emission.ray,
+            beta,
+            emission.pdf_dir,
+            self.max_depth,
+            false,
+            &mut path,
+        );
+        // the first hit from the sky is spread over the beam's cross-section, not by distance
+        if emission.light.is_none() {
+            if let Some(first) = path.get_mut(1) {
+                first.pdf_fwd = emission.pdf_pos;
+                if first.on_surface() {
+                    first.pdf_fwd *= direction.dot(&first.n).abs();
+                }
+            }
+        }
+        path
+    }
+
+    /// Extends `path` by up to `vertices` vertices along `ray`, which leaves its last vertex
+    /// with density `pdf_dir` per unit solid angle. Camera paths that escape end on the sky.
+    #[allow(clippy::too_many_arguments)]
+    fn walk(
+        &self,
+        sources: &Sources,
+        mut ray: Ray,
+        mut beta: Vec3,
+        pdf_dir: f32,
+        vertices: u32,
+        from_camera: bool,
+        path: &mut Vec<Vertex>,
+    ) {
+        let mut pdf_fwd = pdf_dir;
+        let mut throughput = Vec3::new(1.0, 1.0, 1.0);
+        let mut bounces = 0;
+        while bounces < vertices {
+            let depth = if from_camera { bounces } else { bounces + 1 };
+            let record = match trace(&ray, sources.scene, depth) {
+                Some(record) => record,
+                None => {
+                    if from_camera {
+                        let towards = ray.direction().unit_vector();
+                        path.push(Vertex::new(
+                            Kind::Sky,
+                            *ray.origin(),
+                            towards,
+                            beta,
+                            pdf_fwd,
+                        ));
+                    }
+                    return;
+                }
+            };
+            let mut vertex = Vertex::new(Kind::Surface, record.p, record.normal, beta, 0.0);
+            vertex.pdf_fwd = path[path.len() - 1].convert(pdf_fwd, &vertex);
+            if from_camera && !is_black(&record.material.emitted(&record)) {
+                vertex.light = sources.light_at(&record);
+            }
+            let albedo = record.material.lambert(&record);
+            let (direction, weight, pdf_rev) = match albedo {
+                Some(albedo) => {
+                    let normal = facing(&record.normal, ray.direction());
+                    let mut direction = normal + random_unit_vector();
+                    if direction.squared_length() < 1e-12 {
+                        direction = normal;
+                    }
+                    let direction = direction.unit_vector();
+                    pdf_fwd = direction.dot(&normal) / PI;
+                    let pdf_rev = ray.direction().unit_vector().dot(&normal).abs() / PI;
+                    (direction, albedo, pdf_rev)
+                }
+                None => {
+                    let (is_scattered, attenuation, scattered) =
+                        record.material.scatter(&ray, &record);
+                    if !is_scattered {
+                        vertex.record = Some(record);
+                        path.push(vertex);
+                        return;
+                    }
+                    vertex.delta = true;
+                    pdf_fwd = 0.0;
+                    let mut weight = attenuation.as_vec3();
+                    // the path tracer keeps radiance as it is through glass instead of
+                    // scaling it by the squared ratio of the refractive indices, so light
+                    // paths, which carry power, take that ratio the other way to match
+                    let lobe = record.material.lobe(&ray, &record, &scattered);
+                    if !from_camera && lobe == Lobe::Transmission {
+                        weight = weight * record.material.eta(&ray, &record).powi(2);
+                    }
+                    (*scattered.direction(), weight, 0.0)
+                }
+            };
+            vertex.record = Some(record);
+            path.push(vertex);
+            bounces += 1;
+            if bounces == vertices {
+                return;
+            }
+            let n = path.len();
+            path[n - 2].pdf_rev = path[n - 1].convert(pdf_rev, &path[n - 2]);
+            beta = beta * weight;
+            throughput = throughput * weight;
+            if is_black(&beta) {
+                return;
+            }
+            if bounces > self.roulette_depth {
+                let survival = throughput.x.max(throughput.y).max(throughput.z).min(0.95);
+                if random() >= survival {
+                    return;
+                }
+                beta = beta / survival;
+                throughput = throughput / survival;
+            }
+            ray = Ray::new(path[n - 1].p, direction);
+        }
+    }
+
+    /// The weighted light of the path made of the first `s` vertices of `light` and the
+    /// first `t` of `camera`, and where it lands on the image if it was joined to the lens.
+    #[allow(clippy::too_many_arguments)]
+    fn connect(
+        &self,
+        sources: &Sources,
+        light: &[Vertex],
+        camera: &[Vertex],
+        s: usize,
+        t: usize,
+        splatting: bool,
+    ) -> (Vec3, Option<(f32, f32)>) {
+        let zero = Vec3::new(0.0, 0.0, 0.0);
+        let nothing = (zero, None);
+        let pt = &camera[t - 1];
+        if s > 0 && pt.kind == Kind::Sky {
+            return nothing;
+        }
+        let mut sampled = None;
+        let mut raster = None;
+        let contribution = if s == 0 {
+            pt.emission(&sources.scene.background) * pt.beta
+        } else if t == 1 {
+            let qs = &light[s - 1];
+            if qs.albedo().is_none() {
+                return nothing;
+            }
+            let lens = match sources.scene.camera.sample_wi(&qs.p) {
+                Some(lens) if lens.pdf > 0.0 && lens.importance > 0.0 => lens,
+                _ => return nothing,
+            };
+            let white = Vec3::new(1.0, 1.0, 1.0);
+            let beta = white * (lens.importance / lens.pdf);
+            let eye = Vertex::new(Kind::Camera, lens.point, zero, beta, 0.0);
+            let wi = qs.towards(&eye);
+            let l =
+                qs.beta * qs.f(&qs.towards(&light[s - 2]), &wi) * eye.beta * wi.dot(&qs.n).abs();
+            if is_black(&l) || !sources.visible(&qs.p, &eye.p) {
+                return nothing;
+            }
+            raster = Some((lens.s, lens.t));
+            sampled = Some(eye);
+            l
+        } else if s == 1 {
+            if pt.albedo().is_none() {
+                return nothing;
+            }
+            let lamp = match sources.sample(&pt.p) {
+                Some(lamp) => lamp,
+                None => return nothing,
+            };
+            let wi = pt.towards(&lamp);
+            let l =
+                pt.beta * pt.f(&pt.towards(&camera[t - 2]), &wi) * lamp.beta * wi.dot(&pt.n).abs();
+            sampled = Some(lamp);
+            l
+        } else {
+            let qs = &light[s - 1];
+            if qs.albedo().is_none() || pt.albedo().is_none() {
+                return nothing;
+            }
+            let w = qs.towards(pt);
+            let l = qs.beta
+                * qs.f(&qs.towards(&light[s - 2]), &w)
+                * pt.f(&pt.towards(&camera[t - 2]), &-w)
+                * pt.beta;
+            if is_black(&l) || !sources.visible(&qs.p, &pt.p) {
+                return nothing;
+            }
+            let g = w.dot(&qs.n).abs() * w.dot(&pt.n).abs() / (pt.p - qs.p).squared_length();
+            l * g
+        };
+        if is_black(&contribution) {
+            return nothing;
+        }
+        let weight = self.mis_weight(sources, light, camera, sampled.as_ref(), s, t, splatting);
+        (contribution * weight, raster)
+    }
+
+    /// Balance heuristic weight of the strategy with `s` light and `t` camera vertices
+    /// among all that could have made the same path. `sampled` replaces the last light
+    /// vertex when `s` is 1 and the last camera vertex when `t` is 1. Joins to the lens only
+    /// count when they are being splatted, which is always the case when `t` is 1.
+    #[allow(clippy::too_many_arguments)]
+    fn mis_weight(
+        &self,
+        sources: &Sources,
+        light: &[Vertex],
+        camera: &[Vertex],
+        sampled: Option<&Vertex>,
+        s: usize,
+        t: usize,
+        splatting: bool,
+    ) -> f32 {
+        if s + t == 2 {
+            return 1.0;
+        }
+        let qs = match s {
+            0 => None,
+            1 => sampled,
+            _ => Some(&light[s - 1]),
+        };
+        let pt = match t {
+            1 => sampled.expect("joins to the lens sample it"),
+            _ => &camera[t - 1],
+        };
+        let qs_minus = if s > 1 { Some(&light[s - 2]) } else { None };
+        let pt_minus = if t > 1 { Some(&camera[t - 2]) } else { None };
+
+        // densities of the vertices around the join as the other path would sample them
+        let pt_rev = match qs {
+            Some(qs) => sources.pdf(qs, qs_minus, pt),
+            None => sources.pdf_origin(pt),
+        };
+        let pt_minus_rev = pt_minus.map_or(0.0, |pt_minus| match qs {
+            Some(qs) => sources.pdf(pt, Some(qs), pt_minus),
+            None => sources.pdf_light(pt, pt_minus),
+        });
+        let qs_rev = qs.map_or(0.0, |qs| sources.pdf(pt, pt_minus, qs));
+        let qs_minus_rev = match (qs, qs_minus) {
+            (Some(qs), Some(qs_minus)) => sources.pdf(qs, Some(pt), qs_minus),
+            _ => 0.0,
+        };
+
+        // (reverse density, forward density, delta) of each vertex with the join made
+        let on_camera = |i: usize| {
+            if i == t - 1 {
+                (pt_rev, pt.pdf_fwd, false)
+            } else if i + 2 == t {
+                (pt_minus_rev, camera[i].pdf_fwd, camera[i].delta)
+            } else {
+                (camera[i].pdf_rev, camera[i].pdf_fwd, camera[i].delta)
+            }
+        };
+        let on_light = |i: usize| {
+            if i + 1 == s {
+                let qs = qs.expect("s is positive");
+                (qs_rev, qs.pdf_fwd, false)
+            } else if i + 2 == s {
+                (qs_minus_rev, light[i].pdf_fwd, light[i].delta)
+            } else {
+                (light[i].pdf_rev, light[i].pdf_fwd, light[i].delta)
+            }
+        };
+        // densities next to a mirror are zero only to mark it, and cancel out; other zeros
+        // rule out the strategy and every one past it, e.g. a point the light from the
+        // background cannot reach first
+        let remap = |pdf: f32| if pdf != 0.0 { pdf } else { 1.0 };
+
+        let mut sum = 0.0;
+        let mut ratio = 1.0;
+        for i in (1..t).rev() {
+            let (rev, fwd, delta) = on_camera(i);
+            if rev == 0.0 && !(i + 1 < t && on_camera(i + 1).2) {
+                break;
+            }
+            ratio *= remap(rev) / remap(fwd);
+            if !delta && !on_camera(i - 1).2 && (i > 1 || splatting) {
+                sum += ratio;
+            }
+        }
+        ratio = 1.0;
+        for i in (0..s).rev() {
+            let (rev, fwd, delta) = on_light(i);
+            if rev == 0.0 && !(i + 1 < s && on_light(i + 1).2) {
+                break;
+            }
+            ratio *= remap(rev) / remap(fwd);
+            let before_delta = i > 0 && on_light(i - 1).2;
+            if !delta && !before_delta {
+                sum += ratio;
+            }
+        }
+        1.0 / (1.0 + sum)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bvh::Bvh;
+    use crate::integrator::PathTracer;
+    use crate::misc;
+    use crate::render::Renderer;
+    use crate::scene::Scene;
+
+    /// Builds the scene and hands its view to `f`.
+    fn with_view<T>(text: &str, f: impl FnOnce(&SceneView) -> T) -> T {
+        let scene = Scene::parse(text).unwrap();
+        let camera = scene.camera.build(1.0).unwrap();
+        let world = Bvh::new(scene.objects);
+        f(&SceneView {
+            bounds: world.finite_bounds(),
+            world: &world,
+            lights: &scene.lights,
+            background: scene.background,
+            materials: &scene.materials,
+            camera: camera.as_ref(),
+        })
+    }
+
+    /// Mean radiance of `n` rays from `origin` spread over a small cone around `direction`.
+    fn mean(
+        integrator: &dyn Integrator,
+        view: &SceneView,
+        origin: Vec3,
+        direction: Vec3,
+        n: usize,
+    ) -> Vec3 {
+        let mut sum = Vec3::new(0.0, 0.0, 0.0);
+        for _ in 0..n {
+            let r = Ray::new(origin, direction + 0.05 * random_unit_vector());
+            sum = sum + integrator.radiance(&r, view);
+        }
+        sum / n as f32
+    }
+
+    const LAMP: &str = "background 0 0 0\n\
+                        material white lambertian 0.8 0.8 0.8\n\
+                        material red lambertian 0.8 0.2 0.2\n\
+                        material lamp light 4 4 4\n\
+                        quad -2 0 -2  0 0 4  4 0 0  white\n\
+                        quad -2 0 2  0 3 0  4 0 0  red\n\
+                        quad -0.5 2 -0.5  1 0 0  0 0 1  lamp\n\
+                        box -0.8 0 0  -0.2 0.6 0.6  white\n";
+
+    const SKY: &str = "material white lambertian 0.7 0.7 0.7\n\
+                       material mirror metal 0.9 0.9 0.9 0\n\
+                       plane 0 0 0  0 1 0  white\n\
+                       sphere 0 1 0 1 white\n\
+                       sphere 2 1 0 1 mirror\n";
+
+    const GLASS: &str = "background 0 0 0\n\
+                         material white lambertian 0.8 0.8 0.8\n\
+                         material glass dielectric 1.5\n\
+                         material lamp light 6 6 6\n\
+                         quad -4 0 -4  0 0 8  8 0 0  white\n\
+                         sphere 0 1.2 0 0.5 lamp\n\
+                         sphere 0 1.2 0 0.9 glass\n";
+
+    #[test]
+    fn agrees_with_the_path_tracer() {
+        misc::seed(1);
+        for (text, origin, direction) in [
+            (LAMP, Vec3::new(0.0, 1.0, -3.0), Vec3::new(0.0, -0.5, 3.0)),
+            (SKY, Vec3::new(0.0, 2.0, -5.0), Vec3::new(0.5, -1.0, 4.0)),
+            (GLASS, Vec3::new(0.0, 3.0, -3.0), Vec3::new(1.5, -3.0, 3.0)),
+        ] {
+            with_view(text, |view| {
+                let bdpt = BidirectionalPathTracer::new(20);
+                let bdpt = mean(&bdpt, view, origin, direction, 20_000);
+                let path = mean(&PathTracer::new(20), view, origin, direction, 200_000);
+                assert!(
+                    (bdpt - path).length() < 0.03 * path.length(),
+                    "{:?} against {:?}",
+                    bdpt,
+                    path
+                );
+            });
+        }
+    }
+
+    #[test]
+    fn splats_complete_the_image() {
+        // the glass ball with its lamp seen from above, where much of the floor is lit
+        // through the glass and joins straight to the lens carry a good share of the light
+        let text = format!(
+            "{}camera perspective\nlook_from 0 6 -4\nlook_at 0 0.5 0\nfov 40\naperture 0\n",
+            GLASS
+        );
+        let render = |integrator: Box<dyn Integrator>, spp: u32| {
+            let image = Renderer::new(|| Scene::parse(&text).map_err(|e| e.to_string()))
+                .integrator(integrator)
+                .size(12, 12)
+                .spp(spp)
+                .render()
+                .unwrap()
+                .image;
+            image
+                .pixels
+                .iter()
+                .fold(Vec3::new(0.0, 0.0, 0.0), |sum, p| sum + *p)
+                / image.pixels.len() as f32
+        };
+        let bdpt = render(Box::new(BidirectionalPathTracer::new(20)), 64);
+        let path = render(Box::new(PathTracer::new(20)), 1024);
+        assert!(
+            (bdpt - path).length() < 0.03 * path.length(),
+            "{:?} against {:?}",
+            bdpt,
+            path
+        );
+    }
+}
@@ -0,0 +1,225 @@
+use crate::aabb::Aabb;
+use crate::hitable::{HitRecord, Hitable};
+use crate::ray::Ray;
+use crate::stats::{count, Counter};
+
+enum Node {
+    Leaf(Box<dyn Hitable>),
+    Branch {
+        bounds: Aabb,
+        left: Box<Node>,
+        right: Box<Node>,
+    },
+}
+
+impl Node {
+    fn build(mut items: Vec<(Aabb, Box<dyn Hitable>)>) -> Node {
+        if items.len() == 1 {
+            return Node::Leaf(items.pop().unwrap().1);
+        }
+        let bounds = items
+            .iter()
+            .skip(1)
+            .fold(items[0].0, |acc, (b, _)| acc.surrounding(b));
+        // split at the median centroid along the longest axis
+        let extent = bounds.max - bounds.min;
+        let axis = if extent.x > extent.y && extent.x > extent.z {
+            0
+        } else if extent.y > extent.z {
+            1
+        } else {
+            2
+        };
+        items.sort_by(|a, b| {
+            a.0.centroid()[axis]
+                .partial_cmp(&b.0.centroid()[axis])
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        let right = items.split_off(items.len() / 2);
+        Node::Branch {
+            bounds,
+            left: Box::new(Node::build(items)),
+            right: Box::new(Node::build(right)),
+        }
+    }
+
+    fn bounds(&self) -> Aabb {
+        match self {
+            Node::Leaf(h) => h
+                .bounding_box()
+                .expect("Bvh::new sets shapes without a bounding box aside"),
+            Node::Branch { bounds, .. } => *bounds,
+        }
+    }
+
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        count(Counter::BvhNodeVisits);
+        match self {
+            Node::Leaf(h) => {
+                count(Counter::IntersectionTests);
+                h.hit(r, t_min, t_max)
+            }
+            Node::Branch {
+                bounds,
+                left,
+                right,
+            } => {
+                if !bounds.hit(r, t_min, t_max) {
+                    return None;
+                }
+                let left_hit = left.hit(r, t_min, t_max);
+                let closest = left_hit.as_ref().map_or(t_max, |rec| rec.t);
+                right.hit(r, t_min, closest).or(left_hit)
+            }
+        }
+    }
+}
+
+/// Bounding volume hierarchy over a list of hitables.
+///
+/// Shapes without a bounding box, such as infinite planes, are kept aside and tested linearly.
+pub struct Bvh {
+    root: Option<Node>,
+    unbounded: Vec<Box<dyn Hitable>>,
+}
+
+impl Bvh {
+    pub fn new(list: Vec<Box<dyn Hitable>>) -> Bvh {
+        let mut bounded = vec![];
+        let mut unbounded = vec![];
+        for h in list {
+            match h.bounding_box() {
+                Some(b) => bounded.push((b, h)),
+                None => unbounded.push(h),
+            }
+        }
+        Bvh {
+            root: if bounded.is_empty() {
+                None
+            } else {
+                Some(Node::build(bounded))
+            },
+            unbounded,
+        }
+    }
+
+    /// Box around the shapes that have one, leaving out planes and the like.
+    pub fn finite_bounds(&self) -> Option<Aabb> {
+        self.root.as_ref().map(|root| root.bounds())
+    }
+}
+
+impl Hitable for Bvh {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let mut closest: Option<HitRecord> = None;
+        for h in self.unbounded.iter() {
+            count(Counter::IntersectionTests);
+            let limit = closest.as_ref().map_or(t_max, |rec| rec.t);
+            if let Some(rec) = h.hit(r, t_min, limit) {
+                closest = Some(rec);
+            }
+        }
+        let limit = closest.as_ref().map_or(t_max, |rec| rec.t);
+        match self
+            .root
+            .as_ref()
+            .and_then(|root| root.hit(r, t_min, limit))
+        {
+            Some(rec) => Some(rec),
+            None => closest,
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        if self.unbounded.is_empty() {
+            self.root.as_ref().map(|root| root.bounds())
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hitable::{HitableList, Sphere};
+    use crate::material::{Lambertian, Material};
+    use crate::misc;
+    use crate::shapes::Plane;
+    use crate::vec3::{random_unit_vector, Vec3};
+    use std::rc::Rc;
+
+    fn material() -> Rc<Box<dyn Material>> {
+        Rc::new(Box::new(Lambertian::new((0.5, 0.5, 0.5).into())))
+    }
+
+    fn random_spheres(count: usize) -> Vec<Box<dyn Hitable>> {
+        (0..count)
+            .map(|_| {
+                let center = 10.0 * Vec3::new(misc::random(), misc::random(), misc::random())
+                    - Vec3::new(5.0, 5.0, 5.0);
+                Box::new(Sphere {
+                    center,
+                    radius: 0.1 + misc::random(),
+                    material: material(),
+                }) as Box<dyn Hitable>
+            })
+            .collect()
+    }
+
+    fn ground() -> Box<dyn Hitable> {
+        Box::new(Plane::new(
+            Vec3::new(0.0, -6.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            material(),
+        ))
+    }
+
+    #[test]
+    fn hits_match_a_plain_list() {
+        misc::seed(28);
+        let mut shapes = random_spheres(64);
+        shapes.push(ground());
+        misc::seed(28);
+        let mut same = random_spheres(64);
+        same.push(ground());
+        let (bvh, list) = (Bvh::new(shapes), HitableList::new(same));
+        let mut hits = 0;
+        for _ in 0..2000 {
+            let origin = 8.0 * random_unit_vector();
+            let r = Ray::new(origin, random_unit_vector());
+            let (a, b) = (bvh.hit(&r, 0.001, f32::MAX), list.hit(&r, 0.001, f32::MAX));
+            assert_eq!(a.is_some(), b.is_some(), "{:?}", r.direction());
+            if let (Some(a), Some(b)) = (a, b) {
+                assert_eq!(a.t, b.t);
+                assert_eq!(a.p, b.p);
+                hits += 1;
+            }
+        }
+        assert!(hits > 100);
+        assert!(bvh.bounding_box().is_none());
+        assert!(bvh.finite_bounds().is_some());
+    }
+
+    #[test]
+    fn empty_list_hits_nothing() {
+        let bvh = Bvh::new(vec![]);
+        let r = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(bvh.hit(&r, 0.001, f32::MAX).is_none());
+        assert!(bvh.bounding_box().is_none());
+        assert!(bvh.finite_bounds().is_none());
+    }
+
+    #[test]
+    fn unbounded_shapes_alone_are_tested_linearly() {
+        let bvh = Bvh::new(vec![ground()]);
+        assert!(bvh.bounding_box().is_none());
+        assert!(bvh.finite_bounds().is_none());
+        let down = Ray::new(Vec3::new(1.0, 0.0, 2.0), Vec3::new(0.0, -1.0, 0.0));
+        let rec = bvh.hit(&down, 0.001, f32::MAX).expect("expected a hit");
+        assert!((rec.t - 6.0).abs() < 1e-5);
+        assert!(bvh.hit(&down, 0.001, 5.0).is_none());
+        let up = Ray::new(Vec3::new(1.0, 0.0, 2.0), Vec3::new(0.0, 1.0, 0.0));
+        assert!(bvh.hit(&up, 0.001, f32::MAX).is_none());
+    }
+}
@@ -1,20 +1,88 @@
+use crate::aperture::{Aperture, ApertureShape};
 use crate::misc::degree_to_radian;
-use crate::misc::random;
 use crate::ray::Ray;
 use crate::vec3::Vec3;
-pub struct Camera {
+use std::f32::consts::PI;
+
+/// Maps normalized image coordinates to primary rays.
+pub trait Camera {
+    /// `s` runs left to right and `t` bottom to top, both in `[0, 1]`.
+    /// `None` where the projection sees nothing, e.g. outside a fisheye's image circle.
+    fn get_ray(&self, s: f32, t: f32) -> Option<Ray>;
+
+    /// Importance of `r`, a ray leaving the lens, scaled so that adding `we * L / pdf` at
+    /// `raster(r)` for every light path, then dividing by the samples per pixel, estimates
+    /// radiance. 0 off the image; `None` for cameras that cannot connect to light paths.
+    fn we(&self, _r: &Ray) -> Option<f32> {
+        None
+    }
+
+    /// Densities with which `get_ray` makes `r`: of its origin per unit lens area and of its
+    /// direction per unit solid angle.
+    fn pdf_we(&self, _r: &Ray) -> Option<(f32, f32)> {
+        None
+    }
+
+    /// The `(s, t)` at which `r`, leaving the lens, lands on the image; `None` off the image.
+    fn raster(&self, _r: &Ray) -> Option<(f32, f32)> {
+        None
+    }
+
+    /// Picks a point on the lens and the ray from it through `p`, so that light paths can
+    /// be joined to the camera. `None` when `p` is out of view.
+    fn sample_wi(&self, _p: &Vec3) -> Option<LensSample> {
+        None
+    }
+}
+
+/// A point on the lens seen from a point in the scene.
+#[derive(Copy, Clone, Debug)]
+pub struct LensSample {
+    pub point: Vec3,
+    /// `we` of the ray from `point` through the scene point.
+    pub importance: f32,
+    /// Density of `point` per unit solid angle as seen from the scene point.
+    pub pdf: f32,
+    pub s: f32,
+    pub t: f32,
+}
+
+/// Right-handed camera frame: `w` points backwards, away from what the camera looks at.
+pub(crate) struct Frame {
+    pub(crate) u: Vec3,
+    pub(crate) v: Vec3,
+    pub(crate) w: Vec3,
+}
+
+impl Frame {
+    pub(crate) fn new(look_from: Vec3, look_at: Vec3, view_up: Vec3) -> Frame {
+        let w = (look_from - look_at).unit_vector();
+        let u = view_up.cross(&w).unit_vector();
+        let v = w.cross(&u);
+        Frame { u, v, w }
+    }
+
+    /// Direction from camera-space coordinates with -z forward.
+    pub(crate) fn to_world(&self, x: f32, y: f32, z: f32) -> Vec3 {
+        self.u * x + self.v * y + self.w * z
+    }
+}
+
+/// Thin-lens perspective camera.
+pub struct PerspectiveCamera {
     origin: Vec3,
     lower_left_coner: Vec3,
     horizontal: Vec3,
     vertical: Vec3,
     lens_radius: f32,
+    aperture: Aperture,
     u: Vec3,
     v: Vec3,
-    #[allow(dead_code)]
-    w: Vec3,
+    /// Unit view direction, normal to the lens and the image window.
+    forward: Vec3,
 }
 
-impl Camera {
+impl PerspectiveCamera {
     pub fn new(
         look_from: Vec3,
         look_at: Vec3,
@@ -23,14 +91,12 @@ impl Camera {
         aspect: f32,
         aperture: f32,
         focus_dist: f32,
-    ) -> Camera {
+    ) -> PerspectiveCamera {
         let theta = degree_to_radian(v_field_of_view);
         let half_height = (theta / 2.0).tan();
         let half_width = aspect * half_height;
-        let w = (look_from - look_at).unit_vector();
-        let u = view_up.cross(&w).unit_vector();
-        let v = w.cross(&u);
-        Camera {
+        let Frame { u, v, w } = Frame::new(look_from, look_at, view_up);
+        PerspectiveCamera {
             lower_left_coner: look_from
                 - half_width * focus_dist * u
                 - half_height * focus_dist * v
@@ -39,27 +105,559 @@ impl Camera {
             vertical: 2.09 * half_height * focus_dist * v,
             origin: look_from,
             lens_radius: aperture / 2.0,
+            aperture: Aperture::default(),
             u,
             v,
-            w,
+            forward: -w,
+        }
+    }
+
+    /// Replaces the circular aperture; `aperture` passed to `new` still sets its size.
+    pub fn with_aperture(mut self, aperture: Aperture) -> PerspectiveCamera {
+        self.aperture = aperture;
+        self
+    }
+
+    /// One eye of an off-axis stereo pair: the eye moves `eye.sign() * ipd / 2` along the
+    /// camera's right vector while both eyes keep sharing the image window at `convergence`,
+    /// so objects at that distance have zero parallax.
+    #[allow(clippy::too_many_arguments)]
+    pub fn stereo(
+        look_from: Vec3,
+        look_at: Vec3,
+        view_up: Vec3,
+        v_field_of_view: f32,
+        aspect: f32,
+        aperture: f32,
+        focus_dist: f32,
+        ipd: f32,
+        convergence: f32,
+        eye: Eye,
+    ) -> PerspectiveCamera {
+        let mut camera = PerspectiveCamera::new(
+            look_from,
+            look_at,
+            view_up,
+            v_field_of_view,
+            aspect,
+            aperture,
+            focus_dist,
+        );
+        let shift = eye.sign() * ipd / 2.0 * camera.u;
+        camera.origin = camera.origin + shift;
+        camera.lower_left_coner =
+            camera.lower_left_coner + (1.0 - focus_dist / convergence) * shift;
+        camera
+    }
+
+    /// Area of the lens, 1 for a pinhole; `None` unless the lens is sampled uniformly, which
+    /// the importance functions assume.
+    fn lens_area(&self) -> Option<f32> {
+        let uniform = matches!(self.aperture.shape, ApertureShape::Circle)
+            && self.aperture.squeeze == 1.0
+            && self.aperture.cats_eye <= 0.0;
+        if !uniform {
+            None
+        } else if self.lens_radius > 0.0 {
+            Some(PI * self.lens_radius * self.lens_radius)
+        } else {
+            Some(1.0)
         }
     }
 
-    pub fn get_ray(&self, s: f32, t: f32) -> Ray {
-        let rd = self.lens_radius * random_in_unit_disk();
-        let offset = self.u * rd.x + self.v * rd.y;
-        Ray::new(
+    /// Area of the image window moved to unit distance from the lens.
+    fn window_area(&self) -> f32 {
+        let distance = (self.lower_left_coner - self.origin).dot(&self.forward);
+        self.horizontal.length() * self.vertical.length() / (distance * distance)
+    }
+
+    /// Cosine between `r` and the view direction, if `r` lands on the image.
+    fn cosine_on_image(&self, r: &Ray) -> Option<f32> {
+        self.raster(r)?;
+        Some(r.direction().unit_vector().dot(&self.forward))
+    }
+}
+
+impl Camera for PerspectiveCamera {
+    fn get_ray(&self, s: f32, t: f32) -> Option<Ray> {
+        let (x, y) = self.aperture.sample(s, t)?;
+        let offset = self.lens_radius * (self.u * x + self.v * y);
+        Some(Ray::new(
             self.origin + offset,
             self.lower_left_coner + s * self.horizontal + t * self.vertical - self.origin - offset,
-        )
+        ))
+    }
+
+    fn we(&self, r: &Ray) -> Option<f32> {
+        let lens_area = self.lens_area()?;
+        Some(match self.cosine_on_image(r) {
+            Some(cosine) => 1.0 / (self.window_area() * lens_area * cosine.powi(4)),
+            None => 0.0,
+        })
+    }
+
+    fn pdf_we(&self, r: &Ray) -> Option<(f32, f32)> {
+        let lens_area = self.lens_area()?;
+        Some(match self.cosine_on_image(r) {
+            Some(cosine) => (1.0 / lens_area, 1.0 / (self.window_area() * cosine.powi(3))),
+            None => (1.0 / lens_area, 0.0),
+        })
+    }
+
+    fn raster(&self, r: &Ray) -> Option<(f32, f32)> {
+        // where the ray crosses the plane in focus, which get_ray aims at
+        let along = r.direction().dot(&self.forward);
+        if along <= 0.0 {
+            return None;
+        }
+        let reach = (self.lower_left_coner - r.origin()).dot(&self.forward) / along;
+        let q = r.point_at_parameter(reach) - self.lower_left_coner;
+        let s = q.dot(&self.horizontal) / self.horizontal.squared_length();
+        let t = q.dot(&self.vertical) / self.vertical.squared_length();
+        if (0.0..=1.0).contains(&s) && (0.0..=1.0).contains(&t) {
+            Some((s, t))
+        } else {
+            None
+        }
+    }
+
+    fn sample_wi(&self, p: &Vec3) -> Option<LensSample> {
+        let lens_area = self.lens_area()?;
+        let (x, y) = self.aperture.sample(0.5, 0.5)?;
+        let point = self.origin + self.lens_radius * (self.u * x + self.v * y);
+        let r = Ray::new(point, p - &point);
+        let (s, t) = self.raster(&r)?;
+        let cosine = r.direction().unit_vector().dot(&self.forward);
+        Some(LensSample {
+            point,
+            importance: self.we(&r)?,
+            pdf: r.direction().squared_length() / (cosine * lens_area),
+            s,
+            t,
+        })
+    }
+}
+
+/// Parallel projection; `height` is the extent of the view in world units.
+pub struct OrthographicCamera {
+    lower_left_coner: Vec3,
+    horizontal: Vec3,
+    vertical: Vec3,
+    direction: Vec3,
+}
+
+impl OrthographicCamera {
+    pub fn new(
+        look_from: Vec3,
+        look_at: Vec3,
+        view_up: Vec3,
+        height: f32,
+        aspect: f32,
+    ) -> OrthographicCamera {
+        let frame = Frame::new(look_from, look_at, view_up);
+        let horizontal = aspect * height * frame.u;
+        let vertical = height * frame.v;
+        OrthographicCamera {
+            lower_left_coner: look_from - 0.5 * horizontal - 0.5 * vertical,
+            horizontal,
+            vertical,
+            direction: -frame.w,
+        }
+    }
+}
+
+impl Camera for OrthographicCamera {
+    fn get_ray(&self, s: f32, t: f32) -> Option<Ray> {
+        Some(Ray::new(
+            self.lower_left_coner + s * self.horizontal + t * self.vertical,
+            self.direction,
+        ))
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum FisheyeMapping {
+    /// Image radius proportional to the angle off axis.
+    Equidistant,
+    /// Image radius proportional to `sin(angle / 2)`, preserving solid angle.
+    Equisolid,
+}
+
+/// Circular fisheye whose image circle touches the top and bottom of the frame.
+pub struct FisheyeCamera {
+    origin: Vec3,
+    frame: Frame,
+    half_fov: f32,
+    aspect: f32,
+    mapping: FisheyeMapping,
+}
+
+impl FisheyeCamera {
+    pub fn new(
+        look_from: Vec3,
+        look_at: Vec3,
+        view_up: Vec3,
+        field_of_view: f32, // across the image circle in degrees, up to 360
+        aspect: f32,
+        mapping: FisheyeMapping,
+    ) -> FisheyeCamera {
+        FisheyeCamera {
+            origin: look_from,
+            frame: Frame::new(look_from, look_at, view_up),
+            half_fov: degree_to_radian(field_of_view) / 2.0,
+            aspect,
+            mapping,
+        }
+    }
+}
+
+impl Camera for FisheyeCamera {
+    fn get_ray(&self, s: f32, t: f32) -> Option<Ray> {
+        let x = (2.0 * s - 1.0) * self.aspect;
+        let y = 2.0 * t - 1.0;
+        let radius = (x * x + y * y).sqrt();
+        if radius > 1.0 {
+            return None;
+        }
+        let theta = match self.mapping {
+            FisheyeMapping::Equidistant => radius * self.half_fov,
+            FisheyeMapping::Equisolid => 2.0 * (radius * (self.half_fov / 2.0).sin()).asin(),
+        };
+        let phi = y.atan2(x);
+        let direction = self.frame.to_world(
+            theta.sin() * phi.cos(),
+            theta.sin() * phi.sin(),
+            -theta.cos(),
+        );
+        Some(Ray::new(self.origin, direction))
     }
 }
 
-fn random_in_unit_disk() -> Vec3 {
-    loop {
-        let p = 2.0 * Vec3::new(random(), random(), 0.0) - Vec3::from_i(1, 1, 0);
-        if p.dot(&p) <= 1.0 {
-            return p;
+/// Latitude/longitude panorama centered on `look_at`, 180 degrees tall.
+pub struct EquirectangularCamera {
+    origin: Vec3,
+    frame: Frame,
+    longitude_span: f32,
+    eye_offset: f32,
+}
+
+impl EquirectangularCamera {
+    /// Full 360 degree mono panorama.
+    pub fn new(look_from: Vec3, look_at: Vec3, view_up: Vec3) -> EquirectangularCamera {
+        EquirectangularCamera::ods(look_from, look_at, view_up, 360.0, 0.0, Eye::Left)
+    }
+
+    /// Omnidirectional stereo: every column's ray starts `ipd / 2` to the side of its viewing
+    /// direction, as if the eye rotated around `look_from`. A `longitude_span` of 180 gives VR180.
+    pub fn ods(
+        look_from: Vec3,
+        look_at: Vec3,
+        view_up: Vec3,
+        longitude_span: f32, // in degrees
+        ipd: f32,
+        eye: Eye,
+    ) -> EquirectangularCamera {
+        EquirectangularCamera {
+            origin: look_from,
+            frame: Frame::new(look_from, look_at, view_up),
+            longitude_span: degree_to_radian(longitude_span),
+            eye_offset: eye.sign() * ipd / 2.0,
+        }
+    }
+}
+
+impl Camera for EquirectangularCamera {
+    fn get_ray(&self, s: f32, t: f32) -> Option<Ray> {
+        let phi = (s - 0.5) * self.longitude_span;
+        let theta = (t - 0.5) * PI;
+        let direction = self.frame.to_world(
+            theta.cos() * phi.sin(),
+            theta.sin(),
+            -theta.cos() * phi.cos(),
+        );
+        let offset = self.frame.to_world(
+            self.eye_offset * phi.cos(),
+            0.0,
+            self.eye_offset * phi.sin(),
+        );
+        Some(Ray::new(self.origin + offset, direction))
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Eye {
+    Left,
+    Right,
+}
+
+impl Eye {
+    /// -1 for the left eye and 1 for the right, i.e. the direction of its offset along `u`.
+    pub fn sign(self) -> f32 {
+        match self {
+            Eye::Left => -1.0,
+            Eye::Right => 1.0,
+        }
+    }
+}
+
+/// How the two eyes of a stereo image share the frame.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum StereoLayout {
+    /// Left eye on the left half.
+    SideBySide,
+    /// Left eye on the top half.
+    TopBottom,
+}
+
+impl StereoLayout {
+    /// Aspect ratio of one eye's view in a frame of the given aspect ratio.
+    pub fn eye_aspect(self, frame_aspect: f32) -> f32 {
+        match self {
+            StereoLayout::SideBySide => frame_aspect / 2.0,
+            StereoLayout::TopBottom => frame_aspect * 2.0,
+        }
+    }
+}
+
+/// Renders two cameras into one frame.
+pub struct StereoRig {
+    pub left: Box<dyn Camera>,
+    pub right: Box<dyn Camera>,
+    pub layout: StereoLayout,
+}
+
+impl Camera for StereoRig {
+    fn get_ray(&self, s: f32, t: f32) -> Option<Ray> {
+        match self.layout {
+            StereoLayout::SideBySide if s < 0.5 => self.left.get_ray(2.0 * s, t),
+            StereoLayout::SideBySide => self.right.get_ray(2.0 * s - 1.0, t),
+            StereoLayout::TopBottom if t >= 0.5 => self.left.get_ray(s, 2.0 * t - 1.0),
+            StereoLayout::TopBottom => self.right.get_ray(s, 2.0 * t),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn look() -> (Vec3, Vec3, Vec3) {
+        ((0, 0, 0).into(), (0, 0, -1).into(), (0, 1, 0).into())
+    }
+
+    fn angle_off_axis(r: &Ray) -> f32 {
+        r.direction()
+            .unit_vector()
+            .dot(&Vec3::from_i(0, 0, -1))
+            .clamp(-1.0, 1.0)
+            .acos()
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-3, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn perspective_field_of_view() {
+        let (from, at, up) = look();
+        let camera = PerspectiveCamera::new(from, at, up, 90.0, 2.0, 0.0, 1.0);
+        let bottom = camera.get_ray(0.5, 0.0).unwrap();
+        assert_close(angle_off_axis(&bottom), degree_to_radian(45.0));
+        // the image has always reached a little further up than down
+        let top = camera.get_ray(0.5, 1.0).unwrap();
+        assert_close(angle_off_axis(&top), 1.09f32.atan());
+        let right = camera.get_ray(1.0, 0.5).unwrap();
+        assert_close(angle_off_axis(&right), 2.0f32.atan());
+        assert!(right.direction().x > 0.0);
+    }
+
+    #[test]
+    fn aperture_shapes_the_lens() {
+        use crate::aperture::ApertureShape;
+        let (from, at, up) = look();
+        let square =
+            PerspectiveCamera::new(from, at, up, 40.0, 1.0, 2.0, 1.0).with_aperture(Aperture {
+                shape: ApertureShape::Polygon {
+                    blades: 4,
+                    rotation: 45.0,
+                },
+                ..Aperture::default()
+            });
+        let mut corner = false;
+        for _ in 0..2000 {
+            let o = *square.get_ray(0.5, 0.5).unwrap().origin();
+            let half_side = std::f32::consts::FRAC_1_SQRT_2;
+            assert!(o.x.abs() <= half_side + 1e-5 && o.y.abs() <= half_side + 1e-5);
+            corner |= o.x.abs() > 0.6 && o.y.abs() > 0.6;
+        }
+        assert!(corner);
+    }
+
+    #[test]
+    fn orthographic_rays_are_parallel() {
+        let (from, at, up) = look();
+        let camera = OrthographicCamera::new(from, at, up, 4.0, 1.5);
+        let a = camera.get_ray(0.0, 0.0).unwrap();
+        let b = camera.get_ray(1.0, 1.0).unwrap();
+        assert_eq!(a.direction(), b.direction());
+        assert_close(a.origin().x, -3.0);
+        assert_close(b.origin().y, 2.0);
+    }
+
+    #[test]
+    fn fisheye_mappings() {
+        let (from, at, up) = look();
+        for mapping in [FisheyeMapping::Equidistant, FisheyeMapping::Equisolid] {
+            let camera = FisheyeCamera::new(from, at, up, 180.0, 1.0, mapping);
+            assert_close(angle_off_axis(&camera.get_ray(0.5, 0.5).unwrap()), 0.0);
+            assert_close(angle_off_axis(&camera.get_ray(0.5, 1.0).unwrap()), PI / 2.0);
+            assert!(camera.get_ray(1.0, 1.0).is_none());
+        }
+        let equidistant = FisheyeCamera::new(from, at, up, 180.0, 1.0, FisheyeMapping::Equidistant);
+        assert_close(
+            angle_off_axis(&equidistant.get_ray(0.75, 0.5).unwrap()),
+            PI / 4.0,
+        );
+        let equisolid = FisheyeCamera::new(from, at, up, 180.0, 1.0, FisheyeMapping::Equisolid);
+        let theta = angle_off_axis(&equisolid.get_ray(0.75, 0.5).unwrap());
+        assert_close((theta / 2.0).sin(), 0.5 * (PI / 4.0).sin());
+    }
+
+    #[test]
+    fn equirectangular_covers_the_sphere() {
+        let (from, at, up) = look();
+        let camera = EquirectangularCamera::new(from, at, up);
+        let center = camera.get_ray(0.5, 0.5).unwrap();
+        assert!((center.direction().unit_vector() - Vec3::from_i(0, 0, -1)).length() < 1e-5);
+        let behind = camera.get_ray(0.0, 0.5).unwrap();
+        assert!((behind.direction().unit_vector() - Vec3::from_i(0, 0, 1)).length() < 1e-5);
+        let right = camera.get_ray(0.75, 0.5).unwrap();
+        assert!((right.direction().unit_vector() - Vec3::from_i(1, 0, 0)).length() < 1e-5);
+        let up = camera.get_ray(0.3, 1.0).unwrap();
+        assert!((up.direction().unit_vector() - Vec3::from_i(0, 1, 0)).length() < 1e-5);
+    }
+
+    #[test]
+    fn stereo_eyes_converge() {
+        let (from, at, up) = look();
+        let eye =
+            |eye| PerspectiveCamera::stereo(from, at, up, 40.0, 1.0, 0.0, 3.0, 0.064, 5.0, eye);
+        let (left, right) = (eye(Eye::Left), eye(Eye::Right));
+        for &(s, t) in &[(0.5, 0.5), (0.1, 0.9), (0.7, 0.2)] {
+            let (l, r) = (left.get_ray(s, t).unwrap(), right.get_ray(s, t).unwrap());
+            assert!((l.origin().x + 0.032).abs() < 1e-6);
+            assert!((r.origin().x - 0.032).abs() < 1e-6);
+            // both eyes see the same point on the convergence plane
+            let on_plane = |ray: &Ray| ray.point_at_parameter(-5.0 / ray.direction().z);
+            assert!((on_plane(&l) - on_plane(&r)).length() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn ods_eyes_circle_the_center() {
+        let (from, at, up) = look();
+        let left = EquirectangularCamera::ods(from, at, up, 360.0, 0.064, Eye::Left);
+        let right = EquirectangularCamera::ods(from, at, up, 360.0, 0.064, Eye::Right);
+        for &s in &[0.0, 0.3, 0.5, 0.8] {
+            let (l, r) = (
+                left.get_ray(s, 0.5).unwrap(),
+                right.get_ray(s, 0.5).unwrap(),
+            );
+            assert_eq!(l.direction(), r.direction());
+            assert!((l.origin().length() - 0.032).abs() < 1e-6);
+            assert!(l.origin().dot(l.direction()).abs() < 1e-6);
+            // right eye is to the right of the viewing direction
+            assert!(l.direction().cross(r.origin()).y < 0.0);
+        }
+        let vr180 = EquirectangularCamera::ods(from, at, up, 180.0, 0.0, Eye::Left);
+        let edge = vr180.get_ray(1.0, 0.5).unwrap();
+        assert!((edge.direction().unit_vector() - Vec3::from_i(1, 0, 0)).length() < 1e-5);
+    }
+
+    #[test]
+    fn stereo_rig_splits_the_frame() {
+        let (from, at, up) = look();
+        let rig = |layout| StereoRig {
+            left: Box::new(OrthographicCamera::new(
+                from - Vec3::from_i(1, 0, 0),
+                at,
+                up,
+                1.0,
+                1.0,
+            )),
+            right: Box::new(OrthographicCamera::new(
+                from + Vec3::from_i(1, 0, 0),
+                at,
+                up,
+                1.0,
+                1.0,
+            )),
+            layout,
+        };
+        let side = rig(StereoLayout::SideBySide);
+        assert!(side.get_ray(0.25, 0.5).unwrap().origin().x < -0.9);
+        assert!(side.get_ray(0.75, 0.5).unwrap().origin().x > 0.9);
+        let stacked = rig(StereoLayout::TopBottom);
+        assert!(stacked.get_ray(0.5, 0.75).unwrap().origin().x < -0.9);
+        assert!(stacked.get_ray(0.5, 0.25).unwrap().origin().x > 0.9);
+        assert_eq!(StereoLayout::TopBottom.eye_aspect(1.0), 2.0);
+    }
+
+    #[test]
+    fn importance_inverts_the_rays() {
+        let (from, at, up) = look();
+        for aperture in [0.0, 0.5] {
+            let camera = PerspectiveCamera::stereo(
+                from,
+                at,
+                up,
+                50.0,
+                1.5,
+                aperture,
+                3.0,
+                0.2,
+                2.0,
+                Eye::Left,
+            );
+            for &(s, t) in &[(0.5, 0.5), (0.1, 0.9), (0.97, 0.02)] {
+                let r = camera.get_ray(s, t).unwrap();
+                let (rs, rt) = camera.raster(&r).unwrap();
+                assert_close(rs, s);
+                assert_close(rt, t);
+                // importance over the densities that made the ray is the cosine's inverse
+                let (pdf_pos, pdf_dir) = camera.pdf_we(&r).unwrap();
+                let cosine = r.direction().unit_vector().z.abs();
+                assert_close(camera.we(&r).unwrap() * cosine / (pdf_pos * pdf_dir), 1.0);
+            }
+        }
+        let camera = PerspectiveCamera::new(from, at, up, 90.0, 1.0, 0.0, 1.0);
+        let behind = Ray::new(from, (0, 0, 1).into());
+        assert_eq!(camera.raster(&behind), None);
+        assert_eq!(camera.we(&behind), Some(0.0));
+        let cats_eye = camera.with_aperture(Aperture {
+            cats_eye: 0.5,
+            ..Aperture::default()
+        });
+        assert_eq!(cats_eye.we(&behind), None);
+    }
+
+    #[test]
+    fn lens_samples_see_the_point() {
+        let (from, at, up) = look();
+        let camera = PerspectiveCamera::new(from, at, up, 90.0, 1.0, 0.4, 2.0);
+        // in focus, so every point on the lens sees it at the same place on the image
+        let p = Vec3::new(1.0, -0.5, -2.0);
+        for _ in 0..20 {
+            let sample = camera.sample_wi(&p).unwrap();
+            assert!(sample.point.z == 0.0 && sample.point.length() <= 0.2 + 1e-6);
+            assert_close(sample.s, 0.75);
+            assert_close(sample.t, 1.5 / 4.18);
+            let r = Ray::new(sample.point, p - sample.point);
+            assert_close(sample.importance, camera.we(&r).unwrap());
+            let cosine = r.direction().unit_vector().z.abs();
+            let d2 = (p - sample.point).squared_length();
+            assert_close(sample.pdf * cosine / d2, 1.0 / (PI * 0.04));
         }
+        assert!(camera.sample_wi(&Vec3::new(0.0, 0.0, 1.0)).is_none());
     }
 }
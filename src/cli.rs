@@ -0,0 +1,876 @@
+use crate::compare::{Metric, DEFAULT_PPD};
+use crate::integrator::{BounceLimits, IntegratorKind};
+use crate::scene::{CameraSettings, Projection};
+use crate::vec3::Vec3;
+use std::fmt;
+use std::str::FromStr;
+
+pub const USAGE: &str = "\
+Usage: ray_tracing_001 <command> [options] [scene file]
+
+Commands:
+  render    render a scene, or every frame of an animated one
+  preview   show the image in a browser as it refines
+  bench     time repeated renders of a scene
+  info      describe a scene without rendering it
+  compare   measure how far a rendered image is from a reference
+
+Without a scene file the random spheres from the book cover are used.
+Run `ray_tracing_001 <command> --help` for the options of a command.";
+
+pub const RENDER_USAGE: &str = "\
+Usage: ray_tracing_001 render [options] [scene file]
+
+Image:
+  -w, --width <px>         image width (default 400)
+      --height <px>        image height
+      --aspect <w:h>       width over height, e.g. 16:9 or 1.5 (default: the scene's, else 16:9)
+                           at most two of width, height and aspect may be given
+Sampling:
+      --spp <n>            samples per pixel (default 100, preview 16)
+      --max-depth <n>      bounces before a path is cut off (default 50)
+      --max-diffuse <n>    diffuse bounces the path tracer follows
+      --max-specular <n>   mirror-like reflections the path tracer follows
+      --max-transmission <n>
+                           passes through glass the path tracer follows; each kind is
+                           only limited by --max-depth unless given
+      --integrator <name>  path, bdpt, photons, sppm, mlt, direct, ao, whitted, or the
+                           debug views normals, uvs, depth, barycentrics and
+                           material_ids (default: the scene's, else path); ao:<distance>
+                           and depth:<far> set their reach, photons:<radius> and
+                           sppm:<radius> how far they look for photons
+      --seed <n>           seed of the random sequence (default 1234)
+      --threads <n>        render threads (default: one per core)
+Output:
+  -o, --output <path>      file to write; frames of animated scenes are numbered where the
+                           path has `#`s, otherwise a .y4m, .gif or .png path gets them all
+      --format <fmt>       p3, ppm, png, y4m, gif or apng (default: from the extension; P3 on
+                           standard output when there is no path)
+Reporting:
+  -q, --quiet              no progress bar or statistics on standard error
+      --stats-json <path>  also write the statistics as JSON, `-` for standard output
+Camera overrides, applied after the scene and its keyframes:
+      --projection <name>  perspective, orthographic, fisheye-equidistant,
+                           fisheye-equisolid, equirectangular or lens
+      --look-from <x,y,z>
+      --look-at <x,y,z>
+      --view-up <x,y,z>
+      --fov <degrees>
+      --aperture <diameter>
+      --focus-dist <distance>";
+
+pub const PREVIEW_USAGE: &str = "\
+Usage: ray_tracing_001 preview [options] [scene file]
+
+Serves the image as it refines at http://127.0.0.1:<port>/, where the camera, the samples
+per pixel and the exposure can be changed while it renders. With --output it rewrites that
+file after every pass instead.
+
+      --port <n>           port of the local server (default 8080, 0 for any free one)
+      --terminal           draw the image in the terminal with 24-bit colors instead,
+                           shrunk to fit and redrawn after every pass
+
+Takes the options of `render` otherwise, with 16 samples per pixel by default.";
+
+pub const BENCH_USAGE: &str = "\
+Usage: ray_tracing_001 bench [options] [scene file]
+
+Renders the scene `--runs` times (default 3) without writing it and reports the timings.
+Takes the image, sampling and camera options of `render`, with a default width of 200 and
+16 samples per pixel.";
+
+pub const INFO_USAGE: &str = "\
+Usage: ray_tracing_001 info [scene file]
+
+Prints the objects, camera, film and frame range a scene sets up.";
+
+pub const COMPARE_USAGE: &str = "\
+Usage: ray_tracing_001 compare [options] <reference> <test>
+
+Prints the MSE, relMSE, PSNR, SSIM and FLIP error of the test image against the reference.
+Both may be PPM/PGM, PNG, PFM or OpenEXR; a linear image compared with a display-referred
+one is sRGB-encoded first.
+
+      --metric <name>      mse, relmse, psnr, ssim or flip (default flip)
+      --threshold <x>      exit with status 2 when the metric is worse than this: above it
+                           for errors, below it for psnr and ssim
+      --diff <path>        write the per-pixel FLIP error as a false-color PNG
+      --ppd <n>            pixels per degree of visual angle for FLIP (default 67)
+      --json               print the metrics as JSON";
+
+#[derive(Debug, PartialEq)]
+pub struct CliError(pub String);
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for CliError {}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum OutputFormat {
+    /// Plain-text PPM.
+    P3,
+    Ppm,
+    Png,
+    Y4m,
+    Gif,
+    Apng,
+}
+
+impl OutputFormat {
+    /// Guessed from the extension of `path`.
+    pub fn of_path(path: &str) -> Option<OutputFormat> {
+        let extension = std::path::Path::new(path).extension()?;
+        extension.to_string_lossy().to_lowercase().parse().ok()
+    }
+
+    /// Whether a single file of this format can hold a whole animation.
+    pub fn is_animation(self) -> bool {
+        matches!(
+            self,
+            OutputFormat::Y4m | OutputFormat::Gif | OutputFormat::Apng
+        )
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<OutputFormat, String> {
+        match s {
+            "p3" => Ok(OutputFormat::P3),
+            "ppm" => Ok(OutputFormat::Ppm),
+            "png" => Ok(OutputFormat::Png),
+            "y4m" => Ok(OutputFormat::Y4m),
+            "gif" => Ok(OutputFormat::Gif),
+            "apng" => Ok(OutputFormat::Apng),
+            _ => Err(format!(
+                "unknown format `{}` (expected p3, ppm, png, y4m, gif or apng)",
+                s
+            )),
+        }
+    }
+}
+
+/// Camera settings given on the command line, overriding the scene's.
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct CameraOverrides {
+    pub projection: Option<Projection>,
+    pub look_from: Option<Vec3>,
+    pub look_at: Option<Vec3>,
+    pub view_up: Option<Vec3>,
+    pub fov: Option<f32>,
+    pub aperture: Option<f32>,
+    pub focus_dist: Option<f32>,
+}
+
+impl CameraOverrides {
+    /// Fails when the overrides leave the camera without a usable view.
+    pub fn apply(&self, camera: &mut CameraSettings) -> Result<(), CliError> {
+        if let Some(projection) = self.projection {
+            camera.projection = projection;
+        }
+        if let Some(v) = self.look_from {
+            camera.look_from = v;
+        }
+        if let Some(v) = self.look_at {
+            camera.look_at = v;
+        }
+        if let Some(v) = self.view_up {
+            camera.view_up = v;
+        }
+        if let Some(v) = self.fov {
+            camera.fov = v;
+        }
+        if let Some(v) = self.aperture {
+            camera.aperture = v;
+        }
+        if let Some(v) = self.focus_dist {
+            camera.focus_dist = v;
+        }
+        if camera.projection == Projection::Lens && camera.lens.is_none() {
+            return Err(CliError(
+                "--projection lens needs a scene that sets a lens table".to_string(),
+            ));
+        }
+        // the field of view allowed, for one, depends on the projection they end up with
+        camera.check().map_err(|e| CliError(e.to_string()))
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct RenderOptions {
+    pub scene: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub aspect: Option<f32>,
+    pub spp: u32,
+    pub max_depth: u32,
+    pub bounces: BounceLimits,
+    /// `None` for the scene's own.
+    pub integrator: Option<IntegratorKind>,
+    pub seed: u64,
+    /// `None` for one per core.
+    pub threads: Option<usize>,
+    pub output: Option<String>,
+    pub format: Option<OutputFormat>,
+    /// No progress bar and no statistics on standard error.
+    pub quiet: bool,
+    /// Where to write the statistics as JSON.
+    pub stats_json: Option<String>,
+    pub camera: CameraOverrides,
+}
+
+impl Default for RenderOptions {
+    fn default() -> RenderOptions {
+        RenderOptions {
+            scene: None,
+            width: None,
+            height: None,
+            aspect: None,
+            spp: 100,
+            max_depth: 50,
+            bounces: BounceLimits::default(),
+            integrator: None,
+            seed: 1234,
+            threads: None,
+            output: None,
+            format: None,
+            quiet: false,
+            stats_json: None,
+            camera: CameraOverrides::default(),
+        }
+    }
+}
+
+const DEFAULT_WIDTH: u32 = 400;
+
+impl RenderOptions {
+    /// Image size in pixels; `scene_aspect` is used unless the command line settles it.
+    pub fn resolution(&self, scene_aspect: Option<f32>) -> Result<(usize, usize), CliError> {
+        let aspect = || self.aspect.or(scene_aspect).unwrap_or(16.0 / 9.0);
+        let (width, height) = match (self.width, self.height) {
+            (Some(w), Some(h)) => (w as f32, h as f32),
+            (Some(w), None) => (w as f32, (w as f32 / aspect()).floor()),
+            (None, Some(h)) => ((h as f32 * aspect()).floor(), h as f32),
+            (None, None) => {
+                let w = DEFAULT_WIDTH as f32;
+                (w, (w / aspect()).floor())
+            }
+        };
+        if width < 1.0 || height < 1.0 {
+            return Err(CliError(format!(
+                "an aspect of {} leaves a {}x{} image; give both --width and --height",
+                aspect(),
+                width,
+                height
+            )));
+        }
+        Ok((width as usize, height as usize))
+    }
+
+    /// Number of threads to render with.
+    pub fn thread_count(&self) -> usize {
+        self.threads.unwrap_or_else(|| {
+            std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1)
+        })
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct PreviewOptions {
+    pub render: RenderOptions,
+    pub port: u16,
+    /// Draw into the terminal rather than serving to a browser.
+    pub terminal: bool,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct BenchOptions {
+    pub render: RenderOptions,
+    pub runs: u32,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct CompareOptions {
+    pub reference: String,
+    pub test: String,
+    pub metric: Metric,
+    pub threshold: Option<f64>,
+    pub diff: Option<String>,
+    pub ppd: f32,
+    pub json: bool,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Command {
+    Render(RenderOptions),
+    Preview(PreviewOptions),
+    Bench(BenchOptions),
+    Info {
+        scene: Option<String>,
+    },
+    Compare(CompareOptions),
+    /// Show this text and stop.
+    Help(&'static str),
+}
+
+pub(crate) fn positive<T: FromStr + PartialOrd + Default>(
+    name: &str,
+    value: &str,
+) -> Result<T, CliError> {
+    match value.parse::<T>() {
+        Ok(n) if n > T::default() => Ok(n),
+        _ => Err(CliError(format!(
+            "{}: expected a positive whole number, got `{}`",
+            name, value
+        ))),
+    }
+}
+
+fn whole<T: FromStr>(name: &str, value: &str) -> Result<T, CliError> {
+    value.parse().map_err(|_| {
+        CliError(format!(
+            "{}: expected a whole number, got `{}`",
+            name, value
+        ))
+    })
+}
+
+pub(crate) fn real(name: &str, value: &str) -> Result<f32, CliError> {
+    match value.parse::<f32>() {
+        Ok(x) if x.is_finite() => Ok(x),
+        _ => Err(CliError(format!(
+            "{}: expected a number, got `{}`",
+            name, value
+        ))),
+    }
+}
+
+pub(crate) fn positive_real(name: &str, value: &str) -> Result<f32, CliError> {
+    match real(name, value)? {
+        x if x > 0.0 => Ok(x),
+        _ => Err(CliError(format!(
+            "{}: expected a positive number, got `{}`",
+            name, value
+        ))),
+    }
+}
+
+/// `16:9`, `4/3` or a plain ratio such as `1.5`.
+fn aspect(name: &str, value: &str) -> Result<f32, CliError> {
+    let error = || {
+        CliError(format!(
+            "{}: expected a ratio such as 16:9 or 1.5, got `{}`",
+            name, value
+        ))
+    };
+    let ratio = match value.find([':', '/']) {
+        Some(i) => {
+            let w = positive_real(name, &value[..i]).map_err(|_| error())?;
+            let h = positive_real(name, &value[i + 1..]).map_err(|_| error())?;
+            w / h
+        }
+        None => positive_real(name, value).map_err(|_| error())?,
+    };
+    if ratio.is_finite() {
+        Ok(ratio)
+    } else {
+        Err(error())
+    }
+}
+
+/// `x,y,z`.
+pub(crate) fn vec3(name: &str, value: &str) -> Result<Vec3, CliError> {
+    let parts: Vec<&str> = value.split(',').map(str::trim).collect();
+    let numbers: Vec<f32> = parts.iter().filter_map(|p| p.parse().ok()).collect();
+    if parts.len() != 3 || numbers.len() != 3 || numbers.iter().any(|x| !x.is_finite()) {
+        return Err(CliError(format!(
+            "{}: expected three numbers such as 13,2,3, got `{}`",
+            name, value
+        )));
+    }
+    Ok(Vec3::new(numbers[0], numbers[1], numbers[2]))
+}
+
+/// Splits `--name=value` and `--name value` alike, and keeps positional arguments apart.
+struct Arguments<'a> {
+    args: std::slice::Iter<'a, String>,
+    positional: Vec<&'a str>,
+}
+
+impl<'a> Arguments<'a> {
+    fn new(args: &'a [String]) -> Arguments<'a> {
+        Arguments {
+            args: args.iter(),
+            positional: vec![],
+        }
+    }
+
+    /// The next option and its inline value, if it had one.
+    fn next_option(&mut self) -> Option<(&'a str, Option<&'a str>)> {
+        for arg in self.args.by_ref() {
+            if arg.len() > 1 && arg.starts_with('-') {
+                return Some(match arg.find('=') {
+                    Some(i) if arg.starts_with("--") => (&arg[..i], Some(&arg[i + 1..])),
+                    _ => (arg.as_str(), None),
+                });
+            }
+            self.positional.push(arg);
+        }
+        None
+    }
+
+    fn value(&mut self, name: &str, inline: Option<&'a str>) -> Result<&'a str, CliError> {
+        match inline {
+            Some(v) => Ok(v),
+            None => self
+                .args
+                .next()
+                .map(String::as_str)
+                .ok_or_else(|| CliError(format!("{} needs a value", name))),
+        }
+    }
+
+    /// The scene file, the only positional argument commands take.
+    fn scene(self, command: &str) -> Result<Option<String>, CliError> {
+        match self.positional.as_slice() {
+            [] => Ok(None),
+            [scene] => Ok(Some(scene.to_string())),
+            [_, extra, ..] => Err(CliError(format!(
+                "{}: unexpected argument `{}`; only one scene file is taken",
+                command, extra
+            ))),
+        }
+    }
+}
+
+fn unknown_option(command: &str, name: &str) -> CliError {
+    CliError(format!(
+        "{}: unknown option `{}`; see `ray_tracing_001 {} --help`",
+        command, name, command
+    ))
+}
+
+/// Parses the options shared by `render`, `preview` and `bench` into `options`; `extra`
+/// handles options of its own and returns `Ok(false)` for ones it does not know either.
+fn parse_render<'a>(
+    command: &str,
+    args: &'a [String],
+    mut options: RenderOptions,
+    mut extra: impl FnMut(&str, &mut Arguments<'a>, Option<&'a str>) -> Result<bool, CliError>,
+) -> Result<Result<RenderOptions, &'static str>, CliError> {
+    let mut args = Arguments::new(args);
+    while let Some((name, inline)) = args.next_option() {
+        match name {
+            "-h" | "--help" => return Ok(Err(RENDER_USAGE)),
+            "-w" | "--width" => options.width = Some(positive(name, args.value(name, inline)?)?),
+            "--height" => options.height = Some(positive(name, args.value(name, inline)?)?),
+            "--aspect" => options.aspect = Some(aspect(name, args.value(name, inline)?)?),
+            "--spp" => options.spp = positive(name, args.value(name, inline)?)?,
+            "--max-depth" => options.max_depth = whole(name, args.value(name, inline)?)?,
+            "--max-diffuse" => options.bounces.diffuse = whole(name, args.value(name, inline)?)?,
+            "--max-specular" => options.bounces.specular = whole(name, args.value(name, inline)?)?,
+            "--max-transmission" => {
+                options.bounces.transmission = whole(name, args.value(name, inline)?)?
+            }
+            "--integrator" => {
+                let value = args.value(name, inline)?;
+                let integrator = value
+                    .parse()
+                    .map_err(|e| CliError(format!("{}: {}", name, e)))?;
+                options.integrator = Some(integrator);
+            }
+            "--seed" => options.seed = whole(name, args.value(name, inline)?)?,
+            "--threads" => options.threads = Some(positive(name, args.value(name, inline)?)?),
+            "-o" | "--output" => options.output = Some(args.value(name, inline)?.to_string()),
+            "-q" | "--quiet" => options.quiet = true,
+            "--stats-json" => options.stats_json = Some(args.value(name, inline)?.to_string()),
+            "--format" => {
+                let value = args.value(name, inline)?;
+                let format = value
+                    .parse()
+                    .map_err(|e| CliError(format!("{}: {}", name, e)))?;
+                options.format = Some(format);
+            }
+            "--projection" => {
+                let value = args.value(name, inline)?;
+                let projection = value
+                    .parse()
+                    .map_err(|e| CliError(format!("{}: {}", name, e)))?;
+                options.camera.projection = Some(projection);
+            }
+            "--look-from" => {
+                options.camera.look_from = Some(vec3(name, args.value(name, inline)?)?)
+            }
+            "--look-at" => options.camera.look_at = Some(vec3(name, args.value(name, inline)?)?),
+            "--view-up" => options.camera.view_up = Some(vec3(name, args.value(name, inline)?)?),
+            "--fov" => options.camera.fov = Some(positive_real(name, args.value(name, inline)?)?),
+            "--aperture" => {
+                let value = args.value(name, inline)?;
+                match real(name, value)? {
+                    x if x >= 0.0 => options.camera.aperture = Some(x),
+                    _ => {
+                        return Err(CliError(format!(
+                            "{}: expected a number of at least 0, got `{}`",
+                            name, value
+                        )))
+                    }
+                }
+            }
+            "--focus-dist" => {
+                options.camera.focus_dist = Some(positive_real(name, args.value(name, inline)?)?)
+            }
+            _ => {
+                if !extra(name, &mut args, inline)? {
+                    return Err(unknown_option(command, name));
+                }
+            }
+        }
+    }
+    if options.width.is_some() && options.height.is_some() && options.aspect.is_some() {
+        return Err(CliError(
+            "--width, --height and --aspect contradict each other; give at most two".to_string(),
+        ));
+    }
+    if let Some(view_up) = options.camera.view_up {
+        if view_up.length() == 0.0 {
+            return Err(CliError("--view-up: must not be zero".to_string()));
+        }
+    }
+    if let (Some(from), Some(at)) = (options.camera.look_from, options.camera.look_at) {
+        if from == at {
+            return Err(CliError(
+                "--look-from and --look-at must be different points".to_string(),
+            ));
+        }
+    }
+    if options.format.is_none() {
+        if let Some(path) = &options.output {
+            if OutputFormat::of_path(path).is_none() {
+                return Err(CliError(format!(
+                    "--output: cannot tell the format of `{}` from its extension; add --format",
+                    path
+                )));
+            }
+        }
+    }
+    if options.stats_json.as_deref() == Some("-") && options.output.is_none() && command != "bench"
+    {
+        return Err(CliError(
+            "--stats-json -: standard output already carries the image; give --output".to_string(),
+        ));
+    }
+    options.scene = args.scene(command)?;
+    Ok(Ok(options))
+}
+
+impl Command {
+    /// Parses the arguments after the program name.
+    pub fn parse(args: &[String]) -> Result<Command, CliError> {
+        let (command, rest) = match args.split_first() {
+            None => return Ok(Command::Help(USAGE)),
+            Some((command, rest)) => (command.as_str(), rest),
+        };
+        let no_extra = |_: &str, _: &mut Arguments, _: Option<&str>| Ok(false);
+        Ok(match command {
+            "-h" | "--help" | "help" => Command::Help(USAGE),
+            "render" => match parse_render(command, rest, RenderOptions::default(), no_extra)? {
+                Ok(options) => Command::Render(options),
+                Err(usage) => Command::Help(usage),
+            },
+            "preview" => {
+                let defaults = RenderOptions {
+                    spp: 16,
+                    ..RenderOptions::default()
+                };
+                let (mut port, mut terminal) = (8080, false);
+                let parsed = parse_render(command, rest, defaults, |name, args, inline| {
+                    match name {
+                        "--port" => port = whole(name, args.value(name, inline)?)?,
+                        "--terminal" => terminal = true,
+                        _ => return Ok(false),
+                    }
+                    Ok(true)
+                })?;
+                match parsed {
+                    Ok(render) => Command::Preview(PreviewOptions {
+                        render,
+                        port,
+                        terminal,
+                    }),
+                    Err(_) => Command::Help(PREVIEW_USAGE),
+                }
+            }
+            "bench" => {
+                let defaults = RenderOptions {
+                    width: Some(200),
+                    spp: 16,
+                    ..RenderOptions::default()
+                };
+                let mut runs = 3;
+                let parsed = parse_render(command, rest, defaults, |name, args, inline| {
+                    match name {
+                        "--runs" => runs = positive(name, args.value(name, inline)?)?,
+                        _ => return Ok(false),
+                    }
+                    Ok(true)
+                })?;
+                match parsed {
+                    Ok(render) => Command::Bench(BenchOptions { render, runs }),
+                    Err(_) => Command::Help(BENCH_USAGE),
+                }
+            }
+            "info" => {
+                let mut args = Arguments::new(rest);
+                if let Some((name, _)) = args.next_option() {
+                    return match name {
+                        "-h" | "--help" => Ok(Command::Help(INFO_USAGE)),
+                        _ => Err(unknown_option(command, name)),
+                    };
+                }
+                Command::Info {
+                    scene: args.scene(command)?,
+                }
+            }
+            "compare" => {
+                let mut args = Arguments::new(rest);
+                let (mut metric, mut threshold, mut diff) = (Metric::Flip, None, None);
+                let (mut ppd, mut json) = (DEFAULT_PPD, false);
+                while let Some((name, inline)) = args.next_option() {
+                    match name {
+                        "-h" | "--help" => return Ok(Command::Help(COMPARE_USAGE)),
+                        "--metric" => {
+                            let value = args.value(name, inline)?;
+                            metric = value
+                                .parse()
+                                .map_err(|e| CliError(format!("{}: {}", name, e)))?;
+                        }
+                        "--threshold" => {
+                            let value = args.value(name, inline)?;
+                            real(name, value)?;
+                            threshold = value.parse().ok();
+                        }
+                        "--diff" => diff = Some(args.value(name, inline)?.to_string()),
+                        "--ppd" => ppd = positive_real(name, args.value(name, inline)?)?,
+                        "--json" => json = true,
+                        _ => return Err(unknown_option(command, name)),
+                    }
+                }
+                match args.positional.as_slice() {
+                    [reference, test] => Command::Compare(CompareOptions {
+                        reference: reference.to_string(),
+                        test: test.to_string(),
+                        metric,
+                        threshold,
+                        diff,
+                        ppd,
+                        json,
+                    }),
+                    _ => {
+                        return Err(CliError(
+                            "compare: expected a reference image and a test image".to_string(),
+                        ))
+                    }
+                }
+            }
+            _ => {
+                return Err(CliError(format!(
+                    "unknown command `{}` (expected render, preview, bench, info or compare)",
+                    command
+                )))
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Result<Command, CliError> {
+        let args: Vec<String> = line.split_whitespace().map(String::from).collect();
+        Command::parse(&args)
+    }
+
+    fn render(line: &str) -> RenderOptions {
+        match parse(line) {
+            Ok(Command::Render(options)) => options,
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_render_options() {
+        let options = render(
+            "render scenes/lens.scene --width 320 --aspect=4:3 --spp 8 --max-depth 0 \
+             --seed 7 --threads 2 -o out.png --look-from 1,2,3 --fov 40 --projection fisheye \
+             --integrator ao:0.5 --max-specular 4 --max-transmission=0",
+        );
+        assert_eq!(options.scene.as_deref(), Some("scenes/lens.scene"));
+        assert_eq!(options.resolution(None), Ok((320, 240)));
+        assert_eq!((options.spp, options.max_depth, options.seed), (8, 0, 7));
+        assert_eq!(options.thread_count(), 2);
+        assert_eq!(
+            options.integrator,
+            Some(IntegratorKind::AmbientOcclusion { distance: 0.5 })
+        );
+        assert_eq!(
+            options.bounces,
+            BounceLimits {
+                diffuse: u32::MAX,
+                specular: 4,
+                transmission: 0
+            }
+        );
+        assert!(!options.quiet);
+        assert_eq!(OutputFormat::of_path("out.png"), Some(OutputFormat::Png));
+        assert_eq!(options.camera.look_from, Some(Vec3::new(1.0, 2.0, 3.0)));
+
+        let mut camera = CameraSettings::default();
+        options.camera.apply(&mut camera).unwrap();
+        assert_eq!(camera.fov, 40.0);
+        assert!(matches!(camera.projection, Projection::Fisheye(_)));
+        assert_eq!(camera.look_at, CameraSettings::default().look_at);
+
+        let mut camera = CameraSettings::default();
+        let straight_down = render("render --look-from 0,5,0 --look-at 0,0,0");
+        assert!(straight_down.camera.apply(&mut camera).is_err());
+        let mut camera = CameraSettings::default();
+        assert!(render("render --projection lens")
+            .camera
+            .apply(&mut camera)
+            .is_err());
+    }
+
+    #[test]
+    fn resolves_the_image_size() {
+        let defaults = render("render");
+        assert_eq!(defaults.spp, 100);
+        assert_eq!(defaults.max_depth, 50);
+        assert_eq!(defaults.integrator, None);
+        assert_eq!(defaults.resolution(None), Ok((400, 225)));
+        assert_eq!(defaults.resolution(Some(1.0)), Ok((400, 400)));
+        assert_eq!(
+            render("render --height 100 --aspect 2").resolution(None),
+            Ok((200, 100))
+        );
+        assert_eq!(
+            render("render -w 30 --height 20").resolution(Some(1.0)),
+            Ok((30, 20))
+        );
+        assert!(render("render -w 1 --aspect 4").resolution(None).is_err());
+    }
+
+    /// The error of a command line that does not parse or, for `render`, whose camera
+    /// overrides leave the default camera unusable.
+    fn rejection(line: &str) -> Option<CliError> {
+        match parse(line) {
+            Err(error) => Some(error),
+            Ok(Command::Render(options)) => {
+                options.camera.apply(&mut CameraSettings::default()).err()
+            }
+            Ok(_) => None,
+        }
+    }
+
+    #[test]
+    fn reports_bad_values() {
+        for line in &[
+            "render --width abc",
+            "render --width 0",
+            "render --width -5",
+            "render --spp",
+            "render --threads 0",
+            "render --aspect 16:0",
+            "render --look-at 1,2",
+            "render --look-from 1,2,x",
+            "render --view-up 0,0,0",
+            "render --fov nan",
+            "render --fov 180",
+            "render --projection fisheye --fov 400",
+            "render --aperture -1",
+            "render --format bmp",
+            "render -o out.bmp",
+            "render --projection spherical",
+            "render --integrator radiosity",
+            "render --integrator normals:2",
+            "render --integrator ao:-1",
+            "render --max-diffuse -1",
+            "render -w 1 --height 1 --aspect 1",
+            "render --stats-json -",
+            "render --frobnicate",
+            "render a.scene b.scene",
+            "bench --runs 0",
+            "preview --port 70000",
+            "info --spp 3",
+            "draw",
+        ] {
+            let error = rejection(line).expect(line);
+            assert!(!error.0.is_empty());
+        }
+        assert!(rejection("render --fov 179").is_none());
+        assert!(rejection("render --projection fisheye --fov 360").is_none());
+        assert_eq!(
+            parse("render --width abc"),
+            Err(CliError(
+                "--width: expected a positive whole number, got `abc`".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn parses_other_commands() {
+        assert_eq!(parse(""), Ok(Command::Help(USAGE)));
+        assert_eq!(parse("render --help"), Ok(Command::Help(RENDER_USAGE)));
+        assert_eq!(parse("bench -h"), Ok(Command::Help(BENCH_USAGE)));
+        match parse("bench --runs 5 --spp 4 -q --stats-json -").unwrap() {
+            Command::Bench(options) => {
+                assert_eq!(options.runs, 5);
+                assert_eq!(options.render.spp, 4);
+                assert_eq!(options.render.width, Some(200));
+                assert!(options.render.quiet);
+                assert_eq!(options.render.stats_json.as_deref(), Some("-"));
+            }
+            other => panic!("{:?}", other),
+        }
+        match parse("preview --port=0").unwrap() {
+            Command::Preview(options) => {
+                assert_eq!(options.render.spp, 16);
+                assert_eq!(options.port, 0);
+                assert!(!options.terminal);
+            }
+            other => panic!("{:?}", other),
+        }
+        match parse("preview --terminal").unwrap() {
+            Command::Preview(options) => assert!(options.terminal),
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(
+            parse("info scenes/random.scene"),
+            Ok(Command::Info {
+                scene: Some("scenes/random.scene".to_string())
+            })
+        );
+        match parse("compare a.pfm b.png --metric=psnr --threshold 30 --diff d.png").unwrap() {
+            Command::Compare(options) => {
+                assert_eq!(
+                    (options.reference.as_str(), options.test.as_str()),
+                    ("a.pfm", "b.png")
+                );
+                assert_eq!(options.metric, Metric::Psnr);
+                assert_eq!(options.threshold, Some(30.0));
+                assert_eq!(options.diff.as_deref(), Some("d.png"));
+                assert_eq!(options.ppd, DEFAULT_PPD);
+            }
+            other => panic!("{:?}", other),
+        }
+        assert!(parse("compare a.png").is_err());
+        assert!(parse("compare a.png b.png --metric l1").is_err());
+        assert_eq!(parse("compare --help"), Ok(Command::Help(COMPARE_USAGE)));
+    }
+}
@@ -0,0 +1,606 @@
+use crate::color::Color;
+use crate::film::Encoding;
+use crate::image::{luminance, Image, ImageError};
+use crate::vec3::Vec3;
+use std::f32::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+
+/// Pixels per degree of visual angle of a 0.7 m wide 4K display seen from 0.7 m, the
+/// viewing condition FLIP is usually evaluated at.
+pub const DEFAULT_PPD: f32 = 67.0;
+
+/// Errors between a reference image and a test image.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Metrics {
+    pub mse: f64,
+    /// Squared error relative to the squared reference, which weighs dark regions the same
+    /// as bright ones.
+    pub rel_mse: f64,
+    /// Peak signal to noise ratio in dB for a peak of 1; infinite for identical images.
+    pub psnr: f64,
+    /// Mean structural similarity of the luminance, 1 for identical images.
+    pub ssim: f64,
+    /// Mean perceived difference in `[0, 1]` as in FLIP (Andersson et al. 2020).
+    pub flip: f64,
+}
+
+impl Metrics {
+    pub fn to_json(&self) -> String {
+        // JSON has no infinity
+        let psnr = if self.psnr.is_finite() {
+            format!("{:.6}", self.psnr)
+        } else {
+            "null".to_string()
+        };
+        format!(
+            "{{\"mse\":{:e},\"rel_mse\":{:e},\"psnr\":{},\"ssim\":{:.6},\"flip\":{:.6}}}",
+            self.mse, self.rel_mse, psnr, self.ssim, self.flip
+        )
+    }
+}
+
+impl fmt::Display for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "mse      {:.6e}", self.mse)?;
+        writeln!(f, "rel_mse  {:.6e}", self.rel_mse)?;
+        writeln!(f, "psnr     {:.3} dB", self.psnr)?;
+        writeln!(f, "ssim     {:.6}", self.ssim)?;
+        write!(f, "flip     {:.6}", self.flip)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Metric {
+    Mse,
+    RelMse,
+    Psnr,
+    Ssim,
+    Flip,
+}
+
+impl Metric {
+    pub fn of(self, metrics: &Metrics) -> f64 {
+        match self {
+            Metric::Mse => metrics.mse,
+            Metric::RelMse => metrics.rel_mse,
+            Metric::Psnr => metrics.psnr,
+            Metric::Ssim => metrics.ssim,
+            Metric::Flip => metrics.flip,
+        }
+    }
+
+    /// Whether `metrics` are within `threshold`: at most it for errors, at least it for
+    /// PSNR and SSIM, which grow as images get closer.
+    pub fn passes(self, metrics: &Metrics, threshold: f64) -> bool {
+        match self {
+            Metric::Psnr | Metric::Ssim => self.of(metrics) >= threshold,
+            _ => self.of(metrics) <= threshold,
+        }
+    }
+}
+
+impl fmt::Display for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Metric::Mse => "mse",
+            Metric::RelMse => "relmse",
+            Metric::Psnr => "psnr",
+            Metric::Ssim => "ssim",
+            Metric::Flip => "flip",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Metric {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Metric, String> {
+        match s {
+            "mse" => Ok(Metric::Mse),
+            "relmse" | "rel-mse" => Ok(Metric::RelMse),
+            "psnr" => Ok(Metric::Psnr),
+            "ssim" => Ok(Metric::Ssim),
+            "flip" => Ok(Metric::Flip),
+            _ => Err(format!(
+                "unknown metric `{}` (expected mse, relmse, psnr, ssim or flip)",
+                s
+            )),
+        }
+    }
+}
+
+/// The metrics and the per-pixel FLIP error behind them.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Comparison {
+    pub metrics: Metrics,
+    pub width: usize,
+    pub height: usize,
+    pub errors: Vec<f32>,
+}
+
+impl Comparison {
+    /// The FLIP error map in a magma-like palette, dark where the images agree.
+    pub fn false_color(&self) -> Vec<Color> {
+        self.errors.iter().map(|&e| magma(e)).collect()
+    }
+}
+
+/// Loads two images to compare. When one holds linear radiance and the other display
+/// values, the linear one is encoded to sRGB so that both mean the same; the returned
+/// encoding is the one they then share.
+pub fn load_pair(reference: &str, test: &str) -> Result<(Image, Image, Encoding), ImageError> {
+    let (mut reference, reference_encoding) = Image::load_with_encoding(reference)?;
+    let (mut test, test_encoding) = Image::load_with_encoding(test)?;
+    match (reference_encoding, test_encoding) {
+        (Encoding::Linear, Encoding::Linear) => return Ok((reference, test, Encoding::Linear)),
+        (Encoding::Linear, _) => reference = to_display(&reference, Encoding::Linear),
+        (_, Encoding::Linear) => test = to_display(&test, Encoding::Linear),
+        _ => {}
+    }
+    Ok((reference, test, Encoding::Srgb))
+}
+
+/// Values in `[0, 1]` ready for a display: linear images are clamped and sRGB-encoded,
+/// others only clamped.
+pub fn to_display(image: &Image, encoding: Encoding) -> Image {
+    let encoding = match encoding {
+        Encoding::Linear => Encoding::Srgb,
+        _ => Encoding::Linear,
+    };
+    let pixels = image
+        .pixels
+        .iter()
+        .map(|p| {
+            Vec3::new(
+                encoding.encode(p.x),
+                encoding.encode(p.y),
+                encoding.encode(p.z),
+            )
+        })
+        .collect();
+    Image {
+        width: image.width,
+        height: image.height,
+        pixels,
+    }
+}
+
+/// Compares `test` against `reference`, both holding values stored with `encoding`. MSE,
+/// relMSE, PSNR and SSIM use the values as they are; FLIP looks at them as a display at
+/// `ppd` pixels per degree would show them.
+pub fn compare(
+    reference: &Image,
+    test: &Image,
+    encoding: Encoding,
+    ppd: f32,
+) -> Result<Comparison, String> {
+    if (reference.width, reference.height) != (test.width, test.height) {
+        return Err(format!(
+            "the images differ in size: {}x{} and {}x{}",
+            reference.width, reference.height, test.width, test.height
+        ));
+    }
+    if reference.pixels.is_empty() {
+        return Err("the images are empty".to_string());
+    }
+    let (mut squared, mut relative) = (0.0f64, 0.0f64);
+    for (r, t) in reference.pixels.iter().zip(&test.pixels) {
+        for (r, t) in [(r.x, t.x), (r.y, t.y), (r.z, t.z)] {
+            let d = (t - r) as f64 * (t - r) as f64;
+            squared += d;
+            relative += d / (r as f64 * r as f64 + 0.01);
+        }
+    }
+    let n = reference.pixels.len() as f64 * 3.0;
+    let mse = squared / n;
+    let errors = flip(
+        &to_display(reference, encoding),
+        &to_display(test, encoding),
+        ppd,
+    );
+    let flip = errors.iter().map(|&e| e as f64).sum::<f64>() / errors.len() as f64;
+    Ok(Comparison {
+        metrics: Metrics {
+            mse,
+            rel_mse: relative / n,
+            psnr: if mse > 0.0 {
+                -10.0 * mse.log10()
+            } else {
+                f64::INFINITY
+            },
+            ssim: ssim(reference, test),
+            flip,
+        },
+        width: reference.width,
+        height: reference.height,
+        errors,
+    })
+}
+
+/// Normalized Gaussian weights out to `radius` on either side.
+fn gaussian(sigma: f32, radius: usize) -> Vec<f32> {
+    let weights: Vec<f32> = (0..=2 * radius)
+        .map(|i| {
+            let x = i as f32 - radius as f32;
+            (-x * x / (2.0 * sigma * sigma)).exp()
+        })
+        .collect();
+    let sum: f32 = weights.iter().sum();
+    weights.into_iter().map(|w| w / sum).collect()
+}
+
+/// 2D convolution of a single channel with a square kernel, clamping at the borders.
+fn convolve(values: &[f32], width: usize, height: usize, kernel: &[f32]) -> Vec<f32> {
+    let size = (kernel.len() as f32).sqrt() as usize;
+    let radius = size / 2;
+    let mut out = vec![0.0; values.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let mut sum = 0.0;
+            for ky in 0..size {
+                let sy = (y + ky).saturating_sub(radius).min(height - 1);
+                for kx in 0..size {
+                    let sx = (x + kx).saturating_sub(radius).min(width - 1);
+                    sum += kernel[ky * size + kx] * values[sy * width + sx];
+                }
+            }
+            out[y * width + x] = sum;
+        }
+    }
+    out
+}
+
+/// A separable kernel as a square one.
+fn outer(a: &[f32], b: &[f32]) -> Vec<f32> {
+    a.iter()
+        .flat_map(|&y| b.iter().map(move |&x| x * y))
+        .collect()
+}
+
+/// Mean SSIM of the luminances with the usual 11x11 Gaussian window of σ 1.5.
+fn ssim(reference: &Image, test: &Image) -> f64 {
+    let (w, h) = (reference.width, reference.height);
+    let kernel = {
+        let g = gaussian(1.5, 5);
+        outer(&g, &g)
+    };
+    let x: Vec<f32> = reference.pixels.iter().map(luminance).collect();
+    let y: Vec<f32> = test.pixels.iter().map(luminance).collect();
+    let product =
+        |a: &[f32], b: &[f32]| -> Vec<f32> { a.iter().zip(b).map(|(a, b)| a * b).collect() };
+    let mean_x = convolve(&x, w, h, &kernel);
+    let mean_y = convolve(&y, w, h, &kernel);
+    let mean_xx = convolve(&product(&x, &x), w, h, &kernel);
+    let mean_yy = convolve(&product(&y, &y), w, h, &kernel);
+    let mean_xy = convolve(&product(&x, &y), w, h, &kernel);
+    let (c1, c2) = (0.01f64 * 0.01, 0.03f64 * 0.03);
+    let mut sum = 0.0;
+    for i in 0..x.len() {
+        let (mx, my) = (mean_x[i] as f64, mean_y[i] as f64);
+        let vx = mean_xx[i] as f64 - mx * mx;
+        let vy = mean_yy[i] as f64 - my * my;
+        let cov = mean_xy[i] as f64 - mx * my;
+        sum +=
+            (2.0 * mx * my + c1) * (2.0 * cov + c2) / ((mx * mx + my * my + c1) * (vx + vy + c2));
+    }
+    sum / x.len() as f64
+}
+
+fn srgb_to_linear(v: f32) -> f32 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn rgb_to_xyz(c: Vec3) -> Vec3 {
+    Vec3::new(
+        0.412_456_4 * c.x + 0.357_576_1 * c.y + 0.180_437_5 * c.z,
+        0.212_672_9 * c.x + 0.715_152_2 * c.y + 0.072_175 * c.z,
+        0.019_333_9 * c.x + 0.119_192 * c.y + 0.950_304_1 * c.z,
+    )
+}
+
+fn xyz_to_rgb(c: Vec3) -> Vec3 {
+    Vec3::new(
+        3.240_454 * c.x - 1.537_138_5 * c.y - 0.498_531_4 * c.z,
+        -0.969_266 * c.x + 1.876_010_8 * c.y + 0.041_556 * c.z,
+        0.055_643_4 * c.x - 0.204_025_9 * c.y + 1.057_225_2 * c.z,
+    )
+}
+
+/// The D65 white point as XYZ.
+fn white() -> Vec3 {
+    rgb_to_xyz(Vec3::new(1.0, 1.0, 1.0))
+}
+
+/// Linear RGB to the opponent space YCxCz, in which the contrast sensitivity filters apply.
+fn rgb_to_ycxcz(c: Vec3) -> Vec3 {
+    let (xyz, w) = (rgb_to_xyz(c), white());
+    let (x, y, z) = (xyz.x / w.x, xyz.y / w.y, xyz.z / w.z);
+    Vec3::new(116.0 * y - 16.0, 500.0 * (x - y), 200.0 * (y - z))
+}
+
+fn ycxcz_to_rgb(c: Vec3) -> Vec3 {
+    let w = white();
+    let y = (c.x + 16.0) / 116.0;
+    let x = y + c.y / 500.0;
+    let z = y - c.z / 200.0;
+    xyz_to_rgb(Vec3::new(x * w.x, y * w.y, z * w.z))
+}
+
+/// CIELAB with the chroma scaled by lightness after Hunt, so that dark colors differ less.
+fn hunt_lab(c: Vec3) -> Vec3 {
+    let f = |t: f32| {
+        let delta = 6.0f32 / 29.0;
+        if t > delta * delta * delta {
+            t.cbrt()
+        } else {
+            t / (3.0 * delta * delta) + 4.0 / 29.0
+        }
+    };
+    let (xyz, w) = (rgb_to_xyz(c), white());
+    let (fx, fy, fz) = (f(xyz.x / w.x), f(xyz.y / w.y), f(xyz.z / w.z));
+    let l = 116.0 * fy - 16.0;
+    Vec3::new(
+        l,
+        0.01 * l * 500.0 * (fx - fy),
+        0.01 * l * 200.0 * (fy - fz),
+    )
+}
+
+/// HyAB distance: lightness taken apart from chroma.
+fn hyab(a: Vec3, b: Vec3) -> f32 {
+    (a.x - b.x).abs() + ((a.y - b.y).powi(2) + (a.z - b.z).powi(2)).sqrt()
+}
+
+/// Contrast sensitivity of one YCxCz channel as a filter kernel: a sum of two Gaussians
+/// `a1 √(π/b1) exp(-π² x² / b1) + a2 √(π/b2) exp(-π² x² / b2)` with x in degrees.
+fn csf_kernel(a1: f32, b1: f32, a2: f32, b2: f32, ppd: f32) -> Vec<f32> {
+    let radius = (3.0 * (b1.max(b2) / (2.0 * PI * PI)).sqrt() * ppd).ceil() as i32;
+    let term = |a: f32, b: f32, r2: f32| a * (PI / b).sqrt() * (-PI * PI * r2 / b).exp();
+    let mut kernel = vec![];
+    for y in -radius..=radius {
+        for x in -radius..=radius {
+            let r2 = (x * x + y * y) as f32 / (ppd * ppd);
+            kernel.push(term(a1, b1, r2) + term(a2, b2, r2));
+        }
+    }
+    let sum: f32 = kernel.iter().sum();
+    kernel.into_iter().map(|k| k / sum).collect()
+}
+
+/// First and second derivative of a Gaussian along x, for edges and points, with weights
+/// normalized so that each sign sums to one.
+fn feature_kernels(ppd: f32) -> (Vec<f32>, Vec<f32>) {
+    let sigma = 0.5 * 0.082 * ppd;
+    let radius = (3.0 * sigma).ceil() as i32;
+    let (mut edge, mut point) = (vec![], vec![]);
+    for y in -radius..=radius {
+        for x in -radius..=radius {
+            let g = (-((x * x + y * y) as f32) / (2.0 * sigma * sigma)).exp();
+            edge.push(-(x as f32) * g);
+            point.push(((x * x) as f32 / (sigma * sigma) - 1.0) * g);
+        }
+    }
+    let normalize = |kernel: Vec<f32>| {
+        let positive: f32 = kernel.iter().filter(|&&k| k > 0.0).sum();
+        let negative: f32 = -kernel.iter().filter(|&&k| k < 0.0).sum::<f32>();
+        kernel
+            .into_iter()
+            .map(|k| if k > 0.0 { k / positive } else { k / negative })
+            .collect::<Vec<f32>>()
+    };
+    (normalize(edge), normalize(point))
+}
+
+fn transpose(kernel: &[f32]) -> Vec<f32> {
+    let size = (kernel.len() as f32).sqrt() as usize;
+    (0..kernel.len())
+        .map(|i| kernel[(i % size) * size + i / size])
+        .collect()
+}
+
+/// Edge and point strengths of the normalized luminance of display values.
+fn features(image: &Image, kernels: &(Vec<f32>, Vec<f32>)) -> (Vec<f32>, Vec<f32>) {
+    let (w, h) = (image.width, image.height);
+    let y: Vec<f32> = image
+        .pixels
+        .iter()
+        .map(|p| {
+            let linear = Vec3::new(
+                srgb_to_linear(p.x),
+                srgb_to_linear(p.y),
+                srgb_to_linear(p.z),
+            );
+            (rgb_to_ycxcz(linear).x + 16.0) / 116.0
+        })
+        .collect();
+    let magnitude = |kernel: &[f32]| {
+        let dx = convolve(&y, w, h, kernel);
+        let dy = convolve(&y, w, h, &transpose(kernel));
+        dx.iter()
+            .zip(&dy)
+            .map(|(x, y)| (x * x + y * y).sqrt())
+            .collect::<Vec<f32>>()
+    };
+    (magnitude(&kernels.0), magnitude(&kernels.1))
+}
+
+/// Display values after filtering by the contrast sensitivity of the eye, as Hunt-adjusted
+/// CIELAB.
+fn perceived(image: &Image, ppd: f32) -> Vec<Vec3> {
+    let (w, h) = (image.width, image.height);
+    let opponent: Vec<Vec3> = image
+        .pixels
+        .iter()
+        .map(|p| {
+            rgb_to_ycxcz(Vec3::new(
+                srgb_to_linear(p.x),
+                srgb_to_linear(p.y),
+                srgb_to_linear(p.z),
+            ))
+        })
+        .collect();
+    let channel = |i: usize, kernel: Vec<f32>| {
+        let values: Vec<f32> = opponent.iter().map(|c| [c.x, c.y, c.z][i]).collect();
+        convolve(&values, w, h, &kernel)
+    };
+    let a = channel(0, csf_kernel(1.0, 0.0047, 0.0, 1e-5, ppd));
+    let rg = channel(1, csf_kernel(1.0, 0.0053, 0.0, 1e-5, ppd));
+    let by = channel(2, csf_kernel(34.1, 0.04, 13.5, 0.025, ppd));
+    (0..a.len())
+        .map(|i| {
+            let rgb = ycxcz_to_rgb(Vec3::new(a[i], rg[i], by[i]));
+            hunt_lab(Vec3::new(
+                rgb.x.clamp(0.0, 1.0),
+                rgb.y.clamp(0.0, 1.0),
+                rgb.z.clamp(0.0, 1.0),
+            ))
+        })
+        .collect()
+}
+
+/// Per-pixel FLIP error of display values: a color difference after spatial filtering,
+/// raised to a power that shrinks as edges and points differ more.
+fn flip(reference: &Image, test: &Image, ppd: f32) -> Vec<f32> {
+    const QC: f32 = 0.7;
+    const PC: f32 = 0.4;
+    const PT: f32 = 0.95;
+    const QF: f32 = 0.5;
+    let max = hyab(
+        hunt_lab(Vec3::new(0.0, 1.0, 0.0)),
+        hunt_lab(Vec3::new(0.0, 0.0, 1.0)),
+    )
+    .powf(QC);
+    let (r, t) = (perceived(reference, ppd), perceived(test, ppd));
+    let kernels = feature_kernels(ppd);
+    let (edges_r, points_r) = features(reference, &kernels);
+    let (edges_t, points_t) = features(test, &kernels);
+    (0..r.len())
+        .map(|i| {
+            let e = hyab(r[i], t[i]).powf(QC);
+            // compress large color differences into the top of the range
+            let color = if e < PC * max {
+                PT / (PC * max) * e
+            } else {
+                PT + (e - PC * max) / (max - PC * max) * (1.0 - PT)
+            };
+            let feature = ((edges_r[i] - edges_t[i])
+                .abs()
+                .max((points_r[i] - points_t[i]).abs())
+                / 2f32.sqrt())
+            .powf(QF);
+            color.min(1.0).powf(1.0 - feature)
+        })
+        .collect()
+}
+
+/// Magma-like palette from black through purple and orange to pale yellow.
+fn magma(t: f32) -> Color {
+    const STOPS: [(f32, f32, f32); 9] = [
+        (0.001, 0.000, 0.014),
+        (0.079, 0.054, 0.212),
+        (0.232, 0.060, 0.438),
+        (0.390, 0.100, 0.502),
+        (0.550, 0.161, 0.506),
+        (0.716, 0.215, 0.475),
+        (0.869, 0.288, 0.409),
+        (0.967, 0.440, 0.360),
+        (0.987, 0.991, 0.750),
+    ];
+    let position = t.clamp(0.0, 1.0) * (STOPS.len() - 1) as f32;
+    let i = (position as usize).min(STOPS.len() - 2);
+    let f = position - i as f32;
+    let (a, b) = (STOPS[i], STOPS[i + 1]);
+    let mix = |a: f32, b: f32| ((a + (b - a) * f) * 255.0).round() as usize;
+    Color::new(mix(a.0, b.0), mix(a.1, b.1), mix(a.2, b.2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(width: usize, height: usize, offset: f32) -> Image {
+        let mut image = Image::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let v = ((x + 2 * y) % 5) as f32 / 5.0;
+                image.set(
+                    x,
+                    y,
+                    Vec3::new(v, 0.5 * v, 1.0 - v) + Vec3::new(offset, offset, 0.0),
+                );
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn identical_images_agree() {
+        let image = pattern(16, 12, 0.0);
+        let c = compare(&image, &image, Encoding::Srgb, DEFAULT_PPD).unwrap();
+        assert_eq!(c.metrics.mse, 0.0);
+        assert_eq!(c.metrics.psnr, f64::INFINITY);
+        assert!((c.metrics.ssim - 1.0).abs() < 1e-6);
+        assert!(c.metrics.flip < 1e-6);
+        assert!(c.metrics.to_json().contains("\"psnr\":null"));
+        assert_eq!(c.false_color()[0], magma(0.0));
+    }
+
+    #[test]
+    fn errors_grow_with_the_difference() {
+        let reference = pattern(16, 12, 0.0);
+        let near = compare(
+            &reference,
+            &pattern(16, 12, 0.02),
+            Encoding::Srgb,
+            DEFAULT_PPD,
+        );
+        let far = compare(
+            &reference,
+            &pattern(16, 12, 0.2),
+            Encoding::Srgb,
+            DEFAULT_PPD,
+        );
+        let (near, far) = (near.unwrap().metrics, far.unwrap().metrics);
+        // two of three channels moved by the offset
+        assert!((near.mse - 0.02 * 0.02 * 2.0 / 3.0).abs() < 1e-6);
+        assert!((near.psnr - 10.0 * (1.0 / near.mse).log10()).abs() < 1e-9);
+        assert!(far.mse > near.mse && far.rel_mse > near.rel_mse);
+        assert!(far.psnr < near.psnr && far.ssim < near.ssim);
+        assert!(0.0 < near.flip && near.flip < far.flip && far.flip <= 1.0);
+
+        assert!(Metric::Flip.passes(&near, 0.1));
+        assert!(!Metric::Flip.passes(&far, near.flip));
+        assert!(Metric::Psnr.passes(&near, 30.0));
+        assert!(!Metric::Ssim.passes(&far, near.ssim));
+        assert_eq!("relmse".parse(), Ok(Metric::RelMse));
+        assert!("l1".parse::<Metric>().is_err());
+    }
+
+    #[test]
+    fn flip_sees_black_and_white_as_far_apart() {
+        let mut black = Image::new(8, 8);
+        let mut white = Image::new(8, 8);
+        for i in 0..64 {
+            black.pixels[i] = Vec3::new(0.0, 0.0, 0.0);
+            white.pixels[i] = Vec3::new(1.0, 1.0, 1.0);
+        }
+        let c = compare(&black, &white, Encoding::Srgb, DEFAULT_PPD).unwrap();
+        assert!(c.metrics.flip > 0.9, "{}", c.metrics.flip);
+        assert!(compare(&black, &Image::new(8, 4), Encoding::Srgb, DEFAULT_PPD).is_err());
+    }
+
+    #[test]
+    fn displays_linear_values() {
+        let mut image = Image::new(1, 1);
+        image.set(0, 0, Vec3::new(0.0, 0.214, 4.0));
+        let display = to_display(&image, Encoding::Linear).get(0, 0);
+        assert!((display.y - 0.5).abs() < 1e-3);
+        assert!((display.z - 1.0).abs() < 1e-6);
+        assert_eq!(to_display(&image, Encoding::Srgb).get(0, 0).z, 1.0);
+    }
+}
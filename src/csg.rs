@@ -0,0 +1,346 @@
+use crate::aabb::Aabb;
+use crate::hitable::{HitRecord, Hitable, Interval};
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum CsgOp {
+    Union,
+    Intersection,
+    /// `left` with `right` carved out of it.
+    Difference,
+}
+
+impl CsgOp {
+    fn inside(self, in_left: bool, in_right: bool) -> bool {
+        match self {
+            CsgOp::Union => in_left || in_right,
+            CsgOp::Intersection => in_left && in_right,
+            CsgOp::Difference => in_left && !in_right,
+        }
+    }
+}
+
+/// Boolean combination of two solids.
+///
+/// Surfaces carved out by `Difference` keep the material of the subtracted solid and get their
+/// normal flipped, so it still points out of the result and `Dielectric` sees the correct side.
+pub struct Csg {
+    pub op: CsgOp,
+    pub left: Box<dyn Hitable>,
+    pub right: Box<dyn Hitable>,
+}
+
+impl Csg {
+    pub fn union(left: Box<dyn Hitable>, right: Box<dyn Hitable>) -> Csg {
+        Csg {
+            op: CsgOp::Union,
+            left,
+            right,
+        }
+    }
+
+    pub fn intersection(left: Box<dyn Hitable>, right: Box<dyn Hitable>) -> Csg {
+        Csg {
+            op: CsgOp::Intersection,
+            left,
+            right,
+        }
+    }
+
+    pub fn difference(left: Box<dyn Hitable>, right: Box<dyn Hitable>) -> Csg {
+        Csg {
+            op: CsgOp::Difference,
+            left,
+            right,
+        }
+    }
+}
+
+impl Hitable for Csg {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        if let Some(b) = self.bounding_box() {
+            if !b.hit(r, t_min, t_max) {
+                return None;
+            }
+        }
+        self.intervals(r)
+            .into_iter()
+            .flat_map(|i| vec![i.enter, i.exit])
+            .find(|rec| t_min < rec.t && rec.t < t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let (left, right) = (self.left.bounding_box(), self.right.bounding_box());
+        match self.op {
+            CsgOp::Union => Some(left?.surrounding(&right?)),
+            CsgOp::Intersection => match (left, right) {
+                (Some(a), Some(b)) => Some(overlap(&a, &b)),
+                (a, b) => a.or(b),
+            },
+            CsgOp::Difference => left,
+        }
+    }
+
+    fn intervals(&self, r: &Ray) -> Vec<Interval> {
+        let left = self.left.intervals(r);
+        // nothing of `right` can show without `left` unless they are joined
+        if left.is_empty() && self.op != CsgOp::Union {
+            return left;
+        }
+        combine(left, self.right.intervals(r), self.op, r)
+    }
+}
+
+fn overlap(a: &Aabb, b: &Aabb) -> Aabb {
+    let min = Vec3::new(
+        a.min.x.max(b.min.x),
+        a.min.y.max(b.min.y),
+        a.min.z.max(b.min.z),
+    );
+    let max = Vec3::new(
+        a.max.x.min(b.max.x),
+        a.max.y.min(b.max.y),
+        a.max.z.min(b.max.z),
+    );
+    // disjoint boxes collapse to a point instead of turning inside out
+    Aabb::new(
+        min,
+        Vec3::new(max.x.max(min.x), max.y.max(min.y), max.z.max(min.z)),
+    )
+}
+
+/// Points `rec.normal` against the ray when entering and along it when leaving.
+fn oriented(mut rec: HitRecord, r: &Ray, entering: bool) -> HitRecord {
+    let along = rec.normal.dot(r.direction()) > 0.0;
+    if along == entering {
+        rec.normal = -rec.normal;
+    }
+    rec
+}
+
+/// Merges two disjoint, ascending interval lists with a boolean operation.
+pub fn combine(left: Vec<Interval>, right: Vec<Interval>, op: CsgOp, r: &Ray) -> Vec<Interval> {
+    struct Event {
+        rec: HitRecord,
+        from_left: bool,
+        entering: bool,
+    }
+    let mut events = vec![];
+    for (intervals, from_left) in [(left, true), (right, false)] {
+        for i in intervals {
+            events.push(Event {
+                rec: i.enter,
+                from_left,
+                entering: true,
+            });
+            events.push(Event {
+                rec: i.exit,
+                from_left,
+                entering: false,
+            });
+        }
+    }
+    // on ties handle exits first so touching solids do not produce empty intervals
+    events.sort_by(|a, b| {
+        a.rec
+            .t
+            .partial_cmp(&b.rec.t)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(a.entering.cmp(&b.entering))
+    });
+
+    let (mut in_left, mut in_right) = (false, false);
+    let mut open: Option<HitRecord> = None;
+    let mut result = vec![];
+    for event in events {
+        let was_inside = op.inside(in_left, in_right);
+        if event.from_left {
+            in_left = event.entering;
+        } else {
+            in_right = event.entering;
+        }
+        match (was_inside, op.inside(in_left, in_right)) {
+            (false, true) => open = Some(oriented(event.rec, r, true)),
+            (true, false) => {
+                if let Some(enter) = open.take() {
+                    result.push(Interval {
+                        enter,
+                        exit: oriented(event.rec, r, false),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hitable::{HitableList, Sphere};
+    use crate::material::{Lambertian, Material, Metal};
+    use crate::shapes::{AxisAlignedBox, Cylinder, Plane};
+    use std::rc::Rc;
+
+    fn sphere(x: f32, radius: f32, material: &Rc<Box<dyn Material>>) -> Box<dyn Hitable> {
+        Box::new(Sphere {
+            center: Vec3::new(x, 0.0, 0.0),
+            radius,
+            material: material.clone(),
+        })
+    }
+
+    fn matte() -> Rc<Box<dyn Material>> {
+        Rc::new(Box::new(Lambertian::new((0.5, 0.5, 0.5).into())))
+    }
+
+    fn shiny() -> Rc<Box<dyn Material>> {
+        Rc::new(Box::new(Metal::new((0.5, 0.5, 0.5).into(), 0.0)))
+    }
+
+    fn along_x() -> Ray {
+        Ray::new((-10, 0, 0).into(), (1, 0, 0).into())
+    }
+
+    fn ts(intervals: &[Interval]) -> Vec<(f32, f32)> {
+        intervals.iter().map(|i| (i.enter.t, i.exit.t)).collect()
+    }
+
+    fn assert_ts(actual: Vec<(f32, f32)>, expected: &[(f32, f32)]) {
+        assert_eq!(actual.len(), expected.len(), "{:?}", actual);
+        for (a, e) in actual.iter().zip(expected) {
+            assert!(
+                (a.0 - e.0).abs() < 1e-3 && (a.1 - e.1).abs() < 1e-3,
+                "{:?}",
+                actual
+            );
+        }
+    }
+
+    #[test]
+    fn sphere_intervals() {
+        let s = sphere(0.0, 1.0, &matte());
+        assert_ts(ts(&s.intervals(&along_x())), &[(9.0, 11.0)]);
+        let miss = Ray::new((-10, 2, 0).into(), (1, 0, 0).into());
+        assert!(s.intervals(&miss).is_empty());
+    }
+
+    #[test]
+    fn union_merges_overlaps() {
+        let m = matte();
+        let u = Csg::union(sphere(-0.5, 1.0, &m), sphere(0.5, 1.0, &m));
+        assert_ts(ts(&u.intervals(&along_x())), &[(8.5, 11.5)]);
+        let rec = u.hit(&along_x(), 0.0001, f32::MAX).unwrap();
+        assert!((rec.t - 8.5).abs() < 1e-3);
+        // starting inside the overlap the internal surfaces are gone
+        let inside = Ray::new((0, 0, 0).into(), (1, 0, 0).into());
+        assert!((u.hit(&inside, 0.0001, f32::MAX).unwrap().t - 1.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn intersection_is_lens() {
+        let m = matte();
+        let i = Csg::intersection(sphere(-0.5, 1.0, &m), sphere(0.5, 1.0, &m));
+        assert_ts(ts(&i.intervals(&along_x())), &[(9.5, 10.5)]);
+        let miss = Ray::new((-10, 0, 0).into(), (0, 1, 0).into());
+        assert!(i.hit(&miss, 0.0001, f32::MAX).is_none());
+        let b = i.bounding_box().unwrap();
+        assert!((b.min.x + 0.5).abs() < 1e-6 && (b.max.x - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn difference_flips_carved_surface() {
+        let (a, b) = (matte(), shiny());
+        let d = Csg::difference(sphere(0.0, 2.0, &a), sphere(0.0, 1.0, &b));
+        assert_ts(ts(&d.intervals(&along_x())), &[(8.0, 9.0), (11.0, 12.0)]);
+
+        // from inside the hollow, the first surface is the carved one
+        let inside = Ray::new((0, 0, 0).into(), (1, 0, 0).into());
+        let rec = d.hit(&inside, 0.0001, f32::MAX).unwrap();
+        assert!((rec.t - 1.0).abs() < 1e-3);
+        assert!((rec.normal - Vec3::from_i(-1, 0, 0)).length() < 1e-3);
+        assert!(Rc::ptr_eq(&rec.material, &b));
+
+        let outer = d.hit(&along_x(), 0.0001, f32::MAX).unwrap();
+        assert!((outer.normal - Vec3::from_i(-1, 0, 0)).length() < 1e-3);
+        assert!(Rc::ptr_eq(&outer.material, &a));
+        // the exit into the hollow faces along the ray
+        let exit = d.hit(&along_x(), 8.5, f32::MAX).unwrap();
+        assert!((exit.t - 9.0).abs() < 1e-3);
+        assert!((exit.normal - Vec3::from_i(1, 0, 0)).length() < 1e-3);
+    }
+
+    #[test]
+    fn box_minus_cylinder() {
+        let m = matte();
+        let d = Csg::difference(
+            Box::new(AxisAlignedBox::new(
+                (-1, 0, -1).into(),
+                (1, 1, 1).into(),
+                m.clone(),
+            )),
+            Box::new(Cylinder {
+                center: (0, -1, 0).into(),
+                radius: 0.5,
+                height: 3.0,
+                material: m,
+            }),
+        );
+        // straight down the bore
+        let down = Ray::new((0, 5, 0).into(), (0, -1, 0).into());
+        assert!(d.hit(&down, 0.0001, f32::MAX).is_none());
+        let across = Ray::new((-5.0, 0.5, 0.0).into(), (1, 0, 0).into());
+        assert_ts(ts(&d.intervals(&across)), &[(4.0, 4.5), (5.5, 6.0)]);
+        let bore = d.hit(&across, 4.25, f32::MAX).unwrap();
+        assert!((bore.normal - Vec3::from_i(1, 0, 0)).length() < 1e-3);
+    }
+
+    #[test]
+    fn plane_as_half_space() {
+        let m = matte();
+        let half = Csg::intersection(
+            sphere(0.0, 1.0, &m),
+            Box::new(Plane::new((0, 0, 0).into(), (1, 0, 0).into(), m.clone())),
+        );
+        assert_ts(ts(&half.intervals(&along_x())), &[(9.0, 10.0)]);
+        let flat = half.hit(
+            &Ray::new((5, 0, 0).into(), (-1, 0, 0).into()),
+            0.0001,
+            f32::MAX,
+        );
+        assert!((flat.unwrap().normal - Vec3::from_i(1, 0, 0)).length() < 1e-3);
+    }
+
+    #[test]
+    fn thin_walls_are_not_stepped_over() {
+        // the wall is thinner than the step the default `intervals` takes 10 units away
+        let m = matte();
+        let wall = || -> Box<dyn Hitable> {
+            Box::new(AxisAlignedBox::new(
+                (0.0, -1.0, -1.0).into(),
+                (0.0001, 1.0, 1.0).into(),
+                m.clone(),
+            ))
+        };
+        assert_ts(ts(&wall().intervals(&along_x())), &[(10.0, 10.0001)]);
+
+        // a hole through the middle of the wall
+        let carved = Csg::difference(wall(), sphere(0.0, 0.5, &m));
+        assert!(carved.hit(&along_x(), 0.0001, f32::MAX).is_none());
+        let off = Ray::new((-10.0, 0.7, 0.0).into(), (1, 0, 0).into());
+        let rec = carved.hit(&off, 0.0001, f32::MAX).unwrap();
+        assert!((rec.t - 10.0).abs() < 1e-4);
+        let exit = carved.hit(&off, rec.t + 1e-5, f32::MAX).unwrap();
+        assert!(exit.t < 10.001, "{}", exit.t);
+        assert!((exit.normal - Vec3::from_i(1, 0, 0)).length() < 1e-3);
+    }
+
+    #[test]
+    fn list_is_union() {
+        let m = matte();
+        let list = HitableList::new(vec![sphere(-0.5, 1.0, &m), sphere(0.5, 1.0, &m)]);
+        assert_ts(ts(&list.intervals(&along_x())), &[(8.5, 11.5)]);
+    }
+}
@@ -0,0 +1,391 @@
+use crate::color::Color;
+use crate::image::{luminance, Image};
+use crate::vec3::Vec3;
+use std::str::FromStr;
+
+/// How much light reaches the film. Scene radiance is unitless; an exposure value of 0
+/// (ISO 100, 1 s at f/1) passes it through unchanged and every stop above halves it.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Exposure {
+    /// Exposure value at ISO 100.
+    Ev(f32),
+    Camera {
+        iso: f32,
+        /// Shutter time in seconds.
+        shutter: f32,
+        f_number: f32,
+    },
+}
+
+impl Exposure {
+    pub fn ev100(&self) -> f32 {
+        match *self {
+            Exposure::Ev(ev) => ev,
+            Exposure::Camera {
+                iso,
+                shutter,
+                f_number,
+            } => (f_number * f_number / shutter * 100.0 / iso).log2(),
+        }
+    }
+
+    pub fn scale(&self) -> f32 {
+        (-self.ev100()).exp2()
+    }
+}
+
+/// Curve compressing scene-referred values into `[0, 1]`.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ToneMap {
+    /// Clips everything above 1.
+    Clamp,
+    /// `x / (1 + x)` per channel.
+    Reinhard,
+    /// John Hable's filmic curve from Uncharted 2, white point at 11.2.
+    Hable,
+    /// Krzysztof Narkowicz's fit of the ACES reference rendering and sRGB output transforms.
+    Aces,
+}
+
+impl ToneMap {
+    pub fn apply(&self, x: f32) -> f32 {
+        let x = x.max(0.0);
+        match self {
+            ToneMap::Clamp => x.min(1.0),
+            ToneMap::Reinhard => x / (1.0 + x),
+            ToneMap::Hable => {
+                fn curve(x: f32) -> f32 {
+                    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+                    (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f
+                }
+                // the usual exposure bias of 2 puts mid grey where the curve expects it
+                (curve(2.0 * x) / curve(11.2)).clamp(0.0, 1.0)
+            }
+            ToneMap::Aces => {
+                let x = 0.6 * x;
+                (x * (2.51 * x + 0.03) / (x * (2.43 * x + 0.59) + 0.14)).min(1.0)
+            }
+        }
+    }
+}
+
+impl FromStr for ToneMap {
+    type Err = String;
+    fn from_str(s: &str) -> Result<ToneMap, String> {
+        match s {
+            "clamp" => Ok(ToneMap::Clamp),
+            "reinhard" => Ok(ToneMap::Reinhard),
+            "hable" | "filmic" => Ok(ToneMap::Hable),
+            "aces" => Ok(ToneMap::Aces),
+            _ => Err(format!(
+                "unknown tone map `{}` (expected clamp, reinhard, hable or aces)",
+                s
+            )),
+        }
+    }
+}
+
+/// Opto-electronic transfer function applied before quantizing.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Encoding {
+    Linear,
+    Srgb,
+    Rec709,
+    /// Plain power law, `x^(1/gamma)`.
+    Gamma(f32),
+}
+
+impl Encoding {
+    pub fn encode(&self, x: f32) -> f32 {
+        let x = x.clamp(0.0, 1.0);
+        match *self {
+            Encoding::Linear => x,
+            Encoding::Srgb => {
+                if x <= 0.003_130_8 {
+                    12.92 * x
+                } else {
+                    1.055 * x.powf(1.0 / 2.4) - 0.055
+                }
+            }
+            Encoding::Rec709 => {
+                if x < 0.018 {
+                    4.5 * x
+                } else {
+                    1.099 * x.powf(0.45) - 0.099
+                }
+            }
+            Encoding::Gamma(gamma) => x.powf(1.0 / gamma),
+        }
+    }
+}
+
+/// Chromaticity of a black body at `kelvin`, after Kim et al.'s fit of the Planckian locus
+/// (valid from 1667 K to 25000 K).
+pub fn planckian_xy(kelvin: f32) -> (f32, f32) {
+    let t = kelvin.clamp(1667.0, 25000.0) as f64;
+    let x = if t <= 4000.0 {
+        -0.266_123_9e9 / (t * t * t) - 0.234_358_9e6 / (t * t) + 0.877_695_6e3 / t + 0.179_910
+    } else {
+        -3.025_846_9e9 / (t * t * t) + 2.107_037_9e6 / (t * t) + 0.222_634_7e3 / t + 0.240_390
+    };
+    let y = if t <= 2222.0 {
+        -1.106_381_4 * x * x * x - 1.348_110_2 * x * x + 2.185_558_32 * x - 0.202_196_83
+    } else if t <= 4000.0 {
+        -0.954_947_6 * x * x * x - 1.374_185_93 * x * x + 2.091_370_15 * x - 0.167_488_67
+    } else {
+        3.081_758 * x * x * x - 5.873_386_70 * x * x + 3.751_129_97 * x - 0.370_014_83
+    };
+    (x as f32, y as f32)
+}
+
+type Matrix = [[f32; 3]; 3];
+
+fn mul(m: &Matrix, v: &Vec3) -> Vec3 {
+    Vec3::new(
+        m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+        m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+        m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+    )
+}
+
+const SRGB_TO_XYZ: Matrix = [
+    [0.412_456_4, 0.357_576_1, 0.180_437_5],
+    [0.212_672_9, 0.715_152_2, 0.072_175],
+    [0.019_333_9, 0.119_192, 0.950_304_1],
+];
+const XYZ_TO_SRGB: Matrix = [
+    [3.240_454_2, -1.537_138_5, -0.498_531_4],
+    [-0.969_266, 1.876_010_8, 0.041_556],
+    [0.055_643_4, -0.204_025_9, 1.057_225_2],
+];
+const BRADFORD: Matrix = [
+    [0.895_1, 0.266_4, -0.161_4],
+    [-0.750_2, 1.713_5, 0.036_7],
+    [0.038_9, -0.068_5, 1.029_6],
+];
+const BRADFORD_INVERSE: Matrix = [
+    [0.986_993, -0.147_054, 0.159_963],
+    [0.432_305, 0.518_36, 0.049_291],
+    [-0.008_529, 0.040_043, 0.968_487],
+];
+
+fn xy_to_xyz((x, y): (f32, f32)) -> Vec3 {
+    Vec3::new(x / y, 1.0, (1.0 - x - y) / y)
+}
+
+/// Linear sRGB color of a light at `kelvin`, scaled to unit luminance.
+pub fn blackbody_rgb(kelvin: f32) -> Vec3 {
+    mul(&XYZ_TO_SRGB, &xy_to_xyz(planckian_xy(kelvin)))
+}
+
+/// Neutralizes a light source of the given color temperature by Bradford chromatic
+/// adaptation to the D65 white of sRGB.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct WhiteBalance {
+    /// Per-channel gains in the Bradford cone space.
+    gains: Vec3,
+}
+
+impl WhiteBalance {
+    pub fn new(kelvin: f32) -> WhiteBalance {
+        let source = mul(&BRADFORD, &xy_to_xyz(planckian_xy(kelvin)));
+        let target = mul(&BRADFORD, &xy_to_xyz((0.3127, 0.3290)));
+        WhiteBalance {
+            gains: Vec3::new(
+                target.x / source.x,
+                target.y / source.y,
+                target.z / source.z,
+            ),
+        }
+    }
+
+    pub fn apply(&self, rgb: &Vec3) -> Vec3 {
+        let cone = mul(&BRADFORD, &mul(&SRGB_TO_XYZ, rgb));
+        mul(&XYZ_TO_SRGB, &mul(&BRADFORD_INVERSE, &(cone * self.gains)))
+    }
+}
+
+/// Turns the linear float framebuffer into display values: exposure, white balance,
+/// tone mapping and encoding, in that order.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Film {
+    pub exposure: Exposure,
+    pub white_balance: Option<WhiteBalance>,
+    pub tone_map: ToneMap,
+    /// Apply the tone curve to luminance and scale the color by the ratio, which keeps hues
+    /// from skewing as channels saturate.
+    pub preserve_hue: bool,
+    pub encoding: Encoding,
+}
+
+impl Default for Film {
+    fn default() -> Film {
+        Film {
+            exposure: Exposure::Ev(0.0),
+            white_balance: None,
+            tone_map: ToneMap::Clamp,
+            preserve_hue: false,
+            encoding: Encoding::Srgb,
+        }
+    }
+}
+
+impl Film {
+    /// Display-referred value in `[0, 1]` before encoding.
+    pub fn tone(&self, radiance: &Vec3) -> Vec3 {
+        let mut c = *radiance * self.exposure.scale();
+        if let Some(wb) = &self.white_balance {
+            c = wb.apply(&c);
+        }
+        if self.preserve_hue {
+            let l = luminance(&c);
+            if l <= 0.0 {
+                return Vec3::new(0.0, 0.0, 0.0);
+            }
+            let mapped = c * (self.tone_map.apply(l) / l);
+            Vec3::new(mapped.x.min(1.0), mapped.y.min(1.0), mapped.z.min(1.0))
+        } else {
+            Vec3::new(
+                self.tone_map.apply(c.x),
+                self.tone_map.apply(c.y),
+                self.tone_map.apply(c.z),
+            )
+        }
+    }
+
+    pub fn develop_pixel(&self, radiance: &Vec3) -> Color {
+        let c = self.tone(radiance);
+        let encode = |x: f32| (self.encoding.encode(x) * 255.0).round() as usize;
+        Color::new(encode(c.x), encode(c.y), encode(c.z))
+    }
+
+    /// 8-bit pixels of `image` in its row order.
+    pub fn develop(&self, image: &Image) -> Vec<Color> {
+        image.pixels.iter().map(|p| self.develop_pixel(p)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32, eps: f32) -> bool {
+        (a - b).abs() < eps
+    }
+
+    #[test]
+    fn exposure_in_stops() {
+        assert_eq!(Exposure::Ev(0.0).scale(), 1.0);
+        assert_eq!(Exposure::Ev(1.0).scale(), 0.5);
+        assert_eq!(Exposure::Ev(-2.0).scale(), 4.0);
+        let camera = Exposure::Camera {
+            iso: 100.0,
+            shutter: 1.0,
+            f_number: 1.0,
+        };
+        assert!(close(camera.ev100(), 0.0, 1e-6));
+        // sunny 16: ISO 100, 1/100 s at f/16 is about EV 15
+        let sunny = Exposure::Camera {
+            iso: 100.0,
+            shutter: 0.01,
+            f_number: 16.0,
+        };
+        assert!(close(sunny.ev100(), 14.64, 0.01));
+        // doubling the ISO gains a stop
+        let faster = Exposure::Camera {
+            iso: 200.0,
+            shutter: 0.01,
+            f_number: 16.0,
+        };
+        assert!(close(faster.scale(), 2.0 * sunny.scale(), 1e-9));
+    }
+
+    #[test]
+    fn tone_maps_are_monotonic_and_bounded() {
+        for map in [
+            ToneMap::Clamp,
+            ToneMap::Reinhard,
+            ToneMap::Hable,
+            ToneMap::Aces,
+        ] {
+            assert!(close(map.apply(0.0), 0.0, 1e-6));
+            assert_eq!(map.apply(-1.0), map.apply(0.0));
+            let mut last = 0.0;
+            for i in 1..200 {
+                let y = map.apply(i as f32 * 0.1);
+                assert!(y >= last && y <= 1.0, "{:?}", map);
+                last = y;
+            }
+        }
+        assert_eq!(ToneMap::Reinhard.apply(1.0), 0.5);
+        assert!(close(ToneMap::Hable.apply(5.6), 1.0, 1e-6));
+        assert!(close(ToneMap::Aces.apply(100.0), 1.0, 0.01));
+        assert_eq!("filmic".parse::<ToneMap>(), Ok(ToneMap::Hable));
+        assert!("drago".parse::<ToneMap>().is_err());
+    }
+
+    #[test]
+    fn encodings_match_their_standards() {
+        assert_eq!(Encoding::Srgb.encode(0.0), 0.0);
+        assert!(close(Encoding::Srgb.encode(1.0), 1.0, 1e-6));
+        assert!(close(Encoding::Srgb.encode(0.002), 0.02584, 1e-5));
+        assert!(close(Encoding::Srgb.encode(0.18), 0.4614, 1e-4));
+        assert!(close(Encoding::Rec709.encode(0.01), 0.045, 1e-6));
+        assert!(close(Encoding::Rec709.encode(0.18), 0.4090, 1e-4));
+        assert!(close(Encoding::Gamma(2.0).encode(0.25), 0.5, 1e-6));
+        assert_eq!(Encoding::Linear.encode(2.0), 1.0);
+    }
+
+    #[test]
+    fn white_balance_neutralizes_the_light() {
+        for &kelvin in &[2000.0, 3200.0, 5000.0, 9000.0] {
+            let light = blackbody_rgb(kelvin);
+            let balanced = WhiteBalance::new(kelvin).apply(&light);
+            assert!(
+                close(balanced.x, balanced.y, 1e-3),
+                "{} {:?}",
+                kelvin,
+                balanced
+            );
+            assert!(
+                close(balanced.z, balanced.y, 1e-3),
+                "{} {:?}",
+                kelvin,
+                balanced
+            );
+        }
+        // tungsten light is orange; balancing for it makes neutral surfaces bluer
+        assert!(blackbody_rgb(3200.0).x > blackbody_rgb(3200.0).z);
+        let grey = WhiteBalance::new(3200.0).apply(&Vec3::new(0.5, 0.5, 0.5));
+        assert!(grey.z > grey.x);
+    }
+
+    #[test]
+    fn develops_to_eight_bits() {
+        let mut image = Image::new(2, 1);
+        image.set(0, 0, Vec3::new(0.5, 0.0, 4.0));
+        image.set(1, 0, Vec3::new(1.0, 1.0, 1.0));
+        let film = Film::default();
+        let pixels = film.develop(&image);
+        assert_eq!(pixels[0], Color::new(188, 0, 255));
+        assert_eq!(pixels[1], Color::new(255, 255, 255));
+
+        let film = Film {
+            exposure: Exposure::Ev(1.0),
+            tone_map: ToneMap::Reinhard,
+            encoding: Encoding::Linear,
+            ..Film::default()
+        };
+        // half the light, then x / (1 + x)
+        assert_eq!(film.develop_pixel(&Vec3::new(2.0, 0.0, 0.0)).r, 128);
+
+        let film = Film {
+            tone_map: ToneMap::Reinhard,
+            preserve_hue: true,
+            ..Film::default()
+        };
+        let toned = film.tone(&Vec3::new(1.0, 0.5, 0.25));
+        assert!(close(toned.x / toned.y, 2.0, 1e-4));
+        assert!(close(toned.y / toned.z, 2.0, 1e-4));
+    }
+}
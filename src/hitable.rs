@@ -1,12 +1,19 @@
+use crate::aabb::Aabb;
+use crate::csg::{combine, CsgOp};
 use crate::material::{Lambertian, Material};
 use crate::ray::Ray;
-use crate::vec3::Vec3;
+use crate::stats::{count, Counter};
+use crate::vec3::{random_unit_vector, Vec3};
 use std::rc::Rc;
 
+#[derive(Clone)]
 pub struct HitRecord {
-    t: f32,
+    pub t: f32,
     pub p: Vec3,
+    /// Unit-length, pointing out of the surface regardless of the side the ray came from.
     pub normal: Vec3,
+    pub u: f32,
+    pub v: f32,
     pub material: Rc<Box<dyn Material>>,
 }
 
@@ -16,13 +23,110 @@ impl HitRecord {
             t: 0.0,
             p: (0, 0, 0).into(),
             normal: (0, 0, 0).into(),
+            u: 0.0,
+            v: 0.0,
             material: Rc::new(Box::new(Lambertian::new((0, 0, 0).into()))),
         }
     }
+
+    /// Placeholder boundary at `t = ±inf` for intervals that never end.
+    pub fn unbounded(t: f32) -> HitRecord {
+        let mut rec = HitRecord::null();
+        rec.t = t;
+        rec
+    }
+}
+
+/// Stretch of a ray spent inside a solid.
+#[derive(Clone)]
+pub struct Interval {
+    pub enter: HitRecord,
+    pub exit: HitRecord,
 }
 
 pub trait Hitable {
-    fn hit(self: &Self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord>;
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord>;
+    /// `None` for unbounded shapes such as infinite planes.
+    fn bounding_box(&self) -> Option<Aabb>;
+
+    /// Every inside interval along the whole line of the ray, ascending and disjoint.
+    /// The default walks `hit` from `-inf` and pairs crossings by the side the normal faces,
+    /// so it is only meaningful for closed shapes, with a `Plane` acting as a half-space. Its
+    /// steps grow with the distance and can skip surfaces closer together than that, which is
+    /// why the shapes CSG is mostly built from solve for their intervals instead.
+    fn intervals(&self, r: &Ray) -> Vec<Interval> {
+        let mut crossings = vec![];
+        let mut t_min = f32::MIN;
+        while let Some(rec) = self.hit(r, t_min, f32::MAX) {
+            t_min = rec.t + 0.0001 * rec.t.abs().max(1.0);
+            crossings.push(rec);
+            if crossings.len() >= MAX_CROSSINGS {
+                break;
+            }
+        }
+        pair_crossings(crossings, r)
+    }
+
+    /// Surface area of shapes that can be sampled as lights, `None` for the rest.
+    fn area(&self) -> Option<f32> {
+        None
+    }
+
+    /// A point spread uniformly over the surface and the outward normal there, for the
+    /// shapes with an `area`.
+    fn sample_surface(&self) -> Option<(Vec3, Vec3)> {
+        None
+    }
+}
+
+/// Shapes shared between the world and the lights of a scene.
+impl<H: Hitable + ?Sized> Hitable for Rc<H> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        (**self).hit(r, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        (**self).bounding_box()
+    }
+
+    fn intervals(&self, r: &Ray) -> Vec<Interval> {
+        (**self).intervals(r)
+    }
+
+    fn area(&self) -> Option<f32> {
+        (**self).area()
+    }
+
+    fn sample_surface(&self) -> Option<(Vec3, Vec3)> {
+        (**self).sample_surface()
+    }
+}
+
+const MAX_CROSSINGS: usize = 64;
+
+fn pair_crossings(crossings: Vec<HitRecord>, r: &Ray) -> Vec<Interval> {
+    let mut intervals = vec![];
+    let mut open: Option<HitRecord> = None;
+    for rec in crossings {
+        let entering = rec.normal.dot(r.direction()) < 0.0;
+        if entering {
+            if open.is_none() {
+                open = Some(rec);
+            }
+        } else {
+            let enter = open
+                .take()
+                .unwrap_or_else(|| HitRecord::unbounded(f32::NEG_INFINITY));
+            intervals.push(Interval { enter, exit: rec });
+        }
+    }
+    if let Some(enter) = open {
+        intervals.push(Interval {
+            enter,
+            exit: HitRecord::unbounded(f32::INFINITY),
+        });
+    }
+    intervals
 }
 
 pub struct Sphere {
@@ -32,7 +136,7 @@ pub struct Sphere {
 }
 
 impl Sphere {
-    fn hit_sphere(self: &Self, r: &Ray) -> (f32, f32, f32) {
+    fn hit_sphere(&self, r: &Ray) -> (f32, f32, f32) {
         let center_vector = r.origin() - &self.center;
         let a = r.direction().dot(r.direction());
         let b = center_vector.dot(r.direction());
@@ -44,14 +148,28 @@ impl Sphere {
             (-b + discriminant.sqrt()) / a,
         )
     }
+
+    fn record(&self, r: &Ray, t: f32) -> HitRecord {
+        let p = r.point_at_parameter(t);
+        let normal = (p - self.center) / self.radius;
+        let (u, v) = sphere_uv(&normal);
+        HitRecord {
+            t,
+            p,
+            normal,
+            u,
+            v,
+            material: self.material.clone(),
+        }
+    }
 }
 
 impl Hitable for Sphere {
-    fn hit(self: &Self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
         let (discriminant, negative_root, positive_root) = self.hit_sphere(r);
         if discriminant < 0.0
-            || (!(t_min < negative_root && negative_root < t_max)
-                && !(t_min < positive_root && positive_root < t_max))
+            || !(t_min < negative_root && negative_root < t_max
+                || t_min < positive_root && positive_root < t_max)
         {
             return None;
         }
@@ -61,15 +179,41 @@ impl Hitable for Sphere {
         } else {
             positive_root
         };
+        Some(self.record(r, temp))
+    }
 
-        let p = r.point_at_parameter(temp);
-        return Some(HitRecord {
-            t: temp,
-            p: p,
-            normal: (p - &self.center) / self.radius,
-            material: self.material.clone(),
-        });
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = Vec3::new(self.radius, self.radius, self.radius);
+        Some(Aabb::new(self.center - r, self.center + r))
     }
+
+    fn intervals(&self, r: &Ray) -> Vec<Interval> {
+        let (discriminant, near, far) = self.hit_sphere(r);
+        if discriminant <= 0.0 {
+            return vec![];
+        }
+        vec![Interval {
+            enter: self.record(r, near),
+            exit: self.record(r, far),
+        }]
+    }
+
+    fn area(&self) -> Option<f32> {
+        Some(4.0 * std::f32::consts::PI * self.radius * self.radius)
+    }
+
+    fn sample_surface(&self) -> Option<(Vec3, Vec3)> {
+        let normal = random_unit_vector();
+        Some((self.center + self.radius * normal, normal))
+    }
+}
+
+/// Longitude/latitude of a point on the unit sphere, both in `[0, 1]`.
+pub fn sphere_uv(p: &Vec3) -> (f32, f32) {
+    use std::f32::consts::PI;
+    let theta = (-p.y).clamp(-1.0, 1.0).acos();
+    let phi = (-p.z).atan2(p.x) + PI;
+    (phi / (2.0 * PI), theta / PI)
 }
 
 pub struct HitableList {
@@ -82,17 +226,19 @@ impl HitableList {
         let len = list.len() as isize;
         HitableList { list, size: len }
     }
-    pub fn hit(self: &Self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+}
+
+impl Hitable for HitableList {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
         let mut hit_anything = false;
         let mut closest_so_far = t_max;
         let mut temp_record = HitRecord::null();
 
         for idx in self.list.iter() {
+            count(Counter::IntersectionTests);
             let a = idx;
-            let record = a.hit(r, t_min, closest_so_far);
-            if record.is_some() {
+            if let Some(tmp) = a.hit(r, t_min, closest_so_far) {
                 hit_anything = true;
-                let tmp = record.unwrap();
                 closest_so_far = tmp.t;
                 temp_record = tmp;
             }
@@ -103,4 +249,17 @@ impl HitableList {
             None
         }
     }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let mut boxes = self.list.iter().map(|h| h.bounding_box());
+        let first = boxes.next()??;
+        boxes.try_fold(first, |acc, b| b.map(|b| acc.surrounding(&b)))
+    }
+
+    /// Treats the members as one solid: the union of their intervals.
+    fn intervals(&self, r: &Ray) -> Vec<Interval> {
+        self.list.iter().fold(vec![], |acc, h| {
+            combine(acc, h.intervals(r), CsgOp::Union, r)
+        })
+    }
 }
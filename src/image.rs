@@ -0,0 +1,792 @@
+use crate::color::Color;
+use crate::film::Encoding;
+use crate::inflate::zlib_decompress;
+use crate::vec3::Vec3;
+use crate::video::{crc32, PNG_SIGNATURE};
+use std::fmt;
+use std::fs;
+use std::io::Write;
+
+/// Linear floating point RGB image, rows stored top to bottom.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Vec3>,
+}
+
+#[derive(Debug)]
+pub enum ImageError {
+    Io(std::io::Error),
+    Format(String),
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImageError::Io(e) => write!(f, "{}", e),
+            ImageError::Format(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for ImageError {}
+
+impl From<std::io::Error> for ImageError {
+    fn from(e: std::io::Error) -> ImageError {
+        ImageError::Io(e)
+    }
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Image {
+        Image {
+            width,
+            height,
+            pixels: vec![Vec3::new(0.0, 0.0, 0.0); width * height],
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Vec3 {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, value: Vec3) {
+        self.pixels[y * self.width + x] = value;
+    }
+
+    pub fn load(path: &str) -> Result<Image, ImageError> {
+        Ok(Image::load_with_encoding(path)?.0)
+    }
+
+    /// Loads PGM/PPM, PNG, PFM or OpenEXR, told apart by their magic numbers, along with how
+    /// the stored values are encoded: floating point formats hold linear radiance, the others
+    /// display values.
+    pub fn load_with_encoding(path: &str) -> Result<(Image, Encoding), ImageError> {
+        Image::decode(&fs::read(path)?)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<(Image, Encoding), ImageError> {
+        if bytes.starts_with(&PNG_SIGNATURE) {
+            Ok((Image::decode_png(bytes)?, Encoding::Srgb))
+        } else if bytes.starts_with(b"PF") || bytes.starts_with(b"Pf") {
+            Ok((Image::decode_pfm(bytes)?, Encoding::Linear))
+        } else if bytes.starts_with(&EXR_MAGIC) {
+            Ok((Image::decode_exr(bytes)?, Encoding::Linear))
+        } else {
+            Ok((Image::decode_pnm(bytes)?, Encoding::Srgb))
+        }
+    }
+
+    /// Decodes binary or ASCII PGM/PPM (`P2`, `P3`, `P5`, `P6`) into values in `[0, 1]`.
+    pub fn decode_pnm(bytes: &[u8]) -> Result<Image, ImageError> {
+        let mut pos = 0;
+        let magic = next_token(bytes, &mut pos)?;
+        let (channels, binary) = match magic.as_str() {
+            "P2" => (1, false),
+            "P3" => (3, false),
+            "P5" => (1, true),
+            "P6" => (3, true),
+            _ => {
+                return Err(ImageError::Format(format!(
+                    "unsupported format `{}`",
+                    magic
+                )))
+            }
+        };
+        let width = parse_header(bytes, &mut pos)?;
+        let height = parse_header(bytes, &mut pos)?;
+        let max = parse_header(bytes, &mut pos)?;
+        if max == 0 || max > 65535 {
+            return Err(ImageError::Format(format!("invalid maximum value {}", max)));
+        }
+        let count = width * height * channels;
+        let samples: Vec<usize> = if binary {
+            // exactly one whitespace byte separates the header from the raster
+            pos += 1;
+            let size = if max < 256 { 1 } else { 2 };
+            let raster = bytes
+                .get(pos..pos + count * size)
+                .ok_or_else(|| ImageError::Format("truncated raster".to_string()))?;
+            raster
+                .chunks(size)
+                .map(|c| c.iter().fold(0, |acc, &b| acc * 256 + b as usize))
+                .collect()
+        } else {
+            (0..count)
+                .map(|_| parse_header(bytes, &mut pos))
+                .collect::<Result<_, _>>()?
+        };
+        let scale = 1.0 / max as f32;
+        let pixels = samples
+            .chunks(channels)
+            .map(|c| {
+                if channels == 1 {
+                    let g = c[0] as f32 * scale;
+                    Vec3::new(g, g, g)
+                } else {
+                    Vec3::new(
+                        c[0] as f32 * scale,
+                        c[1] as f32 * scale,
+                        c[2] as f32 * scale,
+                    )
+                }
+            })
+            .collect();
+        Ok(Image {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Decodes a non-interlaced PNG of any bit depth and color type into values in `[0, 1]`,
+    /// dropping alpha.
+    pub fn decode_png(bytes: &[u8]) -> Result<Image, ImageError> {
+        if !bytes.starts_with(&PNG_SIGNATURE) {
+            return Err(format_error("not a PNG file"));
+        }
+        let mut pos = PNG_SIGNATURE.len();
+        let (mut header, mut palette, mut data) = (None, vec![], vec![]);
+        loop {
+            let length = read_u32_be(bytes, pos)? as usize;
+            let chunk = bytes
+                .get(pos + 4..pos + 8 + length)
+                .ok_or_else(|| format_error("truncated PNG chunk"))?;
+            if read_u32_be(bytes, pos + 8 + length)? != crc32(chunk) {
+                return Err(format_error("PNG chunk checksum mismatch"));
+            }
+            let (kind, body) = chunk.split_at(4);
+            pos += 12 + length;
+            match kind {
+                b"IHDR" if body.len() == 13 => header = Some(body.to_vec()),
+                b"PLTE" => palette = body.to_vec(),
+                b"IDAT" => data.extend_from_slice(body),
+                b"IEND" => break,
+                _ => {}
+            }
+        }
+        let header = header.ok_or_else(|| format_error("PNG without a header"))?;
+        let width = read_u32_be(&header, 0)? as usize;
+        let height = read_u32_be(&header, 4)? as usize;
+        let (depth, color_type) = (header[8] as usize, header[9]);
+        if header[12] != 0 {
+            return Err(format_error("interlaced PNG files are not supported"));
+        }
+        let channels = match (color_type, depth) {
+            (0, 1 | 2 | 4 | 8 | 16) => 1,
+            (3, 1 | 2 | 4 | 8) => 1,
+            (4, 8 | 16) => 2,
+            (2, 8 | 16) => 3,
+            (6, 8 | 16) => 4,
+            _ => {
+                return Err(ImageError::Format(format!(
+                    "unsupported PNG color type {} at bit depth {}",
+                    color_type, depth
+                )))
+            }
+        };
+        let raw = zlib_decompress(&data).map_err(ImageError::Format)?;
+        let stride = (width * channels * depth).div_ceil(8);
+        let samples = unfilter(&raw, stride, height, (channels * depth).div_ceil(8))?;
+        let max = ((1u32 << depth) - 1) as f32;
+        let mut pixels = Vec::with_capacity(width * height);
+        for row in samples.chunks(stride) {
+            let sample = |i: usize| -> u32 {
+                match depth {
+                    16 => u16::from_be_bytes([row[2 * i], row[2 * i + 1]]) as u32,
+                    8 => row[i] as u32,
+                    _ => {
+                        let bit = i * depth;
+                        (row[bit / 8] as u32 >> (8 - depth - bit % 8)) & ((1 << depth) - 1)
+                    }
+                }
+            };
+            for x in 0..width {
+                let i = x * channels;
+                pixels.push(match color_type {
+                    3 => {
+                        let entry = sample(i) as usize * 3;
+                        let c = palette
+                            .get(entry..entry + 3)
+                            .ok_or_else(|| format_error("PNG palette index out of range"))?;
+                        Vec3::new(c[0] as f32, c[1] as f32, c[2] as f32) / 255.0
+                    }
+                    0 | 4 => {
+                        let g = sample(i) as f32 / max;
+                        Vec3::new(g, g, g)
+                    }
+                    _ => Vec3::new(
+                        sample(i) as f32 / max,
+                        sample(i + 1) as f32 / max,
+                        sample(i + 2) as f32 / max,
+                    ),
+                });
+            }
+        }
+        Ok(Image {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Decodes a color (`PF`) or grayscale (`Pf`) portable float map. The sign of the scale
+    /// tells the byte order, and rows are stored bottom to top.
+    pub fn decode_pfm(bytes: &[u8]) -> Result<Image, ImageError> {
+        let mut pos = 0;
+        let channels = match next_token(bytes, &mut pos)?.as_str() {
+            "PF" => 3,
+            "Pf" => 1,
+            _ => return Err(format_error("not a PFM file")),
+        };
+        let width = parse_header(bytes, &mut pos)?;
+        let height = parse_header(bytes, &mut pos)?;
+        let token = next_token(bytes, &mut pos)?;
+        let scale: f32 = token
+            .parse()
+            .map_err(|_| ImageError::Format(format!("invalid scale `{}`", token)))?;
+        pos += 1;
+        let raster = bytes
+            .get(pos..pos + width * height * channels * 4)
+            .ok_or_else(|| format_error("truncated raster"))?;
+        let values: Vec<f32> = raster
+            .chunks(4)
+            .map(|b| {
+                let b = [b[0], b[1], b[2], b[3]];
+                if scale < 0.0 {
+                    f32::from_le_bytes(b)
+                } else {
+                    f32::from_be_bytes(b)
+                }
+            })
+            .collect();
+        let mut image = Image::new(width, height);
+        for (i, c) in values.chunks(channels).enumerate() {
+            let (x, y) = (i % width, height - 1 - i / width);
+            let value = if channels == 1 {
+                Vec3::new(c[0], c[0], c[0])
+            } else {
+                Vec3::new(c[0], c[1], c[2])
+            };
+            image.set(x, y, value);
+        }
+        Ok(image)
+    }
+
+    /// Decodes a single part scanline OpenEXR file stored uncompressed or with RLE, ZIPS or
+    /// ZIP compression, taking `R`, `G` and `B`, or `Y` for grayscale.
+    pub fn decode_exr(bytes: &[u8]) -> Result<Image, ImageError> {
+        if !bytes.starts_with(&EXR_MAGIC) {
+            return Err(format_error("not an OpenEXR file"));
+        }
+        let flags = read_u32_le(bytes, 4)?;
+        if flags & 0x1e00 != 0 {
+            return Err(format_error(
+                "only single part scanline OpenEXR files are supported",
+            ));
+        }
+        let mut pos = 8;
+        let (mut channels, mut compression, mut window) = (vec![], None, None);
+        loop {
+            let name = exr_string(bytes, &mut pos)?;
+            if name.is_empty() {
+                break;
+            }
+            let kind = exr_string(bytes, &mut pos)?;
+            let size = read_u32_le(bytes, pos)? as usize;
+            let value = bytes
+                .get(pos + 4..pos + 4 + size)
+                .ok_or_else(|| format_error("truncated OpenEXR header"))?;
+            pos += 4 + size;
+            match (name.as_str(), kind.as_str()) {
+                ("channels", "chlist") => channels = exr_channels(value)?,
+                ("compression", "compression") if size == 1 => compression = Some(value[0]),
+                ("dataWindow", "box2i") if size == 16 => {
+                    let corner = |i: usize| read_u32_le(value, 4 * i).map(|v| v as i32);
+                    window = Some((corner(0)?, corner(1)?, corner(2)?, corner(3)?));
+                }
+                _ => {}
+            }
+        }
+        let (x0, y0, x1, y1) =
+            window.ok_or_else(|| format_error("OpenEXR without a data window"))?;
+        if x1 < x0 || y1 < y0 {
+            return Err(format_error("empty OpenEXR data window"));
+        }
+        let (width, height) = ((x1 - x0 + 1) as usize, (y1 - y0 + 1) as usize);
+        let lines = match compression.unwrap_or(0) {
+            0..=2 => 1,
+            3 => 16,
+            c => {
+                return Err(ImageError::Format(format!(
+                    "unsupported OpenEXR compression {}",
+                    c
+                )))
+            }
+        };
+        let find = |name: &str| channels.iter().position(|c: &ExrChannel| c.name == name);
+        let sources = match (find("R"), find("G"), find("B"), find("Y")) {
+            (Some(r), Some(g), Some(b), _) => [r, g, b],
+            (_, _, _, Some(y)) => [y, y, y],
+            _ => return Err(format_error("OpenEXR without RGB or Y channels")),
+        };
+        let line_size: usize = channels.iter().map(|c| c.size * width).sum();
+        let mut image = Image::new(width, height);
+        for block in 0..height.div_ceil(lines) {
+            let offset = read_u64_le(bytes, pos + 8 * block)? as usize;
+            let y = read_u32_le(bytes, offset)? as i32;
+            let size = read_u32_le(bytes, offset + 4)? as usize;
+            let chunk = bytes
+                .get(offset + 8..offset + 8 + size)
+                .ok_or_else(|| format_error("truncated OpenEXR chunk"))?;
+            if y < y0 || y > y1 {
+                return Err(format_error("OpenEXR chunk outside the data window"));
+            }
+            let first = (y - y0) as usize;
+            let count = lines.min(height - first);
+            let expected = line_size * count;
+            let data = match compression.unwrap_or(0) {
+                _ if size == expected => chunk.to_vec(),
+                0 => return Err(format_error("OpenEXR chunk of the wrong size")),
+                1 => exr_predicted(exr_run_length(chunk)?),
+                _ => exr_predicted(zlib_decompress(chunk).map_err(ImageError::Format)?),
+            };
+            if data.len() != expected {
+                return Err(format_error("OpenEXR chunk of the wrong size"));
+            }
+            for (line, scanline) in data.chunks(line_size).enumerate() {
+                let mut values = vec![];
+                let mut start = 0;
+                for c in &channels {
+                    values.push(&scanline[start..start + c.size * width]);
+                    start += c.size * width;
+                }
+                for x in 0..width {
+                    let get = |i: usize| channels[i].value(values[i], x);
+                    let value = Vec3::new(get(sources[0]), get(sources[1]), get(sources[2]));
+                    image.set(x, first + line, value);
+                }
+            }
+        }
+        Ok(image)
+    }
+}
+
+const EXR_MAGIC: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+
+fn format_error(message: &str) -> ImageError {
+    ImageError::Format(message.to_string())
+}
+
+fn read_u32_be(bytes: &[u8], pos: usize) -> Result<u32, ImageError> {
+    let b = bytes
+        .get(pos..pos + 4)
+        .ok_or_else(|| format_error("unexpected end of file"))?;
+    Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn read_u32_le(bytes: &[u8], pos: usize) -> Result<u32, ImageError> {
+    Ok(read_u32_be(bytes, pos)?.swap_bytes())
+}
+
+fn read_u64_le(bytes: &[u8], pos: usize) -> Result<u64, ImageError> {
+    Ok(read_u32_le(bytes, pos)? as u64 | (read_u32_le(bytes, pos + 4)? as u64) << 32)
+}
+
+/// PNG scanlines with their filters undone; `bpp` is the distance to the corresponding byte
+/// of the previous pixel.
+fn unfilter(raw: &[u8], stride: usize, height: usize, bpp: usize) -> Result<Vec<u8>, ImageError> {
+    if raw.len() < (stride + 1) * height {
+        return Err(format_error("truncated PNG image data"));
+    }
+    let mut out = vec![0u8; stride * height];
+    for y in 0..height {
+        let line = &raw[y * (stride + 1)..(y + 1) * (stride + 1)];
+        let (filter, line) = (line[0], &line[1..]);
+        for x in 0..stride {
+            let a = if x >= bpp {
+                out[y * stride + x - bpp]
+            } else {
+                0
+            };
+            let b = if y > 0 { out[(y - 1) * stride + x] } else { 0 };
+            let c = if x >= bpp && y > 0 {
+                out[(y - 1) * stride + x - bpp]
+            } else {
+                0
+            };
+            let predicted = match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                f => return Err(ImageError::Format(format!("invalid PNG filter {}", f))),
+            };
+            out[y * stride + x] = line[x].wrapping_add(predicted);
+        }
+    }
+    Ok(out)
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+struct ExrChannel {
+    name: String,
+    /// 0 for 32-bit unsigned integers, 1 for halfs and 2 for floats.
+    kind: u32,
+    /// Bytes per sample.
+    size: usize,
+}
+
+impl ExrChannel {
+    fn value(&self, samples: &[u8], x: usize) -> f32 {
+        let b = &samples[x * self.size..(x + 1) * self.size];
+        match self.kind {
+            0 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32,
+            1 => half_to_f32(u16::from_le_bytes([b[0], b[1]])),
+            _ => f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+        }
+    }
+}
+
+fn exr_string(bytes: &[u8], pos: &mut usize) -> Result<String, ImageError> {
+    let len = bytes[(*pos).min(bytes.len())..]
+        .iter()
+        .position(|&b| b == 0)
+        .ok_or_else(|| format_error("truncated OpenEXR header"))?;
+    let s = String::from_utf8_lossy(&bytes[*pos..*pos + len]).into_owned();
+    *pos += len + 1;
+    Ok(s)
+}
+
+fn exr_channels(value: &[u8]) -> Result<Vec<ExrChannel>, ImageError> {
+    let mut channels = vec![];
+    let mut pos = 0;
+    loop {
+        let name = exr_string(value, &mut pos)?;
+        if name.is_empty() {
+            return Ok(channels);
+        }
+        let kind = read_u32_le(value, pos)?;
+        let sampling = (read_u32_le(value, pos + 8)?, read_u32_le(value, pos + 12)?);
+        pos += 16;
+        if kind > 2 {
+            return Err(ImageError::Format(format!(
+                "invalid OpenEXR pixel type {}",
+                kind
+            )));
+        }
+        if sampling != (1, 1) {
+            return Err(format_error(
+                "subsampled OpenEXR channels are not supported",
+            ));
+        }
+        let size = if kind == 1 { 2 } else { 4 };
+        channels.push(ExrChannel { name, kind, size });
+    }
+}
+
+/// Expands OpenEXR run-length encoding: a negative count copies that many bytes, any other
+/// repeats the next byte one more time than the count.
+fn exr_run_length(data: &[u8]) -> Result<Vec<u8>, ImageError> {
+    let mut out = vec![];
+    let mut pos = 0;
+    while pos < data.len() {
+        let count = data[pos] as i8;
+        pos += 1;
+        if count < 0 {
+            let literal = data
+                .get(pos..pos + (-(count as i32)) as usize)
+                .ok_or_else(|| format_error("truncated OpenEXR run"))?;
+            out.extend_from_slice(literal);
+            pos += literal.len();
+        } else {
+            let &b = data
+                .get(pos)
+                .ok_or_else(|| format_error("truncated OpenEXR run"))?;
+            out.extend(std::iter::repeat_n(b, count as usize + 1));
+            pos += 1;
+        }
+    }
+    Ok(out)
+}
+
+/// Undoes the byte delta predictor and the split into halves that OpenEXR applies before
+/// RLE and ZIP compression.
+fn exr_predicted(mut data: Vec<u8>) -> Vec<u8> {
+    for i in 1..data.len() {
+        data[i] = data[i].wrapping_add(data[i - 1]).wrapping_sub(128);
+    }
+    let half = data.len().div_ceil(2);
+    (0..data.len())
+        .map(|i| {
+            if i % 2 == 0 {
+                data[i / 2]
+            } else {
+                data[half + i / 2]
+            }
+        })
+        .collect()
+}
+
+/// IEEE 754 half precision to single.
+fn half_to_f32(h: u16) -> f32 {
+    let sign = if h & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((h >> 10) & 0x1f) as i32;
+    let mantissa = (h & 0x3ff) as f32;
+    sign * match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        31 if mantissa == 0.0 => f32::INFINITY,
+        31 => f32::NAN,
+        e => (1.0 + mantissa / 1024.0) * 2f32.powi(e - 15),
+    }
+}
+
+/// Binary PPM (`P6`) of 8-bit pixels stored top to bottom.
+pub fn encode_ppm(width: usize, height: usize, pixels: &[Color]) -> Vec<u8> {
+    let mut bytes = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+    for c in pixels {
+        bytes.extend_from_slice(&[c.r.min(255) as u8, c.g.min(255) as u8, c.b.min(255) as u8]);
+    }
+    bytes
+}
+
+/// Little-endian color PFM, rows stored bottom to top as the format wants.
+pub fn encode_pfm(image: &Image) -> Vec<u8> {
+    let mut bytes = format!("PF\n{} {}\n-1.0\n", image.width, image.height).into_bytes();
+    for y in (0..image.height).rev() {
+        for x in 0..image.width {
+            let p = image.get(x, y);
+            for v in [p.x, p.y, p.z] {
+                bytes.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+    }
+    bytes
+}
+
+pub fn write_ppm(path: &str, width: usize, height: usize, pixels: &[Color]) -> std::io::Result<()> {
+    fs::File::create(path)?.write_all(&encode_ppm(width, height, pixels))
+}
+
+/// Rec. 709 relative luminance.
+pub fn luminance(c: &Vec3) -> f32 {
+    0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z
+}
+
+fn next_token(bytes: &[u8], pos: &mut usize) -> Result<String, ImageError> {
+    loop {
+        match bytes.get(*pos) {
+            Some(b'#') => {
+                while !matches!(bytes.get(*pos), Some(b'\n') | None) {
+                    *pos += 1;
+                }
+            }
+            Some(b) if b.is_ascii_whitespace() => *pos += 1,
+            Some(_) => break,
+            None => return Err(ImageError::Format("unexpected end of file".to_string())),
+        }
+    }
+    let start = *pos;
+    while matches!(bytes.get(*pos), Some(b) if !b.is_ascii_whitespace()) {
+        *pos += 1;
+    }
+    Ok(String::from_utf8_lossy(&bytes[start..*pos]).into_owned())
+}
+
+fn parse_header(bytes: &[u8], pos: &mut usize) -> Result<usize, ImageError> {
+    let token = next_token(bytes, pos)?;
+    token
+        .parse()
+        .map_err(|_| ImageError::Format(format!("expected a number but got `{}`", token)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::video::{encode_png, png_chunk, zlib_stored};
+
+    #[test]
+    fn decodes_ascii_and_binary() {
+        let ascii = Image::decode_pnm(b"P2\n# comment\n2 1\n4\n0 4\n").unwrap();
+        assert_eq!((ascii.width, ascii.height), (2, 1));
+        assert_eq!(ascii.get(1, 0), Vec3::new(1.0, 1.0, 1.0));
+
+        let mut binary = b"P6 1 2 255\n".to_vec();
+        binary.extend_from_slice(&[255, 0, 0, 0, 51, 255]);
+        let image = Image::decode_pnm(&binary).unwrap();
+        assert_eq!(image.get(0, 0), Vec3::new(1.0, 0.0, 0.0));
+        let second = image.get(0, 1);
+        assert!((second - Vec3::new(0.0, 0.2, 1.0)).length() < 1e-6);
+    }
+
+    #[test]
+    fn encodes_what_it_decodes() {
+        let pixels = [Color::new(255, 0, 51), Color::new(0, 128, 300)];
+        let image = Image::decode_pnm(&encode_ppm(1, 2, &pixels)).unwrap();
+        assert_eq!((image.width, image.height), (1, 2));
+        assert!((image.get(0, 0) - Vec3::new(1.0, 0.0, 0.2)).length() < 1e-6);
+        assert_eq!(image.get(0, 1).z, 1.0);
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        assert!(Image::decode_pnm(b"P7 1 1 255\n").is_err());
+        assert!(Image::decode_pnm(b"P5 2 2 255\n\x00").is_err());
+        assert!(Image::decode_pnm(b"P2 1 1 x\n").is_err());
+    }
+
+    fn png(header: [u8; 13], palette: &[u8], raw: &[u8]) -> Vec<u8> {
+        let mut out = PNG_SIGNATURE.to_vec();
+        png_chunk(&mut out, b"IHDR", &header);
+        if !palette.is_empty() {
+            png_chunk(&mut out, b"PLTE", palette);
+        }
+        png_chunk(&mut out, b"IDAT", &zlib_stored(raw));
+        png_chunk(&mut out, b"IEND", &[]);
+        out
+    }
+
+    #[test]
+    fn decodes_png() {
+        let pixels = [Color::new(255, 0, 51), Color::new(0, 128, 255)];
+        let (image, encoding) = Image::decode(&encode_png(2, 1, &pixels)).unwrap();
+        assert_eq!(encoding, Encoding::Srgb);
+        assert!((image.get(0, 0) - Vec3::new(1.0, 0.0, 0.2)).length() < 1e-6);
+
+        // 2x2 16-bit gray with alpha: sub filter, then Paeth
+        let header = [0, 0, 0, 2, 0, 0, 0, 2, 16, 4, 0, 0, 0];
+        let raw = [
+            1, 0x80, 0, 0xff, 0xff, 0x80, 0, 0, 0, //
+            4, 0x7f, 0xff, 0, 0, 0x80, 0x01, 0, 0,
+        ];
+        let image = Image::decode_png(&png(header, &[], &raw)).unwrap();
+        assert!((image.get(0, 0).x - 0.5).abs() < 1e-4);
+        assert_eq!(image.get(1, 0), Vec3::new(0.0, 0.0, 0.0));
+        assert_eq!(image.get(0, 1), Vec3::new(1.0, 1.0, 1.0));
+        assert_eq!(image.get(1, 1), Vec3::new(0.0, 0.0, 0.0));
+
+        // 3x1 paletted at 2 bits per pixel
+        let header = [0, 0, 0, 3, 0, 0, 0, 1, 2, 3, 0, 0, 0];
+        let palette = [0, 0, 0, 255, 0, 0, 0, 0, 255];
+        let image = Image::decode_png(&png(header, &palette, &[0, 0b0001_1000])).unwrap();
+        assert_eq!(image.get(0, 0), Vec3::new(0.0, 0.0, 0.0));
+        assert_eq!(image.get(1, 0), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(image.get(2, 0), Vec3::new(0.0, 0.0, 1.0));
+
+        let mut corrupt = encode_png(2, 1, &pixels);
+        let last = corrupt.len() - 20;
+        corrupt[last] ^= 1;
+        assert!(Image::decode_png(&corrupt).is_err());
+    }
+
+    #[test]
+    fn decodes_pfm() {
+        let mut bytes = b"PF\n1 2\n-1.0\n".to_vec();
+        for v in [0.25f32, 0.5, 4.0, 1.0, 2.0, 3.0] {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        let (image, encoding) = Image::decode(&bytes).unwrap();
+        assert_eq!(encoding, Encoding::Linear);
+        // bottom row first
+        assert_eq!(image.get(0, 1), Vec3::new(0.25, 0.5, 4.0));
+        assert_eq!(image.get(0, 0), Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!(encode_pfm(&image), bytes);
+
+        let mut bytes = b"Pf 1 1 1.0\n".to_vec();
+        bytes.extend_from_slice(&8.0f32.to_be_bytes());
+        assert_eq!(Image::decode_pfm(&bytes).unwrap().get(0, 0).y, 8.0);
+    }
+
+    /// A 2x1 scanline OpenEXR file with half `B`, `G`, `R` channels, each line compressed
+    /// by `compress`.
+    fn exr(compression: u8, compress: impl Fn(Vec<u8>) -> Vec<u8>) -> Vec<u8> {
+        let mut out = EXR_MAGIC.to_vec();
+        out.extend_from_slice(&2u32.to_le_bytes());
+        let attribute = |out: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]| {
+            for s in [name, kind] {
+                out.extend_from_slice(s.as_bytes());
+                out.push(0);
+            }
+            out.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            out.extend_from_slice(value);
+        };
+        let mut channels = vec![];
+        for name in ["B", "G", "R"] {
+            channels.extend_from_slice(name.as_bytes());
+            channels.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0]);
+        }
+        channels.push(0);
+        attribute(&mut out, "channels", "chlist", &channels);
+        attribute(&mut out, "compression", "compression", &[compression]);
+        attribute(
+            &mut out,
+            "dataWindow",
+            "box2i",
+            &[0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0],
+        );
+        out.push(0);
+        let offset = out.len() as u64 + 8;
+        out.extend_from_slice(&offset.to_le_bytes());
+        // halfs 0.5, 1.0, 2.0, -0.25 and 0: B then G then R
+        let line = vec![
+            0x00, 0x38, 0x00, 0x00, 0x00, 0x3c, 0x00, 0xb4, 0x00, 0x40, 0x00, 0x00,
+        ];
+        let data = compress(line);
+        out.extend_from_slice(&0u32.to_le_bytes());
+        out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        out.extend_from_slice(&data);
+        out
+    }
+
+    /// The inverse of `exr_predicted`.
+    fn exr_predict(data: Vec<u8>) -> Vec<u8> {
+        let mut split: Vec<u8> = data.iter().step_by(2).copied().collect();
+        split.extend(data.iter().skip(1).step_by(2));
+        let mut out = split.clone();
+        for i in 1..out.len() {
+            out[i] = split[i].wrapping_sub(split[i - 1]).wrapping_add(128);
+        }
+        out
+    }
+
+    #[test]
+    fn decodes_exr() {
+        let expected = [Vec3::new(2.0, 1.0, 0.5), Vec3::new(0.0, -0.25, 0.0)];
+        let (image, encoding) = Image::decode(&exr(0, |line| line)).unwrap();
+        assert_eq!(encoding, Encoding::Linear);
+        assert_eq!(image.pixels, expected);
+        let zip = exr(2, |line| zlib_stored(&exr_predict(line)));
+        assert_eq!(Image::decode_exr(&zip).unwrap().pixels, expected);
+        // literal runs only
+        let rle = exr(1, |line| {
+            let predicted = exr_predict(line);
+            let mut out = vec![];
+            for run in predicted.chunks(6) {
+                out.push((-(run.len() as i8)) as u8);
+                out.extend_from_slice(run);
+            }
+            out
+        });
+        assert_eq!(Image::decode_exr(&rle).unwrap().pixels, expected);
+        assert!(Image::decode_exr(&exr(5, |line| line)[..60]).is_err());
+        assert_eq!(half_to_f32(0x7bff), 65504.0);
+        assert_eq!(half_to_f32(0x0001), 2f32.powi(-24));
+    }
+}
@@ -0,0 +1,306 @@
+use crate::video::adler32;
+
+/// Reads a deflate stream least significant bit first.
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit: u32,
+}
+
+impl<'a> Bits<'a> {
+    fn take(&mut self, n: u32) -> Result<u32, String> {
+        let mut value = 0;
+        for i in 0..n {
+            let byte = *self
+                .data
+                .get(self.pos)
+                .ok_or_else(|| "truncated deflate stream".to_string())?;
+            value |= ((byte as u32 >> self.bit) & 1) << i;
+            self.bit += 1;
+            if self.bit == 8 {
+                self.bit = 0;
+                self.pos += 1;
+            }
+        }
+        Ok(value)
+    }
+
+    fn align(&mut self) {
+        if self.bit > 0 {
+            self.bit = 0;
+            self.pos += 1;
+        }
+    }
+}
+
+/// Canonical Huffman code, decoded a bit at a time.
+struct Huffman {
+    /// Number of codes of each length.
+    counts: [u16; 16],
+    /// Symbols ordered by code.
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Huffman, String> {
+        let mut counts = [0u16; 16];
+        for &l in lengths {
+            counts[l as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut left = 1i32;
+        for &count in &counts[1..] {
+            left = 2 * left - count as i32;
+            if left < 0 {
+                return Err("oversubscribed Huffman code".to_string());
+            }
+        }
+        let mut offsets = [0u16; 16];
+        for len in 1..15 {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &l) in lengths.iter().enumerate() {
+            if l != 0 {
+                symbols[offsets[l as usize] as usize] = symbol as u16;
+                offsets[l as usize] += 1;
+            }
+        }
+        Ok(Huffman { counts, symbols })
+    }
+
+    fn decode(&self, bits: &mut Bits) -> Result<u16, String> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..16 {
+            code |= bits.take(1)? as i32;
+            let count = self.counts[len] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("invalid Huffman code".to_string())
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// Order in which code length code lengths are stored.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    for (i, l) in lengths.iter_mut().enumerate() {
+        *l = match i {
+            0..=143 => 8,
+            144..=255 => 9,
+            256..=279 => 7,
+            _ => 8,
+        };
+    }
+    (
+        Huffman::new(&lengths).expect("the fixed code is complete"),
+        Huffman::new(&[5; 30]).expect("the fixed code is complete"),
+    )
+}
+
+fn dynamic_codes(bits: &mut Bits) -> Result<(Huffman, Huffman), String> {
+    let literals = bits.take(5)? as usize + 257;
+    let distances = bits.take(5)? as usize + 1;
+    let code_lengths = bits.take(4)? as usize + 4;
+    let mut lengths = [0u8; 19];
+    for &i in &CODE_LENGTH_ORDER[..code_lengths] {
+        lengths[i] = bits.take(3)? as u8;
+    }
+    let code = Huffman::new(&lengths)?;
+    let mut lengths = vec![];
+    while lengths.len() < literals + distances {
+        let (value, repeat) = match code.decode(bits)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => (
+                *lengths
+                    .last()
+                    .ok_or_else(|| "repeat without a previous length".to_string())?,
+                3 + bits.take(2)?,
+            ),
+            17 => (0, 3 + bits.take(3)?),
+            _ => (0, 11 + bits.take(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(value, repeat as usize));
+    }
+    if lengths.len() > literals + distances {
+        return Err("code lengths overrun".to_string());
+    }
+    Ok((
+        Huffman::new(&lengths[..literals])?,
+        Huffman::new(&lengths[literals..])?,
+    ))
+}
+
+/// Decompresses a raw deflate stream.
+pub fn inflate(data: &[u8]) -> Result<Vec<u8>, String> {
+    inflate_from(&mut Bits {
+        data,
+        pos: 0,
+        bit: 0,
+    })
+}
+
+fn inflate_from(bits: &mut Bits) -> Result<Vec<u8>, String> {
+    let mut out: Vec<u8> = vec![];
+    loop {
+        let last = bits.take(1)? == 1;
+        match bits.take(2)? {
+            0 => {
+                bits.align();
+                let header = bits
+                    .data
+                    .get(bits.pos..bits.pos + 4)
+                    .ok_or_else(|| "truncated stored block".to_string())?;
+                let len = u16::from_le_bytes([header[0], header[1]]);
+                if len != !u16::from_le_bytes([header[2], header[3]]) {
+                    return Err("corrupt stored block length".to_string());
+                }
+                let start = bits.pos + 4;
+                let block = bits
+                    .data
+                    .get(start..start + len as usize)
+                    .ok_or_else(|| "truncated stored block".to_string())?;
+                out.extend_from_slice(block);
+                bits.pos = start + len as usize;
+            }
+            kind @ 1..=2 => {
+                let (literal, distance) = if kind == 1 {
+                    fixed_codes()
+                } else {
+                    dynamic_codes(bits)?
+                };
+                loop {
+                    let symbol = literal.decode(bits)? as usize;
+                    if symbol < 256 {
+                        out.push(symbol as u8);
+                        continue;
+                    }
+                    if symbol == 256 {
+                        break;
+                    }
+                    let i = symbol - 257;
+                    if i >= LENGTH_BASE.len() {
+                        return Err("invalid length code".to_string());
+                    }
+                    let length =
+                        LENGTH_BASE[i] as usize + bits.take(LENGTH_EXTRA[i] as u32)? as usize;
+                    let d = distance.decode(bits)? as usize;
+                    if d >= DISTANCE_BASE.len() {
+                        return Err("invalid distance code".to_string());
+                    }
+                    let back =
+                        DISTANCE_BASE[d] as usize + bits.take(DISTANCE_EXTRA[d] as u32)? as usize;
+                    if back > out.len() {
+                        return Err("distance reaches before the start".to_string());
+                    }
+                    let from = out.len() - back;
+                    for k in 0..length {
+                        out.push(out[from + k]);
+                    }
+                }
+            }
+            _ => return Err("invalid block type".to_string()),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+/// Decompresses a zlib stream and checks its Adler-32.
+pub fn zlib_decompress(data: &[u8]) -> Result<Vec<u8>, String> {
+    if data.len() < 6
+        || data[0] & 0x0f != 8
+        || !(data[0] as u16 * 256 + data[1] as u16).is_multiple_of(31)
+    {
+        return Err("not a zlib stream".to_string());
+    }
+    if data[1] & 0x20 != 0 {
+        return Err("zlib preset dictionaries are not supported".to_string());
+    }
+    let mut bits = Bits {
+        data,
+        pos: 2,
+        bit: 0,
+    };
+    let out = inflate_from(&mut bits)?;
+    bits.align();
+    let check = data
+        .get(bits.pos..bits.pos + 4)
+        .ok_or_else(|| "missing zlib checksum".to_string())?;
+    if u32::from_be_bytes([check[0], check[1], check[2], check[3]]) != adler32(&out) {
+        return Err("zlib checksum mismatch".to_string());
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::video::zlib_stored;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn inflates_every_block_type() {
+        let data: Vec<u8> = (0..70000).map(|i| (i % 251) as u8).collect();
+        assert_eq!(zlib_decompress(&zlib_stored(&data)).unwrap(), data);
+
+        // fixed codes, as written by zlib
+        let fixed = [
+            120, 218, 203, 72, 205, 201, 201, 87, 200, 64, 39, 117, 20, 50, 243, 210, 114, 18, 75,
+            82, 21, 1, 208, 90, 12, 1,
+        ];
+        assert_eq!(
+            zlib_decompress(&fixed).unwrap(),
+            b"hello hello hello hello, inflate!"
+        );
+
+        // dynamic codes
+        let dynamic = hex(
+            "78da05c1011200100804c0b71ea206c9a0be6f175c8f5b821e0dacbb0bbd82b9d83437887876e620\
+             8c49436e5878ff15a2150b",
+        );
+        let expected: Vec<u8> = (0..50u32)
+            .map(|i| ((i * i * 7 + i / 3) % 23 + 97) as u8)
+            .collect();
+        assert_eq!(zlib_decompress(&dynamic).unwrap(), expected);
+    }
+
+    #[test]
+    fn rejects_corrupt_streams() {
+        let mut stream = zlib_stored(b"abc");
+        *stream.last_mut().unwrap() ^= 1;
+        assert!(zlib_decompress(&stream).is_err());
+        assert!(zlib_decompress(b"\x78").is_err());
+        assert!(inflate(&[0xff]).is_err());
+    }
+}
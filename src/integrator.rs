@@ -0,0 +1,746 @@
+use crate::aabb::Aabb;
+use crate::bdpt::BidirectionalPathTracer;
+use crate::camera::Camera;
+use crate::hitable::{HitRecord, Hitable};
+use crate::light::{sample_light, Background};
+use crate::material::{Lobe, Material};
+use crate::misc::random;
+use crate::mlt::MetropolisLightTransport;
+use crate::photon::{PhotonMapper, ProgressivePhotonMapper};
+use crate::ray::Ray;
+use crate::stats::{count, Counter};
+use crate::vec3::{random_unit_vector, Vec3};
+use std::f32::consts::PI;
+use std::rc::Rc;
+use std::str::FromStr;
+
+/// What an integrator sees of a built scene.
+pub struct SceneView<'a> {
+    pub world: &'a dyn Hitable,
+    /// Shapes that give off light and can be sampled, also part of `world`.
+    pub lights: &'a [Rc<dyn Hitable>],
+    pub background: Background,
+    /// Every material in the order the scene defines them, to tell them apart.
+    pub materials: &'a [Rc<Box<dyn Material>>],
+    /// Box around the shapes of `world` that have one, which light from the background is
+    /// aimed at when paths start there.
+    pub bounds: Option<Aabb>,
+    /// The camera that made the rays, for paths that start at the lights.
+    pub camera: &'a dyn Camera,
+}
+
+/// Light a path carries to the camera at some other place on the image than the pixel being
+/// sampled, at `(s, t)` as `Camera::get_ray` takes them.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Splat {
+    pub s: f32,
+    pub t: f32,
+    pub radiance: Vec3,
+}
+
+/// Light transport algorithm: the radiance arriving along camera rays.
+pub trait Integrator: Sync {
+    /// Linear and unbounded.
+    fn radiance(&self, r: &Ray, scene: &SceneView) -> Vec3;
+
+    /// `radiance`, for integrators that also follow light from the lights to the camera and
+    /// leave it in `splats`. Summed over every sample of the image and divided by the
+    /// samples per pixel, splats add to the image what `radiance` leaves out.
+    fn radiance_and_splats(&self, r: &Ray, scene: &SceneView, _splats: &mut Vec<Splat>) -> Vec3 {
+        self.radiance(r, scene)
+    }
+
+    /// Runs before the camera rays of each pass over the image, once, with one render
+    /// thread's copy of the scene, for integrators that trace the scene ahead of the camera,
+    /// such as photon mappers. Passes count from 0.
+    fn prepare(&self, _scene: &SceneView, _pass: u32) {}
+
+    /// Whether every sample of a pixel needs a pass over the image of its own, after its
+    /// own `prepare`; otherwise all of them are taken in a single pass.
+    fn progressive(&self) -> bool {
+        false
+    }
+}
+
+impl<I: Integrator + ?Sized> Integrator for Box<I> {
+    fn radiance(&self, r: &Ray, scene: &SceneView) -> Vec3 {
+        (**self).radiance(r, scene)
+    }
+
+    fn radiance_and_splats(&self, r: &Ray, scene: &SceneView, splats: &mut Vec<Splat>) -> Vec3 {
+        (**self).radiance_and_splats(r, scene, splats)
+    }
+
+    fn prepare(&self, scene: &SceneView, pass: u32) {
+        (**self).prepare(scene, pass)
+    }
+
+    fn progressive(&self) -> bool {
+        (**self).progressive()
+    }
+}
+
+/// Integrators by name, as chosen in scene files and on the command line.
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub enum IntegratorKind {
+    #[default]
+    Path,
+    Bidirectional,
+    /// Photon mapping with final gathering, looking no further than `radius` for photons.
+    Photons {
+        radius: Option<f32>,
+    },
+    /// Stochastic progressive photon mapping, starting from `radius`.
+    ProgressivePhotons {
+        radius: Option<f32>,
+    },
+    Metropolis,
+    Direct,
+    AmbientOcclusion {
+        distance: f32,
+    },
+    Whitted,
+    Debug(DebugView),
+}
+
+impl IntegratorKind {
+    /// `parameter` is the reach of `ao`, the far distance of `depth` and the photon search
+    /// radius of `photons` and `sppm`; the others take none.
+    pub fn new(name: &str, parameter: Option<f32>) -> Result<IntegratorKind, String> {
+        if let Some(x) = parameter {
+            if x.is_nan() || x <= 0.0 {
+                return Err(format!("the {} distance must be positive", name));
+            }
+        }
+        let kind = match name {
+            "path" => IntegratorKind::Path,
+            "bdpt" => IntegratorKind::Bidirectional,
+            "photons" => IntegratorKind::Photons { radius: parameter },
+            "sppm" => IntegratorKind::ProgressivePhotons { radius: parameter },
+            "mlt" => IntegratorKind::Metropolis,
+            "direct" => IntegratorKind::Direct,
+            "ao" => IntegratorKind::AmbientOcclusion {
+                distance: parameter.unwrap_or(f32::MAX),
+            },
+            "whitted" => IntegratorKind::Whitted,
+            "normals" => IntegratorKind::Debug(DebugView::Normals),
+            "uvs" => IntegratorKind::Debug(DebugView::Uvs),
+            "depth" => IntegratorKind::Debug(DebugView::Depth {
+                far: parameter.unwrap_or(20.0),
+            }),
+            "barycentrics" => IntegratorKind::Debug(DebugView::Barycentrics),
+            "material_ids" => IntegratorKind::Debug(DebugView::MaterialIds),
+            _ => {
+                return Err(format!(
+                "unknown integrator `{}` (expected path, bdpt, photons, sppm, mlt, direct, ao, \
+                     whitted, normals, uvs, depth, barycentrics or material_ids)",
+                name
+            ))
+            }
+        };
+        match (kind, parameter) {
+            (IntegratorKind::AmbientOcclusion { .. }, _)
+            | (IntegratorKind::Photons { .. }, _)
+            | (IntegratorKind::ProgressivePhotons { .. }, _)
+            | (IntegratorKind::Debug(DebugView::Depth { .. }), _)
+            | (_, None) => Ok(kind),
+            _ => Err(format!("the {} integrator takes no distance", name)),
+        }
+    }
+
+    /// `max_depth` bounds the bounces of the integrators that follow rays, and `bounces`
+    /// those of each lobe for the path tracer and Metropolis light transport over it.
+    pub fn build(self, max_depth: u32, bounces: BounceLimits) -> Box<dyn Integrator> {
+        match self {
+            IntegratorKind::Path => Box::new(PathTracer::new(max_depth).with_bounces(bounces)),
+            IntegratorKind::Bidirectional => Box::new(BidirectionalPathTracer::new(max_depth)),
+            IntegratorKind::Photons { radius } => {
+                let mapper = PhotonMapper::new(max_depth);
+                Box::new(match radius {
+                    Some(radius) => mapper.with_radius(radius),
+                    None => mapper,
+                })
+            }
+            IntegratorKind::ProgressivePhotons { radius } => {
+                let mapper = ProgressivePhotonMapper::new(max_depth);
+                Box::new(match radius {
+                    Some(radius) => mapper.with_radius(radius),
+                    None => mapper,
+                })
+            }
+            IntegratorKind::Metropolis => Box::new(MetropolisLightTransport::new(
+                PathTracer::new(max_depth).with_bounces(bounces),
+            )),
+            IntegratorKind::Direct => Box::new(DirectLighting { max_depth }),
+            IntegratorKind::AmbientOcclusion { distance } => {
+                Box::new(AmbientOcclusion { distance })
+            }
+            IntegratorKind::Whitted => Box::new(Whitted { max_depth }),
+            IntegratorKind::Debug(view) => Box::new(view),
+        }
+    }
+}
+
+/// A name, optionally followed by a colon and the distance, e.g. `ao:0.5`.
+impl FromStr for IntegratorKind {
+    type Err = String;
+    fn from_str(s: &str) -> Result<IntegratorKind, String> {
+        match s.split_once(':') {
+            None => IntegratorKind::new(s, None),
+            Some((name, distance)) => match distance.parse() {
+                Ok(distance) => IntegratorKind::new(name, Some(distance)),
+                Err(_) => Err(format!("expected a distance, got `{}`", distance)),
+            },
+        }
+    }
+}
+
+/// Bounces of each lobe a path may take, on top of the overall maximum depth.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct BounceLimits {
+    pub diffuse: u32,
+    pub specular: u32,
+    pub transmission: u32,
+}
+
+impl Default for BounceLimits {
+    /// No limit but the depth.
+    fn default() -> BounceLimits {
+        BounceLimits {
+            diffuse: u32::MAX,
+            specular: u32::MAX,
+            transmission: u32::MAX,
+        }
+    }
+}
+
+impl BounceLimits {
+    fn of(&self, lobe: Lobe) -> u32 {
+        match lobe {
+            Lobe::Diffuse => self.diffuse,
+            Lobe::Specular => self.specular,
+            Lobe::Transmission => self.transmission,
+        }
+    }
+}
+
+/// Follows one scattered ray per bounce until it escapes to the background, carrying the
+/// product of the attenuations so far. Past `roulette_depth` bounces a path goes on with a
+/// probability of its throughput and is weighted up by the same factor, so dim paths stop
+/// early without darkening the image.
+pub struct PathTracer {
+    /// Bounces after which a path is cut off whatever its throughput.
+    pub max_depth: u32,
+    pub bounces: BounceLimits,
+    /// Bounces taken before Russian roulette starts; `u32::MAX` turns it off.
+    pub roulette_depth: u32,
+}
+
+impl PathTracer {
+    pub fn new(max_depth: u32) -> PathTracer {
+        PathTracer {
+            max_depth,
+            bounces: BounceLimits::default(),
+            roulette_depth: 3,
+        }
+    }
+
+    pub fn with_bounces(mut self, bounces: BounceLimits) -> PathTracer {
+        self.bounces = bounces;
+        self
+    }
+
+    pub fn with_roulette_depth(mut self, roulette_depth: u32) -> PathTracer {
+        self.roulette_depth = roulette_depth;
+        self
+    }
+}
+
+impl Integrator for PathTracer {
+    fn radiance(&self, r: &Ray, scene: &SceneView) -> Vec3 {
+        let mut ray = Ray::new(*r.origin(), *r.direction());
+        let mut throughput = Vec3::new(1.0, 1.0, 1.0);
+        let mut radiance = Vec3::new(0.0, 0.0, 0.0);
+        let (mut diffuse, mut specular, mut transmission) = (0, 0, 0);
+        for depth in 0..=self.max_depth {
+            let record = match trace(&ray, scene, depth) {
+                Some(record) => record,
+                None => return radiance + throughput * scene.background.radiance(ray.direction()),
+            };
+            let material = &record.material;
+            radiance = radiance + throughput * material.emitted(&record);
+            let (is_scattered, attenuation, scattered) = material.scatter(&ray, &record);
+            if depth == self.max_depth || !is_scattered {
+                break;
+            }
+            let lobe = material.lobe(&ray, &record, &scattered);
+            let taken = match lobe {
+                Lobe::Diffuse => &mut diffuse,
+                Lobe::Specular => &mut specular,
+                Lobe::Transmission => &mut transmission,
+            };
+            if *taken == self.bounces.of(lobe) {
+                break;
+            }
+            *taken += 1;
+            throughput = throughput * attenuation.as_vec3();
+            if depth >= self.roulette_depth {
+                let survival = throughput.x.max(throughput.y).max(throughput.z).min(0.95);
+                if random() >= survival {
+                    break;
+                }
+                throughput = throughput / survival;
+            }
+            ray = scattered;
+        }
+        radiance
+    }
+}
+
+/// Counts the ray as primary or secondary by its bounce and finds what it hits.
+pub(crate) fn trace(r: &Ray, scene: &SceneView, depth: u32) -> Option<HitRecord> {
+    count(if depth == 0 {
+        Counter::PrimaryRays
+    } else {
+        Counter::SecondaryRays
+    });
+    scene.world.hit(r, 0.0001, f32::MAX)
+}
+
+/// The normal on the side the ray came from.
+pub(crate) fn facing(normal: &Vec3, direction: &Vec3) -> Vec3 {
+    if normal.dot(direction) > 0.0 {
+        -*normal
+    } else {
+        *normal
+    }
+}
+
+/// Light that reaches diffuse surfaces straight from the lights and the background, seen
+/// directly or through mirrors and glass. Lights that cannot be sampled, such as glowing
+/// planes, only show where they are seen.
+pub struct DirectLighting {
+    pub max_depth: u32,
+}
+
+impl Integrator for DirectLighting {
+    fn radiance(&self, r: &Ray, scene: &SceneView) -> Vec3 {
+        self.direct(r, scene, 0)
+    }
+}
+
+impl DirectLighting {
+    fn direct(&self, r: &Ray, scene: &SceneView, depth: u32) -> Vec3 {
+        let record = match trace(r, scene, depth) {
+            Some(record) => record,
+            None => return scene.background.radiance(r.direction()),
+        };
+        let material = &record.material;
+        let emitted = material.emitted(&record);
+        if let Some(albedo) = material.lambert(&record) {
+            return emitted + direct_light(r, &record, albedo, scene);
+        }
+        let (is_scattered, attenuation, scattered) = material.scatter(r, &record);
+        if depth < self.max_depth && is_scattered {
+            emitted + attenuation.as_vec3() * self.direct(&scattered, scene, depth + 1)
+        } else {
+            emitted
+        }
+    }
+}
+
+/// Light reaching a diffuse surface of reflectance `albedo` from one point sampled on the
+/// lights, and from the background through the cosine-distributed scattered ray, whose
+/// weight is the albedo.
+pub(crate) fn direct_light(r: &Ray, record: &HitRecord, albedo: Vec3, scene: &SceneView) -> Vec3 {
+    let (is_scattered, attenuation, scattered) = record.material.scatter(r, record);
+    let normal = facing(&record.normal, r.direction());
+    let mut light = Vec3::new(0.0, 0.0, 0.0);
+    if let Some(sample) = sample_light(scene.lights, scene.world, &record.p) {
+        let cosine = normal.dot(&sample.direction).max(0.0);
+        light = albedo / PI * sample.radiance * (cosine / sample.pdf);
+    }
+    count(Counter::SecondaryRays);
+    if is_scattered && scene.world.hit(&scattered, 0.0001, f32::MAX).is_none() {
+        light = light + attenuation.as_vec3() * scene.background.radiance(scattered.direction());
+    }
+    light
+}
+
+/// Hemisphere visibility within `distance`: white where nothing is near, black in creases.
+pub struct AmbientOcclusion {
+    pub distance: f32,
+}
+
+impl Integrator for AmbientOcclusion {
+    fn radiance(&self, r: &Ray, scene: &SceneView) -> Vec3 {
+        let white = Vec3::new(1.0, 1.0, 1.0);
+        let record = match trace(r, scene, 0) {
+            Some(record) => record,
+            None => return white,
+        };
+        let normal = facing(&record.normal, r.direction());
+        let mut direction = normal + random_unit_vector();
+        if direction.squared_length() < 1e-12 {
+            direction = normal;
+        }
+        count(Counter::ShadowRays);
+        let reach = self.distance / direction.length();
+        match scene
+            .world
+            .hit(&Ray::new(record.p, direction), 0.0001, reach)
+        {
+            Some(_) => Vec3::new(0.0, 0.0, 0.0),
+            None => white,
+        }
+    }
+}
+
+/// Branches stop once they carry less than this share of the camera ray's light.
+const MIN_WEIGHT: f32 = 1e-3;
+
+/// Classic recursive ray tracing: diffuse surfaces see each light through one shadow ray
+/// and the background as ambient light from the direction of their normal, mirrors and
+/// glass split into their ideal reflected and refracted rays.
+pub struct Whitted {
+    pub max_depth: u32,
+}
+
+impl Integrator for Whitted {
+    fn radiance(&self, r: &Ray, scene: &SceneView) -> Vec3 {
+        self.whitted(r, scene, 0, 1.0)
+    }
+}
+
+impl Whitted {
+    fn whitted(&self, r: &Ray, scene: &SceneView, depth: u32, weight: f32) -> Vec3 {
+        let record = match trace(r, scene, depth) {
+            Some(record) => record,
+            None => return scene.background.radiance(r.direction()),
+        };
+        let material = &record.material;
+        let mut radiance = material.emitted(&record);
+        if let Some(albedo) = material.lambert(&record) {
+            let normal = facing(&record.normal, r.direction());
+            let mut light = scene.background.radiance(&normal);
+            for i in 0..scene.lights.len() {
+                let light_i = &scene.lights[i..=i];
+                if let Some(sample) = sample_light(light_i, scene.world, &record.p) {
+                    let cosine = normal.dot(&sample.direction).max(0.0);
+                    light = light + sample.radiance * (cosine / PI / sample.pdf);
+                }
+            }
+            radiance = radiance + albedo * light;
+        }
+        if depth < self.max_depth {
+            for (share, ray) in material.specular(r, &record) {
+                let next = weight * share.x.max(share.y).max(share.z);
+                if next >= MIN_WEIGHT {
+                    radiance = radiance + share * self.whitted(&ray, scene, depth + 1, next);
+                }
+            }
+        }
+        radiance
+    }
+}
+
+/// False-color views of what camera rays hit, black where they hit nothing.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum DebugView {
+    /// Outward normals mapped from `[-1, 1]` to `[0, 1]`.
+    Normals,
+    /// `u` in red and `v` in green.
+    Uvs,
+    /// White at the camera fading to black at `far`.
+    Depth { far: f32 },
+    /// The weights of the corners of a triangle in red, green and blue. No shape here is
+    /// made of triangles, so this is `1 - u - v`, `u` and `v`, which is what a triangle
+    /// parameterized by its edges would give.
+    Barycentrics,
+    /// A color of its own for each material of the scene.
+    MaterialIds,
+}
+
+impl Integrator for DebugView {
+    fn radiance(&self, r: &Ray, scene: &SceneView) -> Vec3 {
+        let record = match trace(r, scene, 0) {
+            Some(record) => record,
+            None => return Vec3::new(0.0, 0.0, 0.0),
+        };
+        match *self {
+            DebugView::Normals => 0.5 * (record.normal + Vec3::new(1.0, 1.0, 1.0)),
+            DebugView::Uvs => Vec3::new(record.u, record.v, 0.0),
+            DebugView::Depth { far } => {
+                let gray = (1.0 - record.t * r.direction().length() / far).max(0.0);
+                Vec3::new(gray, gray, gray)
+            }
+            DebugView::Barycentrics => {
+                Vec3::new((1.0 - record.u - record.v).max(0.0), record.u, record.v)
+            }
+            DebugView::MaterialIds => scene
+                .materials
+                .iter()
+                .position(|m| Rc::ptr_eq(m, &record.material))
+                .map_or(Vec3::new(0.5, 0.5, 0.5), palette),
+        }
+    }
+}
+
+/// Well-separated hues for consecutive indices, stepping round the color wheel by the
+/// golden angle.
+fn palette(index: usize) -> Vec3 {
+    let hue = (index as f32 * 0.618_034).fract() * 6.0;
+    let (saturation, value) = (0.65, 0.95);
+    let channel = |n: f32| {
+        let k = (n + hue) % 6.0;
+        value - value * saturation * (k.min(4.0 - k).clamp(0.0, 1.0))
+    };
+    Vec3::new(channel(5.0), channel(3.0), channel(1.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bvh::Bvh;
+    use crate::misc;
+    use crate::scene::Scene;
+    use crate::stats::Counters;
+
+    /// Builds the scene and hands its view to `f`.
+    fn with_view<T>(text: &str, f: impl FnOnce(&SceneView) -> T) -> T {
+        let scene = Scene::parse(text).unwrap();
+        let camera = scene.camera.build(1.0).unwrap();
+        let world = Bvh::new(scene.objects);
+        f(&SceneView {
+            bounds: world.finite_bounds(),
+            world: &world,
+            lights: &scene.lights,
+            background: scene.background,
+            materials: &scene.materials,
+            camera: camera.as_ref(),
+        })
+    }
+
+    /// Mean radiance of `n` rays from `origin` spread over a small cone around `direction`.
+    fn mean(
+        integrator: &dyn Integrator,
+        view: &SceneView,
+        origin: Vec3,
+        direction: Vec3,
+        n: usize,
+    ) -> Vec3 {
+        let mut sum = Vec3::new(0.0, 0.0, 0.0);
+        for _ in 0..n {
+            let r = Ray::new(origin, direction + 0.05 * random_unit_vector());
+            sum = sum + integrator.radiance(&r, view);
+        }
+        sum / n as f32
+    }
+
+    #[test]
+    fn estimators_of_direct_light_agree() {
+        // a floor under a square lamp in the dark, where one bounce of the path tracer is
+        // exactly the direct light
+        misc::seed(3);
+        let scene = "background 0 0 0\n\
+                     material white lambertian 0.8 0.8 0.8\n\
+                     material lamp light 4 4 4\n\
+                     quad -2 0 -2  0 0 4  4 0 0  white\n\
+                     quad -0.5 1 -0.5  1 0 0  0 0 1  lamp\n";
+        with_view(scene, |view| {
+            assert_eq!(view.lights.len(), 1);
+            let (origin, direction) = (Vec3::new(0.0, 0.5, -3.0), Vec3::new(0.0, -0.5, 3.0));
+            let direct = mean(
+                &DirectLighting { max_depth: 1 },
+                view,
+                origin,
+                direction,
+                20_000,
+            );
+            let path = mean(&PathTracer::new(1), view, origin, direction, 200_000);
+            let whitted = mean(&Whitted { max_depth: 1 }, view, origin, direction, 20_000);
+            assert!(direct.x > 0.1, "{:?}", direct);
+            for other in [path, whitted] {
+                assert!(
+                    (other.x - direct.x).abs() < 0.03 * direct.x,
+                    "{:?} against {:?}",
+                    other,
+                    direct
+                );
+            }
+        });
+    }
+
+    #[test]
+    fn russian_roulette_leaves_the_mean_alone() {
+        misc::seed(8);
+        let room =
+            std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/scenes/cornell.scene"))
+                .unwrap();
+        with_view(&room, |view| {
+            let (origin, direction) = (Vec3::new(2.5, 2.5, -2.0), Vec3::new(0.0, -1.0, 2.0));
+            let mean_of = |roulette_depth: u32| {
+                let path = PathTracer::new(50).with_roulette_depth(roulette_depth);
+                Counters::take();
+                let mean = mean(&path, view, origin, direction, 100_000);
+                (mean, Counters::take().secondary_rays)
+            };
+            let (reference, full_length) = mean_of(u32::MAX);
+            for roulette_depth in [0, 3] {
+                let (mean, rays) = mean_of(roulette_depth);
+                assert!(
+                    (mean - reference).length() < 0.03 * reference.length(),
+                    "{:?} against {:?}",
+                    mean,
+                    reference
+                );
+                assert!(rays < full_length * 3 / 4, "{} of {}", rays, full_length);
+            }
+        });
+    }
+
+    #[test]
+    fn long_paths_need_no_stack() {
+        // a ray bouncing straight between two facing mirrors until the depth runs out
+        let mirrors = "background 1 1 1\n\
+                       material mirror metal 1 1 1 0\n\
+                       plane 0 0 0  0 1 0  mirror\n\
+                       plane 0 1 0  0 -1 0  mirror\n";
+        with_view(mirrors, |view| {
+            let depth = 200_000;
+            let path = PathTracer::new(depth).with_roulette_depth(u32::MAX);
+            let r = Ray::new(Vec3::new(0.0, 0.5, 0.0), Vec3::new(0.0, -1.0, 0.0));
+            Counters::take();
+            assert_eq!(path.radiance(&r, view), Vec3::new(0.0, 0.0, 0.0));
+            assert_eq!(Counters::take().secondary_rays, depth as u64);
+        });
+    }
+
+    #[test]
+    fn lobes_are_limited_separately() {
+        misc::seed(9);
+        let scene = "background 1 1 1\n\
+                     material white lambertian 0.5 0.5 0.5\n\
+                     material glass dielectric 1.5\n\
+                     sphere 0 0 0 1 glass\n\
+                     plane 0 -5 0  0 1 0  white\n";
+        with_view(scene, |view| {
+            let at = |limits: BounceLimits, target: Vec3| {
+                let path = PathTracer::new(50).with_bounces(limits);
+                let origin = Vec3::new(0.0, 0.0, 5.0);
+                mean(&path, view, origin, target - origin, 2000)
+            };
+            let (ball, floor) = (Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, -5.0, -5.0));
+            let unlimited = BounceLimits::default();
+            let opaque = BounceLimits {
+                transmission: 0,
+                ..unlimited
+            };
+            let black = BounceLimits {
+                specular: 0,
+                ..opaque
+            };
+            // glass that may neither reflect nor refract shows nothing
+            assert_eq!(at(black, ball), Vec3::new(0.0, 0.0, 0.0));
+            // without transmission only the few reflections are left
+            let (through, reflected) = (at(unlimited, ball), at(opaque, ball));
+            assert!(reflected.x > 0.0 && reflected.x < 0.3 * through.x);
+            // the floor lit by the background takes one diffuse bounce
+            let diffuse = |n| BounceLimits {
+                diffuse: n,
+                ..unlimited
+            };
+            assert_eq!(at(diffuse(0), floor), Vec3::new(0.0, 0.0, 0.0));
+            assert!(at(diffuse(1), floor).x > 0.3);
+        });
+    }
+
+    #[test]
+    fn ambient_occlusion_darkens_creases() {
+        misc::seed(4);
+        let scene = "material white lambertian 0.8 0.8 0.8\n\
+                     plane 0 0 0  0 1 0  white\n\
+                     box -1 0 -1  1 0.5 1  white\n";
+        with_view(scene, |view| {
+            let down = |x: f32| Ray::new(Vec3::new(x, 3.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+            let ao = AmbientOcclusion { distance: 0.25 };
+            let white = Vec3::new(1.0, 1.0, 1.0);
+            // the top of the box and the open floor see the whole sky within reach
+            assert!((0..100).all(|_| ao.radiance(&down(0.0), view) == white));
+            assert!((0..100).all(|_| ao.radiance(&down(3.0), view) == white));
+            // right by the box a good part of the hemisphere is blocked
+            let n = 4000;
+            let seen = (0..n)
+                .map(|_| ao.radiance(&down(1.01), view).x)
+                .sum::<f32>()
+                / n as f32;
+            assert!((0.3..0.9).contains(&seen), "{}", seen);
+        });
+    }
+
+    #[test]
+    fn debug_views_show_the_hit() {
+        let scene = "material red lambertian 0.9 0.1 0.1\n\
+                     material blue lambertian 0.1 0.1 0.9\n\
+                     sphere 0 0 -5 1 blue\n\
+                     sphere 0 0 0 1 red\n";
+        with_view(scene, |view| {
+            let towards = Ray::new(Vec3::new(0.0, 0.0, 3.0), Vec3::new(0.0, 0.0, -2.0));
+            let away = Ray::new(Vec3::new(0.0, 0.0, 3.0), Vec3::new(0.0, 0.0, 1.0));
+
+            let normal = DebugView::Normals.radiance(&towards, view);
+            assert!((normal - Vec3::new(0.5, 0.5, 1.0)).length() < 1e-4);
+            let depth = DebugView::Depth { far: 4.0 }.radiance(&towards, view);
+            assert!((depth.x - 0.5).abs() < 1e-4);
+            // ids follow the order the materials are defined in, not the objects
+            assert_eq!(DebugView::MaterialIds.radiance(&towards, view), palette(0));
+            assert!((palette(0) - palette(1)).length() > 0.1);
+            for debug in [
+                DebugView::Normals,
+                DebugView::Uvs,
+                DebugView::Depth { far: 1.0 },
+                DebugView::Barycentrics,
+                DebugView::MaterialIds,
+            ] {
+                assert_eq!(debug.radiance(&away, view), Vec3::new(0.0, 0.0, 0.0));
+            }
+        });
+    }
+
+    #[test]
+    fn integrators_parse_by_name() {
+        assert_eq!("path".parse(), Ok(IntegratorKind::Path));
+        assert_eq!("whitted".parse(), Ok(IntegratorKind::Whitted));
+        assert_eq!(
+            "ao".parse(),
+            Ok(IntegratorKind::AmbientOcclusion { distance: f32::MAX })
+        );
+        assert_eq!(
+            "depth:8".parse(),
+            Ok(IntegratorKind::Debug(DebugView::Depth { far: 8.0 }))
+        );
+        assert_eq!(
+            IntegratorKind::new("material_ids", None),
+            Ok(IntegratorKind::Debug(DebugView::MaterialIds))
+        );
+        assert_eq!("bdpt".parse(), Ok(IntegratorKind::Bidirectional));
+        assert_eq!(
+            "photons".parse(),
+            Ok(IntegratorKind::Photons { radius: None })
+        );
+        assert_eq!(
+            "sppm:0.25".parse(),
+            Ok(IntegratorKind::ProgressivePhotons { radius: Some(0.25) })
+        );
+        for bad in [
+            "radiosity",
+            "sppm:0",
+            "path:3",
+            "ao:0",
+            "ao:far",
+            "depth:-1",
+        ] {
+            assert!(bad.parse::<IntegratorKind>().is_err(), "{}", bad);
+        }
+    }
+}
@@ -0,0 +1,503 @@
+use crate::camera::{Camera, Frame};
+use crate::material::refract;
+use crate::misc::random;
+use crate::ray::Ray;
+use crate::scene::SceneError;
+use crate::vec3::Vec3;
+
+/// Lens tables are written in millimetres while scene units are taken to be metres.
+const MILLIMETRE: f32 = 0.001;
+
+/// One surface of a lens prescription, or the aperture stop when `radius` is 0.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct LensElement {
+    /// Signed radius of curvature; positive when the center lies toward the film.
+    pub radius: f32,
+    /// Distance along the axis to the next surface, or to the film for the rear one.
+    pub thickness: f32,
+    /// Index of refraction of the medium between this surface and the next.
+    pub ior: f32,
+    pub aperture_radius: f32,
+}
+
+/// Lens prescription with surfaces ordered from the front (scene side) to the rear.
+///
+/// Rays are traced in camera space: the film lies in the `z = 0` plane and the lens extends
+/// toward `-z`, where the scene is.
+#[derive(Clone, PartialEq, Debug)]
+pub struct LensSystem {
+    pub elements: Vec<LensElement>,
+}
+
+impl LensSystem {
+    pub fn load(path: &str) -> Result<LensSystem, Box<dyn std::error::Error>> {
+        let text = std::fs::read_to_string(path)?;
+        Ok(LensSystem::parse(&text)?)
+    }
+
+    /// Parses a table with one surface per line: radius, thickness, index of refraction and
+    /// aperture diameter, all lengths in millimetres. A radius of 0 marks the aperture stop and
+    /// an index of 0 stands for air, as in many published tables. `#` starts a comment.
+    pub fn parse(text: &str) -> Result<LensSystem, SceneError> {
+        let mut elements = vec![];
+        for (index, raw) in text.lines().enumerate() {
+            let error = |message: String| SceneError {
+                line: index + 1,
+                message,
+            };
+            let line = raw.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let values = line
+                .split_whitespace()
+                .map(|word| match word.parse::<f32>() {
+                    Ok(f) if f.is_finite() => Ok(f),
+                    _ => Err(error(format!("expected a number but got `{}`", word))),
+                })
+                .collect::<Result<Vec<f32>, SceneError>>()?;
+            if values.len() != 4 {
+                return Err(error(format!(
+                    "a lens surface takes 4 columns (radius, thickness, ior, aperture) but {} were given",
+                    values.len()
+                )));
+            }
+            let (radius, thickness, ior, aperture) = (values[0], values[1], values[2], values[3]);
+            if thickness < 0.0 {
+                return Err(error("thickness must not be negative".to_string()));
+            }
+            if aperture <= 0.0 {
+                return Err(error("aperture must be positive".to_string()));
+            }
+            if ior != 0.0 && ior < 1.0 {
+                return Err(error(format!("invalid index of refraction {}", ior)));
+            }
+            elements.push(LensElement {
+                radius: radius * MILLIMETRE,
+                thickness: thickness * MILLIMETRE,
+                ior: if ior == 0.0 { 1.0 } else { ior },
+                aperture_radius: aperture / 2.0 * MILLIMETRE,
+            });
+        }
+        if elements.is_empty() {
+            return Err(SceneError {
+                line: text.lines().count(),
+                message: "lens table has no surfaces".to_string(),
+            });
+        }
+        Ok(LensSystem { elements })
+    }
+
+    /// Distance from the rear surface to the film.
+    pub fn back_focus(&self) -> f32 {
+        self.elements[self.elements.len() - 1].thickness
+    }
+
+    fn rear(&self) -> &LensElement {
+        &self.elements[self.elements.len() - 1]
+    }
+
+    /// Axial position of each surface's vertex.
+    fn vertices(&self) -> Vec<f32> {
+        let mut z = 0.0;
+        let mut vertices: Vec<f32> = self
+            .elements
+            .iter()
+            .rev()
+            .map(|e| {
+                z -= e.thickness;
+                z
+            })
+            .collect();
+        vertices.reverse();
+        vertices
+    }
+
+    /// Refracts through surface `i` at vertex `z`, from the medium with index `eta_i` into the
+    /// one with `eta_t`. `None` when the ray misses the surface, is clipped by its aperture or
+    /// is totally internally reflected.
+    fn interface(
+        &self,
+        i: usize,
+        z: f32,
+        origin: Vec3,
+        dir: Vec3,
+        eta_i: f32,
+        eta_t: f32,
+    ) -> Option<(Vec3, Vec3)> {
+        let element = &self.elements[i];
+        let t = if element.radius == 0.0 {
+            (z - origin.z) / dir.z
+        } else {
+            let center = Vec3::new(0.0, 0.0, z + element.radius);
+            let oc = origin - center;
+            let a = dir.dot(&dir);
+            let half_b = oc.dot(&dir);
+            let c = oc.dot(&oc) - element.radius * element.radius;
+            let discriminant = half_b * half_b - a * c;
+            if discriminant < 0.0 {
+                return None;
+            }
+            let root = discriminant.sqrt();
+            // the vertex side of the sphere is the near one when the curvature faces the ray
+            let near = (dir.z > 0.0) ^ (element.radius < 0.0);
+            if near {
+                (-half_b - root) / a
+            } else {
+                (-half_b + root) / a
+            }
+        };
+        if t.is_nan() || t <= 0.0 {
+            return None;
+        }
+        let p = origin + t * dir;
+        if p.x * p.x + p.y * p.y > element.aperture_radius * element.aperture_radius {
+            return None;
+        }
+        if element.radius == 0.0 {
+            return Some((p, dir));
+        }
+        let mut normal = (p - Vec3::new(0.0, 0.0, z + element.radius)).unit_vector();
+        if normal.dot(&dir) > 0.0 {
+            normal = -normal;
+        }
+        Some((p, refract(&dir, &normal, eta_i / eta_t)?))
+    }
+
+    /// Follows a ray leaving the film through every surface; returns where it exits the front
+    /// element and its direction there.
+    pub fn trace_from_film(&self, origin: Vec3, dir: Vec3) -> Option<(Vec3, Vec3)> {
+        let vertices = self.vertices();
+        let (mut origin, mut dir) = (origin, dir);
+        for i in (0..self.elements.len()).rev() {
+            let outside = if i == 0 {
+                1.0
+            } else {
+                self.elements[i - 1].ior
+            };
+            let (p, d) =
+                self.interface(i, vertices[i], origin, dir, self.elements[i].ior, outside)?;
+            origin = p;
+            dir = d;
+        }
+        Some((origin, dir))
+    }
+
+    /// Follows a ray from the scene through every surface toward the film.
+    pub fn trace_from_scene(&self, origin: Vec3, dir: Vec3) -> Option<(Vec3, Vec3)> {
+        let vertices = self.vertices();
+        let (mut origin, mut dir) = (origin, dir);
+        for (i, &z) in vertices.iter().enumerate() {
+            let outside = if i == 0 {
+                1.0
+            } else {
+                self.elements[i - 1].ior
+            };
+            let (p, d) = self.interface(i, z, origin, dir, outside, self.elements[i].ior)?;
+            origin = p;
+            dir = d;
+        }
+        Some((origin, dir))
+    }
+
+    /// Axial position where a paraxial ray from the on-axis point at `z` comes to a focus
+    /// behind the lens.
+    fn paraxial_image(&self, z: f32) -> Option<f32> {
+        let front = &self.elements[0];
+        let front_z = self.vertices()[0];
+        let target = Vec3::new(0.01 * front.aperture_radius, 0.0, front_z);
+        let dir = (target - Vec3::new(0.0, 0.0, z)).unit_vector();
+        // start just ahead of the lens; a far away origin loses all precision in f32
+        let origin = target - front.aperture_radius * dir;
+        let (p, d) = self.trace_from_scene(origin, dir)?;
+        if d.x >= 0.0 {
+            // diverging: the image is virtual
+            return None;
+        }
+        Some(p.z - p.x / d.x * d.z)
+    }
+
+    /// Moves the lens so that a subject `distance` in front of the film is in focus, the way
+    /// unit focusing does; the stretched back focus is what makes the field of view breathe.
+    /// `None` when the lens cannot focus that close.
+    pub fn autofocus(&self, distance: f32) -> Option<LensSystem> {
+        let mut lens = self.clone();
+        // the image moves by the longitudinal magnification for every step the lens takes,
+        // which is tiny unless the subject is within a few focal lengths
+        for _ in 0..64 {
+            let image = lens.paraxial_image(-distance)?;
+            let back = lens.back_focus() + image;
+            if back.is_nan() || back <= 0.0 {
+                return None;
+            }
+            let n = lens.elements.len();
+            lens.elements[n - 1].thickness = back;
+            if image.abs() < 1e-4 * back {
+                return Some(lens);
+            }
+        }
+        None
+    }
+}
+
+/// Radial bins over the film for which the exit pupil's position is precomputed.
+const PUPIL_BINS: usize = 32;
+/// Rays per side of the grid used to find the exit pupil.
+const PUPIL_GRID: usize = 48;
+
+/// Camera that traces primary rays through a multi-element lens prescription, so distortion,
+/// vignetting and focus breathing come out of the optics rather than being faked.
+pub struct RealisticCamera {
+    origin: Vec3,
+    frame: Frame,
+    lens: LensSystem,
+    film_width: f32,
+    film_height: f32,
+    /// Radius of the on-axis exit pupil on the rear element's plane.
+    pupil_radius: f32,
+    /// Offset of the exit pupil's center away from the axis, per radial bin of the film.
+    pupil_centers: Vec<f32>,
+}
+
+impl RealisticCamera {
+    /// `look_from` is the center of the film; `film_diagonal` is in millimetres, e.g. 43.27
+    /// for a full-frame 35mm sensor. The lens should already be focused with
+    /// `LensSystem::autofocus`.
+    pub fn new(
+        look_from: Vec3,
+        look_at: Vec3,
+        view_up: Vec3,
+        lens: LensSystem,
+        film_diagonal: f32,
+        aspect: f32,
+    ) -> RealisticCamera {
+        let diagonal = film_diagonal * MILLIMETRE;
+        let film_height = diagonal / (1.0 + aspect * aspect).sqrt();
+        let mut camera = RealisticCamera {
+            origin: look_from,
+            frame: Frame::new(look_from, look_at, view_up),
+            lens,
+            film_width: aspect * film_height,
+            film_height,
+            pupil_radius: 0.0,
+            pupil_centers: vec![],
+        };
+        camera.find_exit_pupil();
+        camera
+    }
+
+    /// Fires a grid of rays from points along the film's x axis toward the rear element and
+    /// records where the ones that make it through the whole lens pass.
+    fn find_exit_pupil(&mut self) {
+        let rear = self.lens.rear().aperture_radius;
+        let rear_z = -self.lens.back_focus();
+        let cell = 2.0 * rear / PUPIL_GRID as f32;
+        let passing = |x: f32| -> Vec<(f32, f32)> {
+            let film = Vec3::new(x, 0.0, 0.0);
+            let mut points = vec![];
+            for j in 0..PUPIL_GRID {
+                for i in 0..PUPIL_GRID {
+                    let px = -rear + (i as f32 + 0.5) * cell;
+                    let py = -rear + (j as f32 + 0.5) * cell;
+                    let target = Vec3::new(px, py, rear_z);
+                    if self.lens.trace_from_film(film, target - film).is_some() {
+                        points.push((px, py));
+                    }
+                }
+            }
+            points
+        };
+        let on_axis = passing(0.0);
+        let pupil_radius = if on_axis.is_empty() {
+            rear
+        } else {
+            (on_axis.len() as f32 * cell * cell / std::f32::consts::PI).sqrt()
+        };
+        let film_radius = self.film_radius();
+        let mut center = 0.0;
+        let pupil_centers = (0..PUPIL_BINS)
+            .map(|k| {
+                let points = passing((k as f32 + 0.5) / PUPIL_BINS as f32 * film_radius);
+                if !points.is_empty() {
+                    let min = points.iter().fold(f32::MAX, |m, p| m.min(p.0));
+                    let max = points.iter().fold(f32::MIN, |m, p| m.max(p.0));
+                    center = (min + max) / 2.0;
+                }
+                center
+            })
+            .collect();
+        self.pupil_radius = pupil_radius;
+        self.pupil_centers = pupil_centers;
+    }
+
+    fn film_radius(&self) -> f32 {
+        (self.film_width * self.film_width + self.film_height * self.film_height).sqrt() / 2.0
+    }
+
+    /// Film position seen at `(s, t)`; the lens forms an inverted image.
+    fn film_point(&self, s: f32, t: f32) -> Vec3 {
+        Vec3::new(
+            -(s - 0.5) * self.film_width,
+            -(t - 0.5) * self.film_height,
+            0.0,
+        )
+    }
+
+    fn pupil_center(&self, film: &Vec3) -> (f32, f32) {
+        let r = (film.x * film.x + film.y * film.y).sqrt();
+        if r == 0.0 {
+            return (0.0, 0.0);
+        }
+        let bin = ((r / self.film_radius() * PUPIL_BINS as f32) as usize).min(PUPIL_BINS - 1);
+        let c = self.pupil_centers[bin];
+        (c * film.x / r, c * film.y / r)
+    }
+
+    fn to_world(&self, (origin, dir): (Vec3, Vec3)) -> Ray {
+        Ray::new(
+            self.origin + self.frame.to_world(origin.x, origin.y, origin.z),
+            self.frame.to_world(dir.x, dir.y, dir.z),
+        )
+    }
+}
+
+impl Camera for RealisticCamera {
+    fn get_ray(&self, s: f32, t: f32) -> Option<Ray> {
+        let film = self.film_point(s, t);
+        let (cx, cy) = self.pupil_center(&film);
+        let (x, y) = loop {
+            let (x, y) = (2.0 * random() - 1.0, 2.0 * random() - 1.0);
+            if x * x + y * y <= 1.0 {
+                break (x, y);
+            }
+        };
+        let target = Vec3::new(
+            cx + self.pupil_radius * x,
+            cy + self.pupil_radius * y,
+            -self.lens.back_focus(),
+        );
+        // rays blocked inside the barrel are the vignetting
+        self.lens
+            .trace_from_film(film, target - film)
+            .map(|exit| self.to_world(exit))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOUBLE_GAUSS: &str = include_str!("../lenses/dgauss50.lens");
+
+    fn double_gauss() -> LensSystem {
+        LensSystem::parse(DOUBLE_GAUSS).unwrap()
+    }
+
+    fn camera(lens: &LensSystem, focus: f32) -> RealisticCamera {
+        RealisticCamera::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            lens.autofocus(focus).unwrap(),
+            43.27,
+            1.5,
+        )
+    }
+
+    /// Tangent of the angle to the axis of the ray through the middle of the exit pupil.
+    fn chief_slope(camera: &RealisticCamera, s: f32) -> f32 {
+        let film = camera.film_point(s, 0.5);
+        let (cx, cy) = camera.pupil_center(&film);
+        let target = Vec3::new(cx, cy, -camera.lens.back_focus());
+        let (_, d) = camera.lens.trace_from_film(film, target - film).unwrap();
+        d.x / -d.z
+    }
+
+    #[test]
+    fn parses_tables() {
+        let lens = double_gauss();
+        assert_eq!(lens.elements.len(), 11);
+        let stop = lens.elements[5];
+        assert_eq!(stop.radius, 0.0);
+        assert_eq!(stop.ior, 1.0);
+        assert!((stop.aperture_radius - 0.00855).abs() < 1e-7);
+
+        assert_eq!(LensSystem::parse("10 2 1.5").unwrap_err().line, 1);
+        assert_eq!(
+            LensSystem::parse("# only a comment\n10 2 x 5")
+                .unwrap_err()
+                .line,
+            2
+        );
+        assert!(LensSystem::parse("10 -2 1.5 5").is_err());
+        assert!(LensSystem::parse("10 2 0.5 5").is_err());
+        assert!(LensSystem::parse("").is_err());
+    }
+
+    #[test]
+    fn focuses_at_the_requested_distance() {
+        for &distance in &[0.8, 1.5] {
+            let camera = camera(&double_gauss(), distance);
+            // spread of the rays from the film center across planes around the subject
+            let spread = |z: f32| {
+                let mut max = 0.0f32;
+                for _ in 0..200 {
+                    if let Some(r) = camera.get_ray(0.5, 0.5) {
+                        let t = (z - r.origin().z) / r.direction().z;
+                        let p = r.point_at_parameter(t);
+                        max = max.max((p.x * p.x + p.y * p.y).sqrt());
+                    }
+                }
+                max
+            };
+            let sharp = spread(-distance);
+            assert!(sharp < 0.3 * spread(-distance * 0.7));
+            assert!(sharp < 0.3 * spread(-distance * 1.5));
+        }
+        // inside the focal length the image would be virtual
+        assert!(double_gauss().autofocus(0.03).is_none());
+    }
+
+    #[test]
+    fn focus_breathes() {
+        let lens = double_gauss();
+        let near = camera(&lens, 0.6);
+        let far = camera(&lens, 50.0);
+        assert!(near.lens.back_focus() > far.lens.back_focus());
+        // focusing closer pushes the lens out and narrows the view
+        assert!(chief_slope(&near, 1.0) < 0.97 * chief_slope(&far, 1.0));
+    }
+
+    #[test]
+    fn image_is_upright_and_distorted() {
+        let camera = camera(&double_gauss(), 10.0);
+        // right of the image looks right, top looks up
+        assert!(chief_slope(&camera, 1.0) > 0.0);
+        let up = (0..100).find_map(|_| camera.get_ray(0.5, 1.0)).unwrap();
+        assert!(up.direction().y > 0.0);
+
+        // with the stop ahead of a single positive element, magnification falls off with
+        // field: barrel distortion
+        let landscape = LensSystem::parse("0 15 1 6\n1e6 4 1.5 24\n-25 45 1 24").unwrap();
+        let camera = RealisticCamera::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            landscape.autofocus(100.0).unwrap(),
+            43.27,
+            1.5,
+        );
+        let ratio = chief_slope(&camera, 1.0) / chief_slope(&camera, 0.75);
+        assert!(ratio > 2.0 * 1.02, "{}", ratio);
+    }
+
+    #[test]
+    fn corners_are_vignetted() {
+        let camera = camera(&double_gauss(), 10.0);
+        let through = |s: f32, t: f32| (0..2000).filter(|_| camera.get_ray(s, t).is_some()).count();
+        let center = through(0.5, 0.5);
+        let corner = through(1.0, 1.0);
+        assert!(center > 1800);
+        assert!(corner < center * 3 / 4);
+    }
+}
@@ -1,7 +1,32 @@
+pub mod aabb;
+pub mod animation;
+pub mod aperture;
+pub mod bdpt;
+pub mod bvh;
 pub mod camera;
+pub mod cli;
 pub mod color;
+pub mod compare;
+pub mod csg;
+pub mod film;
 pub mod hitable;
+pub mod image;
+pub mod inflate;
+pub mod integrator;
+pub mod lens;
+pub mod light;
 pub mod material;
 pub mod misc;
+pub mod mlt;
+pub mod photon;
+pub mod preview;
 pub mod ray;
+pub mod render;
+pub mod scene;
+pub mod sdf;
+pub mod shapes;
+pub mod stats;
+pub mod terminal;
+pub mod transform;
 pub mod vec3;
+pub mod video;
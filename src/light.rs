@@ -0,0 +1,247 @@
+use crate::aabb::Aabb;
+use crate::hitable::{HitRecord, Hitable};
+use crate::misc::random;
+use crate::ray::Ray;
+use crate::stats::{count, Counter};
+use crate::vec3::{orthonormal_basis, random_unit_vector, Vec3};
+use std::f32::consts::PI;
+use std::rc::Rc;
+
+/// What rays see once they leave the scene.
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub enum Background {
+    /// White at the horizon and below, blending into light blue overhead.
+    #[default]
+    Sky,
+    /// The same radiance in every direction; black for scenes lit only by their lights.
+    Uniform(Vec3),
+}
+
+impl Background {
+    pub fn radiance(&self, direction: &Vec3) -> Vec3 {
+        match *self {
+            Background::Sky => {
+                let ud = direction.unit_vector();
+                let t = 0.5 * (ud.y + 1.0);
+                (1.0 - t) * Vec3::from_i(1, 1, 1) + t * Vec3::new(0.5, 0.7, 1.0)
+            }
+            Background::Uniform(radiance) => radiance,
+        }
+    }
+
+    /// Whether it gives off any light, so that paths of light may start there.
+    pub fn shines(&self) -> bool {
+        *self != Background::Uniform(Vec3::new(0.0, 0.0, 0.0))
+    }
+}
+
+/// Light arriving at a point from one point on the lights.
+#[derive(Copy, Clone, Debug)]
+pub struct LightSample {
+    /// Unit vector towards the light.
+    pub direction: Vec3,
+    pub radiance: Vec3,
+    /// Density of `direction` per unit solid angle, the choice of light included.
+    pub pdf: f32,
+}
+
+/// Picks one of `lights` and a point on it uniformly by area, then traces a shadow ray to it
+/// from `p` through `world`. `None` when there are no lights, the point is seen edge-on or
+/// something stands in between.
+pub fn sample_light(
+    lights: &[Rc<dyn Hitable>],
+    world: &dyn Hitable,
+    p: &Vec3,
+) -> Option<LightSample> {
+    if lights.is_empty() {
+        return None;
+    }
+    let light = &lights[((random() * lights.len() as f32) as usize).min(lights.len() - 1)];
+    let (area, (q, normal)) = (light.area()?, light.sample_surface()?);
+    let to_light = q - p;
+    let distance_squared = to_light.squared_length();
+    let direction = to_light.unit_vector();
+    let cosine = normal.dot(&direction).abs();
+    if cosine < 1e-6 || distance_squared < 1e-12 {
+        return None;
+    }
+    // the shadow ray spans the way to the light as t goes from 0 to 1
+    let shadow = Ray::new(*p, to_light);
+    count(Counter::ShadowRays);
+    if world.hit(&shadow, 0.0001, 1.0 - 1e-3).is_some() {
+        return None;
+    }
+    let record = light.hit(&shadow, 1.0 - 1e-3, 1.0 + 1e-3)?;
+    Some(LightSample {
+        direction,
+        radiance: record.material.emitted(&record),
+        pdf: distance_squared / (cosine * area) / lights.len() as f32,
+    })
+}
+
+/// Where a path of light starts, as sampled by [`emit`].
+pub struct Emission {
+    /// Leaves the source in a unit direction.
+    pub ray: Ray,
+    pub radiance: Vec3,
+    /// Outward normal of the light at the origin; for the background, the unit direction
+    /// the light comes from.
+    pub normal: Vec3,
+    /// The light the ray leaves, `None` for the background.
+    pub light: Option<usize>,
+    /// The light's surface at the origin.
+    pub record: Option<HitRecord>,
+    /// Density of choosing the source.
+    pub pdf_choice: f32,
+    /// Density of the origin per unit area, across the beam for the background.
+    pub pdf_pos: f32,
+    /// Density of the direction per unit solid angle.
+    pub pdf_dir: f32,
+}
+
+impl Emission {
+    /// The power the ray carries over the density of sampling it.
+    pub fn power(&self) -> Vec3 {
+        let cosine = match self.light {
+            Some(_) => self.normal.dot(self.ray.direction()).abs(),
+            None => 1.0,
+        };
+        self.radiance * (cosine / (self.pdf_choice * self.pdf_pos * self.pdf_dir))
+    }
+}
+
+/// The sphere around `bounds` that light from the background is sent across.
+pub fn bounding_sphere(bounds: &Aabb) -> (Vec3, f32) {
+    let center = bounds.centroid();
+    (center, (bounds.max - center).length().max(1e-3))
+}
+
+/// Starts a path of light on one of `lights` or, when it shines, the `background`, each
+/// chosen with the same probability. Lights shine from both sides, cosine-distributed; the
+/// background sends a parallel beam from a uniform direction across a disk as wide as the
+/// bounding sphere of `bounds`, one radius behind its center. `None` when there is nothing
+/// to start from or the sample missed.
+pub fn emit(
+    lights: &[Rc<dyn Hitable>],
+    background: &Background,
+    bounds: Option<Aabb>,
+) -> Option<Emission> {
+    let count = lights.len() + background.shines() as usize;
+    if count == 0 {
+        return None;
+    }
+    let pdf_choice = 1.0 / count as f32;
+    let i = ((random() * count as f32) as usize).min(count - 1);
+    if i == lights.len() {
+        let (center, radius) = bounding_sphere(&bounds?);
+        let direction = random_unit_vector();
+        let (a, b) = orthonormal_basis(&direction);
+        let (x, y) = loop {
+            let (x, y) = (2.0 * random() - 1.0, 2.0 * random() - 1.0);
+            if x * x + y * y <= 1.0 {
+                break (x, y);
+            }
+        };
+        let origin = center + radius * (x * a + y * b) - radius * direction;
+        return Some(Emission {
+            ray: Ray::new(origin, direction),
+            radiance: background.radiance(&-direction),
+            normal: -direction,
+            light: None,
+            record: None,
+            pdf_choice,
+            pdf_pos: 1.0 / (PI * radius * radius),
+            pdf_dir: 1.0 / (4.0 * PI),
+        });
+    }
+    let light = &lights[i];
+    let (area, (q, normal)) = (light.area()?, light.sample_surface()?);
+    let record = light.hit(&Ray::new(q + normal, -normal), 1.0 - 1e-3, 1.0 + 1e-3)?;
+    let side = if random() < 0.5 { normal } else { -normal };
+    let mut direction = side + random_unit_vector();
+    if direction.squared_length() < 1e-12 {
+        direction = side;
+    }
+    let direction = direction.unit_vector();
+    Some(Emission {
+        ray: Ray::new(q, direction),
+        radiance: record.material.emitted(&record),
+        normal,
+        light: Some(i),
+        record: Some(record),
+        pdf_choice,
+        pdf_pos: 1.0 / area,
+        pdf_dir: direction.dot(&normal).abs() / (2.0 * PI),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hitable::{HitableList, Sphere};
+    use crate::material::{DiffuseLight, Lambertian, Material};
+    use crate::misc;
+    use crate::shapes::Quad;
+
+    fn material(m: impl Material + 'static) -> Rc<Box<dyn Material>> {
+        Rc::new(Box::new(m))
+    }
+
+    #[test]
+    fn light_samples_estimate_the_irradiance() {
+        // a unit square light two units above a point, facing it
+        misc::seed(5);
+        let lamp: Rc<dyn Hitable> = Rc::new(Quad::new(
+            (-0.5, 2.0, -0.5).into(),
+            (1, 0, 0).into(),
+            (0, 0, 1).into(),
+            material(DiffuseLight::new((3, 3, 3).into())),
+        ));
+        let world = HitableList::new(vec![Box::new(lamp.clone())]);
+        let lights = [lamp];
+        let p = Vec3::new(0.0, 0.0, 0.0);
+        let n = 20000;
+        let mut irradiance = 0.0;
+        for _ in 0..n {
+            let sample = sample_light(&lights, &world, &p).expect("nothing is in the way");
+            irradiance += sample.radiance.x * sample.direction.y / sample.pdf;
+        }
+        irradiance /= n as f32;
+        // E = L * integral of cos(theta) over the square, by numeric quadrature
+        let steps = 400;
+        let mut expected = 0.0;
+        for i in 0..steps {
+            for j in 0..steps {
+                let x = -0.5 + (i as f32 + 0.5) / steps as f32;
+                let z = -0.5 + (j as f32 + 0.5) / steps as f32;
+                let d2 = x * x + 4.0 + z * z;
+                expected += 3.0 * 4.0 / (d2 * d2) / (steps * steps) as f32;
+            }
+        }
+        assert!(
+            (irradiance - expected).abs() < 0.01 * expected,
+            "{} against {}",
+            irradiance,
+            expected
+        );
+    }
+
+    #[test]
+    fn blocked_lights_give_nothing() {
+        let lamp: Rc<dyn Hitable> = Rc::new(Sphere {
+            center: (0, 3, 0).into(),
+            radius: 0.5,
+            material: material(DiffuseLight::new((1, 1, 1).into())),
+        });
+        let blocker = Sphere {
+            center: Vec3::new(0.0, 1.5, 0.0),
+            radius: 1.0,
+            material: material(Lambertian::new((128, 128, 128).into())),
+        };
+        let world = HitableList::new(vec![Box::new(lamp.clone()), Box::new(blocker)]);
+        let lights = [lamp];
+        let p = Vec3::new(0.0, 0.0, 0.0);
+        assert!((0..100).all(|_| sample_light(&lights, &world, &p).is_none()));
+        assert!(sample_light(&[], &world, &p).is_none());
+    }
+}
@@ -1,161 +1,614 @@
-use ray_tracing_001::camera::Camera;
+use ray_tracing_001::animation::{frame_path, frame_seed};
+use ray_tracing_001::cli::{
+    BenchOptions, CameraOverrides, Command, CompareOptions, OutputFormat, PreviewOptions,
+    RenderOptions, BENCH_USAGE, COMPARE_USAGE, PREVIEW_USAGE, RENDER_USAGE,
+};
 use ray_tracing_001::color::Color;
-use ray_tracing_001::hitable::{Hitable, HitableList, Sphere};
-use ray_tracing_001::material::{Dielectric, Lambertian, Material, Metal};
-use ray_tracing_001::misc::random;
-use ray_tracing_001::ray::Ray;
+use ray_tracing_001::compare::{compare, load_pair};
+use ray_tracing_001::image::{encode_ppm, Image};
+use ray_tracing_001::integrator::IntegratorKind;
+use ray_tracing_001::misc;
+use ray_tracing_001::preview::Preview;
+use ray_tracing_001::render::Renderer;
+use ray_tracing_001::scene::Scene;
+use ray_tracing_001::stats::{self, Counters, Progress, Report};
+use ray_tracing_001::terminal::{self, Canvas};
 use ray_tracing_001::vec3::Vec3;
-use std::rc::Rc;
+use ray_tracing_001::video::{encode_png, ApngWriter, FrameSink, GifWriter, Y4mWriter};
 use std::env;
-use std::str::FromStr;
+use std::fs::File;
+use std::io::{self, BufWriter, IsTerminal, Write};
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::time::Instant;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        eprintln!("Usage {} <width>", args[0]);
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match Command::parse(&args) {
+        Err(e) => Err(format!("{}\n\n{}", e, usage_of(&args))),
+        Ok(Command::Help(text)) => {
+            println!("{}", text);
+            Ok(())
+        }
+        Ok(Command::Render(options)) => render_command(&options),
+        Ok(Command::Preview(options)) => preview(&options),
+        Ok(Command::Bench(options)) => bench(&options),
+        Ok(Command::Info { scene }) => info(scene.as_deref()),
+        Ok(Command::Compare(options)) => compare_images(&options).map(|passed| {
+            if !passed {
+                std::process::exit(2);
+            }
+        }),
+    };
+    if let Err(message) = result {
+        eprintln!("error: {}", message);
         std::process::exit(1);
     }
+}
+
+/// Short reminder printed after a command line that failed to parse.
+fn usage_of(args: &[String]) -> &'static str {
+    match args.first().map(String::as_str) {
+        Some("render") => RENDER_USAGE.lines().next().unwrap_or(""),
+        Some("preview") => PREVIEW_USAGE.lines().next().unwrap_or(""),
+        Some("bench") => BENCH_USAGE.lines().next().unwrap_or(""),
+        Some("compare") => COMPARE_USAGE.lines().next().unwrap_or(""),
+        _ => "Usage: ray_tracing_001 <render|preview|bench|info|compare> [options] [scene file]",
+    }
+}
+
+/// The scene at `frame` with the command line's camera overrides applied.
+fn load_scene(options: &RenderOptions, frame: u32) -> Result<Scene, String> {
+    // the scene is rebuilt from the same sequence every time so random spheres stay put
+    misc::seed(options.seed);
+    let mut scene = match &options.scene {
+        Some(path) => Scene::load_at(path, frame as f32).map_err(|e| format!("{}: {}", path, e))?,
+        None => Scene::random(),
+    };
+    options
+        .camera
+        .apply(&mut scene.camera)
+        .map_err(|e| e.to_string())?;
+    Ok(scene)
+}
 
-    let aspect_ratio = 16.0 / 9.0;
-    let width = u32::from_str(&args[1]).unwrap();
-    let height = ((width as f64) / aspect_ratio) as usize;
-
-    let sampling_num = 100usize;
-
-    let hitables = random_scene();
-    let world = HitableList::new(hitables);
-    // settings of camera
-    let look_from = (13, 2, 3).into();
-    let look_at: Vec3 = (0, 0, 0).into();
-    let aperture = 0.1;
-    let camera = Camera::new(
-        look_from,
-        look_at,
-        (0, 1, 0).into(),
-        20.0,
-        (width as f32) / (height as f32),
-        aperture,
-        10.0,
+fn render_command(options: &RenderOptions) -> Result<(), String> {
+    let scene = load_scene(options, 0)?;
+    let (width, height) = options
+        .resolution(scene.aspect)
+        .map_err(|e| e.to_string())?;
+    let path = options.output.as_deref();
+    let format = options
+        .format
+        .or_else(|| path.and_then(OutputFormat::of_path))
+        .unwrap_or(OutputFormat::P3);
+    let error = |e: io::Error| format!("{}: {}", path.unwrap_or("standard output"), e);
+    let count = scene.frames.map_or(1, |f| f.end - f.start + 1);
+    let progress = progress_for(
+        options,
+        (width * height * count as usize) as u64 * options.spp as u64,
     );
-    println!("P3\n{} {}\n255\n", width, height);
-    for y in (0..height).rev() {
-        for x in 0..width {
-            // samping for anti-aliasing
-            let mut temp_sum_color = Color { r: 0, g: 0, b: 0 };
-            for _ in 0..sampling_num {
-                let v = ((y as f32) + random()) / (height as f32);
-                let u = ((x as f32) + random()) / (width as f32);
-                let ray = &camera.get_ray(u, v);
-                temp_sum_color = temp_sum_color + color(&ray, &world, 0);
+    let start = Instant::now();
+    let finish = |counters: Counters| {
+        finish(
+            options,
+            &progress,
+            Report {
+                width,
+                height,
+                spp: options.spp,
+                frames: count,
+                threads: options.thread_count(),
+                seconds: start.elapsed().as_secs_f64(),
+                counters,
+                peak_memory: stats::peak_memory(),
+            },
+        )
+    };
+
+    let frames = match scene.frames {
+        None => {
+            let (image, counters) = render(
+                options,
+                scene.integrator,
+                0,
+                options.seed,
+                options.spp,
+                (width, height),
+                &progress,
+            )?;
+            let pixels = scene.film.develop(&image);
+            let mut out = create(path).map_err(error)?;
+            if format.is_animation() {
+                let mut sink = animation_sink(out, format, 1, 24.0);
+                sink.push(width, height, &pixels).map_err(error)?;
+                sink.finish().map_err(error)?;
+            } else {
+                write_still(&mut out, format, width, height, &pixels)
+                    .and_then(|_| out.flush())
+                    .map_err(error)?;
+            }
+            return finish(counters);
+        }
+        Some(frames) => frames,
+    };
+    let mut sink = match (format.is_animation(), path) {
+        (true, Some(pattern)) if pattern.contains('#') => {
+            return Err(format!(
+                "{}: numbered frames are written as p3, ppm or png",
+                pattern
+            ))
+        }
+        (true, _) => Some(animation_sink(
+            create(path).map_err(error)?,
+            format,
+            count,
+            frames.fps,
+        )),
+        (false, Some(_)) => None,
+        (false, None) => {
+            return Err(
+                "an animated scene needs an output path such as frame_####.png, or --format \
+                 y4m, gif or apng to stream it to standard output"
+                    .to_string(),
+            )
+        }
+    };
+    let mut counters = Counters::default();
+    for frame in frames.frames() {
+        let scene = load_scene(options, frame)?;
+        let seed = frame_seed(options.seed, frame);
+        let (image, frame_counters) = render(
+            options,
+            scene.integrator,
+            frame,
+            seed,
+            options.spp,
+            (width, height),
+            &progress,
+        )?;
+        counters = counters.add(&frame_counters);
+        let pixels = scene.film.develop(&image);
+        match sink.as_mut() {
+            Some(sink) => {
+                sink.push(width, height, &pixels).map_err(error)?;
+                if !options.quiet {
+                    progress.message(&format!("frame {}", frame));
+                }
+            }
+            None => {
+                let path = frame_path(path.unwrap_or_default(), frame);
+                let mut out = create(Some(&path)).map_err(|e| format!("{}: {}", path, e))?;
+                write_still(&mut out, format, width, height, &pixels)
+                    .and_then(|_| out.flush())
+                    .map_err(|e| format!("{}: {}", path, e))?;
+                if !options.quiet {
+                    progress.message(&format!("frame {} -> {}", frame, path));
+                }
             }
-            let color = (temp_sum_color / (sampling_num as f32)).hoge_gamma();
-            println!("{} {} {}", color.r, color.g, color.b);
         }
     }
+    if let Some(mut sink) = sink {
+        sink.finish().map_err(error)?;
+    }
+    finish(counters)
 }
 
-fn color(r: &Ray, world: &HitableList, depth: isize) -> Color {
-    let rec = world.hit(r, 0.0001, f32::MAX);
+fn progress_for(options: &RenderOptions, samples: u64) -> Progress {
+    Progress::new(samples, !options.quiet && io::stderr().is_terminal())
+}
 
-    // object
-    if rec.is_some() {
-        let temp_record = rec.unwrap();
-        let material = &temp_record.material;
-        let (is_scattered, attenuation, scattered) = material.scatter(r, &temp_record);
-        if depth < 50 && is_scattered {
-            return attenuation * color(&scattered, world, depth + 1);
+/// Closes the progress bar and reports the statistics as asked.
+fn finish(options: &RenderOptions, progress: &Progress, report: Report) -> Result<(), String> {
+    progress.finish();
+    if !options.quiet {
+        eprintln!("{}", report);
+    }
+    match options.stats_json.as_deref() {
+        None => Ok(()),
+        Some("-") => {
+            println!("{}", report.to_json());
+            Ok(())
+        }
+        Some(path) => {
+            std::fs::write(path, report.to_json() + "\n").map_err(|e| format!("{}: {}", path, e))
+        }
+    }
+}
+
+fn preview(options: &PreviewOptions) -> Result<(), String> {
+    match (options.terminal, &options.render.output) {
+        (true, _) => preview_passes(&options.render, true),
+        (false, Some(_)) => preview_passes(&options.render, false),
+        (false, None) => serve(options),
+    }
+}
+
+/// Samples per pixel in one pass of the browser preview at most, so that a moved camera
+/// shows up quickly.
+const SERVED_PASS: u32 = 4;
+
+/// Renders passes for the browser preview until the process is stopped, starting over
+/// whenever the camera moves.
+fn serve(options: &PreviewOptions) -> Result<(), String> {
+    let render_options = &options.render;
+    let frame = load_scene(render_options, 0)?.frames.map_or(0, |f| f.start);
+    let scene = load_scene(render_options, frame)?;
+    let size = render_options
+        .resolution(scene.aspect)
+        .map_err(|e| e.to_string())?;
+    let listener = TcpListener::bind(("127.0.0.1", options.port))
+        .map_err(|e| format!("port {}: {}", options.port, e))?;
+    let address = listener.local_addr().map_err(|e| e.to_string())?;
+    let integrator = scene.integrator;
+    let preview = Arc::new(Preview::new(scene.camera, render_options.spp, scene.film));
+    let server = preview.clone();
+    std::thread::spawn(move || server.serve(listener));
+    eprintln!("preview at http://{}/, stop with Ctrl-C", address);
+
+    let hidden = Progress::new(0, false);
+    let mut image = Image::new(size.0, size.1);
+    let (mut generation, mut done, mut pass) = (u64::MAX, 0, 0);
+    loop {
+        let (settings, current) = preview.next_job(generation, done);
+        if current != generation {
+            generation = current;
+            done = 0;
+            pass = 0;
+        }
+        let options = RenderOptions {
+            camera: CameraOverrides {
+                look_from: Some(settings.look_from),
+                look_at: Some(settings.look_at),
+                fov: Some(settings.fov),
+                aperture: Some(settings.aperture),
+                focus_dist: Some(settings.focus_dist),
+                ..render_options.camera.clone()
+            },
+            ..render_options.clone()
+        };
+        let spp = done.max(1).min(settings.spp - done).min(SERVED_PASS);
+        let seed = frame_seed(frame_seed(options.seed, frame), pass);
+        match render(&options, integrator, frame, seed, spp, size, &hidden) {
+            Ok((next, _)) => {
+                let weight = spp as f32 / (done + spp) as f32;
+                for (p, n) in image.pixels.iter_mut().zip(&next.pixels) {
+                    *p = (1.0 - weight) * *p + weight * *n;
+                }
+                done += spp;
+                pass += 1;
+                preview.publish(generation, done, image.clone());
+            }
+            Err(e) => preview.fail(generation, e),
+        }
+    }
+}
+
+/// Renders in passes of growing sample counts, drawing each into the terminal or rewriting
+/// the output after it so an image viewer that reloads it shows the picture refining.
+fn preview_passes(options: &RenderOptions, in_terminal: bool) -> Result<(), String> {
+    let scene = load_scene(options, 0)?;
+    let frame = scene.frames.map_or(0, |f| f.start);
+    let scene = load_scene(options, frame)?;
+    let size = options
+        .resolution(scene.aspect)
+        .map_err(|e| e.to_string())?;
+    let path = options.output.as_deref();
+    let format = options
+        .format
+        .or_else(|| path.and_then(OutputFormat::of_path))
+        .unwrap_or(OutputFormat::P3);
+    if format.is_animation() {
+        return Err("preview writes still images: p3, ppm or png".to_string());
+    }
+    let error = |e: io::Error| format!("{}: {}", path.unwrap_or("standard output"), e);
+
+    // the bar would scribble over the drawing
+    let progress = Progress::new(
+        (size.0 * size.1) as u64 * options.spp as u64,
+        !in_terminal && !options.quiet && io::stderr().is_terminal(),
+    );
+    let (columns, rows) = terminal::size().unwrap_or((80, 24));
+    let shrunk = terminal::fit(size.0, size.1, columns, rows.saturating_sub(2).max(1));
+    let mut canvas = Canvas::new();
+    let start = Instant::now();
+    let mut counters = Counters::default();
+    let mut image = Image::new(size.0, size.1);
+    let (mut done, mut pass) = (0, 0);
+    while done < options.spp {
+        let spp = done.max(1).min(options.spp - done);
+        let seed = frame_seed(frame_seed(options.seed, frame), pass);
+        let (next, pass_counters) =
+            render(options, scene.integrator, frame, seed, spp, size, &progress)?;
+        counters = counters.add(&pass_counters);
+        let weight = spp as f32 / (done + spp) as f32;
+        for (p, n) in image.pixels.iter_mut().zip(&next.pixels) {
+            *p = (1.0 - weight) * *p + weight * *n;
+        }
+        done += spp;
+        pass += 1;
+        if in_terminal {
+            let pixels = terminal::downsample(
+                &scene.film.develop(&image),
+                size.0,
+                size.1,
+                shrunk.0,
+                shrunk.1,
+            );
+            let status = format!("{} / {} samples per pixel", done, options.spp);
+            let mut out = io::stdout().lock();
+            out.write_all(
+                canvas
+                    .frame(&pixels, shrunk.0, shrunk.1, &status)
+                    .as_bytes(),
+            )
+            .and_then(|_| out.flush())
+            .map_err(|e| format!("standard output: {}", e))?;
+            continue;
+        }
+        if !options.quiet {
+            progress.message(&format!("pass {}: {} samples per pixel", pass, done));
+        }
+        if path.is_some() || done == options.spp {
+            let mut out = create(path).map_err(error)?;
+            write_still(
+                &mut out,
+                format,
+                size.0,
+                size.1,
+                &scene.film.develop(&image),
+            )
+            .and_then(|_| out.flush())
+            .map_err(error)?;
+        }
+    }
+    finish(
+        options,
+        &progress,
+        Report {
+            width: size.0,
+            height: size.1,
+            spp: options.spp,
+            frames: 1,
+            threads: options.thread_count(),
+            seconds: start.elapsed().as_secs_f64(),
+            counters,
+            peak_memory: stats::peak_memory(),
+        },
+    )
+}
+
+fn bench(options: &BenchOptions) -> Result<(), String> {
+    let render_options = &options.render;
+    let scene = load_scene(render_options, 0)?;
+    let size = render_options
+        .resolution(scene.aspect)
+        .map_err(|e| e.to_string())?;
+    let samples = (size.0 * size.1) as f64 * render_options.spp as f64;
+    let hidden = Progress::new(0, false);
+    println!(
+        "{}x{} at {} spp, {} threads",
+        size.0,
+        size.1,
+        render_options.spp,
+        render_options.thread_count()
+    );
+    let mut reports: Vec<Report> = vec![];
+    for run in 1..=options.runs {
+        let start = Instant::now();
+        let (_, counters) = render(
+            render_options,
+            scene.integrator,
+            0,
+            render_options.seed,
+            render_options.spp,
+            size,
+            &hidden,
+        )?;
+        let report = Report {
+            width: size.0,
+            height: size.1,
+            spp: render_options.spp,
+            frames: 1,
+            threads: render_options.thread_count(),
+            seconds: start.elapsed().as_secs_f64(),
+            counters,
+            peak_memory: stats::peak_memory(),
+        };
+        println!(
+            "run {}: {:.3} s, {:.3} Msamples/s, {:.3} MRays/s",
+            run,
+            report.seconds,
+            samples / report.seconds / 1e6,
+            report.rays_per_second() / 1e6
+        );
+        reports.push(report);
+    }
+    let mean = reports.iter().map(|r| r.seconds).sum::<f64>() / reports.len() as f64;
+    let best = reports
+        .into_iter()
+        .reduce(|a, b| if b.seconds < a.seconds { b } else { a })
+        .expect("at least one run");
+    println!(
+        "best {:.3} s, mean {:.3} s, {:.3} MRays/s at best",
+        best.seconds,
+        mean,
+        best.rays_per_second() / 1e6
+    );
+    finish(render_options, &hidden, best)
+}
+
+fn info(path: Option<&str>) -> Result<(), String> {
+    misc::seed(RenderOptions::default().seed);
+    let scene = match path {
+        Some(path) => Scene::load(path).map_err(|e| format!("{}: {}", path, e))?,
+        None => Scene::random(),
+    };
+    let vec = |v: Vec3| format!("({}, {}, {})", v.x, v.y, v.z);
+    println!("scene    {}", path.unwrap_or("built-in random spheres"));
+    let bounds = scene
+        .objects
+        .iter()
+        .map(|o| o.bounding_box())
+        .collect::<Option<Vec<_>>>()
+        .and_then(|boxes| boxes.into_iter().reduce(|a, b| a.surrounding(&b)));
+    match bounds {
+        Some(b) => println!(
+            "objects  {}, within {} to {}",
+            scene.objects.len(),
+            vec(b.min),
+            vec(b.max)
+        ),
+        None => println!("objects  {}, unbounded", scene.objects.len()),
+    }
+    println!(
+        "lights   {}, background {:?}",
+        scene.lights.len(),
+        scene.background
+    );
+    println!("render   {:?} integrator", scene.integrator);
+    let camera = &scene.camera;
+    println!(
+        "camera   {:?} from {} at {}, up {}",
+        camera.projection,
+        vec(camera.look_from),
+        vec(camera.look_at),
+        vec(camera.view_up)
+    );
+    println!(
+        "lens     fov {}, aperture {}, focus distance {}",
+        camera.fov, camera.aperture, camera.focus_dist
+    );
+    if let Some(layout) = camera.stereo {
+        println!("stereo   {:?}, ipd {}", layout, camera.ipd);
+    }
+    match scene.aspect {
+        Some(aspect) => println!("aspect   {}", aspect),
+        None => println!("aspect   not set (16:9 unless given)"),
+    }
+    let film = &scene.film;
+    println!(
+        "film     exposure {:?}, white balance {}, tone map {:?}{}, encoding {:?}",
+        film.exposure,
+        if film.white_balance.is_some() {
+            "on"
         } else {
-            return Color::new(0, 0, 0);
-        }
-    }
-
-    // background
-    let ud = r.direction().unit_vector();
-    let t = 0.5 * (ud.y + 1.0);
-    ((1.0 - t) * Vec3::from_i(1, 1, 1) + t * Vec3::new(0.5, 0.7, 1.0)).as_color()
-}
-
-fn random_scene() -> Vec<Box<dyn Hitable>> {
-    enum Materials {
-        Lambertian,
-        Metal,
-        Dielectric,
-    }
-    fn choose_random_material() -> Materials {
-        let r = random();
-        match r {
-            _ if r < 0.8 => Materials::Lambertian,
-            _ if r < 0.95 => Materials::Metal,
-            _ => Materials::Dielectric,
-        }
-    }
-    fn create_object(center: Vec3, material: Rc<Box<dyn Material>>) -> Box<dyn Hitable> {
-        Box::new(Sphere {
-            center,
-            radius: 0.2,
-            material,
-        })
-    }
-    fn random_material(en: Materials) -> Rc<Box<dyn Material>> {
-        match en {
-            Materials::Lambertian => Rc::new(Box::new(Lambertian::new(
-                (
-                    random() * random(),
-                    random() * random(),
-                    random() * random(),
-                )
-                    .into(),
-            ))),
-            Materials::Metal => Rc::new(Box::new(Metal::new(
-                (
-                    0.5 * (1.0 + random()),
-                    0.5 * (1.0 + random()),
-                    0.5 * (1.0 + random()),
-                )
-                    .into(),
-                0.5 * random(),
-            ))),
-            Materials::Dielectric => Rc::new(Box::new(Dielectric { ref_idx: 1.5 })),
-        }
-    }
-    let mut objects: Vec<Box<dyn Hitable>> = vec![];
-    for cx in -11..11 {
-        for cz in -11..11 {
-            let (cxf, czf) = (cx as f32, cz as f32);
-            let center = Vec3::new(cxf + 0.9 * random(), 0.2, czf + 0.9 * random());
-            if (center - Vec3::new(4.0, 0.2, 0.0)).length() > 0.9 {
-                objects.push(create_object(
-                    center,
-                    random_material(choose_random_material()),
-                ));
+            "off"
+        },
+        film.tone_map,
+        if film.preserve_hue {
+            " on luminance"
+        } else {
+            ""
+        },
+        film.encoding
+    );
+    match scene.frames {
+        Some(frames) => println!(
+            "frames   {} to {} at {} fps",
+            frames.start, frames.end, frames.fps
+        ),
+        None => println!("frames   still"),
+    }
+    Ok(())
+}
+
+/// Whether the chosen metric is within the threshold, or `true` without one.
+fn compare_images(options: &CompareOptions) -> Result<bool, String> {
+    let (reference, test, encoding) =
+        load_pair(&options.reference, &options.test).map_err(|e| e.to_string())?;
+    let comparison = compare(&reference, &test, encoding, options.ppd)?;
+    if let Some(path) = &options.diff {
+        let png = encode_png(
+            comparison.width,
+            comparison.height,
+            &comparison.false_color(),
+        );
+        std::fs::write(path, png).map_err(|e| format!("{}: {}", path, e))?;
+    }
+    let metrics = &comparison.metrics;
+    if options.json {
+        println!("{}", metrics.to_json());
+    } else {
+        println!("{}", metrics);
+    }
+    match options.threshold {
+        Some(threshold) if !options.metric.passes(metrics, threshold) => {
+            eprintln!(
+                "{} is {:.6}, worse than the threshold {}",
+                options.metric,
+                options.metric.of(metrics),
+                threshold
+            );
+            Ok(false)
+        }
+        _ => Ok(true),
+    }
+}
+
+/// The file at `path`, or standard output.
+fn create(path: Option<&str>) -> io::Result<Box<dyn Write>> {
+    Ok(match path {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout())),
+    })
+}
+
+fn write_still(
+    out: &mut dyn Write,
+    format: OutputFormat,
+    width: usize,
+    height: usize,
+    pixels: &[Color],
+) -> io::Result<()> {
+    match format {
+        OutputFormat::Ppm => out.write_all(&encode_ppm(width, height, pixels)),
+        OutputFormat::Png => out.write_all(&encode_png(width, height, pixels)),
+        _ => {
+            writeln!(out, "P3\n{} {}\n255\n", width, height)?;
+            for color in pixels {
+                writeln!(out, "{} {} {}", color.r, color.g, color.b)?;
             }
+            Ok(())
         }
     }
-    let ground_sphere = Box::new(Sphere {
-        center: (0, -1000, 0).into(),
-        radius: 1000.0,
-        material: Rc::new(Box::new(Lambertian::new((0.5, 0.5, 0.5).into()))),
-    });
-    let defined_sphere1 = Box::new(Sphere {
-        center: (0, 1, 0).into(),
-        radius: 1.0,
-        material: Rc::new(Box::new(Dielectric { ref_idx: 1.5 })),
-    });
-    let defined_sphere2 = Box::new(Sphere {
-        center: (-4, 1, 0).into(),
-        radius: 1.0,
-        material: Rc::new(Box::new(Lambertian::new((0.4, 0.2, 0.1).into()))),
-    });
-    let defined_sphere3 = Box::new(Sphere {
-        center: (4, 1, 0).into(),
-        radius: 1.0,
-        material: Rc::new(Box::new(Metal::new((0.7, 0.6, 0.5).into(), 0.0))),
-    });
-    objects.push(ground_sphere);
-    objects.push(defined_sphere1);
-    objects.push(defined_sphere2);
-    objects.push(defined_sphere3);
-    objects
+}
+
+fn animation_sink(
+    out: Box<dyn Write>,
+    format: OutputFormat,
+    frames: u32,
+    fps: f32,
+) -> Box<dyn FrameSink> {
+    match format {
+        OutputFormat::Y4m => Box::new(Y4mWriter::new(out, fps)),
+        OutputFormat::Gif => Box::new(GifWriter::new(out, fps)),
+        _ => Box::new(ApngWriter::new(out, fps, frames)),
+    }
+}
+
+/// Linear radiance of `frame` rendered with the command line's settings, with the
+/// scene's `integrator` unless the command line picks one.
+fn render(
+    options: &RenderOptions,
+    integrator: IntegratorKind,
+    frame: u32,
+    seed: u64,
+    spp: u32,
+    (width, height): (usize, usize),
+    progress: &Progress,
+) -> Result<(Image, Counters), String> {
+    let integrator = options.integrator.unwrap_or(integrator);
+    let output = Renderer::new(|| load_scene(options, frame))
+        .integrator(integrator.build(options.max_depth, options.bounces))
+        .size(width, height)
+        .spp(spp)
+        .seed(seed)
+        .threads(options.thread_count())
+        .on_tile(|tile| progress.advance((tile.width * tile.height) as u64 * spp as u64))
+        .render()
+        .map_err(|e| e.to_string())?;
+    Ok((output.image, output.counters))
 }
 
 #[cfg(test)]
@@ -2,10 +2,60 @@ use crate::color::Color;
 use crate::hitable::HitRecord;
 use crate::misc::random;
 use crate::ray::Ray;
-use crate::vec3::{random_in_unit_sphere, Vec3};
+use crate::vec3::{random_in_unit_sphere, random_unit_vector, Vec3};
 
 pub trait Material {
-    fn scatter(self: &Self, r_in: &Ray, _record: &HitRecord) -> (bool, Color, Ray);
+    fn scatter(&self, r_in: &Ray, _record: &HitRecord) -> (bool, Color, Ray);
+
+    /// Radiance the surface gives off by itself, linear and unbounded.
+    fn emitted(&self, _record: &HitRecord) -> Vec3 {
+        Vec3::new(0.0, 0.0, 0.0)
+    }
+
+    /// Albedo of a diffuse surface, for integrators that gather light at it; `None` when the
+    /// material only reflects or refracts.
+    fn lambert(&self, _record: &HitRecord) -> Option<Vec3> {
+        None
+    }
+
+    /// The ideal reflected and refracted rays with the share of light each carries, for
+    /// Whitted-style ray tracing. Blurry reflections are taken as sharp.
+    fn specular(&self, _r_in: &Ray, _record: &HitRecord) -> Vec<(Vec3, Ray)> {
+        vec![]
+    }
+
+    /// Refractive index on the side `r_in` comes from over the one across the surface, for
+    /// materials that let light through; 1 for the rest.
+    fn eta(&self, _r_in: &Ray, _record: &HitRecord) -> f32 {
+        1.0
+    }
+
+    /// Kind of scattering that turned `r_in` into `scattered`: diffuse for materials with a
+    /// `lambert` albedo, otherwise transmission when the ray went through the surface and
+    /// specular when it stayed on its side.
+    fn lobe(&self, r_in: &Ray, record: &HitRecord, scattered: &Ray) -> Lobe {
+        let (incoming, outgoing) = (
+            r_in.direction().dot(&record.normal),
+            scattered.direction().dot(&record.normal),
+        );
+        if self.lambert(record).is_some() {
+            Lobe::Diffuse
+        } else if (incoming < 0.0) == (outgoing < 0.0) {
+            Lobe::Transmission
+        } else {
+            Lobe::Specular
+        }
+    }
+}
+
+/// Broad kinds of scattering, which integrators may treat and limit separately.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Lobe {
+    Diffuse,
+    /// Mirror-like and blurry reflections.
+    Specular,
+    /// Light passing through the surface.
+    Transmission,
 }
 
 pub struct Lambertian {
@@ -18,12 +68,59 @@ impl Lambertian {
     }
 }
 
+/// Cosine-weighted direction about the normal, towards a point on the unit sphere that
+/// touches the surface.
+fn diffuse(record: &HitRecord) -> Ray {
+    let direction = record.normal + random_unit_vector();
+    // the point opposite the normal leaves no direction
+    if direction.squared_length() < 1e-12 {
+        Ray::new(record.p, record.normal)
+    } else {
+        Ray::new(record.p, direction)
+    }
+}
+
 impl Material for Lambertian {
-    fn scatter(self: &Self, _r_in: &Ray, record: &HitRecord) -> (bool, Color, Ray) {
-        let target = record.p + record.normal + random_in_unit_sphere();
-        let scatterd = Ray::new(record.p, target - record.p);
-        let attenuation = self.albedo;
-        (true, attenuation, scatterd)
+    fn scatter(&self, _r_in: &Ray, record: &HitRecord) -> (bool, Color, Ray) {
+        (true, self.albedo, diffuse(record))
+    }
+
+    fn lambert(&self, _record: &HitRecord) -> Option<Vec3> {
+        Some(self.albedo.as_vec3())
+    }
+}
+
+/// Diffuse material alternating between two albedos in a 3D checker pattern of cubes `size`
+/// wide. The cubes are centered on multiples of `size`, so planes through the origin cut
+/// through their middles rather than along their faces.
+pub struct Checker {
+    even: Color,
+    odd: Color,
+    size: f32,
+}
+
+impl Checker {
+    pub fn new(even: Color, odd: Color, size: f32) -> Checker {
+        Checker { even, odd, size }
+    }
+
+    pub fn albedo(&self, p: &Vec3) -> Color {
+        let cell = |x: f32| (x / self.size + 0.5).floor() as i64;
+        if (cell(p.x) + cell(p.y) + cell(p.z)).rem_euclid(2) == 0 {
+            self.even
+        } else {
+            self.odd
+        }
+    }
+}
+
+impl Material for Checker {
+    fn scatter(&self, _r_in: &Ray, record: &HitRecord) -> (bool, Color, Ray) {
+        (true, self.albedo(&record.p), diffuse(record))
+    }
+
+    fn lambert(&self, record: &HitRecord) -> Option<Vec3> {
+        Some(self.albedo(&record.p).as_vec3())
     }
 }
 
@@ -46,7 +143,7 @@ impl Metal {
 }
 
 impl Material for Metal {
-    fn scatter(self: &Self, r_in: &Ray, record: &HitRecord) -> (bool, Color, Ray) {
+    fn scatter(&self, r_in: &Ray, record: &HitRecord) -> (bool, Color, Ray) {
         let reflected = reflect(&r_in.direction().unit_vector(), &record.normal);
         let scattered: Ray = Ray::new(
             record.p,
@@ -59,6 +156,11 @@ impl Material for Metal {
             scattered,
         )
     }
+
+    fn specular(&self, r_in: &Ray, record: &HitRecord) -> Vec<(Vec3, Ray)> {
+        let reflected = reflect(&r_in.direction().unit_vector(), &record.normal);
+        vec![(self.albedo.as_vec3(), Ray::new(record.p, reflected))]
+    }
 }
 
 pub struct Dielectric {
@@ -66,43 +168,104 @@ pub struct Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(self: &Self, r_in: &Ray, record: &HitRecord) -> (bool, Color, Ray) {
+    fn scatter(&self, r_in: &Ray, record: &HitRecord) -> (bool, Color, Ray) {
         let reflected = reflect(r_in.direction(), &record.normal);
         let attenuation = (255, 255, 255).into();
-        let (outward_normal, rri, cosine) = if r_in.direction().dot(&record.normal) > 0.0 {
-            (
-                -record.normal,
-                self.ref_idx,
-                self.ref_idx * (r_in.direction().dot(&record.normal)) / r_in.direction().length(),
-            )
+        let cosine = -r_in.direction().unit_vector().dot(&record.normal);
+        let entering = cosine > 0.0;
+        let (outward_normal, rri) = if entering {
+            (record.normal, 1.0 / self.ref_idx)
         } else {
-            (
-                record.normal,
-                1.0 / self.ref_idx,
-                -(self.ref_idx * (r_in.direction().dot(&record.normal))
-                    / r_in.direction().length()),
-            )
+            (-record.normal, self.ref_idx)
         };
         let refracted = refract(r_in.direction(), &outward_normal, rri);
-        let is_refracted = refracted.is_some();
-        let probability_of_reflection = if is_refracted {
-            schlick(cosine, self.ref_idx)
-        } else {
-            1.0
+        // reflectance is the same from either side, so Schlick's approximation takes the
+        // cosine on the outside
+        let probability_of_reflection = match refracted {
+            Some(_) if entering => schlick(cosine, self.ref_idx),
+            Some(_) => schlick(
+                (1.0 - rri * rri * (1.0 - cosine * cosine)).sqrt(),
+                self.ref_idx,
+            ),
+            None => 1.0,
         };
-        if random() < probability_of_reflection {
-            (true, attenuation, Ray::new(record.p, reflected))
+        match refracted {
+            Some(refracted) if random() >= probability_of_reflection => {
+                (true, attenuation, Ray::new(record.p, refracted))
+            }
+            _ => (true, attenuation, Ray::new(record.p, reflected)),
+        }
+    }
+
+    fn eta(&self, r_in: &Ray, record: &HitRecord) -> f32 {
+        if r_in.direction().dot(&record.normal) < 0.0 {
+            1.0 / self.ref_idx
         } else {
-            (true, attenuation, Ray::new(record.p, refracted.unwrap()))
+            self.ref_idx
         }
     }
+
+    fn specular(&self, r_in: &Ray, record: &HitRecord) -> Vec<(Vec3, Ray)> {
+        let reflected = Ray::new(record.p, reflect(r_in.direction(), &record.normal));
+        let cosine = -r_in.direction().unit_vector().dot(&record.normal);
+        let entering = cosine > 0.0;
+        let (outward_normal, rri) = if entering {
+            (record.normal, 1.0 / self.ref_idx)
+        } else {
+            (-record.normal, self.ref_idx)
+        };
+        let white = Vec3::new(1.0, 1.0, 1.0);
+        match refract(r_in.direction(), &outward_normal, rri) {
+            Some(refracted) => {
+                let reflectance = if entering {
+                    schlick(cosine, self.ref_idx)
+                } else {
+                    schlick(
+                        (1.0 - rri * rri * (1.0 - cosine * cosine)).sqrt(),
+                        self.ref_idx,
+                    )
+                };
+                vec![
+                    (reflectance * white, reflected),
+                    ((1.0 - reflectance) * white, Ray::new(record.p, refracted)),
+                ]
+            }
+            None => vec![(white, reflected)],
+        }
+    }
+}
+
+/// Gives off `radiance` from both sides and scatters nothing. Shapes made of it are the
+/// lights of a scene.
+pub struct DiffuseLight {
+    pub radiance: Vec3,
+}
+
+impl DiffuseLight {
+    pub fn new(radiance: Vec3) -> DiffuseLight {
+        DiffuseLight { radiance }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(&self, r_in: &Ray, record: &HitRecord) -> (bool, Color, Ray) {
+        (
+            false,
+            (0, 0, 0).into(),
+            Ray::new(record.p, *r_in.direction()),
+        )
+    }
+
+    fn emitted(&self, _record: &HitRecord) -> Vec3 {
+        self.radiance
+    }
 }
 
 fn reflect(v: &Vec3, n: &Vec3) -> Vec3 {
     v - &(2.0 * n * v.dot(n))
 }
 
-fn refract(v: &Vec3, n: &Vec3, relative_refractive_index: f32) -> Option<Vec3> {
+pub(crate) fn refract(v: &Vec3, n: &Vec3, relative_refractive_index: f32) -> Option<Vec3> {
     let uv = v.unit_vector();
     let dt = uv.dot(n);
     let discriminant =
@@ -122,3 +285,555 @@ fn schlick(cosine: f32, ref_idx: f32) -> f32 {
 fn square(v: f32) -> f32 {
     v * v
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hitable::{Hitable, Sphere};
+    use crate::misc::seed;
+    use crate::vec3::orthonormal_basis;
+    use std::f64::consts::PI;
+    use std::rc::Rc;
+
+    /// ln Γ(x) by Lanczos' approximation.
+    fn ln_gamma(x: f64) -> f64 {
+        const G: [f64; 9] = [
+            0.999_999_999_999_809_9,
+            676.520_368_121_885_1,
+            -1_259.139_216_722_402_8,
+            771.323_428_777_653_1,
+            -176.615_029_162_140_6,
+            12.507_343_278_686_905,
+            -0.138_571_095_265_720_12,
+            9.984_369_578_019_572e-6,
+            1.505_632_735_149_311_6e-7,
+        ];
+        let x = x - 1.0;
+        let t = x + 7.5;
+        let sum = G[1..]
+            .iter()
+            .enumerate()
+            .fold(G[0], |sum, (i, g)| sum + g / (x + i as f64 + 1.0));
+        0.5 * (2.0 * PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+    }
+
+    /// Upper regularized incomplete gamma function Q(a, x), by its series below `a + 1` and
+    /// its continued fraction above.
+    fn gamma_q(a: f64, x: f64) -> f64 {
+        if x <= 0.0 {
+            return 1.0;
+        }
+        let scale = (-x + a * x.ln() - ln_gamma(a)).exp();
+        if x < a + 1.0 {
+            let (mut term, mut sum) = (1.0 / a, 1.0 / a);
+            for n in 1..1000 {
+                term *= x / (a + n as f64);
+                sum += term;
+                if term < sum * 1e-15 {
+                    break;
+                }
+            }
+            1.0 - sum * scale
+        } else {
+            // modified Lentz
+            let tiny = 1e-300;
+            let mut b = x + 1.0 - a;
+            let mut c = 1.0 / tiny;
+            let mut d = 1.0 / b;
+            let mut h = d;
+            for i in 1..1000 {
+                let an = -(i as f64) * (i as f64 - a);
+                b += 2.0;
+                d = an * d + b;
+                d = if d.abs() < tiny { tiny } else { d };
+                c = b + an / c;
+                c = if c.abs() < tiny { tiny } else { c };
+                d = 1.0 / d;
+                let delta = d * c;
+                h *= delta;
+                if (delta - 1.0).abs() < 1e-15 {
+                    break;
+                }
+            }
+            scale * h
+        }
+    }
+
+    /// Significance level of a whole test function, split among its `tests` chi-square tests
+    /// by Šidák's correction so that one of them failing by chance stays unlikely.
+    fn significance(tests: usize) -> f64 {
+        1.0 - (1.0 - 0.01f64).powf(1.0 / tests as f64)
+    }
+
+    /// p-value of observed against expected counts, pooling the bins that expect fewer than
+    /// five samples. A sample where none should be gives 0.
+    fn chi_square(observed: &[f64], expected: &[f64]) -> f64 {
+        let (mut chi2, mut bins) = (0.0, 0);
+        let (mut pooled_observed, mut pooled_expected) = (0.0, 0.0);
+        for (&o, &e) in observed.iter().zip(expected) {
+            if e < 5.0 {
+                pooled_observed += o;
+                pooled_expected += e;
+            } else {
+                chi2 += (o - e) * (o - e) / e;
+                bins += 1;
+            }
+        }
+        if pooled_expected > 0.0 {
+            chi2 += (pooled_observed - pooled_expected).powi(2) / pooled_expected;
+            bins += 1;
+        } else if pooled_observed > 0.0 {
+            return 0.0;
+        }
+        gamma_q((bins - 1) as f64 / 2.0, chi2 / 2.0)
+    }
+
+    const SAMPLES: usize = 100_000;
+    const THETA_BINS: usize = 10;
+    const PHI_BINS: usize = 20;
+
+    /// Chi-square test of directions drawn by `sample` against `pdf`, a density over solid
+    /// angle that vanishes where the cosine to `axis` is below `min_cos`. The bins split that
+    /// cap evenly in cos θ and φ, so they cover equal solid angles, and one more bin takes
+    /// whatever falls outside. Returns the p-value.
+    fn directions_test(
+        axis: Vec3,
+        min_cos: f64,
+        mut sample: impl FnMut() -> Vec3,
+        pdf: impl Fn(&Vec3) -> f64,
+    ) -> f64 {
+        let bins = THETA_BINS * PHI_BINS;
+        let (t, b) = orthonormal_basis(&axis);
+        let mut observed = vec![0.0; bins + 1];
+        for _ in 0..SAMPLES {
+            let d = sample().unit_vector();
+            let cos = d.dot(&axis) as f64;
+            let bin = if cos < min_cos {
+                bins
+            } else {
+                let i = ((cos - min_cos) / (1.0 - min_cos) * THETA_BINS as f64) as usize;
+                let phi = (d.dot(&b) as f64).atan2(d.dot(&t) as f64) + PI;
+                let j = (phi / (2.0 * PI) * PHI_BINS as f64) as usize;
+                i.min(THETA_BINS - 1) * PHI_BINS + j.min(PHI_BINS - 1)
+            };
+            observed[bin] += 1.0;
+        }
+        // expected counts by the midpoint rule on a finer grid
+        const SUB: usize = 16;
+        let (rows, columns) = (THETA_BINS * SUB, PHI_BINS * SUB);
+        let cell = (1.0 - min_cos) / rows as f64 * 2.0 * PI / columns as f64;
+        let mut expected = vec![0.0; bins + 1];
+        for i in 0..rows {
+            let cos = min_cos + (i as f64 + 0.5) / rows as f64 * (1.0 - min_cos);
+            let sin = (1.0 - cos * cos).sqrt();
+            for j in 0..columns {
+                let phi = (j as f64 + 0.5) / columns as f64 * 2.0 * PI - PI;
+                let d =
+                    axis * cos as f32 + t * (sin * phi.cos()) as f32 + b * (sin * phi.sin()) as f32;
+                expected[i / SUB * PHI_BINS + j / SUB] += pdf(&d) * cell * SAMPLES as f64;
+            }
+        }
+        let inside: f64 = expected[..bins].iter().sum();
+        expected[bins] = (SAMPLES as f64 - inside).max(0.0);
+        chi_square(&observed, &expected)
+    }
+
+    fn record() -> HitRecord {
+        let mut record = HitRecord::null();
+        record.normal = Vec3::new(0.0, 0.0, 1.0);
+        record
+    }
+
+    /// Direction of a ray arriving at the origin `degrees` off the normal, from above or,
+    /// for `inside`, from below.
+    fn incident(degrees: f64, inside: bool) -> Ray {
+        let theta = degrees.to_radians();
+        let z = if inside { 1.0 } else { -1.0 };
+        let d = Vec3::new(theta.sin() as f32, 0.0, (z * theta.cos()) as f32);
+        Ray::new(-d, d)
+    }
+
+    const ANGLES: [f64; 4] = [0.0, 30.0, 60.0, 85.0];
+
+    #[test]
+    fn incomplete_gamma_matches_known_values() {
+        // chi-square survival function at a few tabulated points
+        assert!((gamma_q(0.5, 3.841 / 2.0) - 0.05).abs() < 1e-4);
+        assert!((gamma_q(5.0, 23.209 / 2.0) - 0.01).abs() < 1e-4);
+        assert!((gamma_q(50.0, 124.342 / 2.0) - 0.05).abs() < 1e-4);
+        assert!((ln_gamma(5.0) - 24f64.ln()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn diffuse_materials_sample_the_cosine_lobe() {
+        seed(1);
+        let white = Color::new(255, 255, 255);
+        let materials: [Box<dyn Material>; 2] = [
+            Box::new(Lambertian::new(white)),
+            Box::new(Checker::new(white, white, 1.0)),
+        ];
+        let normal = record().normal;
+        let alpha = significance(materials.len() * ANGLES.len());
+        for material in &materials {
+            for degrees in ANGLES {
+                let r_in = incident(degrees, false);
+                let p = directions_test(
+                    normal,
+                    0.0,
+                    || *material.scatter(&r_in, &record()).2.direction(),
+                    |d| (d.dot(&normal) as f64).max(0.0) / PI,
+                );
+                assert!(p > alpha, "p = {} at {} degrees", p, degrees);
+            }
+        }
+    }
+
+    /// Density over solid angle of the direction of `r + fuzz * u` for `u` uniform in the
+    /// unit ball: the ball's volume along the direction over its whole volume.
+    fn fuzzy_lobe(r: &Vec3, fuzz: f64, d: &Vec3) -> f64 {
+        let c = d.dot(r) as f64;
+        let h = fuzz * fuzz - (1.0 - c * c);
+        if c <= 0.0 || h <= 0.0 {
+            return 0.0;
+        }
+        let (near, far) = ((c - h.sqrt()).max(0.0), c + h.sqrt());
+        (far.powi(3) - near.powi(3)) / 3.0 / (4.0 / 3.0 * PI * fuzz.powi(3))
+    }
+
+    #[test]
+    fn metal_samples_its_fuzzy_lobe() {
+        seed(2);
+        let fuzzes = [0.1, 0.3, 0.6, 1.0];
+        let angles = [0.0, 45.0, 80.0];
+        let alpha = significance(fuzzes.len() * angles.len());
+        for fuzz in fuzzes {
+            let metal = Metal::new(Color::new(255, 255, 255), fuzz);
+            for degrees in angles {
+                let r_in = incident(degrees, false);
+                let r = reflect(&r_in.direction().unit_vector(), &record().normal);
+                let p = directions_test(
+                    r,
+                    (1.0 - fuzz as f64 * fuzz as f64).sqrt(),
+                    || *metal.scatter(&r_in, &record()).2.direction(),
+                    |d| fuzzy_lobe(&r, fuzz as f64, d),
+                );
+                assert!(
+                    p > alpha,
+                    "p = {} for fuzz {} at {} degrees",
+                    p,
+                    fuzz,
+                    degrees
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn mirrors_reflect_exactly_and_reciprocally() {
+        let mirror = Metal::new(Color::new(255, 255, 255), 0.0);
+        for degrees in ANGLES {
+            let r_in = incident(degrees, false);
+            let (scattered, _, out) = mirror.scatter(&r_in, &record());
+            let expected = Vec3::new(r_in.direction().x, 0.0, -r_in.direction().z);
+            assert!(scattered || degrees == 90.0);
+            assert!((out.direction().unit_vector() - expected).length() < 1e-6);
+            // light going back the way it came retraces the path
+            let back = Ray::new(Vec3::new(0.0, 0.0, 0.0), -*out.direction());
+            let (_, _, returned) = mirror.scatter(&back, &record());
+            assert!(
+                (returned.direction().unit_vector() + r_in.direction().unit_vector()).length()
+                    < 1e-6
+            );
+        }
+    }
+
+    /// Fresnel reflectance by Schlick's approximation, given the cosine on the outside.
+    fn reflectance(cos_outside: f64, ior: f64) -> f64 {
+        let r0 = ((1.0 - ior) / (1.0 + ior)).powi(2);
+        r0 + (1.0 - r0) * (1.0 - cos_outside).powi(5)
+    }
+
+    /// Reflections among `SAMPLES` scatterings, checking every direction on the way.
+    fn reflections(glass: &Dielectric, r_in: &Ray) -> f64 {
+        let d = r_in.direction().unit_vector();
+        let inside = d.z > 0.0;
+        let (n1, n2) = if inside {
+            (glass.ref_idx, 1.0)
+        } else {
+            (1.0, glass.ref_idx)
+        };
+        let sin_in = (d.x * d.x + d.y * d.y).sqrt();
+        let mut count = 0.0;
+        for _ in 0..SAMPLES {
+            let out = glass.scatter(r_in, &record()).2.direction().unit_vector();
+            if out.z * d.z < 0.0 {
+                count += 1.0;
+                assert!((out - Vec3::new(d.x, d.y, -d.z)).length() < 1e-5);
+            } else {
+                // Snell's law
+                let sin_out = (out.x * out.x + out.y * out.y).sqrt();
+                assert!((n1 * sin_in - n2 * sin_out).abs() < 1e-5);
+            }
+        }
+        count
+    }
+
+    #[test]
+    fn dielectric_splits_by_fresnel() {
+        seed(3);
+        let iors = [1.33, 1.5, 2.4];
+        let alpha = significance(iors.len() * ANGLES.len() * 2);
+        for ior in iors {
+            let glass = Dielectric { ref_idx: ior };
+            for degrees in ANGLES {
+                for inside in [false, true] {
+                    let reflected = reflections(&glass, &incident(degrees, inside));
+                    let cos = degrees.to_radians().cos();
+                    let ior = ior as f64;
+                    let probability = if !inside {
+                        reflectance(cos, ior)
+                    } else {
+                        let sin_outside = ior * (1.0 - cos * cos).sqrt();
+                        if sin_outside >= 1.0 {
+                            1.0
+                        } else {
+                            reflectance((1.0 - sin_outside * sin_outside).sqrt(), ior)
+                        }
+                    };
+                    let n = SAMPLES as f64;
+                    let p = chi_square(
+                        &[reflected, n - reflected],
+                        &[n * probability, n * (1.0 - probability)],
+                    );
+                    assert!(
+                        p > alpha,
+                        "p = {} for ior {} at {} degrees{}",
+                        p,
+                        ior,
+                        degrees,
+                        if inside { " inside" } else { "" }
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn dielectric_is_reciprocal() {
+        seed(4);
+        for ior in [1.33f32, 1.5, 2.4] {
+            let glass = Dielectric { ref_idx: ior };
+            for degrees in [10.0f64, 40.0, 70.0] {
+                // the refracted direction, traced back, leaves the way the light came in
+                let r_in = incident(degrees, false);
+                let d = r_in.direction().unit_vector();
+                let t = refract(&d, &record().normal, 1.0 / ior)
+                    .unwrap()
+                    .unit_vector();
+                let back = refract(&-t, &-record().normal, ior).unwrap().unit_vector();
+                assert!((back + d).length() < 1e-5);
+                // and both ways through the surface reflect alike
+                let r_back = Ray::new(t, -t);
+                let n = SAMPLES as f64;
+                let outside = reflections(&glass, &r_in) / n;
+                let inside = reflections(&glass, &r_back) / n;
+                let sigma = (outside * (1.0 - outside) * 2.0 / n).sqrt();
+                assert!(
+                    (outside - inside).abs() < 5.0 * sigma + 1e-4,
+                    "{} against {} for ior {} at {} degrees",
+                    outside,
+                    inside,
+                    ior,
+                    degrees
+                );
+            }
+        }
+    }
+
+    fn every_material() -> Vec<Box<dyn Material>> {
+        let white = Color::new(255, 255, 255);
+        let mut materials: Vec<Box<dyn Material>> = vec![
+            Box::new(Lambertian::new(white)),
+            Box::new(Lambertian::new(Color::new(200, 100, 50))),
+            Box::new(Checker::new(white, Color::new(10, 20, 30), 0.5)),
+        ];
+        for fuzz in [0.0, 0.3, 1.0] {
+            materials.push(Box::new(Metal::new(white, fuzz)));
+        }
+        for ior in [1.33, 1.5, 2.4] {
+            materials.push(Box::new(Dielectric { ref_idx: ior }));
+        }
+        materials
+    }
+
+    #[test]
+    fn materials_conserve_energy() {
+        seed(5);
+        for material in every_material() {
+            for degrees in ANGLES {
+                for inside in [false, true] {
+                    let r_in = incident(degrees, inside);
+                    let mut throughput = Vec3::new(0.0, 0.0, 0.0);
+                    for _ in 0..10_000 {
+                        let (scattered, attenuation, _) = material.scatter(&r_in, &record());
+                        let a = attenuation.as_vec3();
+                        assert!(a.x <= 1.0 && a.y <= 1.0 && a.z <= 1.0);
+                        if scattered {
+                            throughput = throughput + a;
+                        }
+                    }
+                    let mean = throughput / 10_000.0;
+                    assert!(mean.x <= 1.0 && mean.y <= 1.0 && mean.z <= 1.0);
+                }
+            }
+        }
+    }
+
+    /// Mean radiance seen on a unit sphere of `material` lit by a uniform white environment,
+    /// with every path followed until it escapes.
+    fn furnace(material: Box<dyn Material>) -> f64 {
+        let sphere = Sphere {
+            center: Vec3::new(0.0, 0.0, 0.0),
+            radius: 1.0,
+            material: Rc::new(material),
+        };
+        let paths = 20_000;
+        let mut total = 0.0;
+        for _ in 0..paths {
+            // aimed at a random point of the sphere's silhouette disk
+            let (x, y) = loop {
+                let (x, y) = (2.0 * random() - 1.0, 2.0 * random() - 1.0);
+                if x * x + y * y < 1.0 {
+                    break (x, y);
+                }
+            };
+            let mut ray = Ray::new(Vec3::new(x, y, -5.0), Vec3::new(0.0, 0.0, 1.0));
+            let mut weight = 1.0;
+            for _ in 0..1000 {
+                match sphere.hit(&ray, 1e-4, f32::MAX) {
+                    None => break,
+                    Some(hit) => {
+                        let (scattered, attenuation, next) = hit.material.scatter(&ray, &hit);
+                        if !scattered {
+                            weight = 0.0;
+                            break;
+                        }
+                        weight *= attenuation.as_vec3().y as f64;
+                        ray = next;
+                    }
+                }
+            }
+            total += weight;
+        }
+        total / paths as f64
+    }
+
+    #[test]
+    fn white_furnace() {
+        seed(6);
+        // a white albedo of 255 reflects 255 / 255.99 of the light, squared for the few
+        // grazing paths that hit the sphere again just beside where they left it
+        let white = Color::new(255, 255, 255).as_vec3().y as f64;
+        let diffuse = furnace(Box::new(Lambertian::new(Color::new(255, 255, 255))));
+        assert!((diffuse - white).abs() < 1e-4, "{}", diffuse);
+        for ior in [1.33, 1.5, 2.4] {
+            // lossless but for the albedo, applied at every surface event
+            let glass = furnace(Box::new(Dielectric { ref_idx: ior }));
+            assert!(
+                glass <= white && glass > white.powi(8),
+                "{} for ior {}",
+                glass,
+                ior
+            );
+        }
+        for fuzz in [0.0, 0.5, 1.0] {
+            let metal = furnace(Box::new(Metal::new(Color::new(255, 255, 255), fuzz)));
+            assert!(metal <= white + 1e-9, "{} for fuzz {}", metal, fuzz);
+        }
+    }
+
+    #[test]
+    fn specular_rays_are_the_ones_scatter_picks_from() {
+        seed(6);
+        let glass = Dielectric { ref_idx: 1.5 };
+        for inside in [false, true] {
+            for degrees in ANGLES {
+                let r_in = incident(degrees, inside);
+                let split = glass.specular(&r_in, &record());
+                let total = split.iter().fold(0.0, |sum, (share, _)| sum + share.x);
+                assert!((total - 1.0).abs() < 1e-6);
+                for (_, ray) in &split {
+                    let d = ray.direction().unit_vector();
+                    let scattered = (0..200)
+                        .map(|_| glass.scatter(&r_in, &record()).2.direction().unit_vector());
+                    assert!(scattered.into_iter().any(|s| (s - d).length() < 1e-5));
+                }
+                let reflected =
+                    (split[0].0.x as f64 - reflections(&glass, &r_in) / SAMPLES as f64).abs();
+                assert!(reflected < 0.02, "{} degrees", degrees);
+            }
+        }
+        let mirror = Metal::new(Color::new(200, 100, 50), 0.5);
+        let r_in = incident(30.0, false);
+        let split = mirror.specular(&r_in, &record());
+        let d = r_in.direction().unit_vector();
+        assert_eq!(split.len(), 1);
+        assert!((split[0].1.direction().unit_vector() - Vec3::new(d.x, d.y, -d.z)).length() < 1e-5);
+        assert!(Lambertian::new(Color::new(1, 2, 3))
+            .specular(&r_in, &record())
+            .is_empty());
+    }
+
+    #[test]
+    fn scattering_falls_into_lobes() {
+        seed(10);
+        let r_in = incident(30.0, false);
+        let lobe = |material: &dyn Material| {
+            let scattered = material.scatter(&r_in, &record()).2;
+            material.lobe(&r_in, &record(), &scattered)
+        };
+        let gray = Color::new(128, 128, 128);
+        assert_eq!(lobe(&Lambertian::new(gray)), Lobe::Diffuse);
+        assert_eq!(lobe(&Checker::new(gray, gray, 1.0)), Lobe::Diffuse);
+        assert_eq!(lobe(&Metal::new(gray, 0.3)), Lobe::Specular);
+        let glass = Dielectric { ref_idx: 1.5 };
+        for r_in in [incident(30.0, false), incident(30.0, true)] {
+            for (_, ray) in glass.specular(&r_in, &record()) {
+                let crosses = ray.direction().z * r_in.direction().z > 0.0;
+                assert_eq!(
+                    glass.lobe(&r_in, &record(), &ray),
+                    if crosses {
+                        Lobe::Transmission
+                    } else {
+                        Lobe::Specular
+                    }
+                );
+            }
+        }
+        // light passing into the glass goes from the thinner medium into the denser one
+        assert_eq!(glass.eta(&incident(30.0, false), &record()), 1.0 / 1.5);
+        assert_eq!(glass.eta(&incident(30.0, true), &record()), 1.5);
+        assert_eq!(Metal::new(gray, 0.3).eta(&r_in, &record()), 1.0);
+    }
+
+    #[test]
+    fn only_lights_emit() {
+        let lamp = DiffuseLight::new(Vec3::new(4.0, 3.0, 2.0));
+        assert_eq!(lamp.emitted(&record()), Vec3::new(4.0, 3.0, 2.0));
+        assert!(!lamp.scatter(&incident(0.0, false), &record()).0);
+        for material in every_material() {
+            assert_eq!(material.emitted(&record()), Vec3::new(0.0, 0.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn checker_alternates_between_cells() {
+        let checker = Checker::new(Color::new(255, 255, 255), Color::new(0, 0, 0), 0.5);
+        let white = Color::new(255, 255, 255);
+        // a plane through the origin crosses the middle of a row of cells
+        assert_eq!(checker.albedo(&Vec3::new(0.1, 1e-7, 0.1)), white);
+        assert_eq!(checker.albedo(&Vec3::new(0.1, -1e-7, 0.1)), white);
+        assert_ne!(checker.albedo(&Vec3::new(0.6, 0.0, 0.1)), white);
+        assert_eq!(checker.albedo(&Vec3::new(0.6, 0.0, -0.6)), white);
+    }
+}
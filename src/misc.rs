@@ -1,15 +1,31 @@
-use std::sync::Mutex;
-use lazy_static::lazy_static;
+use std::cell::{Cell, RefCell};
 use rand::rngs::StdRng;
 use rand::{Rng, SeedableRng};
 
-lazy_static!{
-    static ref RNG: Mutex<StdRng> = Mutex::new(StdRng::seed_from_u64(1234));
+thread_local!{
+    /// One sequence per thread, so render threads neither contend nor disturb each other.
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::seed_from_u64(1234));
+    /// Stands in for the sequence while set.
+    static SOURCE: Cell<Option<fn() -> f32>> = const { Cell::new(None) };
 }
 
 pub fn random() -> f32 {
-    let mut rng = RNG.lock().unwrap();
-    rng.gen()
+    match SOURCE.with(Cell::get) {
+        Some(source) => source(),
+        None => RNG.with(|rng| rng.borrow_mut().random()),
+    }
+}
+
+/// Makes `random` on the calling thread return what `source` does instead, until it is set
+/// back to `None`; Metropolis light transport replays and mutates paths this way.
+pub fn set_source(source: Option<fn() -> f32>) {
+    SOURCE.with(|s| s.set(source));
+}
+
+/// Restarts the calling thread's random sequence, e.g. to make a frame of an animation
+/// reproducible.
+pub fn seed(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
 }
 
 pub fn degree_to_radian(d: f32) -> f32 {
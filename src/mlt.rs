@@ -0,0 +1,411 @@
+use crate::animation::frame_seed;
+use crate::image::luminance;
+use crate::integrator::{Integrator, PathTracer, SceneView, Splat};
+use crate::misc::{self, random};
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cell::RefCell;
+use std::f32::consts::PI;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+
+/// Primary sample space Metropolis light transport on top of the path tracer: rather than
+/// drawing fresh random numbers for every path, each render thread keeps a Markov chain over
+/// the numbers a path through the image is made from, and wanders towards the bright ones.
+/// Most mutations nudge every number a little, which keeps finding its way through the same
+/// narrow gap once a path got through; the others draw a path anew so that the chain does
+/// not stay stuck. Before the render, paths drawn at random measure how bright the image is
+/// on the whole, as the chains only tell how the light is spread over it, and the chains
+/// start from among them.
+///
+/// Every sample of a pixel is one mutation, and its light is splatted wherever the path
+/// meets the image, so which pixels a thread's chain reaches and the image with them depend
+/// on the threads and tiles. `radiance` alone is that of the path tracer.
+pub struct MetropolisLightTransport {
+    pub path: PathTracer,
+    /// Paths drawn at random before the render.
+    pub bootstrap: usize,
+    /// Chance that a mutation draws a path anew instead of nudging the numbers.
+    pub large_step_probability: f32,
+    /// Standard deviation of the nudge to every number.
+    pub sigma: f32,
+    start: RwLock<Option<Bootstrap>>,
+}
+
+/// What the paths drawn before the render found.
+struct Bootstrap {
+    /// Tells chains started from other bootstraps apart.
+    generation: u64,
+    /// Mean luminance of the paths: that of the image.
+    brightness: f32,
+    /// Seeds of the paths, to start a chain from one of them.
+    seeds: Vec<u64>,
+    /// Running sums of the luminance of the paths.
+    cdf: Vec<f32>,
+}
+
+static GENERATIONS: AtomicU64 = AtomicU64::new(0);
+
+impl MetropolisLightTransport {
+    pub fn new(path: PathTracer) -> MetropolisLightTransport {
+        MetropolisLightTransport {
+            path,
+            bootstrap: 100_000,
+            large_step_probability: 0.3,
+            sigma: 0.01,
+            start: RwLock::new(None),
+        }
+    }
+
+    pub fn with_bootstrap(mut self, bootstrap: usize) -> MetropolisLightTransport {
+        self.bootstrap = bootstrap;
+        self
+    }
+
+    pub fn with_large_step_probability(mut self, p: f32) -> MetropolisLightTransport {
+        self.large_step_probability = p;
+        self
+    }
+
+    pub fn with_sigma(mut self, sigma: f32) -> MetropolisLightTransport {
+        self.sigma = sigma;
+        self
+    }
+
+    fn chain(&self, seed: u64, generation: u64) -> Chain {
+        Chain::new(seed, generation, self.sigma, self.large_step_probability)
+    }
+
+    /// Traces the path that the calling thread's chain now stands for.
+    fn evaluate(&self, scene: &SceneView) -> Sample {
+        misc::set_source(Some(draw));
+        let (s, t) = (random(), random());
+        let radiance = match scene.camera.get_ray(s, t) {
+            Some(ray) => self.path.radiance(&ray, scene),
+            None => Vec3::new(0.0, 0.0, 0.0),
+        };
+        misc::set_source(None);
+        let importance = luminance(&radiance);
+        Sample {
+            s,
+            t,
+            radiance,
+            importance: if importance.is_finite() && importance > 0.0 {
+                importance
+            } else {
+                0.0
+            },
+        }
+    }
+}
+
+impl Integrator for MetropolisLightTransport {
+    fn radiance(&self, r: &Ray, scene: &SceneView) -> Vec3 {
+        self.path.radiance(r, scene)
+    }
+
+    /// Takes the next step of the calling thread's chain, started from the bootstrap on the
+    /// first call; without `prepare` it is `radiance`.
+    fn radiance_and_splats(&self, r: &Ray, scene: &SceneView, splats: &mut Vec<Splat>) -> Vec3 {
+        let zero = Vec3::new(0.0, 0.0, 0.0);
+        let start = self.start.read().unwrap();
+        let start = match start.as_ref() {
+            Some(start) => start,
+            None => return self.radiance(r, scene),
+        };
+        let total = match start.cdf.last() {
+            Some(&total) if total > 0.0 => total,
+            _ => return zero,
+        };
+        let running = with_chain(|chain| chain.as_ref().map(|chain| chain.generation));
+        if running != Some(start.generation) {
+            let u = random() * total;
+            let i = start.cdf.partition_point(|&sum| sum <= u);
+            let seed = start.seeds[i.min(start.seeds.len() - 1)];
+            let chain = self.chain(seed, start.generation);
+            with_chain(|c| *c = Some(chain));
+            let current = self.evaluate(scene);
+            with_chain(|c| c.as_mut().unwrap().current = current);
+        }
+
+        with_chain(|c| c.as_mut().unwrap().start_iteration());
+        let proposed = self.evaluate(scene);
+        with_chain(|c| {
+            let chain = c.as_mut().unwrap();
+            let current = chain.current;
+            let accept = if current.importance > 0.0 {
+                (proposed.importance / current.importance).min(1.0)
+            } else {
+                1.0
+            };
+            // both ends of the step are splatted, each by its chance of being where the
+            // chain goes next
+            for (sample, weight) in [(proposed, accept), (current, 1.0 - accept)] {
+                if sample.importance > 0.0 && weight > 0.0 {
+                    splats.push(Splat {
+                        s: sample.s,
+                        t: sample.t,
+                        radiance: sample.radiance * (start.brightness * weight / sample.importance),
+                    });
+                }
+            }
+            if chain.rng.random::<f32>() < accept {
+                chain.accept();
+                chain.current = proposed;
+            } else {
+                chain.reject();
+            }
+        });
+        zero
+    }
+
+    /// Draws the bootstrap paths, each from a chain of its own.
+    fn prepare(&self, scene: &SceneView, _pass: u32) {
+        let generation = GENERATIONS.fetch_add(1, Ordering::Relaxed) + 1;
+        let base = (random() * u32::MAX as f32) as u64;
+        let mut seeds = Vec::with_capacity(self.bootstrap);
+        let mut cdf = Vec::with_capacity(self.bootstrap);
+        let mut sum = 0.0;
+        for i in 0..self.bootstrap {
+            let seed = frame_seed(base, i as u32);
+            let chain = self.chain(seed, generation);
+            with_chain(|c| *c = Some(chain));
+            sum += self.evaluate(scene).importance;
+            seeds.push(seed);
+            cdf.push(sum);
+        }
+        with_chain(|c| *c = None);
+        *self.start.write().unwrap() = Some(Bootstrap {
+            generation,
+            brightness: sum / self.bootstrap.max(1) as f32,
+            seeds,
+            cdf,
+        });
+    }
+}
+
+/// A path through the image: where it meets it and the light it brings.
+#[derive(Copy, Clone)]
+struct Sample {
+    s: f32,
+    t: f32,
+    radiance: Vec3,
+    /// Luminance of `radiance`, which the chain's samples are distributed by.
+    importance: f32,
+}
+
+/// One of the numbers a path is made from, with the iteration that last changed it.
+#[derive(Copy, Clone, Default)]
+struct PrimarySample {
+    value: f32,
+    modified: u64,
+    backup: f32,
+    backup_modified: u64,
+}
+
+/// A point in primary sample space and the chain of mutations that moves it. Numbers are
+/// only mutated as paths ask for them, catching up on the iterations they missed.
+struct Chain {
+    generation: u64,
+    rng: StdRng,
+    samples: Vec<PrimarySample>,
+    /// Index of the number the path asks for next.
+    next: usize,
+    iteration: u64,
+    large_step: bool,
+    last_large_step: u64,
+    sigma: f32,
+    large_step_probability: f32,
+    current: Sample,
+}
+
+thread_local! {
+    /// The chain of the render thread, which `random` draws from while a path is traced.
+    static CHAIN: RefCell<Option<Chain>> = const { RefCell::new(None) };
+}
+
+fn with_chain<T>(f: impl FnOnce(&mut Option<Chain>) -> T) -> T {
+    CHAIN.with(|chain| f(&mut chain.borrow_mut()))
+}
+
+fn draw() -> f32 {
+    with_chain(|chain| chain.as_mut().expect("no chain to draw from").draw())
+}
+
+impl Chain {
+    /// Its first path is drawn at random, as a large step.
+    fn new(seed: u64, generation: u64, sigma: f32, large_step_probability: f32) -> Chain {
+        Chain {
+            generation,
+            rng: StdRng::seed_from_u64(seed),
+            samples: vec![],
+            next: 0,
+            iteration: 0,
+            large_step: true,
+            last_large_step: 0,
+            sigma,
+            large_step_probability,
+            current: Sample {
+                s: 0.0,
+                t: 0.0,
+                radiance: Vec3::new(0.0, 0.0, 0.0),
+                importance: 0.0,
+            },
+        }
+    }
+
+    fn start_iteration(&mut self) {
+        self.iteration += 1;
+        self.large_step = self.rng.random::<f32>() < self.large_step_probability;
+        self.next = 0;
+    }
+
+    fn draw(&mut self) -> f32 {
+        let i = self.next;
+        self.next += 1;
+        // numbers no path asked for before start out uniform, like the rest
+        while self.samples.len() <= i {
+            let value = self.rng.random();
+            self.samples.push(PrimarySample {
+                value,
+                ..PrimarySample::default()
+            });
+        }
+        let rng = &mut self.rng;
+        let x = &mut self.samples[i];
+        // a number the last accepted large step did not reach is drawn now, as it would have been
+        if x.modified < self.last_large_step {
+            x.value = rng.random();
+            x.modified = self.last_large_step;
+        }
+        x.backup = x.value;
+        x.backup_modified = x.modified;
+        if self.large_step {
+            x.value = rng.random();
+        } else {
+            // a normal step for every iteration missed, by Box-Muller
+            let steps = (self.iteration - x.modified) as f32;
+            let (u1, u2): (f32, f32) = (rng.random(), rng.random());
+            let normal = (-2.0 * (1.0 - u1).ln()).sqrt() * (2.0 * PI * u2).cos();
+            x.value += normal * self.sigma * steps.sqrt();
+            x.value = (x.value - x.value.floor()).min(1.0 - f32::EPSILON / 2.0);
+        }
+        x.modified = self.iteration;
+        x.value
+    }
+
+    fn accept(&mut self) {
+        if self.large_step {
+            self.last_large_step = self.iteration;
+        }
+    }
+
+    /// Puts back the numbers the iteration changed.
+    fn reject(&mut self) {
+        for x in &mut self.samples {
+            if x.modified == self.iteration {
+                x.value = x.backup;
+                x.modified = x.backup_modified;
+            }
+        }
+        self.iteration -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::Renderer;
+    use crate::scene::Scene;
+
+    fn numbers(chain: &mut Chain, n: usize) -> Vec<f32> {
+        chain.next = 0;
+        (0..n).map(|_| chain.draw()).collect()
+    }
+
+    #[test]
+    fn mutations_move_the_numbers_and_rejections_put_them_back() {
+        let mut chain = Chain::new(7, 1, 0.01, 0.0);
+        let first = numbers(&mut chain, 5);
+        chain.start_iteration();
+        let small = numbers(&mut chain, 5);
+        for (a, b) in first.iter().zip(&small) {
+            let d = (a - b).abs();
+            assert!(d > 0.0 && d.min(1.0 - d) < 0.1, "{} to {}", a, b);
+        }
+        chain.reject();
+        chain.start_iteration();
+        chain.large_step = true;
+        let large = numbers(&mut chain, 3);
+        chain.reject();
+        // the numbers are back where they were and nudged from there on the next step
+        chain.start_iteration();
+        let again = numbers(&mut chain, 5);
+        for (a, b) in first.iter().zip(&again) {
+            let d = (a - b).abs();
+            assert!(d.min(1.0 - d) < 0.1, "{} to {}", a, b);
+        }
+        assert!(first[..3]
+            .iter()
+            .zip(&large)
+            .any(|(a, b)| (a - b).abs() > 0.1));
+        assert!(again.iter().all(|x| (0.0..1.0).contains(x)));
+
+        // an accepted large step replaces numbers no path has asked for yet
+        chain.start_iteration();
+        chain.large_step = true;
+        let large = numbers(&mut chain, 2);
+        chain.accept();
+        chain.start_iteration();
+        let after = numbers(&mut chain, 5);
+        assert!(
+            (after[0] - large[0])
+                .abs()
+                .min(1.0 - (after[0] - large[0]).abs())
+                < 0.1
+        );
+        assert_ne!(after[4], again[4]);
+    }
+
+    #[test]
+    fn converges_to_the_path_tracer() {
+        // a lamp behind a wall, whose light gets to the floor through a slit
+        let text = "background 0 0 0\n\
+                    material white lambertian 0.8 0.8 0.8\n\
+                    material lamp light 20 20 20\n\
+                    quad -3 0 -3  0 0 6  6 0 0  white\n\
+                    quad -3 0 1  0 2 0  2.9 0 0  white\n\
+                    quad 0.1 0 1  0 2 0  2.9 0 0  white\n\
+                    quad -0.5 1.5 1.5  1 0 0  0 0 1  lamp\n\
+                    camera perspective\nlook_from 0 4 -3\nlook_at 0 0 0.5\nfov 50\naperture 0\n";
+        let render = |integrator: Box<dyn Integrator>, spp: u32| {
+            Renderer::new(|| Scene::parse(text).map_err(|e| e.to_string()))
+                .integrator(integrator)
+                .size(8, 8)
+                .spp(spp)
+                .threads(4)
+                .render()
+                .unwrap()
+                .image
+        };
+        let mlt = MetropolisLightTransport::new(PathTracer::new(10)).with_bootstrap(400_000);
+        let mlt = render(Box::new(mlt), 1024);
+        let path = render(Box::new(PathTracer::new(10)), 4096);
+        let (mut total, mut reference, mut error) = (0.0, 0.0, 0.0);
+        for (a, b) in mlt.pixels.iter().zip(&path.pixels) {
+            total += luminance(a);
+            reference += luminance(b);
+            error += (luminance(a) - luminance(b)).abs();
+        }
+        // pixel by pixel, what is left is mostly the noise of the two images
+        assert!(
+            (total - reference).abs() < 0.05 * reference,
+            "{} against {}",
+            total,
+            reference
+        );
+        assert!(error < 0.2 * reference, "{} off {}", error, reference);
+    }
+}
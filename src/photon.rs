@@ -0,0 +1,694 @@
+use crate::integrator::{direct_light, facing, trace, Integrator, SceneView};
+use crate::light::{bounding_sphere, emit};
+use crate::material::Lobe;
+use crate::misc::random;
+use crate::ray::Ray;
+use crate::vec3::{random_unit_vector, Vec3};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::f32::consts::PI;
+use std::sync::RwLock;
+
+/// Light that a path traced from a source left on a diffuse surface.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Photon {
+    pub p: Vec3,
+    /// Normal of the surface on the side the photon arrived from.
+    pub normal: Vec3,
+    /// The power it carries, over the density of sampling its path and the photons shot.
+    pub power: Vec3,
+    /// Bounces it took before landing here, 0 for light straight from a source.
+    pub bounces: u32,
+    /// Whether it got here through mirrors and glass alone, after at least one of them.
+    pub caustic: bool,
+}
+
+/// Shoots `count` photons from the lights and the background and follows each of them
+/// through up to `max_depth` bounces, leaving one at every diffuse surface it lands on.
+/// Mirrors and glass scatter photons as they do camera rays; off a diffuse surface a photon
+/// goes on with a probability of its albedo.
+pub fn shoot(scene: &SceneView, count: usize, max_depth: u32) -> Vec<Photon> {
+    let mut photons = vec![];
+    for _ in 0..count {
+        let emission = match emit(scene.lights, &scene.background, scene.bounds) {
+            Some(emission) => emission,
+            None => continue,
+        };
+        let mut power = emission.power() / count as f32;
+        let mut ray = emission.ray;
+        let mut diffuse = false;
+        for bounces in 0..=max_depth {
+            let record = match trace(&ray, scene, bounces + 1) {
+                Some(record) => record,
+                None => break,
+            };
+            let material = &record.material;
+            if let Some(albedo) = material.lambert(&record) {
+                let normal = facing(&record.normal, ray.direction());
+                photons.push(Photon {
+                    p: record.p,
+                    normal,
+                    power,
+                    bounces,
+                    caustic: !diffuse && bounces > 0,
+                });
+                let survival = albedo.x.max(albedo.y).max(albedo.z).min(1.0);
+                if random() >= survival {
+                    break;
+                }
+                power = power * albedo / survival;
+                diffuse = true;
+                let mut direction = normal + random_unit_vector();
+                if direction.squared_length() < 1e-12 {
+                    direction = normal;
+                }
+                ray = Ray::new(record.p, direction);
+                continue;
+            }
+            let (is_scattered, attenuation, scattered) = material.scatter(&ray, &record);
+            if !is_scattered {
+                break;
+            }
+            let mut weight = attenuation.as_vec3();
+            // through glass, as for the light paths of the bidirectional path tracer
+            if material.lobe(&ray, &record, &scattered) == Lobe::Transmission {
+                weight = weight * material.eta(&ray, &record).powi(2);
+            }
+            power = power * weight;
+            ray = scattered;
+        }
+    }
+    photons
+}
+
+/// Photons in a balanced kd-tree, kept in one array: the middle of every range splits the
+/// rest of it at the median along the axis on which the range is widest.
+pub struct PhotonMap {
+    photons: Vec<Photon>,
+    /// The axis each photon splits its range on.
+    axes: Vec<u8>,
+}
+
+impl PhotonMap {
+    pub fn new(mut photons: Vec<Photon>) -> PhotonMap {
+        let mut axes = vec![0; photons.len()];
+        balance(&mut photons, &mut axes);
+        PhotonMap { photons, axes }
+    }
+
+    pub fn len(&self) -> usize {
+        self.photons.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.photons.is_empty()
+    }
+
+    /// Calls `found` with every photon within `radius` of `p`.
+    pub fn within(&self, p: &Vec3, radius: f32, mut found: impl FnMut(&Photon)) {
+        let mut r2 = radius * radius;
+        self.search(0, self.photons.len(), p, &mut r2, &mut |i, _, _| {
+            found(&self.photons[i])
+        });
+    }
+
+    /// The `k` photons nearest to `p` but no further than `max_radius`, with the squared
+    /// distance to the furthest of them, or to `max_radius` when fewer were found.
+    pub fn nearest(&self, p: &Vec3, k: usize, max_radius: f32) -> (Vec<&Photon>, f32) {
+        let mut r2 = max_radius * max_radius;
+        if k == 0 {
+            return (vec![], r2);
+        }
+        let mut heap = BinaryHeap::with_capacity(k + 1);
+        self.search(0, self.photons.len(), p, &mut r2, &mut |i, d2, r2| {
+            heap.push(Near(d2, i));
+            if heap.len() > k {
+                heap.pop();
+            }
+            if heap.len() == k {
+                *r2 = heap.peek().map_or(*r2, |near| near.0);
+            }
+        });
+        let found = heap.into_iter().map(|near| &self.photons[near.1]).collect();
+        (found, r2)
+    }
+
+    /// Visits the photons of `lo..hi` within `r2` of `p`, nearer halves first; `visit` may
+    /// narrow `r2` as it goes.
+    fn search(
+        &self,
+        lo: usize,
+        hi: usize,
+        p: &Vec3,
+        r2: &mut f32,
+        visit: &mut impl FnMut(usize, f32, &mut f32),
+    ) {
+        if lo >= hi {
+            return;
+        }
+        let mid = lo + (hi - lo) / 2;
+        let photon = &self.photons[mid];
+        let axis = self.axes[mid] as usize;
+        let delta = p[axis] - photon.p[axis];
+        let (near, far) = if delta < 0.0 {
+            ((lo, mid), (mid + 1, hi))
+        } else {
+            ((mid + 1, hi), (lo, mid))
+        };
+        self.search(near.0, near.1, p, r2, visit);
+        let d2 = (photon.p - p).squared_length();
+        if d2 <= *r2 {
+            visit(mid, d2, r2);
+        }
+        if delta * delta <= *r2 {
+            self.search(far.0, far.1, p, r2, visit);
+        }
+    }
+}
+
+/// Squared distance and index of a photon, ordered by distance.
+struct Near(f32, usize);
+
+impl PartialEq for Near {
+    fn eq(&self, other: &Near) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Near {}
+
+impl PartialOrd for Near {
+    fn partial_cmp(&self, other: &Near) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Near {
+    fn cmp(&self, other: &Near) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+fn balance(photons: &mut [Photon], axes: &mut [u8]) {
+    if photons.len() <= 1 {
+        return;
+    }
+    let (mut min, mut max) = (photons[0].p, photons[0].p);
+    for photon in photons.iter() {
+        let p = photon.p;
+        min = Vec3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z));
+        max = Vec3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z));
+    }
+    let extent = max - min;
+    let axis = if extent.x >= extent.y && extent.x >= extent.z {
+        0
+    } else if extent.y >= extent.z {
+        1
+    } else {
+        2
+    };
+    let mid = photons.len() / 2;
+    photons.select_nth_unstable_by(mid, |a, b| a.p[axis].total_cmp(&b.p[axis]));
+    axes[mid] = axis as u8;
+    let (left, rest) = photons.split_at_mut(mid);
+    let (left_axes, rest_axes) = axes.split_at_mut(mid);
+    balance(left, left_axes);
+    balance(&mut rest[1..], &mut rest_axes[1..]);
+}
+
+/// Radiance leaving a diffuse surface of reflectance `albedo` facing `normal`, from
+/// `photons` found within a disk of squared radius `r2` on it. Photons that landed on the
+/// other side, or on a surface facing elsewhere, are left out.
+fn estimate<'p>(
+    photons: impl IntoIterator<Item = &'p Photon>,
+    normal: &Vec3,
+    albedo: Vec3,
+    r2: f32,
+) -> Vec3 {
+    let mut power = Vec3::new(0.0, 0.0, 0.0);
+    for photon in photons {
+        if photon.normal.dot(normal) > 0.5 {
+            power = power + photon.power;
+        }
+    }
+    albedo / PI * power / (PI * r2)
+}
+
+/// A twentieth of the radius of the scene's bounding sphere, or 1 when it has none.
+fn default_radius(scene: &SceneView) -> f32 {
+    scene
+        .bounds
+        .as_ref()
+        .map_or(1.0, |bounds| bounding_sphere(bounds).1 / 20.0)
+}
+
+/// Cosine-distributed around `normal`.
+fn cosine_direction(normal: &Vec3) -> Vec3 {
+    let direction = *normal + random_unit_vector();
+    if direction.squared_length() < 1e-12 {
+        *normal
+    } else {
+        direction
+    }
+}
+
+/// Photon mapping with final gathering: before the camera rays, photons shot from the
+/// lights land in a global map at every diffuse surface they meet, and in a caustic map
+/// when they got there through mirrors and glass alone. Where a camera ray, through any
+/// mirrors and glass, first meets a diffuse surface, light comes straight from the lights
+/// as for `DirectLighting`, focused by glass from the caustic map, and otherwise from gather
+/// rays that look the global map up where they land.
+///
+/// Lights that cannot be sampled, such as glowing planes, only show where they are seen,
+/// and light from the background only lands within the scene's bounded shapes.
+pub struct PhotonMapper {
+    pub max_depth: u32,
+    /// Photons shot from the lights.
+    pub photons: usize,
+    /// Photons a radiance estimate looks for.
+    pub nearest: usize,
+    /// Furthest a radiance estimate looks; `None` for a twentieth of the radius of the
+    /// sphere around the scene.
+    pub radius: Option<f32>,
+    /// Gather rays for each camera ray.
+    pub gather: u32,
+    maps: RwLock<Option<Maps>>,
+}
+
+struct Maps {
+    global: PhotonMap,
+    caustic: PhotonMap,
+    radius: f32,
+}
+
+impl PhotonMapper {
+    pub fn new(max_depth: u32) -> PhotonMapper {
+        PhotonMapper {
+            max_depth,
+            photons: 100_000,
+            nearest: 50,
+            radius: None,
+            gather: 4,
+            maps: RwLock::new(None),
+        }
+    }
+
+    pub fn with_photons(mut self, photons: usize) -> PhotonMapper {
+        self.photons = photons;
+        self
+    }
+
+    pub fn with_nearest(mut self, nearest: usize) -> PhotonMapper {
+        self.nearest = nearest;
+        self
+    }
+
+    pub fn with_radius(mut self, radius: f32) -> PhotonMapper {
+        self.radius = Some(radius);
+        self
+    }
+
+    pub fn with_gather(mut self, gather: u32) -> PhotonMapper {
+        self.gather = gather;
+        self
+    }
+
+    fn lookup(&self, map: &PhotonMap, p: &Vec3, normal: &Vec3, albedo: Vec3, radius: f32) -> Vec3 {
+        let (photons, r2) = map.nearest(p, self.nearest, radius);
+        estimate(photons, normal, albedo, r2)
+    }
+
+    /// Radiance the global map gives the first diffuse surface `ray` meets through mirrors
+    /// and glass. Light it finds straight from a source or the background has been
+    /// counted already, directly or in the caustic map.
+    fn gathered(&self, mut ray: Ray, scene: &SceneView, maps: &Maps, depth: u32) -> Vec3 {
+        let zero = Vec3::new(0.0, 0.0, 0.0);
+        let mut throughput = Vec3::new(1.0, 1.0, 1.0);
+        for depth in depth..=self.max_depth {
+            let record = match trace(&ray, scene, depth) {
+                Some(record) => record,
+                None => return zero,
+            };
+            let material = &record.material;
+            if let Some(albedo) = material.lambert(&record) {
+                let normal = facing(&record.normal, ray.direction());
+                let light = self.lookup(&maps.global, &record.p, &normal, albedo, maps.radius);
+                return throughput * light;
+            }
+            let (is_scattered, attenuation, scattered) = material.scatter(&ray, &record);
+            if !is_scattered {
+                return zero;
+            }
+            throughput = throughput * attenuation.as_vec3();
+            ray = scattered;
+        }
+        zero
+    }
+}
+
+impl Integrator for PhotonMapper {
+    /// Without `prepare` there are no photons, and only direct light is found.
+    fn radiance(&self, r: &Ray, scene: &SceneView) -> Vec3 {
+        let maps = self.maps.read().unwrap();
+        let mut ray = Ray::new(*r.origin(), *r.direction());
+        let mut throughput = Vec3::new(1.0, 1.0, 1.0);
+        let mut radiance = Vec3::new(0.0, 0.0, 0.0);
+        for depth in 0..=self.max_depth {
+            let record = match trace(&ray, scene, depth) {
+                Some(record) => record,
+                None => return radiance + throughput * scene.background.radiance(ray.direction()),
+            };
+            let material = &record.material;
+            radiance = radiance + throughput * material.emitted(&record);
+            if let Some(albedo) = material.lambert(&record) {
+                let mut light = direct_light(&ray, &record, albedo, scene);
+                if let Some(maps) = maps.as_ref() {
+                    let normal = facing(&record.normal, ray.direction());
+                    light =
+                        light + self.lookup(&maps.caustic, &record.p, &normal, albedo, maps.radius);
+                    let mut gathered = Vec3::new(0.0, 0.0, 0.0);
+                    for _ in 0..self.gather {
+                        let gather = Ray::new(record.p, cosine_direction(&normal));
+                        gathered = gathered + self.gathered(gather, scene, maps, depth + 1);
+                    }
+                    light = light + albedo * gathered / self.gather.max(1) as f32;
+                }
+                return radiance + throughput * light;
+            }
+            let (is_scattered, attenuation, scattered) = material.scatter(&ray, &record);
+            if depth == self.max_depth || !is_scattered {
+                break;
+            }
+            throughput = throughput * attenuation.as_vec3();
+            ray = scattered;
+        }
+        radiance
+    }
+
+    fn prepare(&self, scene: &SceneView, _pass: u32) {
+        let photons = shoot(scene, self.photons, self.max_depth);
+        let caustic = photons.iter().filter(|p| p.caustic).copied().collect();
+        *self.maps.write().unwrap() = Some(Maps {
+            global: PhotonMap::new(photons),
+            caustic: PhotonMap::new(caustic),
+            radius: self.radius.unwrap_or_else(|| default_radius(scene)),
+        });
+    }
+}
+
+/// Stochastic progressive photon mapping, in the probabilistic form of Knaus and Zwicker:
+/// every sample of a pixel is a pass of its own, which shoots photons anew and estimates
+/// the light where camera rays first meet a diffuse surface from those within a radius that
+/// shrinks from one pass to the next. Each pass is biased, blurring light over the radius,
+/// but their average converges, sharp caustics included. Light straight from the lights is
+/// sampled as for `DirectLighting`, and light from the background only lands within the
+/// scene's bounded shapes.
+pub struct ProgressivePhotonMapper {
+    pub max_depth: u32,
+    /// Photons shot every pass.
+    pub photons: usize,
+    /// Radius of the first pass; `None` for a twentieth of the radius of the sphere around
+    /// the scene.
+    pub radius: Option<f32>,
+    /// Between 0 and 1, how slowly the radius shrinks: the area it covers goes down by a
+    /// factor of `(i + alpha) / (i + 1)` after pass `i`.
+    pub alpha: f32,
+    pass: RwLock<Option<(PhotonMap, f32)>>,
+}
+
+impl ProgressivePhotonMapper {
+    pub fn new(max_depth: u32) -> ProgressivePhotonMapper {
+        ProgressivePhotonMapper {
+            max_depth,
+            photons: 20_000,
+            radius: None,
+            alpha: 2.0 / 3.0,
+            pass: RwLock::new(None),
+        }
+    }
+
+    pub fn with_photons(mut self, photons: usize) -> ProgressivePhotonMapper {
+        self.photons = photons;
+        self
+    }
+
+    pub fn with_radius(mut self, radius: f32) -> ProgressivePhotonMapper {
+        self.radius = Some(radius);
+        self
+    }
+
+    pub fn with_alpha(mut self, alpha: f32) -> ProgressivePhotonMapper {
+        self.alpha = alpha;
+        self
+    }
+
+    /// The radius of pass `pass`, counting from 0, starting from `radius`.
+    fn shrink(&self, radius: f32, pass: u32) -> f32 {
+        let mut r2 = radius * radius;
+        for i in 1..=pass {
+            r2 *= (i as f32 + self.alpha) / (i as f32 + 1.0);
+        }
+        r2.sqrt()
+    }
+}
+
+impl Integrator for ProgressivePhotonMapper {
+    /// Without `prepare` there are no photons, and only direct light is found.
+    fn radiance(&self, r: &Ray, scene: &SceneView) -> Vec3 {
+        let pass = self.pass.read().unwrap();
+        let mut ray = Ray::new(*r.origin(), *r.direction());
+        let mut throughput = Vec3::new(1.0, 1.0, 1.0);
+        let mut radiance = Vec3::new(0.0, 0.0, 0.0);
+        for depth in 0..=self.max_depth {
+            let record = match trace(&ray, scene, depth) {
+                Some(record) => record,
+                None => return radiance + throughput * scene.background.radiance(ray.direction()),
+            };
+            let material = &record.material;
+            radiance = radiance + throughput * material.emitted(&record);
+            if let Some(albedo) = material.lambert(&record) {
+                let mut light = direct_light(&ray, &record, albedo, scene);
+                if let Some((map, radius)) = pass.as_ref() {
+                    let normal = facing(&record.normal, ray.direction());
+                    let mut found = vec![];
+                    map.within(&record.p, *radius, |photon| found.push(*photon));
+                    light = light + estimate(&found, &normal, albedo, radius * radius);
+                }
+                return radiance + throughput * light;
+            }
+            let (is_scattered, attenuation, scattered) = material.scatter(&ray, &record);
+            if depth == self.max_depth || !is_scattered {
+                break;
+            }
+            throughput = throughput * attenuation.as_vec3();
+            ray = scattered;
+        }
+        radiance
+    }
+
+    /// Shoots the photons of `pass`; those straight from a source are left out, as direct
+    /// light is sampled.
+    fn prepare(&self, scene: &SceneView, pass: u32) {
+        let photons = shoot(scene, self.photons, self.max_depth)
+            .into_iter()
+            .filter(|photon| photon.bounces > 0)
+            .collect();
+        let radius = self.radius.unwrap_or_else(|| default_radius(scene));
+        *self.pass.write().unwrap() = Some((PhotonMap::new(photons), self.shrink(radius, pass)));
+    }
+
+    fn progressive(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bvh::Bvh;
+    use crate::integrator::PathTracer;
+    use crate::misc;
+    use crate::render::Renderer;
+    use crate::scene::Scene;
+
+    /// Builds the scene and hands its view to `f`.
+    fn with_view<T>(text: &str, f: impl FnOnce(&SceneView) -> T) -> T {
+        let scene = Scene::parse(text).unwrap();
+        let camera = scene.camera.build(1.0).unwrap();
+        let world = Bvh::new(scene.objects);
+        f(&SceneView {
+            bounds: world.finite_bounds(),
+            world: &world,
+            lights: &scene.lights,
+            background: scene.background,
+            materials: &scene.materials,
+            camera: camera.as_ref(),
+        })
+    }
+
+    /// Mean radiance of `n` rays from `origin` spread over a small cone around `direction`.
+    fn mean(
+        integrator: &dyn Integrator,
+        view: &SceneView,
+        origin: Vec3,
+        direction: Vec3,
+        n: usize,
+    ) -> Vec3 {
+        let mut sum = Vec3::new(0.0, 0.0, 0.0);
+        for _ in 0..n {
+            let r = Ray::new(origin, direction + 0.05 * random_unit_vector());
+            sum = sum + integrator.radiance(&r, view);
+        }
+        sum / n as f32
+    }
+
+    const LAMP: &str = "background 0 0 0\n\
+                        material white lambertian 0.8 0.8 0.8\n\
+                        material red lambertian 0.8 0.2 0.2\n\
+                        material lamp light 4 4 4\n\
+                        quad -2 0 -2  0 0 4  4 0 0  white\n\
+                        quad -2 0 2  0 3 0  4 0 0  red\n\
+                        quad -0.5 2 -0.5  1 0 0  0 0 1  lamp\n\
+                        box -0.8 0 0  -0.2 0.6 0.6  white\n";
+
+    /// A lamp inside a glass ball over a floor, lit by nothing but caustics.
+    const GLASS: &str = "background 0 0 0\n\
+                         material white lambertian 0.8 0.8 0.8\n\
+                         material glass dielectric 1.5\n\
+                         material lamp light 6 6 6\n\
+                         quad -4 0 -4  0 0 8  8 0 0  white\n\
+                         sphere 0 1.2 0 0.5 lamp\n\
+                         sphere 0 1.2 0 0.9 glass\n";
+
+    #[test]
+    fn searches_find_what_brute_force_does() {
+        misc::seed(2);
+        let photons: Vec<Photon> = (0..2000)
+            .map(|i| Photon {
+                p: Vec3::new(random(), random() * 0.5, random() * 2.0),
+                normal: Vec3::new(0.0, 1.0, 0.0),
+                power: Vec3::new(i as f32, 0.0, 0.0),
+                bounces: 0,
+                caustic: false,
+            })
+            .collect();
+        let map = PhotonMap::new(photons.clone());
+        assert_eq!(map.len(), photons.len());
+        for _ in 0..50 {
+            let p = Vec3::new(random(), random(), random());
+            let ids = |found: Vec<&Photon>| {
+                let mut ids: Vec<u32> = found.iter().map(|photon| photon.power.x as u32).collect();
+                ids.sort();
+                ids
+            };
+            let mut by_distance: Vec<&Photon> = photons.iter().collect();
+            by_distance.sort_by(|a, b| {
+                let (da, db) = ((a.p - p).squared_length(), (b.p - p).squared_length());
+                da.total_cmp(&db)
+            });
+
+            let mut within = vec![];
+            map.within(&p, 0.1, |photon| within.push(*photon));
+            let expected: Vec<&Photon> = by_distance
+                .iter()
+                .copied()
+                .filter(|photon| (photon.p - p).length() <= 0.1)
+                .collect();
+            assert_eq!(ids(within.iter().collect()), ids(expected));
+
+            let (nearest, r2) = map.nearest(&p, 10, 1.0);
+            assert_eq!(ids(nearest), ids(by_distance[..10].to_vec()));
+            assert_eq!(r2, (by_distance[9].p - p).squared_length());
+
+            let (few, r2) = map.nearest(&p, 10, 0.01);
+            assert!(few.len() < 10 && r2 == 0.01 * 0.01);
+        }
+    }
+
+    #[test]
+    fn photons_carry_the_power_of_the_lights() {
+        // a lamp of one square unit shines 2 pi L from its two sides, and a photon leaves
+        // it on every shot
+        with_view(LAMP, |view| {
+            misc::seed(3);
+            let n = 20_000;
+            let mut power = 0.0;
+            for _ in 0..n {
+                power += emit(view.lights, &view.background, view.bounds)
+                    .unwrap()
+                    .power()
+                    .x;
+            }
+            let expected = 2.0 * PI * 4.0;
+            assert!(
+                (power / n as f32 - expected).abs() < 1e-3 * expected,
+                "{} against {}",
+                power / n as f32,
+                expected
+            );
+            let photons = shoot(view, 1000, 20);
+            assert!(photons.iter().any(|p| p.bounces == 0));
+            assert!(photons.iter().any(|p| p.bounces > 0));
+        });
+    }
+
+    #[test]
+    fn agrees_with_the_path_tracer() {
+        // one map is noisy where the rays land, so the estimates of a few are averaged
+        misc::seed(1);
+        for (text, origin, direction) in [
+            (LAMP, Vec3::new(0.0, 1.0, -3.0), Vec3::new(0.0, -0.5, 3.0)),
+            (GLASS, Vec3::new(0.0, 3.0, -3.0), Vec3::new(1.5, -3.0, 3.0)),
+        ] {
+            with_view(text, |view| {
+                let mapper = PhotonMapper::new(20).with_photons(20_000);
+                let maps = 16;
+                let mut photons = Vec3::new(0.0, 0.0, 0.0);
+                for _ in 0..maps {
+                    mapper.prepare(view, 0);
+                    photons = photons + mean(&mapper, view, origin, direction, 500) / maps as f32;
+                }
+                let path = mean(&PathTracer::new(20), view, origin, direction, 200_000);
+                assert!(
+                    (photons - path).length() < 0.05 * path.length(),
+                    "{:?} against {:?}",
+                    photons,
+                    path
+                );
+            });
+        }
+    }
+
+    #[test]
+    fn progressive_passes_converge_to_the_path_tracer() {
+        let text = format!(
+            "{}camera perspective\nlook_from 0 6 -4\nlook_at 0 0.5 0\nfov 40\naperture 0\n",
+            GLASS
+        );
+        let render = |integrator: Box<dyn Integrator>, spp: u32| {
+            let image = Renderer::new(|| Scene::parse(&text).map_err(|e| e.to_string()))
+                .integrator(integrator)
+                .size(12, 12)
+                .spp(spp)
+                .render()
+                .unwrap()
+                .image;
+            image
+                .pixels
+                .iter()
+                .fold(Vec3::new(0.0, 0.0, 0.0), |sum, p| sum + *p)
+                / image.pixels.len() as f32
+        };
+        let sppm = ProgressivePhotonMapper::new(20).with_photons(5000);
+        assert!(sppm.shrink(1.0, 10) < sppm.shrink(1.0, 1));
+        assert!(sppm.shrink(1.0, 1) < 1.0 && sppm.shrink(1.0, 0) == 1.0);
+        let sppm = render(Box::new(sppm), 64);
+        let path = render(Box::new(PathTracer::new(20)), 1024);
+        assert!(
+            (sppm - path).length() < 0.04 * path.length(),
+            "{:?} against {:?}",
+            sppm,
+            path
+        );
+    }
+}
@@ -0,0 +1,531 @@
+use crate::cli::{positive, real, vec3, CliError};
+use crate::film::{Exposure, Film};
+use crate::image::Image;
+use crate::scene::CameraSettings;
+use crate::vec3::Vec3;
+use crate::video::encode_png;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+
+/// What the browser can change while the preview runs.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Settings {
+    pub look_from: Vec3,
+    pub look_at: Vec3,
+    pub fov: f32,
+    pub aperture: f32,
+    pub focus_dist: f32,
+    pub spp: u32,
+    /// Exposure value in stops; only changes how the image is developed.
+    pub exposure: f32,
+}
+
+impl Settings {
+    pub fn new(camera: &CameraSettings, spp: u32, film: &Film) -> Settings {
+        Settings {
+            look_from: camera.look_from,
+            look_at: camera.look_at,
+            fov: camera.fov,
+            aperture: camera.aperture,
+            focus_dist: camera.focus_dist,
+            spp,
+            exposure: film.exposure.ev100(),
+        }
+    }
+
+    /// Whether the image has to be rendered again rather than refined or redeveloped.
+    fn moves_camera(&self, other: &Settings) -> bool {
+        self.look_from != other.look_from
+            || self.look_at != other.look_at
+            || self.fov != other.fov
+            || self.aperture != other.aperture
+            || self.focus_dist != other.focus_dist
+    }
+
+    /// Sets the parts of `camera` these settings control.
+    pub fn apply(&self, camera: &mut CameraSettings) {
+        camera.look_from = self.look_from;
+        camera.look_at = self.look_at;
+        camera.fov = self.fov;
+        camera.aperture = self.aperture;
+        camera.focus_dist = self.focus_dist;
+    }
+
+    /// Applies an `application/x-www-form-urlencoded` update such as `spp=64&fov=30`, and
+    /// fails unless the result still makes a usable `camera`.
+    pub fn update(&mut self, form: &str, camera: &CameraSettings) -> Result<(), CliError> {
+        let mut next = *self;
+        for pair in form.split('&').filter(|p| !p.is_empty()) {
+            let (key, value) = match pair.find('=') {
+                Some(i) => (&pair[..i], url_decode(&pair[i + 1..])),
+                None => (pair, String::new()),
+            };
+            match key {
+                "look_from" => next.look_from = vec3(key, &value)?,
+                "look_at" => next.look_at = vec3(key, &value)?,
+                "fov" => next.fov = real(key, &value)?,
+                "aperture" => next.aperture = real(key, &value)?,
+                "focus_dist" => next.focus_dist = real(key, &value)?,
+                "spp" => next.spp = positive(key, &value)?,
+                "exposure" => next.exposure = real(key, &value)?,
+                _ => return Err(CliError(format!("unknown setting `{}`", key))),
+            }
+        }
+        let mut camera = camera.clone();
+        next.apply(&mut camera);
+        camera.check().map_err(|e| CliError(e.to_string()))?;
+        *self = next;
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> String {
+        let v = |v: Vec3| format!("\"{},{},{}\"", v.x, v.y, v.z);
+        format!(
+            "{{\"look_from\":{},\"look_at\":{},\"fov\":{},\"aperture\":{},\"focus_dist\":{},\
+             \"spp\":{},\"exposure\":{}}}",
+            v(self.look_from),
+            v(self.look_at),
+            self.fov,
+            self.aperture,
+            self.focus_dist,
+            self.spp,
+            self.exposure
+        )
+    }
+}
+
+fn url_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = |b: u8| (b as char).to_digit(16).map(|d| d as u8);
+                match (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                    (Some(high), Some(low)) => {
+                        out.push(high * 16 + low);
+                        i += 2;
+                    }
+                    _ => out.push(b'%'),
+                }
+            }
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+struct State {
+    settings: Settings,
+    /// Counts camera changes; work done for an older one is thrown away.
+    generation: u64,
+    image: Option<Image>,
+    samples: u32,
+    /// Why the current generation cannot be rendered.
+    error: Option<String>,
+    /// Counts everything a browser should hear about.
+    version: u64,
+}
+
+/// A progressively refined framebuffer shared between a render loop and the HTTP server
+/// that shows it.
+pub struct Preview {
+    state: Mutex<State>,
+    changed: Condvar,
+    /// What the settings are applied to, to check them.
+    camera: CameraSettings,
+    film: Film,
+}
+
+impl Preview {
+    pub fn new(camera: CameraSettings, spp: u32, film: Film) -> Preview {
+        Preview {
+            state: Mutex::new(State {
+                settings: Settings::new(&camera, spp, &film),
+                generation: 0,
+                image: None,
+                samples: 0,
+                error: None,
+                version: 0,
+            }),
+            changed: Condvar::new(),
+            camera,
+            film,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn notify(&self, state: &mut State) {
+        state.version += 1;
+        self.changed.notify_all();
+    }
+
+    /// Blocks until there is work for the render loop, which has `samples` per pixel of
+    /// `generation`: either more samples are wanted or the camera moved.
+    pub fn next_job(&self, generation: u64, samples: u32) -> (Settings, u64) {
+        let mut state = self.lock();
+        while state.generation == generation
+            && (samples >= state.settings.spp || state.error.is_some())
+        {
+            state = self.changed.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+        (state.settings, state.generation)
+    }
+
+    /// Shows `image`, the average of `samples` per pixel, unless the camera has moved since.
+    pub fn publish(&self, generation: u64, samples: u32, image: Image) {
+        let mut state = self.lock();
+        if state.generation == generation {
+            state.image = Some(image);
+            state.samples = samples;
+            self.notify(&mut state);
+        }
+    }
+
+    pub fn fail(&self, generation: u64, message: String) {
+        let mut state = self.lock();
+        if state.generation == generation {
+            state.error = Some(message);
+            self.notify(&mut state);
+        }
+    }
+
+    pub fn update(&self, form: &str) -> Result<Settings, CliError> {
+        let mut state = self.lock();
+        let mut settings = state.settings;
+        settings.update(form, &self.camera)?;
+        if settings.moves_camera(&state.settings) {
+            state.generation += 1;
+            state.samples = 0;
+        }
+        state.settings = settings;
+        state.error = None;
+        self.notify(&mut state);
+        Ok(settings)
+    }
+
+    fn status(&self, state: &State) -> String {
+        format!(
+            "{{\"generation\":{},\"samples\":{},\"settings\":{},\"error\":{}}}",
+            state.generation,
+            state.samples,
+            state.settings.to_json(),
+            state
+                .error
+                .as_deref()
+                .map_or("null".to_string(), json_string)
+        )
+    }
+
+    /// The image as developed with the current exposure.
+    pub fn png(&self) -> Option<Vec<u8>> {
+        let state = self.lock();
+        let image = state.image.as_ref()?;
+        let film = Film {
+            exposure: Exposure::Ev(state.settings.exposure),
+            ..self.film
+        };
+        Some(encode_png(image.width, image.height, &film.develop(image)))
+    }
+
+    /// Answers requests until the listener fails, one thread per connection.
+    pub fn serve(self: Arc<Self>, listener: TcpListener) -> io::Result<()> {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let preview = self.clone();
+            std::thread::spawn(move || {
+                // a browser going away mid-answer is no concern of the preview
+                let _ = preview.handle(stream);
+            });
+        }
+        Ok(())
+    }
+
+    fn handle(&self, stream: TcpStream) -> io::Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut request = String::new();
+        reader.read_line(&mut request)?;
+        let mut length = 0;
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+                break;
+            }
+            let lower = header.to_ascii_lowercase();
+            if let Some(value) = lower.strip_prefix("content-length:") {
+                length = value.trim().parse().unwrap_or(0);
+            }
+        }
+        let mut body = vec![0; length.min(1 << 16)];
+        reader.read_exact(&mut body)?;
+        let mut words = request.split_whitespace();
+        let method = words.next().unwrap_or("");
+        let target = words.next().unwrap_or("/");
+        let path = target.split('?').next().unwrap_or("/");
+
+        let mut out = stream;
+        match (method, path) {
+            ("GET", "/") => respond(
+                &mut out,
+                "200 OK",
+                "text/html; charset=utf-8",
+                PAGE.as_bytes(),
+            ),
+            ("GET", "/frame.png") => match self.png() {
+                Some(png) => respond(&mut out, "200 OK", "image/png", &png),
+                None => respond(&mut out, "404 Not Found", "text/plain", b"no image yet"),
+            },
+            ("GET", "/status") => {
+                let status = self.status(&self.lock());
+                respond(&mut out, "200 OK", "application/json", status.as_bytes())
+            }
+            ("POST", "/settings") => match self.update(&String::from_utf8_lossy(&body)) {
+                Ok(_) => {
+                    let status = self.status(&self.lock());
+                    respond(&mut out, "200 OK", "application/json", status.as_bytes())
+                }
+                Err(e) => respond(
+                    &mut out,
+                    "400 Bad Request",
+                    "text/plain; charset=utf-8",
+                    e.0.as_bytes(),
+                ),
+            },
+            ("GET", "/events") => self.events(out),
+            _ => respond(&mut out, "404 Not Found", "text/plain", b"not found"),
+        }
+    }
+
+    /// Server-sent events carrying the status whenever it changes.
+    fn events(&self, mut out: TcpStream) -> io::Result<()> {
+        out.write_all(
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\n\
+              Connection: keep-alive\r\n\r\n",
+        )?;
+        let mut seen = None;
+        loop {
+            let message = {
+                let mut state = self.lock();
+                if seen == Some(state.version) {
+                    state = self
+                        .changed
+                        .wait_timeout(state, Duration::from_secs(15))
+                        .unwrap_or_else(|e| e.into_inner())
+                        .0;
+                }
+                if seen == Some(state.version) {
+                    // keeps proxies from closing the stream and notices a closed browser
+                    ":\n\n".to_string()
+                } else {
+                    seen = Some(state.version);
+                    format!("data: {}\n\n", self.status(&state))
+                }
+            };
+            out.write_all(message.as_bytes())?;
+            out.flush()?;
+        }
+    }
+}
+
+fn respond(out: &mut TcpStream, status: &str, content_type: &str, body: &[u8]) -> io::Result<()> {
+    write!(
+        out,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\n\
+         Connection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    out.write_all(body)?;
+    out.flush()
+}
+
+const PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>ray_tracing_001 preview</title>
+<style>
+body { font-family: sans-serif; background: #222; color: #ddd; margin: 1em; }
+img { image-rendering: pixelated; max-width: 100%; background: #000; }
+form { display: grid; grid-template-columns: auto 12em; gap: .3em 1em; margin-top: 1em; }
+#status { margin-top: .5em; }
+.error { color: #f66; }
+</style>
+</head>
+<body>
+<img id="frame" alt="waiting for the first pass">
+<div id="status"></div>
+<form id="settings">
+<label for="look_from">look from</label><input id="look_from" name="look_from">
+<label for="look_at">look at</label><input id="look_at" name="look_at">
+<label for="fov">field of view</label><input id="fov" name="fov" type="number" step="any">
+<label for="aperture">aperture</label><input id="aperture" name="aperture" type="number" step="any">
+<label for="focus_dist">focus distance</label><input id="focus_dist" name="focus_dist" type="number" step="any">
+<label for="spp">samples per pixel</label><input id="spp" name="spp" type="number" min="1">
+<label for="exposure">exposure (EV)</label><input id="exposure" name="exposure" type="number" step="0.5">
+<span></span><button>apply</button>
+</form>
+<script>
+const form = document.getElementById("settings");
+const status = document.getElementById("status");
+let filled = false;
+function show(s) {
+  if (!filled) {
+    for (const [k, v] of Object.entries(s.settings)) form.elements[k].value = v;
+    filled = true;
+  }
+  status.className = s.error ? "error" : "";
+  status.textContent = s.error || `${s.samples} / ${s.settings.spp} samples per pixel`;
+  if (s.samples > 0) document.getElementById("frame").src = "/frame.png?" + s.generation + "-" + s.samples + "-" + s.settings.exposure;
+}
+new EventSource("/events").onmessage = e => show(JSON.parse(e.data));
+form.onsubmit = async e => {
+  e.preventDefault();
+  const answer = await fetch("/settings", { method: "POST", body: new URLSearchParams(new FormData(form)) });
+  if (!answer.ok) { status.className = "error"; status.textContent = await answer.text(); }
+};
+</script>
+</body>
+</html>
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::FisheyeMapping;
+    use crate::lens::LensSystem;
+    use crate::scene::Projection;
+
+    fn preview() -> Preview {
+        Preview::new(CameraSettings::default(), 16, Film::default())
+    }
+
+    #[test]
+    fn updates_settings_from_forms() {
+        let camera = CameraSettings::default();
+        let mut s = Settings::new(&camera, 16, &Film::default());
+        s.update("look_from=1%2C2%2C3&fov=30&spp=64&exposure=-1.5", &camera)
+            .unwrap();
+        assert_eq!(s.look_from, Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!((s.fov, s.spp, s.exposure), (30.0, 64, -1.5));
+        let before = s;
+        for bad in [
+            "fov=0",
+            "fov=400",
+            "aperture=-1",
+            "focus_dist=0",
+            "spp=-3",
+            "look_at=1,2,3&look_from=1,2,3",
+            "zoom=2",
+        ] {
+            assert!(s.update(bad, &camera).is_err(), "{}", bad);
+        }
+        assert_eq!(s, before);
+
+        // the limits are those of the scene's camera
+        let fisheye = CameraSettings {
+            projection: Projection::Fisheye(FisheyeMapping::Equidistant),
+            ..CameraSettings::default()
+        };
+        s.update("fov=270", &fisheye).unwrap();
+        let lens = CameraSettings {
+            projection: Projection::Lens,
+            lens: Some(LensSystem::load("lenses/dgauss50.lens").unwrap()),
+            ..CameraSettings::default()
+        };
+        s.update("focus_dist=2", &lens).unwrap();
+        let error = s.update("focus_dist=0.001", &lens).unwrap_err();
+        assert_eq!(error.0, "the lens cannot focus at 0.001");
+        assert_eq!(url_decode("a+b%2Cc%zz%4"), "a b,c%zz%4");
+    }
+
+    #[test]
+    fn restarts_only_when_the_camera_moves() {
+        let preview = preview();
+        let (s, generation) = preview.next_job(u64::MAX, 0);
+        assert_eq!((s.spp, generation), (16, 0));
+        preview.publish(0, 16, Image::new(2, 1));
+        assert!(preview.png().is_some());
+
+        // more samples refine the same image
+        preview.update("spp=32&exposure=2").unwrap();
+        assert_eq!(preview.next_job(0, 16).1, 0);
+        preview.update("fov=45").unwrap();
+        assert_eq!(preview.next_job(0, 16).1, 1);
+        // a pass of the old view arriving late is dropped
+        preview.publish(0, 32, Image::new(2, 1));
+        assert_eq!(preview.lock().samples, 0);
+        preview.fail(1, "no".to_string());
+        assert!(preview.status(&preview.lock()).contains("\"error\":\"no\""));
+    }
+
+    fn request(port: u16, text: &str) -> String {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream.write_all(text.as_bytes()).unwrap();
+        let mut answer = String::new();
+        stream.read_to_string(&mut answer).unwrap();
+        answer
+    }
+
+    #[test]
+    fn serves_over_http() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let preview = Arc::new(preview());
+        let server = preview.clone();
+        std::thread::spawn(move || server.serve(listener));
+
+        let page = request(port, "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(page.starts_with("HTTP/1.1 200 OK"));
+        assert!(page.contains("EventSource"));
+        assert!(request(port, "GET /frame.png HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 404"));
+
+        let body = "look_at=0,1,0&spp=8";
+        let answer = request(
+            port,
+            &format!(
+                "POST /settings HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            ),
+        );
+        assert!(answer.starts_with("HTTP/1.1 200 OK"));
+        assert!(answer.contains("\"generation\":1"));
+        assert!(answer.contains("\"spp\":8"));
+        let rejected = request(
+            port,
+            "POST /settings HTTP/1.1\r\nContent-Length: 5\r\n\r\nspp=x",
+        );
+        assert!(rejected.starts_with("HTTP/1.1 400"));
+        assert!(rejected.contains("spp: expected a positive whole number"));
+        let rejected = request(
+            port,
+            "POST /settings HTTP/1.1\r\nContent-Length: 7\r\n\r\nfov=400",
+        );
+        assert!(rejected.starts_with("HTTP/1.1 400"));
+        assert!(rejected.contains("field of view"));
+    }
+}
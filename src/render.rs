@@ -0,0 +1,536 @@
+use crate::animation::frame_seed;
+use crate::bvh::Bvh;
+use crate::image::Image;
+use crate::integrator::{Integrator, PathTracer, SceneView, Splat};
+use crate::misc::{self, random};
+use crate::scene::{CameraSettings, Scene};
+use crate::stats::Counters;
+use crate::vec3::Vec3;
+use std::fmt;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Once};
+
+/// Where the samples of a pixel fall.
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub enum Sampler {
+    /// Uniformly at random over the pixel.
+    #[default]
+    Independent,
+    /// One jittered sample in each cell of the largest square grid the sample count fills,
+    /// the remainder at random.
+    Stratified,
+}
+
+impl Sampler {
+    /// Offset within the pixel of sample `index` out of `spp`, both coordinates in `[0, 1)`.
+    fn offset(self, index: u32, spp: u32) -> (f32, f32) {
+        let side = (spp as f32).sqrt() as u32;
+        match self {
+            Sampler::Stratified if index < side * side => (
+                ((index % side) as f32 + random()) / side as f32,
+                ((index / side) as f32 + random()) / side as f32,
+            ),
+            _ => (random(), random()),
+        }
+    }
+}
+
+/// Stops a render from another thread. Clones share the same flag.
+#[derive(Clone, Default, Debug)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RenderError {
+    /// The scene could not be built.
+    Scene(String),
+    /// The `CancelToken` fired before the last tile was done.
+    Cancelled,
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RenderError::Scene(message) => write!(f, "{}", message),
+            RenderError::Cancelled => write!(f, "render cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for RenderError {}
+
+/// A finished block of the image, passed to `Renderer::on_tile`.
+pub struct Tile<'a> {
+    /// Column of the left edge.
+    pub x: usize,
+    /// Row of the top edge, counting from the top of the image.
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+    /// Linear radiance, row by row from the top. Light that integrators splat onto the image
+    /// from paths started at the lights is only added to the finished image, and progressive
+    /// integrators hand over every tile once a pass, with the samples of that pass alone.
+    pub pixels: &'a [Vec3],
+}
+
+pub struct Output {
+    /// Linear radiance, ready for `Film::develop`.
+    pub image: Image,
+    pub counters: Counters,
+}
+
+type SceneSource<'a> = Box<dyn Fn() -> Result<Scene, String> + Sync + 'a>;
+type CameraChange<'a> = Box<dyn Fn(&mut CameraSettings) + Sync + 'a>;
+type TileCallback<'a> = Box<dyn Fn(&Tile) + Sync + 'a>;
+type ProgressCallback<'a> = Box<dyn Fn(u64, u64) + Sync + 'a>;
+
+/// Renders a scene into a framebuffer on several threads, a tile at a time.
+///
+/// Scenes share their materials through `Rc`, so rather than a scene it takes a function
+/// that builds one, which every thread calls for a copy of its own; the camera is changed
+/// through a function for the same reason:
+///
+/// ```no_run
+/// use ray_tracing_001::render::Renderer;
+/// use ray_tracing_001::scene::Scene;
+///
+/// let output = Renderer::new(|| Scene::load("scenes/random.scene").map_err(|e| e.to_string()))
+///     .camera(|camera| camera.fov = 30.0)
+///     .size(320, 180)
+///     .spp(16)
+///     .on_progress(|done, total| eprintln!("{} of {} samples", done, total))
+///     .render()
+///     .unwrap();
+/// assert_eq!(output.image.width, 320);
+/// ```
+///
+/// Every pixel draws from a sequence of its own, so the image depends on the seed but not
+/// on the number of threads or the tile size. Splats from integrators that trace light to
+/// the camera are the exception: each thread sums its own, so the order of the additions,
+/// and with it the last bits of those pixels, varies.
+///
+/// Progressive integrators, such as stochastic progressive photon mapping, take the samples
+/// of every pixel in as many passes over the image, each after tracing the scene anew.
+pub struct Renderer<'a> {
+    scene: SceneSource<'a>,
+    camera: Option<CameraChange<'a>>,
+    sampler: Sampler,
+    integrator: Box<dyn Integrator + 'a>,
+    size: (usize, usize),
+    spp: u32,
+    seed: u64,
+    threads: usize,
+    tile_size: usize,
+    on_tile: Option<TileCallback<'a>>,
+    on_progress: Option<ProgressCallback<'a>>,
+    cancel: Option<CancelToken>,
+}
+
+impl<'a> Renderer<'a> {
+    /// A 400x225 path-traced image at 1 sample per pixel, on every core.
+    pub fn new(scene: impl Fn() -> Result<Scene, String> + Sync + 'a) -> Renderer<'a> {
+        Renderer {
+            scene: Box::new(scene),
+            camera: None,
+            sampler: Sampler::default(),
+            integrator: Box::new(PathTracer::new(50)),
+            size: (400, 225),
+            spp: 1,
+            seed: 0,
+            threads: std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1),
+            tile_size: 16,
+            on_tile: None,
+            on_progress: None,
+            cancel: None,
+        }
+    }
+
+    /// Changes the scene's own camera, e.g. to move it or replace it altogether.
+    pub fn camera(mut self, change: impl Fn(&mut CameraSettings) + Sync + 'a) -> Renderer<'a> {
+        self.camera = Some(Box::new(change));
+        self
+    }
+
+    pub fn sampler(mut self, sampler: Sampler) -> Renderer<'a> {
+        self.sampler = sampler;
+        self
+    }
+
+    pub fn integrator(mut self, integrator: impl Integrator + 'a) -> Renderer<'a> {
+        self.integrator = Box::new(integrator);
+        self
+    }
+
+    pub fn size(mut self, width: usize, height: usize) -> Renderer<'a> {
+        self.size = (width, height);
+        self
+    }
+
+    /// Samples per pixel. Panics on 0, which leaves nothing to average.
+    pub fn spp(mut self, spp: u32) -> Renderer<'a> {
+        assert!(spp > 0, "a render needs at least one sample per pixel");
+        self.spp = spp;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Renderer<'a> {
+        self.seed = seed;
+        self
+    }
+
+    pub fn threads(mut self, threads: usize) -> Renderer<'a> {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Width and height of the square tiles handed to threads, in pixels.
+    pub fn tile_size(mut self, tile_size: usize) -> Renderer<'a> {
+        self.tile_size = tile_size.max(1);
+        self
+    }
+
+    /// Called from the render threads as each tile is finished, in no particular order.
+    pub fn on_tile(mut self, callback: impl Fn(&Tile) + Sync + 'a) -> Renderer<'a> {
+        self.on_tile = Some(Box::new(callback));
+        self
+    }
+
+    /// Called from the render threads with the samples done so far and the total, after
+    /// each tile.
+    pub fn on_progress(mut self, callback: impl Fn(u64, u64) + Sync + 'a) -> Renderer<'a> {
+        self.on_progress = Some(Box::new(callback));
+        self
+    }
+
+    /// Checked before every tile; a cancelled render returns `RenderError::Cancelled`.
+    pub fn cancel_token(mut self, token: CancelToken) -> Renderer<'a> {
+        self.cancel = Some(token);
+        self
+    }
+
+    pub fn render(&self) -> Result<Output, RenderError> {
+        let (width, height) = self.size;
+        let tiles = self.tiles();
+        let done = AtomicU64::new(0);
+        let total = (width * height) as u64 * self.spp as u64;
+        let threads = self.threads.min(tiles.len());
+        let passes = if self.integrator.progressive() {
+            self.spp.max(1)
+        } else {
+            1
+        };
+        let mut image = Image::new(width, height);
+        let mut counters = Counters::default();
+        let mut splatted = vec![Vec3::new(0.0, 0.0, 0.0); width * height];
+        let mut splats_made = false;
+        for pass in 0..passes {
+            let next_tile = AtomicUsize::new(0);
+            let prepared = Once::new();
+            let per_pass = self.spp / passes;
+            let samples = pass * per_pass..(pass + 1) * per_pass;
+            let results = std::thread::scope(|s| {
+                let workers: Vec<_> = (0..threads)
+                    .map(|_| {
+                        s.spawn(|| {
+                            let pass = Pass {
+                                index: pass,
+                                samples: samples.clone(),
+                                prepared: &prepared,
+                            };
+                            self.work(&pass, &tiles, &next_tile, &done, total)
+                        })
+                    })
+                    .collect();
+                workers
+                    .into_iter()
+                    .map(|w| w.join().expect("render thread panicked"))
+                    .collect::<Result<Vec<_>, RenderError>>()
+            })?;
+            for (thread_tiles, thread_splats, thread_counters) in &results {
+                counters = counters.add(thread_counters);
+                for (index, pixels) in thread_tiles {
+                    let (x0, y0, w, _) = tiles[*index];
+                    for (i, pixel) in pixels.iter().enumerate() {
+                        let (x, y) = (x0 + i % w, y0 + i / w);
+                        image.set(x, y, image.get(x, y) + *pixel / passes as f32);
+                    }
+                }
+                for (sum, splat) in splatted.iter_mut().zip(thread_splats) {
+                    *sum = *sum + *splat;
+                }
+                splats_made |= !thread_splats.is_empty();
+            }
+        }
+        if splats_made {
+            for (i, splat) in splatted.into_iter().enumerate() {
+                let (x, y) = (i % width, i / width);
+                image.set(x, y, image.get(x, y) + splat / self.spp as f32);
+            }
+        }
+        Ok(Output { image, counters })
+    }
+
+    /// Left, top, width and height of every tile, in rows from the top.
+    fn tiles(&self) -> Vec<(usize, usize, usize, usize)> {
+        let (width, height) = self.size;
+        let n = self.tile_size;
+        (0..height)
+            .step_by(n)
+            .flat_map(|y| {
+                (0..width)
+                    .step_by(n)
+                    .map(move |x| (x, y, n.min(width - x), n.min(height - y)))
+            })
+            .collect()
+    }
+
+    /// One thread's share of a pass: tiles taken in turn until none are left, and the sum of
+    /// the splats made along the way, row by row from the top, or empty if there were none.
+    #[allow(clippy::type_complexity)]
+    fn work(
+        &self,
+        pass: &Pass,
+        tiles: &[(usize, usize, usize, usize)],
+        next_tile: &AtomicUsize,
+        done: &AtomicU64,
+        total: u64,
+    ) -> Result<(Vec<(usize, Vec<Vec3>)>, Vec<Vec3>, Counters), RenderError> {
+        let mut scene = (self.scene)().map_err(RenderError::Scene)?;
+        if let Some(change) = &self.camera {
+            change(&mut scene.camera);
+        }
+        let (width, height) = self.size;
+        let camera = scene
+            .camera
+            .build(width as f32 / height as f32)
+            .map_err(|e| RenderError::Scene(e.to_string()))?;
+        let world = Bvh::new(scene.objects);
+        let view = SceneView {
+            bounds: world.finite_bounds(),
+            world: &world,
+            lights: &scene.lights,
+            background: scene.background,
+            materials: &scene.materials,
+            camera: camera.as_ref(),
+        };
+        let mut finished = vec![];
+        let mut splats = vec![];
+        let mut splatted = vec![];
+        // every pass after the first draws from sequences of its own
+        let seed = match pass.index {
+            0 => self.seed,
+            index => frame_seed(self.seed, u32::MAX - index),
+        };
+        Counters::take();
+        pass.prepared.call_once(|| {
+            misc::seed(frame_seed(seed, u32::MAX));
+            self.integrator.prepare(&view, pass.index);
+        });
+        loop {
+            if self.cancel.as_ref().is_some_and(CancelToken::is_cancelled) {
+                return Err(RenderError::Cancelled);
+            }
+            let index = next_tile.fetch_add(1, Ordering::Relaxed);
+            if index >= tiles.len() {
+                return Ok((finished, splatted, Counters::take()));
+            }
+            let (x0, y0, w, h) = tiles[index];
+            let mut pixels = Vec::with_capacity(w * h);
+            for row in y0..y0 + h {
+                for x in x0..x0 + w {
+                    misc::seed(frame_seed(seed, (row * width + x) as u32));
+                    let y = height - 1 - row;
+                    pixels.push(self.pixel(&view, x, y, pass.samples.clone(), &mut splats));
+                    if !splats.is_empty() && splatted.is_empty() {
+                        splatted = vec![Vec3::new(0.0, 0.0, 0.0); width * height];
+                    }
+                    for splat in splats.drain(..) {
+                        let x = ((splat.s * width as f32) as usize).min(width - 1);
+                        let y = ((splat.t * height as f32) as usize).min(height - 1);
+                        let i = (height - 1 - y) * width + x;
+                        splatted[i] = splatted[i] + splat.radiance;
+                    }
+                }
+            }
+            if let Some(callback) = &self.on_tile {
+                callback(&Tile {
+                    x: x0,
+                    y: y0,
+                    width: w,
+                    height: h,
+                    pixels: &pixels,
+                });
+            }
+            let samples = (w * h) as u64 * pass.samples.len() as u64;
+            let so_far = done.fetch_add(samples, Ordering::Relaxed) + samples;
+            if let Some(callback) = &self.on_progress {
+                callback(so_far, total);
+            }
+            finished.push((index, pixels));
+        }
+    }
+
+    /// Mean radiance over `samples` of the pixel at column `x` and row `y` counted from the
+    /// bottom.
+    fn pixel(
+        &self,
+        scene: &SceneView,
+        x: usize,
+        y: usize,
+        samples: Range<u32>,
+        splats: &mut Vec<Splat>,
+    ) -> Vec3 {
+        let (width, height) = self.size;
+        let mut sum = Vec3::new(0.0, 0.0, 0.0);
+        let n = samples.len();
+        for i in samples {
+            let (du, dv) = self.sampler.offset(i, self.spp);
+            let u = (x as f32 + du) / width as f32;
+            let v = (y as f32 + dv) / height as f32;
+            if let Some(ray) = scene.camera.get_ray(u, v) {
+                sum = sum + self.integrator.radiance_and_splats(&ray, scene, splats);
+            }
+        }
+        sum / n as f32
+    }
+}
+
+/// One go over every tile of the image.
+struct Pass<'p> {
+    index: u32,
+    /// Which of each pixel's samples it takes.
+    samples: Range<u32>,
+    /// Makes sure the integrator is prepared once, by the first thread to get there.
+    prepared: &'p Once,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn scene() -> Result<Scene, String> {
+        misc::seed(1);
+        Ok(Scene::random())
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one sample per pixel")]
+    fn zero_samples_are_rejected() {
+        let _ = Renderer::new(scene).spp(0);
+    }
+
+    #[test]
+    fn images_do_not_depend_on_threads_or_tiles() {
+        let render = |threads: usize, tile_size: usize| {
+            Renderer::new(scene)
+                .size(24, 10)
+                .spp(2)
+                .seed(3)
+                .threads(threads)
+                .tile_size(tile_size)
+                .render()
+                .unwrap()
+                .image
+                .pixels
+        };
+        let reference = render(1, 16);
+        assert_eq!(render(3, 16), reference);
+        assert_eq!(render(2, 7), reference);
+        assert_ne!(
+            Renderer::new(scene)
+                .size(24, 10)
+                .spp(2)
+                .seed(4)
+                .render()
+                .unwrap()
+                .image
+                .pixels,
+            reference
+        );
+    }
+
+    #[test]
+    fn callbacks_see_every_tile() {
+        let tiles = Mutex::new(vec![]);
+        let progress = Mutex::new(vec![]);
+        let output = Renderer::new(scene)
+            .size(20, 12)
+            .spp(3)
+            .tile_size(8)
+            .threads(2)
+            .on_tile(|tile| {
+                let mut tiles = tiles.lock().unwrap();
+                tiles.push((
+                    tile.x,
+                    tile.y,
+                    tile.width,
+                    tile.height,
+                    tile.pixels.to_vec(),
+                ));
+            })
+            .on_progress(|done, total| progress.lock().unwrap().push((done, total)))
+            .render()
+            .unwrap();
+
+        let tiles = tiles.into_inner().unwrap();
+        assert_eq!(tiles.len(), 6);
+        assert_eq!(tiles.iter().map(|t| t.2 * t.3).sum::<usize>(), 20 * 12);
+        for (x, y, w, _, pixels) in &tiles {
+            for (i, p) in pixels.iter().enumerate() {
+                assert_eq!(*p, output.image.get(x + i % w, y + i / w));
+            }
+        }
+        let mut progress = progress.into_inner().unwrap();
+        progress.sort();
+        assert_eq!(progress.len(), 6);
+        assert_eq!(progress.last(), Some(&(20 * 12 * 3, 20 * 12 * 3)));
+        assert!(output.counters.rays() >= 20 * 12 * 3);
+    }
+
+    #[test]
+    fn cancelling_stops_the_render() {
+        let token = CancelToken::new();
+        let result = Renderer::new(scene)
+            .size(64, 64)
+            .tile_size(8)
+            .cancel_token(token.clone())
+            .on_tile(|_| token.cancel())
+            .render();
+        assert_eq!(result.err(), Some(RenderError::Cancelled));
+
+        let failing = Renderer::new(|| Err("no scene".to_string())).render();
+        assert_eq!(
+            failing.err(),
+            Some(RenderError::Scene("no scene".to_string()))
+        );
+    }
+
+    #[test]
+    fn stratified_samples_cover_every_cell() {
+        let mut cells = [false; 9];
+        for i in 0..9 {
+            let (u, v) = Sampler::Stratified.offset(i, 10);
+            cells[(u * 3.0) as usize + 3 * (v * 3.0) as usize] = true;
+        }
+        assert!(cells.iter().all(|&c| c));
+        let (u, v) = Sampler::Stratified.offset(9, 10);
+        assert!((0.0..1.0).contains(&u) && (0.0..1.0).contains(&v));
+    }
+}
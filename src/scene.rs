@@ -0,0 +1,1578 @@
+use crate::animation::{FrameRange, Interpolation, Keyframe, Track};
+use crate::aperture::{Aperture, ApertureMask, ApertureShape};
+use crate::camera::{
+    Camera, EquirectangularCamera, Eye, FisheyeCamera, FisheyeMapping, OrthographicCamera,
+    PerspectiveCamera, StereoLayout, StereoRig,
+};
+use crate::film::{Encoding, Exposure, Film, WhiteBalance};
+use crate::hitable::{HitRecord, Hitable, Sphere};
+use crate::integrator::IntegratorKind;
+use crate::lens::{LensSystem, RealisticCamera};
+use crate::light::Background;
+use crate::material::{Checker, Dielectric, DiffuseLight, Lambertian, Material, Metal};
+use crate::misc::random;
+use crate::shapes::{AxisAlignedBox, Cone, Cylinder, Disk, Plane, Quad, Torus};
+use crate::transform::{Quaternion, Transform, Transformed};
+use crate::vec3::Vec3;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::Arc;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Projection {
+    Perspective,
+    Orthographic,
+    Fisheye(FisheyeMapping),
+    Equirectangular,
+    /// Traced through the lens table set with `lens`.
+    Lens,
+}
+
+impl FromStr for Projection {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Projection, String> {
+        match s {
+            "perspective" => Ok(Projection::Perspective),
+            "orthographic" => Ok(Projection::Orthographic),
+            "fisheye" | "fisheye-equidistant" => {
+                Ok(Projection::Fisheye(FisheyeMapping::Equidistant))
+            }
+            "fisheye-equisolid" => Ok(Projection::Fisheye(FisheyeMapping::Equisolid)),
+            "equirectangular" => Ok(Projection::Equirectangular),
+            "lens" => Ok(Projection::Lens),
+            _ => Err(format!(
+                "unknown projection `{}` (expected perspective, orthographic, \
+                 fisheye-equidistant, fisheye-equisolid, equirectangular or lens)",
+                s
+            )),
+        }
+    }
+}
+
+impl FromStr for StereoLayout {
+    type Err = String;
+    fn from_str(s: &str) -> Result<StereoLayout, String> {
+        match s {
+            "side_by_side" => Ok(StereoLayout::SideBySide),
+            "top_bottom" => Ok(StereoLayout::TopBottom),
+            _ => Err(format!(
+                "unknown stereo layout `{}` (expected side_by_side, top_bottom or off)",
+                s
+            )),
+        }
+    }
+}
+
+/// Everything needed to build a `Camera` once the image aspect ratio is known.
+#[derive(Clone, Debug)]
+pub struct CameraSettings {
+    pub projection: Projection,
+    pub look_from: Vec3,
+    pub look_at: Vec3,
+    pub view_up: Vec3,
+    /// Vertical field of view for perspective, image circle for fisheye, in degrees.
+    pub fov: f32,
+    /// Lens diameter of perspective cameras.
+    pub aperture: f32,
+    pub aperture_shape: Aperture,
+    /// Subject distance the lens focuses on; for lens systems it is measured from the film.
+    pub focus_dist: f32,
+    pub lens: Option<LensSystem>,
+    /// Film diagonal of lens systems in millimetres.
+    pub film_diagonal: f32,
+    /// View height in world units for orthographic.
+    pub ortho_height: f32,
+    /// Horizontal coverage of equirectangular panoramas in degrees; 180 for VR180.
+    pub pano_span: f32,
+    /// Renders both eyes into one frame when set.
+    pub stereo: Option<StereoLayout>,
+    /// Interpupillary distance in world units.
+    pub ipd: f32,
+    /// Distance with zero parallax between the eyes of a perspective stereo pair.
+    pub convergence: f32,
+}
+
+impl Default for CameraSettings {
+    fn default() -> CameraSettings {
+        CameraSettings {
+            projection: Projection::Perspective,
+            look_from: (13, 2, 3).into(),
+            look_at: (0, 0, 0).into(),
+            view_up: (0, 1, 0).into(),
+            fov: 20.0,
+            aperture: 0.1,
+            aperture_shape: Aperture::default(),
+            focus_dist: 10.0,
+            lens: None,
+            film_diagonal: 43.27,
+            ortho_height: 4.0,
+            pano_span: 360.0,
+            stereo: None,
+            ipd: 0.064,
+            convergence: 10.0,
+        }
+    }
+}
+
+/// Camera settings that leave no usable view.
+#[derive(Debug, PartialEq)]
+pub struct CameraError {
+    /// Scene keywords of the settings at fault.
+    pub settings: &'static [&'static str],
+    pub message: String,
+}
+
+impl fmt::Display for CameraError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for CameraError {}
+
+impl CameraSettings {
+    /// Fails on settings that would build a degenerate camera, or one that makes NaN rays.
+    pub fn check(&self) -> Result<(), CameraError> {
+        let error = |settings, message: String| Err(CameraError { settings, message });
+        let view = self.look_from - self.look_at;
+        if view.length() == 0.0 {
+            return error(
+                &["look_from", "look_at"],
+                "the camera looks from the point it looks at".to_string(),
+            );
+        }
+        if self.view_up.cross(&view).length() == 0.0 {
+            return error(
+                &["view_up", "look_from", "look_at"],
+                "the camera's view up is zero or points along its view direction".to_string(),
+            );
+        }
+        match self.projection {
+            Projection::Perspective if !(self.fov > 0.0 && self.fov < 180.0) => {
+                return error(
+                    &["fov", "camera"],
+                    format!(
+                        "a perspective camera needs a field of view between 0 and 180 degrees \
+                         but got {}",
+                        self.fov
+                    ),
+                )
+            }
+            Projection::Fisheye(_) if !(self.fov > 0.0 && self.fov <= 360.0) => {
+                return error(
+                    &["fov", "camera"],
+                    format!(
+                        "a fisheye camera needs a field of view above 0 and up to 360 degrees \
+                         but got {}",
+                        self.fov
+                    ),
+                )
+            }
+            _ => {}
+        }
+        if !(self.aperture >= 0.0 && self.aperture.is_finite()) {
+            return error(
+                &["aperture"],
+                format!("the aperture must not be negative but is {}", self.aperture),
+            );
+        }
+        if !(self.focus_dist > 0.0 && self.focus_dist.is_finite()) {
+            return error(
+                &["focus_dist"],
+                format!(
+                    "the focus distance must be positive but is {}",
+                    self.focus_dist
+                ),
+            );
+        }
+        if !(self.ortho_height > 0.0 && self.ortho_height.is_finite()) {
+            return error(
+                &["ortho_height"],
+                format!(
+                    "the orthographic view height must be positive but is {}",
+                    self.ortho_height
+                ),
+            );
+        }
+        if !(self.pano_span > 0.0 && self.pano_span <= 360.0) {
+            return error(
+                &["pano_span"],
+                format!(
+                    "the panorama span must be above 0 and up to 360 degrees but is {}",
+                    self.pano_span
+                ),
+            );
+        }
+        if self.projection == Projection::Lens {
+            match &self.lens {
+                None => {
+                    return error(
+                        &["camera", "lens"],
+                        "`camera lens` needs a table set with `lens`".to_string(),
+                    )
+                }
+                Some(lens) if lens.autofocus(self.focus_dist).is_none() => {
+                    return error(
+                        &["focus_dist", "lens", "camera"],
+                        format!("the lens cannot focus at {}", self.focus_dist),
+                    )
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// `aspect` is that of the whole frame, even when it holds two eyes. Fails as `check`
+    /// does, since settings changed after parsing have not been checked.
+    pub fn build(&self, aspect: f32) -> Result<Box<dyn Camera>, CameraError> {
+        self.check()?;
+        Ok(match self.stereo {
+            None => self.build_eye(aspect, None),
+            Some(layout) => {
+                let eye_aspect = layout.eye_aspect(aspect);
+                Box::new(StereoRig {
+                    left: self.build_eye(eye_aspect, Some(Eye::Left)),
+                    right: self.build_eye(eye_aspect, Some(Eye::Right)),
+                    layout,
+                })
+            }
+        })
+    }
+
+    fn build_eye(&self, aspect: f32, eye: Option<Eye>) -> Box<dyn Camera> {
+        // projections without a dedicated stereo model get parallel eyes
+        let right = self
+            .view_up
+            .cross(&(self.look_from - self.look_at))
+            .unit_vector();
+        let shift = eye.map_or(0.0, |e| e.sign()) * self.ipd / 2.0 * right;
+        match self.projection {
+            Projection::Perspective => match eye {
+                Some(eye) => Box::new(
+                    PerspectiveCamera::stereo(
+                        self.look_from,
+                        self.look_at,
+                        self.view_up,
+                        self.fov,
+                        aspect,
+                        self.aperture,
+                        self.focus_dist,
+                        self.ipd,
+                        self.convergence,
+                        eye,
+                    )
+                    .with_aperture(self.aperture_shape.clone()),
+                ),
+                None => Box::new(
+                    PerspectiveCamera::new(
+                        self.look_from,
+                        self.look_at,
+                        self.view_up,
+                        self.fov,
+                        aspect,
+                        self.aperture,
+                        self.focus_dist,
+                    )
+                    .with_aperture(self.aperture_shape.clone()),
+                ),
+            },
+            Projection::Orthographic => Box::new(OrthographicCamera::new(
+                self.look_from + shift,
+                self.look_at + shift,
+                self.view_up,
+                self.ortho_height,
+                aspect,
+            )),
+            Projection::Fisheye(mapping) => Box::new(FisheyeCamera::new(
+                self.look_from + shift,
+                self.look_at + shift,
+                self.view_up,
+                self.fov,
+                aspect,
+                mapping,
+            )),
+            Projection::Lens => {
+                let lens = self
+                    .lens
+                    .as_ref()
+                    .and_then(|lens| lens.autofocus(self.focus_dist))
+                    .expect("`check` makes sure there is a lens that can focus");
+                Box::new(RealisticCamera::new(
+                    self.look_from + shift,
+                    self.look_at + shift,
+                    self.view_up,
+                    lens,
+                    self.film_diagonal,
+                    aspect,
+                ))
+            }
+            Projection::Equirectangular => Box::new(EquirectangularCamera::ods(
+                self.look_from,
+                self.look_at,
+                self.view_up,
+                self.pano_span,
+                if eye.is_some() { self.ipd } else { 0.0 },
+                eye.unwrap_or(Eye::Left),
+            )),
+        }
+    }
+}
+
+pub struct Scene {
+    pub objects: Vec<Box<dyn Hitable>>,
+    /// The objects made of a `light` material that can be sampled, shared with `objects`.
+    pub lights: Vec<Rc<dyn Hitable>>,
+    /// Every material in the order it was defined.
+    pub materials: Vec<Rc<Box<dyn Material>>>,
+    pub background: Background,
+    /// Chosen with `integrator`; the command line may override it.
+    pub integrator: IntegratorKind,
+    pub camera: CameraSettings,
+    /// Preferred width over height of the image, e.g. 1 for top/bottom stereo panoramas.
+    pub aspect: Option<f32>,
+    pub film: Film,
+    /// Frames to render when the scene is animated with `key`.
+    pub frames: Option<FrameRange>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SceneError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+impl Scene {
+    /// The spheres from the cover of "Ray Tracing in One Weekend".
+    pub fn random() -> Scene {
+        let mut materials = vec![];
+        Scene {
+            objects: random_scene(&mut materials),
+            lights: vec![],
+            materials,
+            background: Background::Sky,
+            integrator: IntegratorKind::Path,
+            camera: CameraSettings::default(),
+            aspect: None,
+            film: Film::default(),
+            frames: None,
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Scene, Box<dyn std::error::Error>> {
+        Scene::load_at(path, 0.0)
+    }
+
+    pub fn load_at(path: &str, frame: f32) -> Result<Scene, Box<dyn std::error::Error>> {
+        let text = std::fs::read_to_string(path)?;
+        Ok(Scene::parse_at(&text, frame)?)
+    }
+
+    /// Parses the line-based scene format; see `scenes/random.scene` for an example.
+    ///
+    /// Each line is a keyword followed by whitespace-separated arguments; `#` starts a comment.
+    /// Materials are named with `material` and referenced by name from shapes.
+    pub fn parse(text: &str) -> Result<Scene, SceneError> {
+        Scene::parse_at(text, 0.0)
+    }
+
+    /// The scene as it stands at `frame`, with every `key`ed value sampled from its track;
+    /// see `scenes/turntable.scene`.
+    pub fn parse_at(text: &str, frame: f32) -> Result<Scene, SceneError> {
+        let keys = Keys::parse(text)?;
+        let mut camera = CameraSettings::default();
+        let mut materials: HashMap<String, Rc<Box<dyn Material>>> = HashMap::new();
+        let mut objects: Vec<Box<dyn Hitable>> = vec![];
+        let mut lights: Vec<Rc<dyn Hitable>> = vec![];
+        let mut defined = vec![];
+        let mut background = Background::Sky;
+        let mut integrator = IntegratorKind::Path;
+        let mut aspect = None;
+        let mut film = Film::default();
+        let mut frames = None;
+        let mut fps = 24.0;
+        let mut names = vec![];
+        // where each keyword was last given, to blame camera settings checked at the end
+        let mut given: HashMap<&str, usize> = HashMap::new();
+
+        for (index, raw) in text.lines().enumerate() {
+            let error = |message: String| SceneError {
+                line: index + 1,
+                message,
+            };
+            let line = raw.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let args = Args {
+                keyword: words[0],
+                words: &words[1..],
+            };
+            given.insert(words[0], index + 1);
+            match words[0] {
+                "camera" => {
+                    args.expect(1).map_err(error)?;
+                    camera.projection = words[1].parse().map_err(error)?;
+                }
+                "look_from" => camera.look_from = args.vec3_only().map_err(error)?,
+                "look_at" => camera.look_at = args.vec3_only().map_err(error)?,
+                "view_up" => camera.view_up = args.vec3_only().map_err(error)?,
+                "fov" => camera.fov = args.f32_only().map_err(error)?,
+                "aperture" => camera.aperture = args.f32_only().map_err(error)?,
+                "aperture_blades" => {
+                    if words.len() != 2 && words.len() != 3 {
+                        return Err(error(
+                            "`aperture_blades` takes a blade count and an optional rotation"
+                                .to_string(),
+                        ));
+                    }
+                    let blades = match words[1].parse::<u32>() {
+                        Ok(n) if n >= 3 => n,
+                        _ => {
+                            return Err(error(format!(
+                                "`aperture_blades` expects at least 3 blades but got `{}`",
+                                words[1]
+                            )))
+                        }
+                    };
+                    let rotation = if words.len() == 3 {
+                        args.f32(1).map_err(error)?
+                    } else {
+                        0.0
+                    };
+                    camera.aperture_shape.shape = ApertureShape::Polygon { blades, rotation };
+                }
+                "aperture_mask" => {
+                    args.expect(1).map_err(error)?;
+                    let mask = ApertureMask::load(words[1])
+                        .map_err(|e| error(format!("aperture mask `{}`: {}", words[1], e)))?;
+                    camera.aperture_shape.shape = ApertureShape::Mask(Arc::new(mask));
+                }
+                "anamorphic" => {
+                    let squeeze = args.f32_only().map_err(error)?;
+                    if squeeze <= 0.0 {
+                        return Err(error("`anamorphic` squeeze must be positive".to_string()));
+                    }
+                    camera.aperture_shape.squeeze = squeeze;
+                }
+                "cats_eye" => camera.aperture_shape.cats_eye = args.f32_only().map_err(error)?,
+                "lens" => {
+                    args.expect(1).map_err(error)?;
+                    let lens = LensSystem::load(words[1])
+                        .map_err(|e| error(format!("lens `{}`: {}", words[1], e)))?;
+                    camera.lens = Some(lens);
+                    camera.projection = Projection::Lens;
+                }
+                "film_diagonal" => {
+                    camera.film_diagonal = args.f32_only().map_err(error)?;
+                    if camera.film_diagonal <= 0.0 {
+                        return Err(error("`film_diagonal` must be positive".to_string()));
+                    }
+                }
+                "focus_dist" => camera.focus_dist = args.f32_only().map_err(error)?,
+                "ortho_height" => camera.ortho_height = args.f32_only().map_err(error)?,
+                "pano_span" => camera.pano_span = args.f32_only().map_err(error)?,
+                "stereo" => {
+                    args.expect(1).map_err(error)?;
+                    camera.stereo = match words[1] {
+                        "off" => None,
+                        layout => Some(layout.parse().map_err(error)?),
+                    };
+                }
+                "ipd" => {
+                    let ipd = args.f32_only().map_err(error)?;
+                    if ipd <= 0.0 {
+                        return Err(error("`ipd` must be positive".to_string()));
+                    }
+                    camera.ipd = ipd;
+                }
+                "convergence" => {
+                    let convergence = args.f32_only().map_err(error)?;
+                    if convergence <= 0.0 {
+                        return Err(error("`convergence` must be positive".to_string()));
+                    }
+                    camera.convergence = convergence;
+                }
+                "aspect" => {
+                    let ratio = args.f32_only().map_err(error)?;
+                    if !ratio.is_finite() || ratio <= 0.0 {
+                        return Err(error("`aspect` must be positive and finite".to_string()));
+                    }
+                    aspect = Some(ratio);
+                }
+                "exposure" => film.exposure = Exposure::Ev(args.f32_only().map_err(error)?),
+                "exposure_camera" => {
+                    args.expect(3).map_err(error)?;
+                    let (iso, shutter, f_number) = (
+                        args.f32(0).map_err(error)?,
+                        args.f32(1).map_err(error)?,
+                        args.f32(2).map_err(error)?,
+                    );
+                    if iso <= 0.0 || shutter <= 0.0 || f_number <= 0.0 {
+                        return Err(error(
+                            "`exposure_camera` takes a positive ISO, shutter time and f-number"
+                                .to_string(),
+                        ));
+                    }
+                    film.exposure = Exposure::Camera {
+                        iso,
+                        shutter,
+                        f_number,
+                    };
+                }
+                "white_balance" => {
+                    args.expect(1).map_err(error)?;
+                    film.white_balance = if words[1] == "off" {
+                        None
+                    } else {
+                        let kelvin = args.f32(0).map_err(error)?;
+                        if !(1667.0..=25000.0).contains(&kelvin) {
+                            return Err(error(format!(
+                                "`white_balance` expects a temperature between 1667 and 25000 K \
+                                 but got {}",
+                                kelvin
+                            )));
+                        }
+                        Some(WhiteBalance::new(kelvin))
+                    };
+                }
+                "tone_map" => {
+                    if words.len() != 2 && words.len() != 3 {
+                        return Err(error(
+                            "`tone_map` takes a curve and optionally `per_channel` or `luminance`"
+                                .to_string(),
+                        ));
+                    }
+                    film.tone_map = words[1].parse().map_err(error)?;
+                    film.preserve_hue = match words.get(2) {
+                        None | Some(&"per_channel") => false,
+                        Some(&"luminance") => true,
+                        Some(other) => {
+                            return Err(error(format!(
+                                "unknown tone map mode `{}` (expected per_channel or luminance)",
+                                other
+                            )))
+                        }
+                    };
+                }
+                "encoding" => {
+                    film.encoding = match words.get(1) {
+                        Some(&"linear") if words.len() == 2 => Encoding::Linear,
+                        Some(&"srgb") if words.len() == 2 => Encoding::Srgb,
+                        Some(&"rec709") if words.len() == 2 => Encoding::Rec709,
+                        Some(&"gamma") if words.len() == 3 => {
+                            let gamma = args.f32(1).map_err(error)?;
+                            if gamma <= 0.0 {
+                                return Err(error("gamma must be positive".to_string()));
+                            }
+                            Encoding::Gamma(gamma)
+                        }
+                        _ => {
+                            return Err(error(
+                                "`encoding` expects linear, srgb, rec709 or gamma <value>"
+                                    .to_string(),
+                            ))
+                        }
+                    };
+                }
+                "frames" => {
+                    args.expect(2).map_err(error)?;
+                    let range = (words[1].parse::<u32>(), words[2].parse::<u32>());
+                    frames = match range {
+                        (Ok(start), Ok(end)) if start <= end => Some((start, end)),
+                        _ => {
+                            return Err(error(format!(
+                                "`frames` expects a first and a last frame but got `{} {}`",
+                                words[1], words[2]
+                            )))
+                        }
+                    };
+                }
+                "fps" => {
+                    fps = args.f32_only().map_err(error)?;
+                    if fps <= 0.0 {
+                        return Err(error("`fps` must be positive".to_string()));
+                    }
+                }
+                "key" => {}
+                "material" => {
+                    let (name, mut spec) = args.material().map_err(error)?;
+                    keys.apply_to_material(&name, &mut spec, frame);
+                    let material = spec.build();
+                    defined.push(material.clone());
+                    materials.insert(name, material);
+                    names.push(format!("material.{}", words[1]));
+                }
+                "object" => {
+                    if words.len() < 3 {
+                        return Err(error("`object` needs a name and a shape".to_string()));
+                    }
+                    let shape = Args {
+                        keyword: words[2],
+                        words: &words[3..],
+                    };
+                    let inner = match shape.shape(&materials).map_err(error)? {
+                        Some(inner) => inner,
+                        None => {
+                            return Err(error(format!(
+                                "`object` expects a sphere, plane, box, quad, disk, cylinder, cone \
+                                 or torus but got `{}`",
+                                words[2]
+                            )))
+                        }
+                    };
+                    objects.push(match keys.transform(words[1], frame) {
+                        Some(transform) => Box::new(Transformed { inner, transform }),
+                        None => inner,
+                    });
+                    names.push(format!("object.{}", words[1]));
+                }
+                "random_spheres" => {
+                    args.expect(0).map_err(error)?;
+                    objects.extend(random_small_spheres(&mut defined));
+                }
+                "background" => {
+                    background = match words[1..] {
+                        ["sky"] => Background::Sky,
+                        _ => Background::Uniform(args.vec3_only().map_err(|_| {
+                            error("`background` expects `sky` or a color".to_string())
+                        })?),
+                    };
+                }
+                "integrator" => {
+                    let distance = match words.len() {
+                        2 => None,
+                        3 => Some(args.f32(1).map_err(error)?),
+                        _ => {
+                            return Err(error(
+                                "`integrator` takes a name and an optional distance".to_string(),
+                            ))
+                        }
+                    };
+                    integrator = IntegratorKind::new(words[1], distance).map_err(error)?;
+                }
+                other => match args.shape(&materials).map_err(error)? {
+                    // shapes name their material last
+                    Some(shape) if shape.area().is_some() && emits(&materials, &words) => {
+                        let shape: Rc<dyn Hitable> = Rc::from(shape);
+                        lights.push(shape.clone());
+                        objects.push(Box::new(shape));
+                    }
+                    Some(shape) => objects.push(shape),
+                    None => return Err(error(format!("unknown keyword `{}`", other))),
+                },
+            }
+        }
+        keys.check(&names)?;
+        keys.apply_to_camera(&mut camera, frame);
+        for (target, (line, _)) in keys.tracks.iter() {
+            if let Some(setting) = target.strip_prefix("camera.") {
+                given.insert(setting, *line);
+            }
+        }
+        camera.check().map_err(|e| SceneError {
+            line: e
+                .settings
+                .iter()
+                .filter_map(|setting| given.get(setting))
+                .max()
+                .copied()
+                .unwrap_or(0),
+            message: e.message,
+        })?;
+        Ok(Scene {
+            objects,
+            lights,
+            materials: defined,
+            background,
+            integrator,
+            camera,
+            aspect,
+            film,
+            frames: frames.map(|(start, end)| FrameRange { start, end, fps }),
+        })
+    }
+}
+
+struct Args<'a> {
+    keyword: &'a str,
+    words: &'a [&'a str],
+}
+
+impl<'a> Args<'a> {
+    fn expect(&self, n: usize) -> Result<(), String> {
+        if self.words.len() == n {
+            Ok(())
+        } else {
+            Err(format!(
+                "`{}` takes {} argument(s) but {} were given",
+                self.keyword,
+                n,
+                self.words.len()
+            ))
+        }
+    }
+
+    fn f32(&self, i: usize) -> Result<f32, String> {
+        let word = self.words[i];
+        match word.parse::<f32>() {
+            Ok(f) if f.is_finite() => Ok(f),
+            _ => Err(format!(
+                "`{}` expects a number but got `{}`",
+                self.keyword, word
+            )),
+        }
+    }
+
+    fn vec3(&self, i: usize) -> Result<Vec3, String> {
+        Ok(Vec3::new(self.f32(i)?, self.f32(i + 1)?, self.f32(i + 2)?))
+    }
+
+    fn f32_only(&self) -> Result<f32, String> {
+        self.expect(1)?;
+        self.f32(0)
+    }
+
+    fn vec3_only(&self) -> Result<Vec3, String> {
+        self.expect(3)?;
+        self.vec3(0)
+    }
+
+    /// Argument `i`, which gives the size of a shape and so must be above zero.
+    fn size(&self, i: usize, what: &str) -> Result<f32, String> {
+        match self.f32(i)? {
+            x if x > 0.0 => Ok(x),
+            _ => Err(format!(
+                "`{}` expects a positive {} but got `{}`",
+                self.keyword, what, self.words[i]
+            )),
+        }
+    }
+
+    /// A shape keyword and its arguments, or `None` when the keyword is not a shape.
+    fn shape(
+        &self,
+        materials: &HashMap<String, Rc<Box<dyn Material>>>,
+    ) -> Result<Option<Box<dyn Hitable>>, String> {
+        Ok(Some(match self.keyword {
+            "sphere" => {
+                self.expect(5)?;
+                Box::new(Sphere {
+                    center: self.vec3(0)?,
+                    radius: self.size(3, "radius")?,
+                    material: lookup(materials, self.words[4])?,
+                })
+            }
+            "plane" => {
+                self.expect(7)?;
+                Box::new(Plane::new(
+                    self.vec3(0)?,
+                    self.vec3(3)?,
+                    lookup(materials, self.words[6])?,
+                ))
+            }
+            "box" => {
+                self.expect(7)?;
+                Box::new(AxisAlignedBox::new(
+                    self.vec3(0)?,
+                    self.vec3(3)?,
+                    lookup(materials, self.words[6])?,
+                ))
+            }
+            "quad" => {
+                self.expect(10)?;
+                Box::new(Quad::new(
+                    self.vec3(0)?,
+                    self.vec3(3)?,
+                    self.vec3(6)?,
+                    lookup(materials, self.words[9])?,
+                ))
+            }
+            "disk" => {
+                self.expect(8)?;
+                Box::new(Disk::new(
+                    self.vec3(0)?,
+                    self.vec3(3)?,
+                    self.size(6, "radius")?,
+                    lookup(materials, self.words[7])?,
+                ))
+            }
+            "cylinder" => {
+                self.expect(6)?;
+                Box::new(Cylinder {
+                    center: self.vec3(0)?,
+                    radius: self.size(3, "radius")?,
+                    height: self.size(4, "height")?,
+                    material: lookup(materials, self.words[5])?,
+                })
+            }
+            "cone" => {
+                self.expect(6)?;
+                Box::new(Cone {
+                    center: self.vec3(0)?,
+                    radius: self.size(3, "radius")?,
+                    height: self.size(4, "height")?,
+                    material: lookup(materials, self.words[5])?,
+                })
+            }
+            "torus" => {
+                self.expect(6)?;
+                Box::new(Torus {
+                    center: self.vec3(0)?,
+                    major_radius: self.size(3, "major radius")?,
+                    minor_radius: self.size(4, "minor radius")?,
+                    material: lookup(materials, self.words[5])?,
+                })
+            }
+            _ => return Ok(None),
+        }))
+    }
+
+    fn material(&self) -> Result<(String, MaterialSpec), String> {
+        if self.words.len() < 2 {
+            return Err("`material` needs a name and a kind".to_string());
+        }
+        let name = self.words[0].to_string();
+        let rest = Args {
+            keyword: self.words[1],
+            words: &self.words[2..],
+        };
+        let material = match self.words[1] {
+            "lambertian" => {
+                rest.expect(3)?;
+                MaterialSpec::Lambertian {
+                    albedo: rest.vec3(0)?,
+                }
+            }
+            "metal" => {
+                rest.expect(4)?;
+                MaterialSpec::Metal {
+                    albedo: rest.vec3(0)?,
+                    fuzz: rest.f32(3)?,
+                }
+            }
+            "dielectric" => {
+                rest.expect(1)?;
+                MaterialSpec::Dielectric { ior: rest.f32(0)? }
+            }
+            "light" => {
+                rest.expect(3)?;
+                MaterialSpec::Light {
+                    radiance: rest.vec3(0)?,
+                }
+            }
+            "checker" => {
+                rest.expect(7)?;
+                let size = rest.f32(6)?;
+                if size <= 0.0 {
+                    return Err("the checker size must be positive".to_string());
+                }
+                MaterialSpec::Checker {
+                    even: rest.vec3(0)?,
+                    odd: rest.vec3(3)?,
+                    size,
+                }
+            }
+            other => return Err(format!(
+                "unknown material kind `{}` (expected lambertian, metal, dielectric, checker or light)",
+                other
+            )),
+        };
+        Ok((name, material))
+    }
+}
+
+/// Whether the material named last on a shape line gives off light.
+fn emits(materials: &HashMap<String, Rc<Box<dyn Material>>>, words: &[&str]) -> bool {
+    let record = HitRecord::null();
+    words
+        .last()
+        .and_then(|name| materials.get(*name))
+        .is_some_and(|m| m.emitted(&record) != Vec3::new(0.0, 0.0, 0.0))
+}
+
+/// Parameters of a material as written, so keyframes can change them before it is built.
+enum MaterialSpec {
+    Lambertian {
+        albedo: Vec3,
+    },
+    Metal {
+        albedo: Vec3,
+        fuzz: f32,
+    },
+    Dielectric {
+        ior: f32,
+    },
+    Checker {
+        even: Vec3,
+        odd: Vec3,
+        size: f32,
+    },
+    /// Radiance, linear and unbounded unlike albedos.
+    Light {
+        radiance: Vec3,
+    },
+}
+
+impl MaterialSpec {
+    fn build(&self) -> Rc<Box<dyn Material>> {
+        Rc::new(match *self {
+            MaterialSpec::Lambertian { albedo } => Box::new(Lambertian::new(color(albedo))),
+            MaterialSpec::Metal { albedo, fuzz } => Box::new(Metal::new(color(albedo), fuzz)),
+            MaterialSpec::Dielectric { ior } => Box::new(Dielectric { ref_idx: ior }),
+            MaterialSpec::Checker { even, odd, size } => {
+                Box::new(Checker::new(color(even), color(odd), size))
+            }
+            MaterialSpec::Light { radiance } => Box::new(DiffuseLight::new(radiance)),
+        })
+    }
+}
+
+enum AnyTrack {
+    Scalar(Track<f32>),
+    Vector(Track<Vec3>),
+    Rotation(Track<Quaternion>),
+}
+
+/// Keyframe tracks of a scene, by target such as `camera.fov` or `object.ball.rotate`.
+struct Keys {
+    tracks: HashMap<String, (usize, AnyTrack)>,
+}
+
+impl Keys {
+    /// Collects the `key <target> <frame> <interpolation> <value...>` lines. Rotations are an
+    /// axis followed by an angle in degrees.
+    fn parse(text: &str) -> Result<Keys, SceneError> {
+        let mut tracks: HashMap<String, (usize, AnyTrack)> = HashMap::new();
+        for (index, raw) in text.lines().enumerate() {
+            let error = |message: String| SceneError {
+                line: index + 1,
+                message,
+            };
+            let line = raw.split('#').next().unwrap_or("").trim();
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if words.first() != Some(&"key") {
+                continue;
+            }
+            if words.len() < 5 {
+                return Err(error(
+                    "`key` takes a target, a frame, an interpolation and a value".to_string(),
+                ));
+            }
+            let target = words[1];
+            let values = Args {
+                keyword: "key",
+                words: &words[2..],
+            };
+            let frame = values.f32(0).map_err(error)?;
+            let interpolation: Interpolation = words[3].parse().map_err(error)?;
+            let values = Args {
+                keyword: target,
+                words: &words[4..],
+            };
+            let property = target.rsplit('.').next().unwrap_or("");
+            let kind = match (target.split('.').next(), target.split('.').count()) {
+                (Some("camera"), 2) => match property {
+                    "look_from" | "look_at" | "view_up" => "vector",
+                    "fov" | "aperture" | "focus_dist" => "scalar",
+                    _ => "",
+                },
+                (Some("object"), 3) => match property {
+                    "translate" => "vector",
+                    "rotate" => "rotation",
+                    "scale" => "scalar",
+                    _ => "",
+                },
+                (Some("material"), 3) => match property {
+                    "albedo" | "radiance" => "vector",
+                    "fuzz" | "ior" => "scalar",
+                    _ => "",
+                },
+                _ => "",
+            };
+            let entry = tracks.entry(target.to_string());
+            match kind {
+                "scalar" => {
+                    let value = values.f32_only().map_err(error)?;
+                    let track = entry.or_insert((index + 1, AnyTrack::Scalar(Track::new())));
+                    if let AnyTrack::Scalar(track) = &mut track.1 {
+                        track.insert(Keyframe {
+                            frame,
+                            value,
+                            interpolation,
+                        });
+                    }
+                }
+                "vector" => {
+                    let value = values.vec3_only().map_err(error)?;
+                    let track = entry.or_insert((index + 1, AnyTrack::Vector(Track::new())));
+                    if let AnyTrack::Vector(track) = &mut track.1 {
+                        track.insert(Keyframe {
+                            frame,
+                            value,
+                            interpolation,
+                        });
+                    }
+                }
+                "rotation" => {
+                    values.expect(4).map_err(error)?;
+                    let axis = values.vec3(0).map_err(error)?;
+                    if axis.length() == 0.0 {
+                        return Err(error("rotation axis must not be zero".to_string()));
+                    }
+                    let value = Quaternion::from_axis_angle(axis, values.f32(3).map_err(error)?);
+                    let track = entry.or_insert((index + 1, AnyTrack::Rotation(Track::new())));
+                    if let AnyTrack::Rotation(track) = &mut track.1 {
+                        track.insert(Keyframe {
+                            frame,
+                            value,
+                            interpolation,
+                        });
+                    }
+                }
+                _ => {
+                    return Err(error(format!(
+                        "unknown key target `{}` (expected camera.<look_from|look_at|view_up|\
+                         fov|aperture|focus_dist>, object.<name>.<translate|rotate|scale> or \
+                         material.<name>.<albedo|fuzz|ior>)",
+                        target
+                    )))
+                }
+            }
+        }
+        Ok(Keys { tracks })
+    }
+
+    fn scalar(&self, target: &str, frame: f32) -> Option<f32> {
+        match self.tracks.get(target) {
+            Some((_, AnyTrack::Scalar(track))) => track.sample(frame),
+            _ => None,
+        }
+    }
+
+    fn vector(&self, target: &str, frame: f32) -> Option<Vec3> {
+        match self.tracks.get(target) {
+            Some((_, AnyTrack::Vector(track))) => track.sample(frame),
+            _ => None,
+        }
+    }
+
+    fn rotation(&self, target: &str, frame: f32) -> Option<Quaternion> {
+        match self.tracks.get(target) {
+            Some((_, AnyTrack::Rotation(track))) => track.sample(frame),
+            _ => None,
+        }
+    }
+
+    /// Every object and material target must name something the scene defines.
+    fn check(&self, names: &[String]) -> Result<(), SceneError> {
+        for (target, (line, _)) in self.tracks.iter() {
+            if target.starts_with("camera.") {
+                continue;
+            }
+            let owner = &target[..target.rfind('.').unwrap_or(0)];
+            if !names.iter().any(|n| n == owner) {
+                return Err(SceneError {
+                    line: *line,
+                    message: format!("`{}` is animated but never defined", owner),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn apply_to_camera(&self, camera: &mut CameraSettings, frame: f32) {
+        let vectors = [
+            ("camera.look_from", &mut camera.look_from),
+            ("camera.look_at", &mut camera.look_at),
+            ("camera.view_up", &mut camera.view_up),
+        ];
+        for (target, value) in vectors {
+            if let Some(v) = self.vector(target, frame) {
+                *value = v;
+            }
+        }
+        let scalars = [
+            ("camera.fov", &mut camera.fov),
+            ("camera.aperture", &mut camera.aperture),
+            ("camera.focus_dist", &mut camera.focus_dist),
+        ];
+        for (target, value) in scalars {
+            if let Some(v) = self.scalar(target, frame) {
+                *value = v;
+            }
+        }
+    }
+
+    fn apply_to_material(&self, name: &str, spec: &mut MaterialSpec, frame: f32) {
+        let target = |property: &str| format!("material.{}.{}", name, property);
+        match spec {
+            MaterialSpec::Lambertian { albedo } => {
+                if let Some(v) = self.vector(&target("albedo"), frame) {
+                    *albedo = v;
+                }
+            }
+            MaterialSpec::Metal { albedo, fuzz } => {
+                if let Some(v) = self.vector(&target("albedo"), frame) {
+                    *albedo = v;
+                }
+                if let Some(v) = self.scalar(&target("fuzz"), frame) {
+                    *fuzz = v;
+                }
+            }
+            MaterialSpec::Dielectric { ior } => {
+                if let Some(v) = self.scalar(&target("ior"), frame) {
+                    *ior = v;
+                }
+            }
+            MaterialSpec::Checker { .. } => {}
+            MaterialSpec::Light { radiance } => {
+                if let Some(v) = self.vector(&target("radiance"), frame) {
+                    *radiance = v;
+                }
+            }
+        }
+    }
+
+    /// Placement of the named object, or `None` when none of its transform is animated.
+    fn transform(&self, name: &str, frame: f32) -> Option<Transform> {
+        let target = |property: &str| format!("object.{}.{}", name, property);
+        let translation = self.vector(&target("translate"), frame);
+        let rotation = self.rotation(&target("rotate"), frame);
+        let scale = self.scalar(&target("scale"), frame);
+        if translation.is_none() && rotation.is_none() && scale.is_none() {
+            return None;
+        }
+        let identity = Transform::default();
+        Some(Transform {
+            translation: translation.unwrap_or(identity.translation),
+            rotation: rotation.unwrap_or(identity.rotation),
+            scale: scale.unwrap_or(identity.scale),
+        })
+    }
+}
+
+fn color(v: Vec3) -> crate::color::Color {
+    (v.x, v.y, v.z).into()
+}
+
+fn lookup(
+    materials: &HashMap<String, Rc<Box<dyn Material>>>,
+    name: &str,
+) -> Result<Rc<Box<dyn Material>>, String> {
+    materials
+        .get(name)
+        .cloned()
+        .ok_or_else(|| format!("undefined material `{}`", name))
+}
+
+/// The spheres' materials are added to `materials`.
+fn random_small_spheres(materials: &mut Vec<Rc<Box<dyn Material>>>) -> Vec<Box<dyn Hitable>> {
+    enum Materials {
+        Lambertian,
+        Metal,
+        Dielectric,
+    }
+    fn choose_random_material() -> Materials {
+        let r = random();
+        match r {
+            _ if r < 0.8 => Materials::Lambertian,
+            _ if r < 0.95 => Materials::Metal,
+            _ => Materials::Dielectric,
+        }
+    }
+    fn create_object(center: Vec3, material: Rc<Box<dyn Material>>) -> Box<dyn Hitable> {
+        Box::new(Sphere {
+            center,
+            radius: 0.2,
+            material,
+        })
+    }
+    fn random_material(en: Materials) -> Rc<Box<dyn Material>> {
+        match en {
+            Materials::Lambertian => Rc::new(Box::new(Lambertian::new(
+                (
+                    random() * random(),
+                    random() * random(),
+                    random() * random(),
+                )
+                    .into(),
+            ))),
+            Materials::Metal => Rc::new(Box::new(Metal::new(
+                (
+                    0.5 * (1.0 + random()),
+                    0.5 * (1.0 + random()),
+                    0.5 * (1.0 + random()),
+                )
+                    .into(),
+                0.5 * random(),
+            ))),
+            Materials::Dielectric => Rc::new(Box::new(Dielectric { ref_idx: 1.5 })),
+        }
+    }
+    let mut objects: Vec<Box<dyn Hitable>> = vec![];
+    for cx in -11..11 {
+        for cz in -11..11 {
+            let (cxf, czf) = (cx as f32, cz as f32);
+            let center = Vec3::new(cxf + 0.9 * random(), 0.2, czf + 0.9 * random());
+            if (center - Vec3::new(4.0, 0.2, 0.0)).length() > 0.9 {
+                let material = random_material(choose_random_material());
+                materials.push(material.clone());
+                objects.push(create_object(center, material));
+            }
+        }
+    }
+    objects
+}
+
+fn random_scene(materials: &mut Vec<Rc<Box<dyn Material>>>) -> Vec<Box<dyn Hitable>> {
+    let mut objects = random_small_spheres(materials);
+    let ground: Rc<Box<dyn Material>> = Rc::new(Box::new(Lambertian::new((0.5, 0.5, 0.5).into())));
+    let glass: Rc<Box<dyn Material>> = Rc::new(Box::new(Dielectric { ref_idx: 1.5 }));
+    let brown: Rc<Box<dyn Material>> = Rc::new(Box::new(Lambertian::new((0.4, 0.2, 0.1).into())));
+    let mirror: Rc<Box<dyn Material>> = Rc::new(Box::new(Metal::new((0.7, 0.6, 0.5).into(), 0.0)));
+    materials.extend([&ground, &glass, &brown, &mirror].map(Rc::clone));
+    objects.push(Box::new(Plane::new(
+        (0, 0, 0).into(),
+        (0, 1, 0).into(),
+        ground,
+    )));
+    for (x, material) in [(0, glass), (-4, brown), (4, mirror)] {
+        objects.push(Box::new(Sphere {
+            center: (x, 1, 0).into(),
+            radius: 1.0,
+            material,
+        }));
+    }
+    objects
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::film::ToneMap;
+    use crate::ray::Ray;
+
+    #[test]
+    fn parses_camera_and_objects() {
+        let scene = Scene::parse(
+            "# a comment\n\
+             camera fisheye-equisolid\n\
+             look_from 1 2 3   # trailing comment\n\
+             fov 180\n\
+             material red lambertian 0.9 0.1 0.1\n\
+             material glass dielectric 1.5\n\
+             sphere 0 1 0 1 red\n\
+             plane 0 0 0 0 1 0 glass\n\
+             box 0 0 0 1 1 1 red\n\
+             cylinder 3 0 0 0.5 2 red\n\
+             cone -3 0 0 1 2 glass\n\
+             torus 0 0 4 1 0.25 red\n",
+        )
+        .unwrap();
+        assert_eq!(
+            scene.camera.projection,
+            Projection::Fisheye(FisheyeMapping::Equisolid)
+        );
+        assert_eq!(scene.camera.look_from, Vec3::from_i(1, 2, 3));
+        assert_eq!(scene.camera.fov, 180.0);
+        assert_eq!(scene.camera.look_at, CameraSettings::default().look_at);
+        assert_eq!(scene.objects.len(), 6);
+        let down = |x: f32, z: f32| Ray::new(Vec3::new(x, 5.0, z), Vec3::new(0.0, -1.0, 0.0));
+        for (object, x, z, top) in [(3, 3.0, 0.0, 2.0), (4, -3.0, 0.0, 2.0), (5, 1.0, 4.0, 0.25)] {
+            let rec = scene.objects[object]
+                .hit(&down(x, z), 0.0, f32::MAX)
+                .unwrap();
+            assert!((rec.p.y - top).abs() < 1e-3, "{:?}", rec.p);
+        }
+
+        for bad in [
+            "material red lambertian 1 0 0\nsphere 0 0 0 0 red",
+            "material red lambertian 1 0 0\ndisk 0 0 0 0 1 0 -1 red",
+            "material red lambertian 1 0 0\ncylinder 0 0 0 1 0 red",
+            "material red lambertian 1 0 0\ncone 0 0 0 -1 1 red",
+            "material red lambertian 1 0 0\ntorus 0 0 0 1 0 red",
+            "material red lambertian 1 0 0\ntorus 0 0 0 1 red",
+        ] {
+            let err = Scene::parse(bad).err().expect(bad);
+            assert_eq!(err.line, 2, "{}", bad);
+        }
+    }
+
+    #[test]
+    fn parses_lights_and_rendering() {
+        let scene = Scene::parse(
+            "background 0.1 0.1 0.2\n\
+             integrator ao 0.5\n\
+             material lamp light 10 9 8\n\
+             material white lambertian 0.8 0.8 0.8\n\
+             quad 0 2 0  1 0 0  0 0 1  lamp\n\
+             disk 0 3 0  0 -1 0  0.5 lamp\n\
+             sphere 0 4 0 0.2 lamp\n\
+             plane 0 5 0  0 -1 0  lamp\n\
+             quad 0 0 0  1 0 0  0 0 1  white\n",
+        )
+        .unwrap();
+        assert_eq!(scene.objects.len(), 5);
+        // glowing planes cannot be sampled, so they only show where they are seen
+        assert_eq!(scene.lights.len(), 3);
+        assert_eq!(scene.materials.len(), 2);
+        assert_eq!(
+            scene.background,
+            Background::Uniform(Vec3::new(0.1, 0.1, 0.2))
+        );
+        assert_eq!(
+            scene.integrator,
+            IntegratorKind::AmbientOcclusion { distance: 0.5 }
+        );
+        let record = scene.lights[0]
+            .hit(
+                &Ray::new((0.5, 0.0, 0.5).into(), (0, 1, 0).into()),
+                0.0,
+                f32::MAX,
+            )
+            .unwrap();
+        assert_eq!(record.material.emitted(&record), Vec3::new(10.0, 9.0, 8.0));
+
+        let defaults = Scene::parse("").unwrap();
+        assert_eq!(defaults.background, Background::Sky);
+        assert_eq!(defaults.integrator, IntegratorKind::Path);
+        for bad in [
+            "background",
+            "background blue",
+            "integrator",
+            "integrator radiosity",
+            "integrator normals 2",
+            "material lamp light 1 1",
+        ] {
+            assert!(Scene::parse(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn reports_line_of_error() {
+        let err = Scene::parse("camera perspective\n\ncamera pinhole\n")
+            .err()
+            .unwrap();
+        assert_eq!(err.line, 3);
+        assert!(err.message.contains("pinhole"));
+        let err = Scene::parse("sphere 0 0 0 1 nothing").err().unwrap();
+        assert!(err.message.contains("undefined material"));
+        let err = Scene::parse("fov wide").err().unwrap();
+        assert!(err.message.contains("`wide`"));
+        let err = Scene::parse("look_at 1 2").err().unwrap();
+        assert!(err.message.contains("3 argument"));
+        let err = Scene::parse("material board checker 1 1 1 0 0 0 0")
+            .err()
+            .unwrap();
+        assert!(err.message.contains("positive"));
+        for (line, keyword) in [
+            ("camera perspective\nconvergence 0", "convergence"),
+            ("ipd -0.06", "ipd"),
+            ("aspect 0", "aspect"),
+            ("aspect -1.5", "aspect"),
+            ("aspect inf", "aspect"),
+            ("fov 0", "field of view"),
+            ("camera perspective\nfov 400", "field of view"),
+            ("fov 270\ncamera perspective", "field of view"),
+            ("camera fisheye\nfov 400", "field of view"),
+            ("aperture -1", "aperture"),
+            ("focus_dist 0", "focus distance"),
+            ("ortho_height -2", "height"),
+            ("pano_span 400", "span"),
+            ("look_at 1 2 3\nlook_from 1 2 3", "looks from"),
+            ("look_from 0 5 0", "view up"),
+            ("view_up 1 2 3\nlook_at 13 0 3\nlook_from 14 2 6", "view up"),
+            ("fov 30\nkey camera.fov 0 linear 200", "field of view"),
+        ] {
+            let err = Scene::parse(line).err().expect(line);
+            assert_eq!(err.line, line.lines().count(), "{}", line);
+            assert!(err.message.contains(keyword), "{}", err.message);
+        }
+    }
+
+    #[test]
+    fn every_projection_builds() {
+        for name in [
+            "perspective",
+            "orthographic",
+            "fisheye",
+            "fisheye-equisolid",
+            "equirectangular",
+        ] {
+            let scene = Scene::parse(&format!("camera {}", name)).unwrap();
+            assert!(scene.camera.build(1.5).unwrap().get_ray(0.5, 0.5).is_some());
+            let stereo = Scene::parse(&format!("camera {}\nstereo side_by_side", name)).unwrap();
+            assert!(stereo
+                .camera
+                .build(3.0)
+                .unwrap()
+                .get_ray(0.25, 0.5)
+                .is_some());
+        }
+    }
+
+    #[test]
+    fn parses_lens_systems() {
+        let scene = Scene::parse(
+            "lens lenses/dgauss50.lens\n\
+             focus_dist 2\n\
+             film_diagonal 28\n",
+        )
+        .unwrap();
+        assert_eq!(scene.camera.projection, Projection::Lens);
+        assert_eq!(scene.camera.lens.as_ref().unwrap().elements.len(), 11);
+        let camera = scene.camera.build(1.5).unwrap();
+        assert!((0..100).any(|_| camera.get_ray(0.5, 0.5).is_some()));
+        // settings changed after parsing, as the command line and the preview do, are checked
+        // again
+        let mut near = scene.camera.clone();
+        near.focus_dist = 0.001;
+        let err = near.build(1.5).err().unwrap();
+        assert_eq!(err.message, "the lens cannot focus at 0.001");
+
+        let err = Scene::parse("camera lens").err().unwrap();
+        assert_eq!(err.line, 1);
+        let err = Scene::parse("lens lenses/dgauss50.lens\nfocus_dist 0.01")
+            .err()
+            .unwrap();
+        assert!(err.message.contains("cannot focus"));
+        let err = Scene::parse("lens /nonexistent.lens").err().unwrap();
+        assert!(err.message.contains("/nonexistent.lens"));
+    }
+
+    #[test]
+    fn parses_film() {
+        let scene = Scene::parse(
+            "exposure_camera 400 0.01 2.8\n\
+             white_balance 3200\n\
+             tone_map aces luminance\n\
+             encoding gamma 2.2\n",
+        )
+        .unwrap();
+        let film = scene.film;
+        assert!((film.exposure.ev100() - (2.8f32 * 2.8 / 0.01 / 4.0).log2()).abs() < 1e-5);
+        assert_eq!(film.white_balance, Some(WhiteBalance::new(3200.0)));
+        assert_eq!(film.tone_map, ToneMap::Aces);
+        assert!(film.preserve_hue);
+        assert_eq!(film.encoding, Encoding::Gamma(2.2));
+
+        let scene = Scene::parse("exposure -1.5\nwhite_balance off\nencoding rec709").unwrap();
+        assert_eq!(scene.film.exposure, Exposure::Ev(-1.5));
+        assert_eq!(scene.film.white_balance, None);
+        assert_eq!(scene.film.encoding, Encoding::Rec709);
+
+        assert!(Scene::parse("exposure_camera 100 0 2").is_err());
+        assert!(Scene::parse("white_balance 500").is_err());
+        assert!(Scene::parse("tone_map reinhard sideways").is_err());
+        assert!(Scene::parse("encoding gamma").is_err());
+        assert!(Scene::parse("encoding pq").is_err());
+    }
+
+    #[test]
+    fn samples_keyframes() {
+        let text = "frames 0 10\n\
+                    fps 30\n\
+                    material red lambertian 0.8 0.1 0.1\n\
+                    object ball sphere 0 0 0 1 red\n\
+                    key camera.fov 0 linear 20\n\
+                    key camera.fov 10 linear 40\n\
+                    key camera.look_from 0 slerp 10 0 0\n\
+                    key camera.look_from 10 slerp 0 0 10\n\
+                    key object.ball.translate 0 bezier 0 0 0\n\
+                    key object.ball.translate 10 bezier 0 2 0\n\
+                    key object.ball.rotate 0 slerp 0 1 0 0\n\
+                    key material.red.albedo 10 linear 0 0 0\n";
+        let scene = Scene::parse_at(text, 5.0).unwrap();
+        assert_eq!(
+            scene.frames,
+            Some(FrameRange {
+                start: 0,
+                end: 10,
+                fps: 30.0
+            })
+        );
+        assert_eq!(scene.camera.fov, 30.0);
+        let from = scene.camera.look_from;
+        assert!((from.length() - 10.0).abs() < 1e-4 && (from.x - from.z).abs() < 1e-4);
+        // halfway up its path the ball is centered at y = 1
+        let r = Ray::new(Vec3::new(0.0, 1.0, 10.0), Vec3::new(0.0, 0.0, -1.0));
+        let rec = scene.objects[0].hit(&r, 0.0, f32::MAX).unwrap();
+        assert!((rec.t - 9.0).abs() < 1e-4);
+        // frames past the last key hold it
+        assert_eq!(Scene::parse_at(text, 50.0).unwrap().camera.fov, 40.0);
+        assert_eq!(Scene::parse(text).unwrap().camera.fov, 20.0);
+    }
+
+    #[test]
+    fn reports_bad_keyframes() {
+        let err = Scene::parse("key object.ghost.translate 0 linear 1 2 3")
+            .err()
+            .unwrap();
+        assert!(err.message.contains("object.ghost"));
+        let err = Scene::parse("fov 20\nkey camera.zoom 0 linear 2")
+            .err()
+            .unwrap();
+        assert_eq!(err.line, 2);
+        assert!(Scene::parse("key camera.fov x linear 1").is_err());
+        assert!(Scene::parse("key camera.fov 0 cubic 1").is_err());
+        assert!(Scene::parse("key camera.look_at 0 linear 1 2").is_err());
+        assert!(Scene::parse("key camera.fov 0 linear").is_err());
+        assert!(Scene::parse("frames 5 2").is_err());
+        assert!(Scene::parse("object ball random_spheres").is_err());
+    }
+
+    #[test]
+    fn parses_bokeh() {
+        let scene = Scene::parse(
+            "aperture_blades 7 15\n\
+             anamorphic 1.33\n\
+             cats_eye 0.5\n",
+        )
+        .unwrap();
+        let aperture = &scene.camera.aperture_shape;
+        match aperture.shape {
+            ApertureShape::Polygon { blades, rotation } => {
+                assert_eq!((blades, rotation), (7, 15.0))
+            }
+            _ => panic!("expected a polygonal aperture"),
+        }
+        assert_eq!(aperture.squeeze, 1.33);
+        assert_eq!(aperture.cats_eye, 0.5);
+        assert!(Scene::parse("aperture_blades 2").is_err());
+        assert!(Scene::parse("anamorphic 0").is_err());
+        let err = Scene::parse("aperture_mask /nonexistent.pgm")
+            .err()
+            .unwrap();
+        assert!(err.message.contains("/nonexistent.pgm"));
+    }
+
+    #[test]
+    fn parses_stereo() {
+        let scene = Scene::parse(
+            "camera equirectangular\n\
+             stereo top_bottom\n\
+             ipd 0.07\n\
+             convergence 3\n\
+             pano_span 180\n",
+        )
+        .unwrap();
+        assert_eq!(scene.camera.stereo, Some(StereoLayout::TopBottom));
+        assert_eq!(scene.camera.ipd, 0.07);
+        assert_eq!(scene.camera.convergence, 3.0);
+        assert_eq!(scene.camera.pano_span, 180.0);
+        assert!(Scene::parse("stereo off").unwrap().camera.stereo.is_none());
+        assert!(Scene::parse("stereo anaglyph").is_err());
+    }
+}
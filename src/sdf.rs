@@ -0,0 +1,690 @@
+use crate::aabb::Aabb;
+use crate::hitable::{sphere_uv, HitRecord, Hitable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+use std::rc::Rc;
+
+/// Composable signed distance function; negative inside.
+///
+/// Primitives are centered on the origin and placed with `translate`/`scale`.
+pub enum Sdf {
+    Sphere {
+        radius: f32,
+    },
+    Cuboid {
+        half_extents: Vec3,
+    },
+    /// Around the y axis.
+    Torus {
+        major_radius: f32,
+        minor_radius: f32,
+    },
+    /// Capped, along the y axis.
+    Cylinder {
+        radius: f32,
+        half_height: f32,
+    },
+    /// Distance estimate of the power-`power` Mandelbulb, roughly within radius 1.2.
+    Mandelbulb {
+        power: f32,
+        iterations: usize,
+    },
+    Translate {
+        offset: Vec3,
+        child: Box<Sdf>,
+    },
+    Scale {
+        factor: f32,
+        child: Box<Sdf>,
+    },
+    Union(Box<Sdf>, Box<Sdf>),
+    Intersection(Box<Sdf>, Box<Sdf>),
+    Difference(Box<Sdf>, Box<Sdf>),
+    /// Blends within distance `k` of where the surfaces meet.
+    SmoothUnion {
+        k: f32,
+        a: Box<Sdf>,
+        b: Box<Sdf>,
+    },
+    SmoothIntersection {
+        k: f32,
+        a: Box<Sdf>,
+        b: Box<Sdf>,
+    },
+    SmoothDifference {
+        k: f32,
+        a: Box<Sdf>,
+        b: Box<Sdf>,
+    },
+    /// Inflates the child by `radius`, rounding its edges.
+    Round {
+        radius: f32,
+        child: Box<Sdf>,
+    },
+    /// Rotates around the y axis by `rate` radians per unit of height.
+    Twist {
+        rate: f32,
+        child: Box<Sdf>,
+    },
+    /// Rotates around the z axis by `rate` radians per unit along x.
+    Bend {
+        rate: f32,
+        child: Box<Sdf>,
+    },
+    /// Infinite copies every `period` along each axis; a zero component leaves that axis alone.
+    Repeat {
+        period: Vec3,
+        child: Box<Sdf>,
+    },
+    /// Adds `amplitude * sin(fx) sin(fy) sin(fz)` to the surface.
+    Displace {
+        amplitude: f32,
+        frequency: f32,
+        child: Box<Sdf>,
+    },
+}
+
+fn mix(a: f32, b: f32, h: f32) -> f32 {
+    a * (1.0 - h) + b * h
+}
+
+impl Sdf {
+    pub fn sphere(radius: f32) -> Sdf {
+        Sdf::Sphere { radius }
+    }
+
+    pub fn cuboid(half_extents: Vec3) -> Sdf {
+        Sdf::Cuboid { half_extents }
+    }
+
+    pub fn torus(major_radius: f32, minor_radius: f32) -> Sdf {
+        Sdf::Torus {
+            major_radius,
+            minor_radius,
+        }
+    }
+
+    pub fn cylinder(radius: f32, half_height: f32) -> Sdf {
+        Sdf::Cylinder {
+            radius,
+            half_height,
+        }
+    }
+
+    pub fn mandelbulb(power: f32, iterations: usize) -> Sdf {
+        Sdf::Mandelbulb { power, iterations }
+    }
+
+    pub fn translate(self, offset: Vec3) -> Sdf {
+        Sdf::Translate {
+            offset,
+            child: Box::new(self),
+        }
+    }
+
+    pub fn scale(self, factor: f32) -> Sdf {
+        Sdf::Scale {
+            factor,
+            child: Box::new(self),
+        }
+    }
+
+    pub fn union(self, other: Sdf) -> Sdf {
+        Sdf::Union(Box::new(self), Box::new(other))
+    }
+
+    pub fn intersection(self, other: Sdf) -> Sdf {
+        Sdf::Intersection(Box::new(self), Box::new(other))
+    }
+
+    pub fn difference(self, other: Sdf) -> Sdf {
+        Sdf::Difference(Box::new(self), Box::new(other))
+    }
+
+    pub fn smooth_union(self, other: Sdf, k: f32) -> Sdf {
+        Sdf::SmoothUnion {
+            k,
+            a: Box::new(self),
+            b: Box::new(other),
+        }
+    }
+
+    pub fn smooth_intersection(self, other: Sdf, k: f32) -> Sdf {
+        Sdf::SmoothIntersection {
+            k,
+            a: Box::new(self),
+            b: Box::new(other),
+        }
+    }
+
+    pub fn smooth_difference(self, other: Sdf, k: f32) -> Sdf {
+        Sdf::SmoothDifference {
+            k,
+            a: Box::new(self),
+            b: Box::new(other),
+        }
+    }
+
+    pub fn round(self, radius: f32) -> Sdf {
+        Sdf::Round {
+            radius,
+            child: Box::new(self),
+        }
+    }
+
+    pub fn twist(self, rate: f32) -> Sdf {
+        Sdf::Twist {
+            rate,
+            child: Box::new(self),
+        }
+    }
+
+    pub fn bend(self, rate: f32) -> Sdf {
+        Sdf::Bend {
+            rate,
+            child: Box::new(self),
+        }
+    }
+
+    pub fn repeat(self, period: Vec3) -> Sdf {
+        Sdf::Repeat {
+            period,
+            child: Box::new(self),
+        }
+    }
+
+    pub fn displace(self, amplitude: f32, frequency: f32) -> Sdf {
+        Sdf::Displace {
+            amplitude,
+            frequency,
+            child: Box::new(self),
+        }
+    }
+
+    pub fn distance(&self, p: &Vec3) -> f32 {
+        match self {
+            Sdf::Sphere { radius } => p.length() - radius,
+            Sdf::Cuboid { half_extents } => {
+                let q = Vec3::new(
+                    p.x.abs() - half_extents.x,
+                    p.y.abs() - half_extents.y,
+                    p.z.abs() - half_extents.z,
+                );
+                let outside = Vec3::new(q.x.max(0.0), q.y.max(0.0), q.z.max(0.0));
+                outside.length() + q.x.max(q.y).max(q.z).min(0.0)
+            }
+            Sdf::Torus {
+                major_radius,
+                minor_radius,
+            } => {
+                let ring = (p.x * p.x + p.z * p.z).sqrt() - major_radius;
+                (ring * ring + p.y * p.y).sqrt() - minor_radius
+            }
+            Sdf::Cylinder {
+                radius,
+                half_height,
+            } => {
+                let dx = (p.x * p.x + p.z * p.z).sqrt() - radius;
+                let dy = p.y.abs() - half_height;
+                dx.max(dy).min(0.0) + (dx.max(0.0).powi(2) + dy.max(0.0).powi(2)).sqrt()
+            }
+            Sdf::Mandelbulb { power, iterations } => {
+                let mut z = *p;
+                let mut dr = 1.0;
+                let mut r = 0.0;
+                for _ in 0..*iterations {
+                    r = z.length();
+                    if r > 2.0 {
+                        break;
+                    }
+                    let theta = (z.z / r).clamp(-1.0, 1.0).acos() * power;
+                    let phi = z.y.atan2(z.x) * power;
+                    dr = r.powf(power - 1.0) * power * dr + 1.0;
+                    let zr = r.powf(*power);
+                    z =
+                        zr * Vec3::new(
+                            theta.sin() * phi.cos(),
+                            phi.sin() * theta.sin(),
+                            theta.cos(),
+                        ) + *p;
+                }
+                if r == 0.0 {
+                    return 0.0;
+                }
+                0.5 * r.ln() * r / dr
+            }
+            Sdf::Translate { offset, child } => child.distance(&(*p - offset)),
+            Sdf::Scale { factor, child } => child.distance(&(*p / *factor)) * factor,
+            Sdf::Union(a, b) => a.distance(p).min(b.distance(p)),
+            Sdf::Intersection(a, b) => a.distance(p).max(b.distance(p)),
+            Sdf::Difference(a, b) => a.distance(p).max(-b.distance(p)),
+            Sdf::SmoothUnion { k, a, b } => {
+                let (da, db) = (a.distance(p), b.distance(p));
+                let h = (0.5 + 0.5 * (db - da) / k).clamp(0.0, 1.0);
+                mix(db, da, h) - k * h * (1.0 - h)
+            }
+            Sdf::SmoothIntersection { k, a, b } => {
+                let (da, db) = (a.distance(p), b.distance(p));
+                let h = (0.5 - 0.5 * (db - da) / k).clamp(0.0, 1.0);
+                mix(db, da, h) + k * h * (1.0 - h)
+            }
+            Sdf::SmoothDifference { k, a, b } => {
+                let (da, db) = (a.distance(p), b.distance(p));
+                let h = (0.5 - 0.5 * (da + db) / k).clamp(0.0, 1.0);
+                mix(da, -db, h) + k * h * (1.0 - h)
+            }
+            Sdf::Round { radius, child } => child.distance(p) - radius,
+            Sdf::Twist { rate, child } => {
+                let (s, c) = (rate * p.y).sin_cos();
+                child.distance(&Vec3::new(c * p.x - s * p.z, p.y, s * p.x + c * p.z))
+            }
+            Sdf::Bend { rate, child } => {
+                let (s, c) = (rate * p.x).sin_cos();
+                child.distance(&Vec3::new(c * p.x - s * p.y, s * p.x + c * p.y, p.z))
+            }
+            Sdf::Repeat { period, child } => {
+                fn wrap(x: f32, period: f32) -> f32 {
+                    if period > 0.0 {
+                        x - period * (x / period).round()
+                    } else {
+                        x
+                    }
+                }
+                child.distance(&Vec3::new(
+                    wrap(p.x, period.x),
+                    wrap(p.y, period.y),
+                    wrap(p.z, period.z),
+                ))
+            }
+            Sdf::Displace {
+                amplitude,
+                frequency,
+                child,
+            } => {
+                let f = frequency;
+                child.distance(p) + amplitude * (f * p.x).sin() * (f * p.y).sin() * (f * p.z).sin()
+            }
+        }
+    }
+
+    /// Outward normal from the central-difference gradient.
+    pub fn normal(&self, p: &Vec3, h: f32) -> Vec3 {
+        let dx = Vec3::new(h, 0.0, 0.0);
+        let dy = Vec3::new(0.0, h, 0.0);
+        let dz = Vec3::new(0.0, 0.0, h);
+        let gradient = Vec3::new(
+            self.distance(&(*p + dx)) - self.distance(&(*p - dx)),
+            self.distance(&(*p + dy)) - self.distance(&(*p - dy)),
+            self.distance(&(*p + dz)) - self.distance(&(*p - dz)),
+        );
+        if gradient.squared_length() > 0.0 {
+            gradient.unit_vector()
+        } else {
+            Vec3::from_i(0, 1, 0)
+        }
+    }
+
+    /// Conservative bounds of the surface; `None` when it is unbounded, e.g. under `Repeat`.
+    pub fn bounds(&self) -> Option<Aabb> {
+        fn cube(e: f32) -> Aabb {
+            Aabb::new(Vec3::new(-e, -e, -e), Vec3::new(e, e, e))
+        }
+        fn grow(b: Aabb, e: f32) -> Aabb {
+            let e = Vec3::new(e, e, e);
+            Aabb::new(b.min - e, b.max + e)
+        }
+        fn radius_around(b: &Aabb, i: usize, j: usize) -> f32 {
+            let x = b.min[i].abs().max(b.max[i].abs());
+            let y = b.min[j].abs().max(b.max[j].abs());
+            (x * x + y * y).sqrt()
+        }
+        match self {
+            Sdf::Sphere { radius } => Some(cube(*radius)),
+            Sdf::Cuboid { half_extents } => Some(Aabb::new(-*half_extents, *half_extents)),
+            Sdf::Torus {
+                major_radius,
+                minor_radius,
+            } => {
+                let outer = major_radius + minor_radius;
+                Some(Aabb::new(
+                    Vec3::new(-outer, -minor_radius, -outer),
+                    Vec3::new(outer, *minor_radius, outer),
+                ))
+            }
+            Sdf::Cylinder {
+                radius,
+                half_height,
+            } => Some(Aabb::new(
+                Vec3::new(-radius, -half_height, -radius),
+                Vec3::new(*radius, *half_height, *radius),
+            )),
+            Sdf::Mandelbulb { .. } => Some(cube(1.25)),
+            Sdf::Translate { offset, child } => child
+                .bounds()
+                .map(|b| Aabb::new(b.min + *offset, b.max + *offset)),
+            Sdf::Scale { factor, child } => child
+                .bounds()
+                .map(|b| Aabb::from_points(b.min * *factor, b.max * *factor)),
+            Sdf::Union(a, b) => Some(a.bounds()?.surrounding(&b.bounds()?)),
+            Sdf::Intersection(a, b) => a.bounds().or_else(|| b.bounds()),
+            Sdf::Difference(a, _) => a.bounds(),
+            Sdf::SmoothUnion { k, a, b } => Some(grow(a.bounds()?.surrounding(&b.bounds()?), *k)),
+            Sdf::SmoothIntersection { a, b, .. } => a.bounds().or_else(|| b.bounds()),
+            Sdf::SmoothDifference { a, .. } => a.bounds(),
+            Sdf::Round { radius, child } => child.bounds().map(|b| grow(b, *radius)),
+            Sdf::Twist { child, .. } => child.bounds().map(|b| {
+                let r = radius_around(&b, 0, 2);
+                Aabb::new(Vec3::new(-r, b.min.y, -r), Vec3::new(r, b.max.y, r))
+            }),
+            Sdf::Bend { child, .. } => child.bounds().map(|b| {
+                let r = radius_around(&b, 0, 1);
+                Aabb::new(Vec3::new(-r, -r, b.min.z), Vec3::new(r, r, b.max.z))
+            }),
+            Sdf::Repeat { .. } => None,
+            Sdf::Displace {
+                amplitude, child, ..
+            } => child.bounds().map(|b| grow(b, amplitude.abs())),
+        }
+    }
+
+    /// Whether `distance` is a true Euclidean distance bound, so sphere tracing may take full steps.
+    fn is_lipschitz(&self) -> bool {
+        match self {
+            Sdf::Mandelbulb { .. }
+            | Sdf::Twist { .. }
+            | Sdf::Bend { .. }
+            | Sdf::Displace { .. } => false,
+            Sdf::Translate { child, .. }
+            | Sdf::Scale { child, .. }
+            | Sdf::Round { child, .. }
+            | Sdf::Repeat { child, .. } => child.is_lipschitz(),
+            Sdf::Union(a, b)
+            | Sdf::Intersection(a, b)
+            | Sdf::Difference(a, b)
+            | Sdf::SmoothUnion { a, b, .. }
+            | Sdf::SmoothIntersection { a, b, .. }
+            | Sdf::SmoothDifference { a, b, .. } => a.is_lipschitz() && b.is_lipschitz(),
+            _ => true,
+        }
+    }
+}
+
+/// Sphere-traced `Sdf`. UVs are the spherical coordinates of the normal.
+pub struct SdfObject {
+    pub sdf: Sdf,
+    pub material: Rc<Box<dyn Material>>,
+    pub max_steps: usize,
+    /// Surface is considered reached within this distance.
+    pub epsilon: f32,
+    /// Fraction of the distance advanced per step; below 1 for inexact distance fields.
+    pub step_scale: f32,
+    /// Farthest distance traced when the field is unbounded.
+    pub max_distance: f32,
+    bounds: Option<Aabb>,
+}
+
+impl SdfObject {
+    pub fn new(sdf: Sdf, material: Rc<Box<dyn Material>>) -> SdfObject {
+        let step_scale = if sdf.is_lipschitz() { 1.0 } else { 0.5 };
+        SdfObject {
+            bounds: sdf.bounds(),
+            sdf,
+            material,
+            max_steps: 512,
+            epsilon: 0.0001,
+            step_scale,
+            max_distance: 1000.0,
+        }
+    }
+}
+
+impl Hitable for SdfObject {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        // march in units of distance along a normalized direction
+        let length = r.direction().length();
+        let d = r.direction().unit_vector();
+        let (mut start, mut end) = (t_min * length, t_max.min(f32::MAX / length) * length);
+        match self.bounds {
+            Some(b) => {
+                let margin = Vec3::new(1.0, 1.0, 1.0) * (16.0 * self.epsilon);
+                let b = Aabb::new(b.min - margin, b.max + margin);
+                let clip = Ray::new(*r.origin(), d);
+                let (enter, exit) = slab_range(&b, &clip)?;
+                start = start.max(enter);
+                end = end.min(exit);
+            }
+            None => {
+                start = start.max(0.0);
+                end = end.min(self.max_distance);
+            }
+        }
+        if start > end {
+            return None;
+        }
+
+        let at = |t: f32| *r.origin() + t * d;
+        let from_origin = start <= t_min * length;
+        let mut t = start;
+        let mut dist = self.sdf.distance(&at(t));
+        // step off a surface the ray starts on, e.g. after a refraction
+        let mut nudges = 0;
+        while from_origin && dist.abs() < self.epsilon && nudges < 8 {
+            t += 2.0 * self.epsilon;
+            dist = self.sdf.distance(&at(t));
+            nudges += 1;
+        }
+        let side = if dist < 0.0 { -1.0 } else { 1.0 };
+        for _ in 0..self.max_steps {
+            if t > end {
+                return None;
+            }
+            let toward = side * dist;
+            if toward < self.epsilon {
+                let p = at(t);
+                let normal = self.sdf.normal(&p, self.epsilon);
+                let (u, v) = sphere_uv(&normal);
+                return Some(HitRecord {
+                    t: t / length,
+                    p,
+                    normal,
+                    u,
+                    v,
+                    material: self.material.clone(),
+                });
+            }
+            t += (toward * self.step_scale).max(self.epsilon);
+            dist = self.sdf.distance(&at(t));
+        }
+        None
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bounds
+    }
+}
+
+/// Entry and exit distance of a ray through a box, `None` on a miss.
+fn slab_range(b: &Aabb, r: &Ray) -> Option<(f32, f32)> {
+    let (mut t0, mut t1) = (f32::NEG_INFINITY, f32::INFINITY);
+    for axis in 0..3 {
+        let inv_d = 1.0 / r.direction()[axis];
+        let mut near = (b.min[axis] - r.origin()[axis]) * inv_d;
+        let mut far = (b.max[axis] - r.origin()[axis]) * inv_d;
+        if inv_d < 0.0 {
+            std::mem::swap(&mut near, &mut far);
+        }
+        if near > t0 {
+            t0 = near;
+        }
+        if far < t1 {
+            t1 = far;
+        }
+    }
+    if t1 < t0 {
+        None
+    } else {
+        Some((t0, t1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bvh::Bvh;
+    use crate::hitable::{HitableList, Sphere};
+    use crate::material::Lambertian;
+    use crate::misc::random;
+    use crate::shapes::{AxisAlignedBox, Plane};
+
+    fn material() -> Rc<Box<dyn Material>> {
+        Rc::new(Box::new(Lambertian::new((0.5, 0.5, 0.5).into())))
+    }
+
+    fn assert_close(a: f32, b: f32, eps: f32) {
+        assert!((a - b).abs() < eps, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn sphere_matches_analytic() {
+        let sdf = SdfObject::new(Sdf::sphere(1.0).translate((0, 1, 0).into()), material());
+        let r = Ray::new((0, 1, -5).into(), (0, 0, 2).into());
+        let rec = sdf.hit(&r, 0.0001, f32::MAX).unwrap();
+        assert_close(rec.t, 2.0, 1e-3);
+        assert!((rec.normal - Vec3::from_i(0, 0, -1)).length() < 1e-2);
+        // from the inside
+        let r = Ray::new((0, 1, 0).into(), (1, 0, 0).into());
+        let rec = sdf.hit(&r, 0.0001, f32::MAX).unwrap();
+        assert_close(rec.t, 1.0, 1e-3);
+        assert!((rec.normal - Vec3::from_i(1, 0, 0)).length() < 1e-2);
+        // grazing
+        assert!(sdf
+            .hit(
+                &Ray::new((-5.0, 1.99, 0.0).into(), (1, 0, 0).into()),
+                0.0001,
+                100.0
+            )
+            .is_some());
+        assert!(sdf
+            .hit(
+                &Ray::new((-5.0, 2.01, 0.0).into(), (1, 0, 0).into()),
+                0.0001,
+                100.0
+            )
+            .is_none());
+        assert!(sdf
+            .hit(&Ray::new((0, 1, -5).into(), (0, 0, 1).into()), 0.0001, 3.0)
+            .is_none());
+    }
+
+    #[test]
+    fn primitive_distances() {
+        let p = Vec3::new(3.0, 0.0, 0.0);
+        assert_close(Sdf::cuboid((1, 1, 1).into()).distance(&p), 2.0, 1e-6);
+        assert_close(Sdf::torus(2.0, 0.5).distance(&p), 0.5, 1e-6);
+        assert_close(Sdf::cylinder(1.0, 1.0).distance(&p), 2.0, 1e-6);
+        assert_close(
+            Sdf::cylinder(1.0, 1.0).distance(&Vec3::new(0.0, 3.0, 0.0)),
+            2.0,
+            1e-6,
+        );
+        assert_close(
+            Sdf::cuboid((1, 1, 1).into()).distance(&Vec3::new(0.0, 0.0, 0.0)),
+            -1.0,
+            1e-6,
+        );
+        assert_close(Sdf::sphere(1.0).scale(2.0).distance(&p), 1.0, 1e-6);
+    }
+
+    #[test]
+    fn smooth_union_fills_the_gap() {
+        let a = || Sdf::sphere(1.0).translate((-1.1, 0.0, 0.0).into());
+        let b = || Sdf::sphere(1.0).translate((1.1, 0.0, 0.0).into());
+        let sharp = a().union(b());
+        let smooth = a().smooth_union(b(), 0.5);
+        let gap = Vec3::new(0.0, 0.0, 0.0);
+        assert!(sharp.distance(&gap) > 0.0);
+        assert!(smooth.distance(&gap) < 0.0);
+        // far from the seam both agree
+        let far = Vec3::new(3.0, 0.0, 0.0);
+        assert_close(sharp.distance(&far), smooth.distance(&far), 1e-6);
+    }
+
+    #[test]
+    fn repeat_hits_copies() {
+        let spheres = SdfObject::new(Sdf::sphere(0.5).repeat((2, 0, 0).into()), material());
+        assert!(spheres.bounding_box().is_none());
+        let r = Ray::new((4, 5, 0).into(), (0, -1, 0).into());
+        let rec = spheres.hit(&r, 0.0001, f32::MAX).unwrap();
+        assert_close(rec.t, 4.5, 1e-3);
+        let between = Ray::new((3, 5, 0).into(), (0, -1, 0).into());
+        assert!(spheres.hit(&between, 0.0001, f32::MAX).is_none());
+    }
+
+    #[test]
+    fn deformed_shapes_stay_in_bounds() {
+        let shapes = vec![
+            Sdf::cuboid((0.3, 1.0, 0.3).into()).twist(1.5),
+            Sdf::cuboid((1.0, 0.2, 0.2).into()).bend(0.8),
+            Sdf::sphere(1.0).displace(0.1, 8.0),
+            Sdf::mandelbulb(8.0, 8),
+        ];
+        for sdf in shapes {
+            let object = SdfObject::new(sdf, material());
+            assert!(object.step_scale < 1.0);
+            let b = object.bounding_box().unwrap().padded(1e-3);
+            for _ in 0..200 {
+                let origin = Vec3::new(random() - 0.5, random() - 0.5, random() - 0.5) * 10.0;
+                let target = Vec3::new(random() - 0.5, random() - 0.5, random() - 0.5) * 0.5;
+                let r = Ray::new(origin, target - origin);
+                if let Some(rec) = object.hit(&r, 0.0001, f32::MAX) {
+                    for axis in 0..3 {
+                        assert!(
+                            b.min[axis] - 1e-3 <= rec.p[axis] && rec.p[axis] <= b.max[axis] + 1e-3
+                        );
+                    }
+                    assert!(object.sdf.distance(&rec.p).abs() < 1e-2);
+                    assert_close(rec.normal.length(), 1.0, 1e-3);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn mixes_with_analytic_shapes_in_bvh() {
+        let build = || -> Vec<Box<dyn Hitable>> {
+            vec![
+                Box::new(SdfObject::new(
+                    Sdf::torus(1.0, 0.25).smooth_union(Sdf::sphere(0.5), 0.3),
+                    material(),
+                )),
+                Box::new(Sphere {
+                    center: (2, 0, 0).into(),
+                    radius: 0.5,
+                    material: material(),
+                }),
+                Box::new(AxisAlignedBox::new(
+                    (-3, -1, -1).into(),
+                    (-2, 1, 1).into(),
+                    material(),
+                )),
+                Box::new(Plane::new((0, -1, 0).into(), (0, 1, 0).into(), material())),
+            ]
+        };
+        let list = HitableList::new(build());
+        let bvh = Bvh::new(build());
+        for _ in 0..300 {
+            let origin = Vec3::new(random() * 10.0 - 5.0, random() * 4.0, -6.0);
+            let target = Vec3::new(random() * 6.0 - 3.0, random() * 2.0 - 1.0, 0.0);
+            let r = Ray::new(origin, target - origin);
+            let (a, b) = (
+                list.hit(&r, 0.0001, f32::MAX),
+                bvh.hit(&r, 0.0001, f32::MAX),
+            );
+            assert_eq!(a.is_some(), b.is_some());
+            if let (Some(a), Some(b)) = (a, b) {
+                assert_close(a.t, b.t, 1e-5);
+            }
+        }
+    }
+}
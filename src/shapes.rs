@@ -0,0 +1,1449 @@
+use crate::aabb::Aabb;
+use crate::hitable::{HitRecord, Hitable, Interval};
+use crate::material::Material;
+use crate::misc::random;
+use crate::ray::Ray;
+use crate::vec3::{orthonormal_basis, Vec3};
+use std::f32::consts::PI;
+use std::rc::Rc;
+
+/// Thickness given to the bounding boxes of flat shapes.
+const FLAT_PADDING: f32 = 0.0001;
+
+fn record(
+    r: &Ray,
+    t: f32,
+    normal: Vec3,
+    u: f32,
+    v: f32,
+    material: &Rc<Box<dyn Material>>,
+) -> HitRecord {
+    HitRecord {
+        t,
+        p: r.point_at_parameter(t),
+        normal,
+        u,
+        v,
+        material: material.clone(),
+    }
+}
+
+/// Angle of `(x, z)` around the y axis mapped to `[0, 1)`.
+fn azimuth(x: f32, z: f32) -> f32 {
+    let phi = z.atan2(x);
+    if phi < 0.0 {
+        (phi + 2.0 * PI) / (2.0 * PI)
+    } else {
+        phi / (2.0 * PI)
+    }
+}
+
+/// Roots of `a t^2 + 2 b t + c`, ascending.
+fn solve_quadratic(a: f32, half_b: f32, c: f32) -> Option<(f32, f32)> {
+    if a.abs() < f32::EPSILON {
+        if half_b.abs() < f32::EPSILON {
+            return None;
+        }
+        let t = -c / (2.0 * half_b);
+        return Some((t, t));
+    }
+    let discriminant = half_b * half_b - a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let q = -(half_b + half_b.signum() * discriminant.sqrt());
+    let (t0, t1) = if q == 0.0 { (0.0, 0.0) } else { (q / a, c / q) };
+    Some((t0.min(t1), t0.max(t1)))
+}
+
+/// Infinite plane through `point`. UVs tile with period 1 along an arbitrary in-plane basis.
+pub struct Plane {
+    pub point: Vec3,
+    pub normal: Vec3,
+    pub material: Rc<Box<dyn Material>>,
+}
+
+impl Plane {
+    pub fn new(point: Vec3, normal: Vec3, material: Rc<Box<dyn Material>>) -> Plane {
+        Plane {
+            point,
+            normal: normal.unit_vector(),
+            material,
+        }
+    }
+}
+
+impl Hitable for Plane {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let denom = self.normal.dot(r.direction());
+        if denom.abs() < 1e-12 {
+            return None;
+        }
+        let t = (self.point - r.origin()).dot(&self.normal) / denom;
+        if !(t_min < t && t < t_max) {
+            return None;
+        }
+        let local = r.point_at_parameter(t) - self.point;
+        let (tangent, bitangent) = orthonormal_basis(&self.normal);
+        let u = local.dot(&tangent).rem_euclid(1.0);
+        let v = local.dot(&bitangent).rem_euclid(1.0);
+        Some(record(r, t, self.normal, u, v, &self.material))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+}
+
+/// Planar parallelogram spanned by `edge_u` and `edge_v` from `corner`; UVs run 0..1 along the edges.
+pub struct Quad {
+    pub corner: Vec3,
+    pub edge_u: Vec3,
+    pub edge_v: Vec3,
+    pub material: Rc<Box<dyn Material>>,
+}
+
+impl Quad {
+    pub fn new(corner: Vec3, edge_u: Vec3, edge_v: Vec3, material: Rc<Box<dyn Material>>) -> Quad {
+        Quad {
+            corner,
+            edge_u,
+            edge_v,
+            material,
+        }
+    }
+}
+
+impl Hitable for Quad {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let n = self.edge_u.cross(&self.edge_v);
+        let denom = n.dot(r.direction());
+        if denom.abs() < 1e-12 {
+            return None;
+        }
+        let t = (self.corner - r.origin()).dot(&n) / denom;
+        if !(t_min < t && t < t_max) {
+            return None;
+        }
+        let planar = r.point_at_parameter(t) - self.corner;
+        let w = n / n.dot(&n);
+        let u = w.dot(&planar.cross(&self.edge_v));
+        let v = w.dot(&self.edge_u.cross(&planar));
+        if !(0.0..=1.0).contains(&u) || !(0.0..=1.0).contains(&v) {
+            return None;
+        }
+        Some(record(r, t, n.unit_vector(), u, v, &self.material))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let far = self.corner + self.edge_u + self.edge_v;
+        Some(
+            Aabb::from_points(self.corner, far)
+                .surrounding(&Aabb::from_points(
+                    self.corner + self.edge_u,
+                    self.corner + self.edge_v,
+                ))
+                .padded(FLAT_PADDING),
+        )
+    }
+
+    fn area(&self) -> Option<f32> {
+        Some(self.edge_u.cross(&self.edge_v).length())
+    }
+
+    fn sample_surface(&self) -> Option<(Vec3, Vec3)> {
+        let p = self.corner + random() * self.edge_u + random() * self.edge_v;
+        Some((p, self.edge_u.cross(&self.edge_v).unit_vector()))
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+impl Axis {
+    pub fn index(self) -> usize {
+        match self {
+            Axis::X => 0,
+            Axis::Y => 1,
+            Axis::Z => 2,
+        }
+    }
+
+    fn unit(self) -> Vec3 {
+        match self {
+            Axis::X => (1, 0, 0).into(),
+            Axis::Y => (0, 1, 0).into(),
+            Axis::Z => (0, 0, 1).into(),
+        }
+    }
+
+    /// The two in-plane axes of a plane perpendicular to `self`, in ascending order.
+    fn others(self) -> (usize, usize) {
+        match self {
+            Axis::X => (1, 2),
+            Axis::Y => (0, 2),
+            Axis::Z => (0, 1),
+        }
+    }
+}
+
+/// Rectangle at `k` on the `normal` axis, spanning `[a0, a1] x [b0, b1]` on the remaining axes
+/// in x, y, z order. The normal points along the positive axis.
+pub struct AxisAlignedRect {
+    pub normal: Axis,
+    pub a0: f32,
+    pub a1: f32,
+    pub b0: f32,
+    pub b1: f32,
+    pub k: f32,
+    pub material: Rc<Box<dyn Material>>,
+}
+
+impl AxisAlignedRect {
+    pub fn xy(
+        x0: f32,
+        x1: f32,
+        y0: f32,
+        y1: f32,
+        k: f32,
+        material: Rc<Box<dyn Material>>,
+    ) -> AxisAlignedRect {
+        AxisAlignedRect::new(Axis::Z, x0, x1, y0, y1, k, material)
+    }
+
+    pub fn xz(
+        x0: f32,
+        x1: f32,
+        z0: f32,
+        z1: f32,
+        k: f32,
+        material: Rc<Box<dyn Material>>,
+    ) -> AxisAlignedRect {
+        AxisAlignedRect::new(Axis::Y, x0, x1, z0, z1, k, material)
+    }
+
+    pub fn yz(
+        y0: f32,
+        y1: f32,
+        z0: f32,
+        z1: f32,
+        k: f32,
+        material: Rc<Box<dyn Material>>,
+    ) -> AxisAlignedRect {
+        AxisAlignedRect::new(Axis::X, y0, y1, z0, z1, k, material)
+    }
+
+    fn new(
+        normal: Axis,
+        a0: f32,
+        a1: f32,
+        b0: f32,
+        b1: f32,
+        k: f32,
+        material: Rc<Box<dyn Material>>,
+    ) -> AxisAlignedRect {
+        AxisAlignedRect {
+            normal,
+            a0: a0.min(a1),
+            a1: a0.max(a1),
+            b0: b0.min(b1),
+            b1: b0.max(b1),
+            k,
+            material,
+        }
+    }
+}
+
+impl Hitable for AxisAlignedRect {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let n = self.normal.index();
+        let (ia, ib) = self.normal.others();
+        let t = (self.k - r.origin()[n]) / r.direction()[n];
+        if !(t_min < t && t < t_max) {
+            return None;
+        }
+        let p = r.point_at_parameter(t);
+        let (a, b) = (p[ia], p[ib]);
+        if a < self.a0 || a > self.a1 || b < self.b0 || b > self.b1 {
+            return None;
+        }
+        let u = (a - self.a0) / (self.a1 - self.a0);
+        let v = (b - self.b0) / (self.b1 - self.b0);
+        Some(record(r, t, self.normal.unit(), u, v, &self.material))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let n = self.normal.index();
+        let (ia, ib) = self.normal.others();
+        let mut min = [0.0; 3];
+        let mut max = [0.0; 3];
+        min[n] = self.k;
+        max[n] = self.k;
+        min[ia] = self.a0;
+        max[ia] = self.a1;
+        min[ib] = self.b0;
+        max[ib] = self.b1;
+        Some(
+            Aabb::new(
+                Vec3::new(min[0], min[1], min[2]),
+                Vec3::new(max[0], max[1], max[2]),
+            )
+            .padded(FLAT_PADDING),
+        )
+    }
+
+    fn area(&self) -> Option<f32> {
+        Some((self.a1 - self.a0) * (self.b1 - self.b0))
+    }
+
+    fn sample_surface(&self) -> Option<(Vec3, Vec3)> {
+        let (ia, ib) = self.normal.others();
+        let mut p = [0.0; 3];
+        p[self.normal.index()] = self.k;
+        p[ia] = self.a0 + random() * (self.a1 - self.a0);
+        p[ib] = self.b0 + random() * (self.b1 - self.b0);
+        Some((Vec3::new(p[0], p[1], p[2]), self.normal.unit()))
+    }
+}
+
+/// Solid box between two corners. UVs span each face over its two in-plane axes.
+pub struct AxisAlignedBox {
+    pub min: Vec3,
+    pub max: Vec3,
+    pub material: Rc<Box<dyn Material>>,
+}
+
+impl AxisAlignedBox {
+    pub fn new(a: Vec3, b: Vec3, material: Rc<Box<dyn Material>>) -> AxisAlignedBox {
+        let bounds = Aabb::from_points(a, b);
+        AxisAlignedBox {
+            min: bounds.min,
+            max: bounds.max,
+            material,
+        }
+    }
+
+    fn face_record(&self, r: &Ray, t: f32, axis: usize, outward_sign: f32) -> HitRecord {
+        let mut n = [0.0; 3];
+        n[axis] = outward_sign;
+        let p = r.point_at_parameter(t);
+        let (ia, ib) = match axis {
+            0 => (1, 2),
+            1 => (0, 2),
+            _ => (0, 1),
+        };
+        let u = (p[ia] - self.min[ia]) / (self.max[ia] - self.min[ia]);
+        let v = (p[ib] - self.min[ib]) / (self.max[ib] - self.min[ib]);
+        record(r, t, Vec3::new(n[0], n[1], n[2]), u, v, &self.material)
+    }
+
+    /// Where the line of the ray enters and leaves the box and the axes of those faces, or
+    /// `None` when it misses.
+    fn slabs(&self, r: &Ray) -> Option<((f32, usize), (f32, usize))> {
+        let (mut t_near, mut t_far) = (f32::NEG_INFINITY, f32::INFINITY);
+        let (mut near_axis, mut far_axis) = (0, 0);
+        for axis in 0..3 {
+            let inv_d = 1.0 / r.direction()[axis];
+            let mut t0 = (self.min[axis] - r.origin()[axis]) * inv_d;
+            let mut t1 = (self.max[axis] - r.origin()[axis]) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            if t0 > t_near {
+                t_near = t0;
+                near_axis = axis;
+            }
+            if t1 < t_far {
+                t_far = t1;
+                far_axis = axis;
+            }
+        }
+        if t_far < t_near {
+            None
+        } else {
+            Some(((t_near, near_axis), (t_far, far_axis)))
+        }
+    }
+}
+
+impl Hitable for AxisAlignedBox {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let ((t_near, near_axis), (t_far, far_axis)) = self.slabs(r)?;
+        if t_min < t_near && t_near < t_max {
+            let sign = -r.direction()[near_axis].signum();
+            Some(self.face_record(r, t_near, near_axis, sign))
+        } else if t_min < t_far && t_far < t_max {
+            let sign = r.direction()[far_axis].signum();
+            Some(self.face_record(r, t_far, far_axis, sign))
+        } else {
+            None
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::new(self.min, self.max))
+    }
+
+    fn intervals(&self, r: &Ray) -> Vec<Interval> {
+        match self.slabs(r) {
+            Some(((t_near, near_axis), (t_far, far_axis))) => vec![Interval {
+                enter: self.face_record(r, t_near, near_axis, -r.direction()[near_axis].signum()),
+                exit: self.face_record(r, t_far, far_axis, r.direction()[far_axis].signum()),
+            }],
+            None => vec![],
+        }
+    }
+}
+
+/// Flat disk facing `normal`. `u` is the polar angle, `v` the distance from the center over the radius.
+pub struct Disk {
+    pub center: Vec3,
+    pub normal: Vec3,
+    pub radius: f32,
+    pub material: Rc<Box<dyn Material>>,
+}
+
+impl Disk {
+    pub fn new(center: Vec3, normal: Vec3, radius: f32, material: Rc<Box<dyn Material>>) -> Disk {
+        Disk {
+            center,
+            normal: normal.unit_vector(),
+            radius,
+            material,
+        }
+    }
+}
+
+impl Hitable for Disk {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let denom = self.normal.dot(r.direction());
+        if denom.abs() < 1e-12 {
+            return None;
+        }
+        let t = (self.center - r.origin()).dot(&self.normal) / denom;
+        if !(t_min < t && t < t_max) {
+            return None;
+        }
+        let local = r.point_at_parameter(t) - self.center;
+        let dist2 = local.squared_length();
+        if dist2 > self.radius * self.radius {
+            return None;
+        }
+        let (tangent, bitangent) = orthonormal_basis(&self.normal);
+        let u = azimuth(local.dot(&tangent), local.dot(&bitangent));
+        let v = dist2.sqrt() / self.radius;
+        Some(record(r, t, self.normal, u, v, &self.material))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        // extent of a disk along axis i is radius * sqrt(1 - n_i^2)
+        let n = self.normal;
+        let e = self.radius
+            * Vec3::new(
+                (1.0 - n.x * n.x).max(0.0).sqrt(),
+                (1.0 - n.y * n.y).max(0.0).sqrt(),
+                (1.0 - n.z * n.z).max(0.0).sqrt(),
+            );
+        Some(Aabb::new(self.center - e, self.center + e).padded(FLAT_PADDING))
+    }
+
+    fn area(&self) -> Option<f32> {
+        Some(PI * self.radius * self.radius)
+    }
+
+    fn sample_surface(&self) -> Option<(Vec3, Vec3)> {
+        let (tangent, bitangent) = orthonormal_basis(&self.normal);
+        let (r, phi) = (self.radius * random().sqrt(), 2.0 * PI * random());
+        let p = self.center + r * phi.cos() * tangent + r * phi.sin() * bitangent;
+        Some((p, self.normal))
+    }
+}
+
+/// Capped cylinder standing on `center` along +y.
+/// On the side `u` is the azimuth and `v` the height fraction; caps use planar UVs.
+pub struct Cylinder {
+    pub center: Vec3,
+    pub radius: f32,
+    pub height: f32,
+    pub material: Rc<Box<dyn Material>>,
+}
+
+impl Hitable for Cylinder {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let o = r.origin() - &self.center;
+        let d = r.direction();
+        let mut best: Option<(f32, Vec3, f32, f32)> = None;
+        let mut consider = |t: f32, normal: Vec3, u: f32, v: f32| {
+            if t_min < t && t < t_max && best.is_none_or(|b| t < b.0) {
+                best = Some((t, normal, u, v));
+            }
+        };
+
+        let a = d.x * d.x + d.z * d.z;
+        let half_b = o.x * d.x + o.z * d.z;
+        let c = o.x * o.x + o.z * o.z - self.radius * self.radius;
+        if a > 0.0 {
+            if let Some((t0, t1)) = solve_quadratic(a, half_b, c) {
+                for t in [t0, t1] {
+                    let p = o + t * *d;
+                    if 0.0 <= p.y && p.y <= self.height {
+                        let normal = Vec3::new(p.x, 0.0, p.z) / self.radius;
+                        consider(t, normal, azimuth(p.x, p.z), p.y / self.height);
+                    }
+                }
+            }
+        }
+
+        if d.y != 0.0 {
+            for (y, ny) in [(0.0, -1.0), (self.height, 1.0)] {
+                let t = (y - o.y) / d.y;
+                let p = o + t * *d;
+                if p.x * p.x + p.z * p.z <= self.radius * self.radius {
+                    let u = 0.5 * (p.x / self.radius + 1.0);
+                    let v = 0.5 * (p.z / self.radius + 1.0);
+                    consider(t, Vec3::new(0.0, ny, 0.0), u, v);
+                }
+            }
+        }
+
+        best.map(|(t, normal, u, v)| record(r, t, normal, u, v, &self.material))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = self.radius;
+        Some(Aabb::new(
+            self.center - Vec3::new(r, 0.0, r),
+            self.center + Vec3::new(r, self.height, r),
+        ))
+    }
+}
+
+/// Cone with its base disk on `center` and apex `height` above it along +y, capped at the base.
+/// UVs follow `Cylinder`.
+pub struct Cone {
+    pub center: Vec3,
+    pub radius: f32,
+    pub height: f32,
+    pub material: Rc<Box<dyn Material>>,
+}
+
+impl Hitable for Cone {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let o = r.origin() - &self.center;
+        let d = r.direction();
+        let mut best: Option<(f32, Vec3, f32, f32)> = None;
+        let mut consider = |t: f32, normal: Vec3, u: f32, v: f32| {
+            if t_min < t && t < t_max && best.is_none_or(|b| t < b.0) {
+                best = Some((t, normal, u, v));
+            }
+        };
+
+        // x^2 + z^2 = k^2 (h - y)^2
+        let k2 = (self.radius / self.height) * (self.radius / self.height);
+        let oh = self.height - o.y;
+        let a = d.x * d.x + d.z * d.z - k2 * d.y * d.y;
+        let half_b = o.x * d.x + o.z * d.z + k2 * oh * d.y;
+        let c = o.x * o.x + o.z * o.z - k2 * oh * oh;
+        if let Some((t0, t1)) = solve_quadratic(a, half_b, c) {
+            for t in [t0, t1] {
+                let p = o + t * *d;
+                if 0.0 <= p.y && p.y <= self.height {
+                    let gradient = Vec3::new(p.x, k2 * (self.height - p.y), p.z);
+                    let normal = if gradient.squared_length() > 0.0 {
+                        gradient.unit_vector()
+                    } else {
+                        Vec3::from_i(0, 1, 0)
+                    };
+                    consider(t, normal, azimuth(p.x, p.z), p.y / self.height);
+                }
+            }
+        }
+
+        if d.y != 0.0 {
+            let t = -o.y / d.y;
+            let p = o + t * *d;
+            if p.x * p.x + p.z * p.z <= self.radius * self.radius {
+                let u = 0.5 * (p.x / self.radius + 1.0);
+                let v = 0.5 * (p.z / self.radius + 1.0);
+                consider(t, Vec3::from_i(0, -1, 0), u, v);
+            }
+        }
+
+        best.map(|(t, normal, u, v)| record(r, t, normal, u, v, &self.material))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = self.radius;
+        Some(Aabb::new(
+            self.center - Vec3::new(r, 0.0, r),
+            self.center + Vec3::new(r, self.height, r),
+        ))
+    }
+}
+
+/// Torus around the y axis through `center`.
+/// `u` is the angle around the y axis and `v` the angle around the tube.
+pub struct Torus {
+    pub center: Vec3,
+    pub major_radius: f32,
+    pub minor_radius: f32,
+    pub material: Rc<Box<dyn Material>>,
+}
+
+impl Hitable for Torus {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let length = r.direction().length() as f64;
+        let d = r.direction().unit_vector();
+        let o = r.origin() - &self.center;
+        let (big_r, small_r) = (self.major_radius as f64, self.minor_radius as f64);
+
+        // restrict the search to the bounding sphere and move the origin onto it for precision
+        let bound = big_r + small_r;
+        let b = o.dot(&d) as f64;
+        let disc = b * b - (o.dot(&o) as f64 - bound * bound);
+        if disc < 0.0 {
+            return None;
+        }
+        // the torus touches the sphere along its outer equator, so leave room for rounding there
+        let pad = 1e-3 * bound;
+        let (enter, exit) = (-b - disc.sqrt() - pad, -b + disc.sqrt() + pad);
+        let lo = enter.max(t_min as f64 * length);
+        let hi = exit.min(t_max as f64 * length);
+        if lo > hi {
+            return None;
+        }
+        let (ox, oy, oz) = (
+            o.x as f64 + enter * d.x as f64,
+            o.y as f64 + enter * d.y as f64,
+            o.z as f64 + enter * d.z as f64,
+        );
+        let (dx, dy, dz) = (d.x as f64, d.y as f64, d.z as f64);
+
+        // (|p|^2 - R^2 - r^2)^2 = 4 R^2 (r^2 - y^2) with |d| = 1
+        let e = ox * ox + oy * oy + oz * oz - big_r * big_r - small_r * small_r;
+        let f = ox * dx + oy * dy + oz * dz;
+        let four_r2 = 4.0 * big_r * big_r;
+        let coefficients = [
+            1.0,
+            4.0 * f,
+            2.0 * e + 4.0 * f * f + four_r2 * dy * dy,
+            4.0 * f * e + 2.0 * four_r2 * oy * dy,
+            e * e - four_r2 * (small_r * small_r - oy * oy),
+        ];
+        let t_min_unit = t_min as f64 * length;
+        let s = real_roots(&coefficients, lo - enter, hi - enter)
+            .into_iter()
+            .map(|s| s + enter)
+            .find(|&t| t > t_min_unit)?;
+        let t = (s / length) as f32;
+        if !(t_min < t && t < t_max) {
+            return None;
+        }
+
+        let p = r.point_at_parameter(t) - self.center;
+        let param = p.squared_length()
+            - self.major_radius * self.major_radius
+            - self.minor_radius * self.minor_radius;
+        let normal = Vec3::new(
+            p.x * param,
+            p.y * (param + 2.0 * self.major_radius * self.major_radius),
+            p.z * param,
+        )
+        .unit_vector();
+        let ring = (p.x * p.x + p.z * p.z).sqrt() - self.major_radius;
+        let u = azimuth(p.x, p.z);
+        let v = azimuth(ring, p.y);
+        Some(record(r, t, normal, u, v, &self.material))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let outer = self.major_radius + self.minor_radius;
+        let e = Vec3::new(outer, self.minor_radius, outer);
+        Some(Aabb::new(self.center - e, self.center + e))
+    }
+}
+
+fn evaluate(coefficients: &[f64], x: f64) -> f64 {
+    coefficients.iter().fold(0.0, |acc, c| acc * x + c)
+}
+
+/// Real roots of the polynomial (highest degree first) inside `[lo, hi]`, ascending.
+/// Critical points split the interval into monotonic pieces which are then bisected,
+/// so double roots (tangent rays) may be missed but no simple root is.
+fn real_roots(coefficients: &[f64], lo: f64, hi: f64) -> Vec<f64> {
+    let degree = coefficients.len() - 1;
+    if degree == 0 {
+        return vec![];
+    }
+    if coefficients[0] == 0.0 {
+        return real_roots(&coefficients[1..], lo, hi);
+    }
+    if degree == 1 {
+        let root = -coefficients[1] / coefficients[0];
+        return if lo <= root && root <= hi {
+            vec![root]
+        } else {
+            vec![]
+        };
+    }
+    let derivative: Vec<f64> = coefficients[..degree]
+        .iter()
+        .enumerate()
+        .map(|(i, c)| c * (degree - i) as f64)
+        .collect();
+    let mut bounds = vec![lo];
+    bounds.extend(real_roots(&derivative, lo, hi));
+    bounds.push(hi);
+
+    let mut roots = vec![];
+    for pair in bounds.windows(2) {
+        let (mut a, mut b) = (pair[0], pair[1]);
+        let (fa, fb) = (evaluate(coefficients, a), evaluate(coefficients, b));
+        if fa == 0.0 {
+            roots.push(a);
+            continue;
+        }
+        if fa.signum() == fb.signum() {
+            continue;
+        }
+        for _ in 0..64 {
+            let m = 0.5 * (a + b);
+            if evaluate(coefficients, m).signum() == fa.signum() {
+                a = m;
+            } else {
+                b = m;
+            }
+        }
+        roots.push(0.5 * (a + b));
+    }
+    if evaluate(coefficients, hi) == 0.0 {
+        roots.push(hi);
+    }
+    roots.dedup();
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    fn material() -> Rc<Box<dyn Material>> {
+        Rc::new(Box::new(Lambertian::new((0.5, 0.5, 0.5).into())))
+    }
+
+    fn ray(origin: (f32, f32, f32), direction: (f32, f32, f32)) -> Ray {
+        Ray::new(origin.into(), direction.into())
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-3, "{} != {}", a, b);
+    }
+
+    fn assert_vec_close(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-3, "{:?} != {:?}", a, b);
+    }
+
+    /// Invariants every hit must satisfy regardless of the shape.
+    fn check(shape: &dyn Hitable, r: &Ray) -> HitRecord {
+        let rec = shape.hit(r, 0.0001, f32::MAX).expect("expected a hit");
+        assert_close(rec.normal.length(), 1.0);
+        assert!((0.0..=1.0).contains(&rec.u), "u = {}", rec.u);
+        assert!((0.0..=1.0).contains(&rec.v), "v = {}", rec.v);
+        assert_vec_close(rec.p, r.point_at_parameter(rec.t));
+        if let Some(b) = shape.bounding_box() {
+            let b = b.padded(1e-3);
+            for axis in 0..3 {
+                assert!(b.min[axis] - 1e-3 <= rec.p[axis] && rec.p[axis] <= b.max[axis] + 1e-3);
+            }
+        }
+        rec
+    }
+
+    #[test]
+    fn plane_hits() {
+        let plane = Plane::new((0, 0, 0).into(), (0, 1, 0).into(), material());
+        let above = check(&plane, &ray((0.3, 2.0, 0.0), (0.0, -1.0, 0.0)));
+        assert_close(above.t, 2.0);
+        assert_vec_close(above.normal, (0, 1, 0).into());
+        // from below the normal still points up
+        let below = check(&plane, &ray((0.0, -1.0, 0.0), (0.0, 1.0, 0.0)));
+        assert_vec_close(below.normal, (0, 1, 0).into());
+        let grazing = check(&plane, &ray((0.0, 1.0, 0.0), (1.0, -0.001, 0.0)));
+        assert_close(grazing.t, 1000.0);
+        assert!(plane
+            .hit(&ray((0.0, 1.0, 0.0), (1.0, 0.0, 0.0)), 0.0001, f32::MAX)
+            .is_none());
+        assert!(plane.bounding_box().is_none());
+    }
+
+    #[test]
+    fn quad_hits() {
+        let quad = Quad::new(
+            (0, 0, 0).into(),
+            (2, 0, 0).into(),
+            (0, 0, -1).into(),
+            material(),
+        );
+        let front = check(&quad, &ray((1.5, 1.0, -0.25), (0.0, -1.0, 0.0)));
+        assert_vec_close(front.normal, (0, 1, 0).into());
+        assert_close(front.u, 0.75);
+        assert_close(front.v, 0.25);
+        let back = check(&quad, &ray((1.0, -1.0, -0.5), (0.0, 1.0, 0.0)));
+        assert_close(back.t, 1.0);
+        check(&quad, &ray((-0.5, 0.001, -0.5), (1.0, -0.001, 0.0)));
+        assert!(quad
+            .hit(&ray((3.0, 1.0, -0.5), (0.0, -1.0, 0.0)), 0.0001, f32::MAX)
+            .is_none());
+    }
+
+    #[test]
+    fn axis_aligned_rect_hits() {
+        let rect = AxisAlignedRect::xz(-1.0, 1.0, 0.0, 4.0, 2.0, material());
+        let above = check(&rect, &ray((0.0, 3.0, 1.0), (0.0, -1.0, 0.0)));
+        assert_vec_close(above.normal, (0, 1, 0).into());
+        assert_close(above.u, 0.5);
+        assert_close(above.v, 0.25);
+        check(&rect, &ray((0.0, 0.0, 1.0), (0.0, 1.0, 0.0)));
+        check(&rect, &ray((-1.5, 2.001, 1.0), (1.0, -0.001, 0.0)));
+        assert!(rect
+            .hit(&ray((0.0, 3.0, 5.0), (0.0, -1.0, 0.0)), 0.0001, f32::MAX)
+            .is_none());
+        let yz = AxisAlignedRect::yz(0.0, 1.0, 0.0, 1.0, 3.0, material());
+        assert_vec_close(
+            check(&yz, &ray((0.0, 0.5, 0.5), (1.0, 0.0, 0.0))).normal,
+            (1, 0, 0).into(),
+        );
+    }
+
+    #[test]
+    fn box_hits() {
+        let cube = AxisAlignedBox::new((1, 1, 1).into(), (-1, -1, -1).into(), material());
+        let outside = check(&cube, &ray((0.0, 0.0, -5.0), (0.0, 0.0, 1.0)));
+        assert_close(outside.t, 4.0);
+        assert_vec_close(outside.normal, (0, 0, -1).into());
+        let inside = check(&cube, &ray((0.0, 0.0, 0.0), (0.0, 1.0, 0.0)));
+        assert_close(inside.t, 1.0);
+        assert_vec_close(inside.normal, (0, 1, 0).into());
+        let grazing = check(&cube, &ray((-5.0, 0.999, 0.0), (1.0, 0.0, 0.0)));
+        assert_vec_close(grazing.normal, (-1, 0, 0).into());
+        assert!(cube
+            .hit(&ray((-5.0, 1.001, 0.0), (1.0, 0.0, 0.0)), 0.0001, f32::MAX)
+            .is_none());
+    }
+
+    #[test]
+    fn disk_hits() {
+        let disk = Disk::new((0, 1, 0).into(), (0, 0, 1).into(), 2.0, material());
+        let front = check(&disk, &ray((1.0, 1.0, 3.0), (0.0, 0.0, -1.0)));
+        assert_close(front.t, 3.0);
+        assert_close(front.v, 0.5);
+        let back = check(&disk, &ray((0.0, 0.0, -1.0), (0.0, 0.0, 1.0)));
+        assert_vec_close(back.normal, (0, 0, 1).into());
+        check(&disk, &ray((-1.0, 1.0, 0.001), (1.0, 0.0, -0.001)));
+        assert!(disk
+            .hit(&ray((2.1, 1.0, 3.0), (0.0, 0.0, -1.0)), 0.0001, f32::MAX)
+            .is_none());
+    }
+
+    #[test]
+    fn cylinder_hits() {
+        let cylinder = Cylinder {
+            center: (0, 0, 0).into(),
+            radius: 1.0,
+            height: 2.0,
+            material: material(),
+        };
+        let side = check(&cylinder, &ray((-5.0, 1.0, 0.0), (1.0, 0.0, 0.0)));
+        assert_close(side.t, 4.0);
+        assert_vec_close(side.normal, (-1, 0, 0).into());
+        assert_close(side.v, 0.5);
+        let top = check(&cylinder, &ray((0.5, 5.0, 0.0), (0.0, -1.0, 0.0)));
+        assert_close(top.t, 3.0);
+        assert_vec_close(top.normal, (0, 1, 0).into());
+        let inside = check(&cylinder, &ray((0.0, 1.0, 0.0), (0.0, 0.0, 1.0)));
+        assert_close(inside.t, 1.0);
+        assert_vec_close(inside.normal, (0, 0, 1).into());
+        let inside_cap = check(&cylinder, &ray((0.0, 1.0, 0.0), (0.0, -1.0, 0.0)));
+        assert_vec_close(inside_cap.normal, (0, -1, 0).into());
+        check(&cylinder, &ray((-5.0, 1.0, 0.999), (1.0, 0.0, 0.0)));
+        assert!(cylinder
+            .hit(&ray((-5.0, 1.0, 1.001), (1.0, 0.0, 0.0)), 0.0001, f32::MAX)
+            .is_none());
+        assert!(cylinder
+            .hit(&ray((-5.0, 2.5, 0.0), (1.0, 0.0, 0.0)), 0.0001, f32::MAX)
+            .is_none());
+    }
+
+    #[test]
+    fn cone_hits() {
+        let cone = Cone {
+            center: (0, 0, 0).into(),
+            radius: 1.0,
+            height: 1.0,
+            material: material(),
+        };
+        let side = check(&cone, &ray((-5.0, 0.5, 0.0), (1.0, 0.0, 0.0)));
+        assert_close(side.t, 4.5);
+        assert_vec_close(side.normal, Vec3::new(-1.0, 1.0, 0.0).unit_vector());
+        let base = check(&cone, &ray((0.2, -3.0, 0.0), (0.0, 1.0, 0.0)));
+        assert_close(base.t, 3.0);
+        assert_vec_close(base.normal, (0, -1, 0).into());
+        let inside = check(&cone, &ray((0.0, 0.25, 0.0), (0.0, 0.0, 1.0)));
+        assert_close(inside.t, 0.75);
+        // tangent to the slanted side at half height
+        check(&cone, &ray((-5.0, 0.5, 0.499), (1.0, 0.0, 0.0)));
+        assert!(cone
+            .hit(&ray((-5.0, 0.5, 0.501), (1.0, 0.0, 0.0)), 0.0001, f32::MAX)
+            .is_none());
+        assert!(cone
+            .hit(&ray((-5.0, 1.5, 0.0), (1.0, 0.0, 0.0)), 0.0001, f32::MAX)
+            .is_none());
+    }
+
+    #[test]
+    fn torus_hits() {
+        let torus = Torus {
+            center: (0, 1, 0).into(),
+            major_radius: 2.0,
+            minor_radius: 0.5,
+            material: material(),
+        };
+        let outside = check(&torus, &ray((-10.0, 1.0, 0.0), (1.0, 0.0, 0.0)));
+        assert_close(outside.t, 7.5);
+        assert_vec_close(outside.normal, (-1, 0, 0).into());
+        // through the hole: the inner wall of the tube on the far side of the hole
+        let hole = check(&torus, &ray((0.0, 1.0, 0.0), (1.0, 0.0, 0.0)));
+        assert_close(hole.t, 1.5);
+        assert_vec_close(hole.normal, (-1, 0, 0).into());
+        let inside = check(&torus, &ray((2.0, 1.0, 0.0), (0.0, 1.0, 0.0)));
+        assert_close(inside.t, 0.5);
+        assert_vec_close(inside.normal, (0, 1, 0).into());
+        let from_above = check(&torus, &ray((2.0, 10.0, 0.0), (0.0, -2.0, 0.0)));
+        assert_close(from_above.t, 4.25);
+        // grazing the top of the tube
+        let grazing = check(&torus, &ray((-10.0, 1.499, 2.0), (1.0, 0.0, 0.0)));
+        assert!(grazing.normal.y > 0.9);
+        assert!(torus
+            .hit(&ray((-10.0, 1.501, 2.0), (1.0, 0.0, 0.0)), 0.0001, f32::MAX)
+            .is_none());
+        assert!(torus
+            .hit(&ray((0.0, 10.0, 0.0), (0.0, -1.0, 0.0)), 0.0001, f32::MAX)
+            .is_none());
+        // leaving the tube on the outer equator, where it touches the bounding sphere
+        let thin = Torus {
+            center: (0, 0, 0).into(),
+            major_radius: 0.5,
+            minor_radius: 0.19596137,
+            material: material(),
+        };
+        check(
+            &thin,
+            &ray(
+                (0.5758184, -0.078642, -0.016075024),
+                (2.9149745, 2.0004168, -1.8900636),
+            ),
+        );
+    }
+
+    #[test]
+    fn polynomial_roots() {
+        // (x - 1)(x - 2)(x - 3)(x - 4)
+        let roots = real_roots(&[1.0, -10.0, 35.0, -50.0, 24.0], 0.0, 10.0);
+        assert_eq!(roots.len(), 4);
+        for (root, expected) in roots.iter().zip([1.0, 2.0, 3.0, 4.0].iter()) {
+            assert!((root - expected).abs() < 1e-9);
+        }
+        assert_eq!(real_roots(&[1.0, 0.0, 1.0], -10.0, 10.0).len(), 0);
+    }
+
+    /// Invariants checked over random shapes and rays rather than hand-picked cases.
+    mod properties {
+        use super::*;
+        use crate::bvh::Bvh;
+        use crate::hitable::{HitableList, Sphere};
+        use crate::sdf::{Sdf, SdfObject};
+        use proptest::prelude::*;
+
+        /// Parameters of a shape, kept apart from it since shapes are neither `Debug` nor `Send`.
+        #[derive(Clone, Debug)]
+        enum Shape {
+            Sphere {
+                center: Vec3,
+                radius: f32,
+            },
+            Plane {
+                point: Vec3,
+                normal: Vec3,
+            },
+            Quad {
+                corner: Vec3,
+                edge_u: Vec3,
+                edge_v: Vec3,
+            },
+            Rect {
+                normal: Axis,
+                a0: f32,
+                a1: f32,
+                b0: f32,
+                b1: f32,
+                k: f32,
+            },
+            Box {
+                min: Vec3,
+                max: Vec3,
+            },
+            Disk {
+                center: Vec3,
+                normal: Vec3,
+                radius: f32,
+            },
+            Cylinder {
+                center: Vec3,
+                radius: f32,
+                height: f32,
+            },
+            Cone {
+                center: Vec3,
+                radius: f32,
+                height: f32,
+            },
+            Torus {
+                center: Vec3,
+                major: f32,
+                minor: f32,
+            },
+            SdfSphere {
+                center: Vec3,
+                radius: f32,
+            },
+        }
+
+        impl Shape {
+            fn build(&self) -> Box<dyn Hitable> {
+                let material = material();
+                match *self {
+                    Shape::Sphere { center, radius } => Box::new(Sphere {
+                        center,
+                        radius,
+                        material,
+                    }),
+                    Shape::Plane { point, normal } => Box::new(Plane::new(point, normal, material)),
+                    Shape::Quad {
+                        corner,
+                        edge_u,
+                        edge_v,
+                    } => Box::new(Quad::new(corner, edge_u, edge_v, material)),
+                    Shape::Rect {
+                        normal,
+                        a0,
+                        a1,
+                        b0,
+                        b1,
+                        k,
+                    } => Box::new(AxisAlignedRect::new(normal, a0, a1, b0, b1, k, material)),
+                    Shape::Box { min, max } => Box::new(AxisAlignedBox::new(min, max, material)),
+                    Shape::Disk {
+                        center,
+                        normal,
+                        radius,
+                    } => Box::new(Disk::new(center, normal, radius, material)),
+                    Shape::Cylinder {
+                        center,
+                        radius,
+                        height,
+                    } => Box::new(Cylinder {
+                        center,
+                        radius,
+                        height,
+                        material,
+                    }),
+                    Shape::Cone {
+                        center,
+                        radius,
+                        height,
+                    } => Box::new(Cone {
+                        center,
+                        radius,
+                        height,
+                        material,
+                    }),
+                    Shape::Torus {
+                        center,
+                        major,
+                        minor,
+                    } => Box::new(Torus {
+                        center,
+                        major_radius: major,
+                        minor_radius: minor,
+                        material,
+                    }),
+                    Shape::SdfSphere { center, radius } => Box::new(SdfObject::new(
+                        Sdf::sphere(radius).translate(center),
+                        material,
+                    )),
+                }
+            }
+
+            /// Distance from `p` to the surface, or an upper bound of it that is exact on it.
+            fn distance(&self, p: &Vec3) -> f32 {
+                match *self {
+                    Shape::Sphere { center, radius } | Shape::SdfSphere { center, radius } => {
+                        ((*p - center).length() - radius).abs()
+                    }
+                    Shape::Plane { point, normal } => (*p - point).dot(&normal).abs(),
+                    Shape::Quad {
+                        corner,
+                        edge_u,
+                        edge_v,
+                    } => {
+                        let n = edge_u.cross(&edge_v);
+                        let planar = *p - corner;
+                        let w = n / n.dot(&n);
+                        let u = w.dot(&planar.cross(&edge_v));
+                        let v = w.dot(&edge_u.cross(&planar));
+                        planar.dot(&n.unit_vector()).abs()
+                            + excess(u, 0.0, 1.0) * edge_u.length()
+                            + excess(v, 0.0, 1.0) * edge_v.length()
+                    }
+                    Shape::Rect {
+                        normal,
+                        a0,
+                        a1,
+                        b0,
+                        b1,
+                        k,
+                    } => {
+                        let (ia, ib) = normal.others();
+                        (p[normal.index()] - k).abs()
+                            + excess(p[ia], a0, a1)
+                            + excess(p[ib], b0, b1)
+                    }
+                    Shape::Box { min, max } => {
+                        let q: Vec<f32> = (0..3)
+                            .map(|i| {
+                                (p[i] - 0.5 * (min[i] + max[i])).abs() - 0.5 * (max[i] - min[i])
+                            })
+                            .collect();
+                        let outside = Vec3::new(q[0].max(0.0), q[1].max(0.0), q[2].max(0.0));
+                        (outside.length() + q[0].max(q[1]).max(q[2]).min(0.0)).abs()
+                    }
+                    Shape::Disk {
+                        center,
+                        normal,
+                        radius,
+                    } => {
+                        let local = *p - center;
+                        let height = local.dot(&normal);
+                        let radial = (local - height * normal).length();
+                        height.abs() + (radial - radius).max(0.0)
+                    }
+                    Shape::Cylinder {
+                        center,
+                        radius,
+                        height,
+                    } => {
+                        let q = profile(*p - center);
+                        segment_distance(q, (0.0, 0.0), (radius, 0.0))
+                            .min(segment_distance(q, (radius, 0.0), (radius, height)))
+                            .min(segment_distance(q, (0.0, height), (radius, height)))
+                    }
+                    Shape::Cone {
+                        center,
+                        radius,
+                        height,
+                    } => {
+                        let q = profile(*p - center);
+                        segment_distance(q, (0.0, 0.0), (radius, 0.0)).min(segment_distance(
+                            q,
+                            (radius, 0.0),
+                            (0.0, height),
+                        ))
+                    }
+                    Shape::Torus {
+                        center,
+                        major,
+                        minor,
+                    } => {
+                        let (radial, y) = profile(*p - center);
+                        ((radial - major).hypot(y) - minor).abs()
+                    }
+                }
+            }
+
+            /// A point strictly inside a closed shape picked by `s` in the unit cube, `None` for
+            /// shapes without an inside.
+            fn inside(&self, s: Vec3) -> Option<Vec3> {
+                let around = |radial: f32, y: f32| {
+                    let angle = 2.0 * PI * s.x;
+                    Vec3::new(radial * angle.cos(), y, radial * angle.sin())
+                };
+                match *self {
+                    Shape::Sphere { center, radius } | Shape::SdfSphere { center, radius } => {
+                        // a cube of half width r/2 fits inside the sphere
+                        Some(center + radius * (s - Vec3::new(0.5, 0.5, 0.5)))
+                    }
+                    Shape::Box { min, max } => {
+                        let f = |i: usize| min[i] + (max[i] - min[i]) * (0.05 + 0.9 * s[i]);
+                        Some(Vec3::new(f(0), f(1), f(2)))
+                    }
+                    Shape::Cylinder {
+                        center,
+                        radius,
+                        height,
+                    } => Some(center + around(0.9 * radius * s.y, height * (0.05 + 0.9 * s.z))),
+                    Shape::Cone {
+                        center,
+                        radius,
+                        height,
+                    } => {
+                        let y = height * (0.05 + 0.85 * s.z);
+                        Some(center + around(0.9 * s.y * radius * (height - y) / height, y))
+                    }
+                    Shape::Torus {
+                        center,
+                        major,
+                        minor,
+                    } => {
+                        let tube = 2.0 * PI * s.z;
+                        let r = 0.9 * minor * s.y;
+                        Some(center + around(major + r * tube.cos(), r * tube.sin()))
+                    }
+                    _ => None,
+                }
+            }
+        }
+
+        /// How far `x` lies outside `[lo, hi]`.
+        fn excess(x: f32, lo: f32, hi: f32) -> f32 {
+            (lo - x).max(x - hi).max(0.0)
+        }
+
+        /// Distance from the y axis and height, the plane a surface of revolution is swept from.
+        fn profile(local: Vec3) -> (f32, f32) {
+            (local.x.hypot(local.z), local.y)
+        }
+
+        fn segment_distance(q: (f32, f32), a: (f32, f32), b: (f32, f32)) -> f32 {
+            let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+            let s = (((q.0 - a.0) * dx + (q.1 - a.1) * dy) / (dx * dx + dy * dy)).clamp(0.0, 1.0);
+            (q.0 - a.0 - s * dx).hypot(q.1 - a.1 - s * dy)
+        }
+
+        /// Error allowed in positions, growing with the distance from the origin.
+        fn tolerance(p: &Vec3) -> f32 {
+            1e-3 * (1.0 + p.length())
+        }
+
+        fn close(a: f32, b: f32) -> bool {
+            (a - b).abs() <= 1e-3 * (1.0 + b.abs())
+        }
+
+        fn point(range: f32) -> impl Strategy<Value = Vec3> {
+            (-range..range, -range..range, -range..range).prop_map(|(x, y, z)| Vec3::new(x, y, z))
+        }
+
+        fn unit_cube() -> impl Strategy<Value = Vec3> {
+            (0.0f32..1.0, 0.0f32..1.0, 0.0f32..1.0).prop_map(|(x, y, z)| Vec3::new(x, y, z))
+        }
+
+        /// Directions of varied length, since shapes must not assume normalized rays.
+        fn direction() -> impl Strategy<Value = Vec3> {
+            (
+                point(1.0).prop_filter("direction too short", |d| d.length() > 0.1),
+                0.25f32..4.0,
+            )
+                .prop_map(|(d, length)| d * length)
+        }
+
+        /// Origin, a point picked in the unit cube and a length for `aim`; `Ray` itself is
+        /// not `Debug`.
+        fn ray() -> impl Strategy<Value = (Vec3, Vec3, f32)> {
+            (point(5.0), unit_cube(), 0.25f32..4.0)
+        }
+
+        /// Ray from `origin` towards the point `s` picks in a slightly grown bounding box of
+        /// `target`, since rays in random directions mostly miss.
+        fn aim(target: &dyn Hitable, (origin, s, length): (Vec3, Vec3, f32)) -> Ray {
+            let b = target
+                .bounding_box()
+                .unwrap_or_else(|| Aabb::new(Vec3::from_i(-2, -2, -2), Vec3::from_i(2, 2, 2)));
+            let size = b.max - b.min;
+            let to = b.min - 0.1 * size + 1.2 * size * s;
+            let direction = if (to - origin).length() > 1e-3 {
+                (to - origin).unit_vector()
+            } else {
+                Vec3::from_i(0, 1, 0)
+            };
+            Ray::new(origin, length * direction)
+        }
+
+        fn extent() -> impl Strategy<Value = Vec3> {
+            (0.1f32..3.0, 0.1f32..3.0, 0.1f32..3.0).prop_map(|(x, y, z)| Vec3::new(x, y, z))
+        }
+
+        fn shape() -> impl Strategy<Value = Shape> {
+            let axis = prop_oneof![Just(Axis::X), Just(Axis::Y), Just(Axis::Z)];
+            prop_oneof![
+                (point(2.0), 0.2f32..2.0)
+                    .prop_map(|(center, radius)| Shape::Sphere { center, radius }),
+                (point(2.0), direction()).prop_map(|(point, normal)| Shape::Plane {
+                    point,
+                    normal: normal.unit_vector()
+                }),
+                (point(2.0), point(2.0), point(2.0))
+                    .prop_filter("degenerate quad", |(_, u, v)| u.cross(v).length() > 0.1)
+                    .prop_map(|(corner, edge_u, edge_v)| Shape::Quad {
+                        corner,
+                        edge_u,
+                        edge_v
+                    }),
+                (axis, point(2.0), extent()).prop_map(|(normal, low, size)| Shape::Rect {
+                    normal,
+                    a0: low.x,
+                    a1: low.x + size.x,
+                    b0: low.y,
+                    b1: low.y + size.y,
+                    k: low.z,
+                }),
+                (point(2.0), extent()).prop_map(|(min, size)| Shape::Box {
+                    min,
+                    max: min + size
+                }),
+                (point(2.0), direction(), 0.2f32..2.0).prop_map(|(center, normal, radius)| {
+                    Shape::Disk {
+                        center,
+                        normal: normal.unit_vector(),
+                        radius,
+                    }
+                }),
+                (point(2.0), 0.2f32..2.0, 0.2f32..3.0).prop_map(|(center, radius, height)| {
+                    Shape::Cylinder {
+                        center,
+                        radius,
+                        height,
+                    }
+                }),
+                (point(2.0), 0.2f32..2.0, 0.2f32..3.0).prop_map(|(center, radius, height)| {
+                    Shape::Cone {
+                        center,
+                        radius,
+                        height,
+                    }
+                }),
+                (point(2.0), 0.5f32..2.0, 0.1f32..0.45).prop_map(|(center, major, f)| {
+                    Shape::Torus {
+                        center,
+                        major,
+                        minor: major * f,
+                    }
+                }),
+                (point(2.0), 0.2f32..2.0)
+                    .prop_map(|(center, radius)| Shape::SdfSphere { center, radius }),
+            ]
+        }
+
+        proptest! {
+            #[test]
+            fn hits_lie_on_the_surface(
+                shape in shape(),
+                ray in ray(),
+                t_min in 0.0001f32..1.0,
+                span in prop_oneof![Just(f32::MAX), 0.1f32..20.0],
+            ) {
+                let hitable = shape.build();
+                let r = aim(hitable.as_ref(), ray);
+                let t_max = if span == f32::MAX { span } else { t_min + span };
+                if let Some(rec) = hitable.hit(&r, t_min, t_max) {
+                    prop_assert!(
+                        t_min < rec.t && rec.t < t_max,
+                        "t = {} outside ({}, {})", rec.t, t_min, t_max
+                    );
+                    let tolerance = tolerance(&rec.p);
+                    prop_assert!((rec.p - r.point_at_parameter(rec.t)).length() < tolerance);
+                    let distance = shape.distance(&rec.p);
+                    prop_assert!(
+                        distance < tolerance,
+                        "{:?} is {} off the surface", rec.p, distance
+                    );
+                    prop_assert!(
+                        (rec.normal.length() - 1.0).abs() < 1e-3,
+                        "|normal| = {}", rec.normal.length()
+                    );
+                }
+            }
+
+            /// Clipping the ray at `t_max` keeps the nearest hit before it and nothing after it.
+            #[test]
+            fn hits_are_the_nearest_before_t_max(
+                shape in shape(),
+                ray in ray(),
+                t_max in 0.01f32..20.0,
+            ) {
+                let hitable = shape.build();
+                let r = aim(hitable.as_ref(), ray);
+                let first = hitable.hit(&r, 0.0001, f32::MAX).map(|rec| rec.t);
+                let clipped = hitable.hit(&r, 0.0001, t_max).map(|rec| rec.t);
+                match first {
+                    Some(t) if t < t_max * 0.999 => prop_assert!(
+                        clipped.is_some_and(|c| close(c, t)),
+                        "nearest hit {} but {:?} before {}", t, clipped, t_max
+                    ),
+                    Some(t) if t <= t_max * 1.001 => {}
+                    _ => prop_assert!(
+                        clipped.is_none(),
+                        "{:?} before {} but {:?} unclipped", clipped, t_max, first
+                    ),
+                }
+            }
+
+            /// Rays leaving a closed shape hit it from inside, with the normal still outward.
+            #[test]
+            fn rays_from_inside_hit(shape in shape(), s in unit_cube(), direction in direction()) {
+                if let Some(origin) = shape.inside(s) {
+                    prop_assert!(shape.distance(&origin) > 1e-3);
+                    let rec = shape.build().hit(&Ray::new(origin, direction), 0.0001, f32::MAX);
+                    prop_assert!(rec.is_some(), "no hit from {:?}", origin);
+                    let normal = rec.unwrap().normal;
+                    prop_assert!(
+                        normal.dot(&direction.unit_vector()) > -1e-3,
+                        "inward normal {:?}", normal
+                    );
+                }
+            }
+
+            #[test]
+            fn lists_return_the_nearest_member(
+                shapes in prop::collection::vec(shape(), 1..6),
+                ray in ray(),
+                pick in 0usize..6,
+            ) {
+                let r = aim(shapes[pick % shapes.len()].build().as_ref(), ray);
+                let nearest = shapes
+                    .iter()
+                    .filter_map(|s| s.build().hit(&r, 0.0001, f32::MAX))
+                    .map(|rec| rec.t)
+                    .reduce(f32::min);
+                let list = HitableList::new(shapes.iter().map(Shape::build).collect());
+                let bvh = Bvh::new(shapes.iter().map(Shape::build).collect());
+                for (name, world) in [("list", &list as &dyn Hitable), ("bvh", &bvh)] {
+                    let t = world.hit(&r, 0.0001, f32::MAX).map(|rec| rec.t);
+                    match (t, nearest) {
+                        (Some(a), Some(b)) => {
+                            prop_assert!(close(a, b), "{} hit at {} instead of {}", name, a, b)
+                        }
+                        (None, None) => {}
+                        _ => prop_assert!(
+                            false,
+                            "{} hit at {:?} but the members at {:?}", name, t, nearest
+                        ),
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,376 @@
+use std::cell::Cell;
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Events counted while rendering.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Counter {
+    /// Rays leaving the camera.
+    PrimaryRays,
+    /// Rays scattered off surfaces.
+    SecondaryRays,
+    /// Visibility rays towards lights.
+    ShadowRays,
+    /// Shapes tested against a ray by an accelerator or list.
+    IntersectionTests,
+    BvhNodeVisits,
+}
+
+thread_local! {
+    /// Per thread, so counting costs no synchronization.
+    static COUNTS: [Cell<u64>; 5] = Default::default();
+}
+
+pub fn count(counter: Counter) {
+    COUNTS.with(|counts| {
+        let cell = &counts[counter as usize];
+        cell.set(cell.get() + 1);
+    });
+}
+
+/// Totals of the counters.
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub struct Counters {
+    pub primary_rays: u64,
+    pub secondary_rays: u64,
+    pub shadow_rays: u64,
+    pub intersection_tests: u64,
+    pub bvh_node_visits: u64,
+}
+
+impl Counters {
+    /// What the calling thread counted since the last call, which starts it over.
+    pub fn take() -> Counters {
+        COUNTS.with(|counts| {
+            let take = |c: Counter| counts[c as usize].replace(0);
+            Counters {
+                primary_rays: take(Counter::PrimaryRays),
+                secondary_rays: take(Counter::SecondaryRays),
+                shadow_rays: take(Counter::ShadowRays),
+                intersection_tests: take(Counter::IntersectionTests),
+                bvh_node_visits: take(Counter::BvhNodeVisits),
+            }
+        })
+    }
+
+    pub fn add(&self, other: &Counters) -> Counters {
+        Counters {
+            primary_rays: self.primary_rays + other.primary_rays,
+            secondary_rays: self.secondary_rays + other.secondary_rays,
+            shadow_rays: self.shadow_rays + other.shadow_rays,
+            intersection_tests: self.intersection_tests + other.intersection_tests,
+            bvh_node_visits: self.bvh_node_visits + other.bvh_node_visits,
+        }
+    }
+
+    pub fn rays(&self) -> u64 {
+        self.primary_rays + self.secondary_rays + self.shadow_rays
+    }
+}
+
+/// Largest resident set of this process so far in bytes, where the platform reports it.
+pub fn peak_memory() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+    let kilobytes: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kilobytes * 1024)
+}
+
+/// Summary of a finished render.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Report {
+    pub width: usize,
+    pub height: usize,
+    pub spp: u32,
+    pub frames: u32,
+    pub threads: usize,
+    pub seconds: f64,
+    pub counters: Counters,
+    pub peak_memory: Option<u64>,
+}
+
+fn ratio(a: u64, b: u64) -> f64 {
+    if b == 0 {
+        0.0
+    } else {
+        a as f64 / b as f64
+    }
+}
+
+/// `1234567` as `1,234,567`.
+fn grouped(n: u64) -> String {
+    let digits = n.to_string();
+    let mut out = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
+
+impl Report {
+    pub fn rays_per_second(&self) -> f64 {
+        if self.seconds > 0.0 {
+            self.counters.rays() as f64 / self.seconds
+        } else {
+            0.0
+        }
+    }
+
+    /// Rays per camera path, the camera ray included.
+    pub fn average_path_length(&self) -> f64 {
+        let c = &self.counters;
+        ratio(c.primary_rays + c.secondary_rays, c.primary_rays)
+    }
+
+    pub fn intersection_tests_per_ray(&self) -> f64 {
+        ratio(self.counters.intersection_tests, self.counters.rays())
+    }
+
+    pub fn bvh_node_visits_per_ray(&self) -> f64 {
+        ratio(self.counters.bvh_node_visits, self.counters.rays())
+    }
+
+    pub fn to_json(&self) -> String {
+        let c = &self.counters;
+        format!(
+            "{{\"width\":{},\"height\":{},\"spp\":{},\"frames\":{},\"threads\":{},\
+             \"seconds\":{:.6},\"rays\":{},\"rays_per_second\":{:.1},\"primary_rays\":{},\
+             \"secondary_rays\":{},\"shadow_rays\":{},\"average_path_length\":{:.4},\
+             \"intersection_tests\":{},\"intersection_tests_per_ray\":{:.4},\
+             \"bvh_node_visits\":{},\"bvh_node_visits_per_ray\":{:.4},\"peak_memory_bytes\":{}}}",
+            self.width,
+            self.height,
+            self.spp,
+            self.frames,
+            self.threads,
+            self.seconds,
+            c.rays(),
+            self.rays_per_second(),
+            c.primary_rays,
+            c.secondary_rays,
+            c.shadow_rays,
+            self.average_path_length(),
+            c.intersection_tests,
+            self.intersection_tests_per_ray(),
+            c.bvh_node_visits,
+            self.bvh_node_visits_per_ray(),
+            self.peak_memory
+                .map_or("null".to_string(), |m| m.to_string())
+        )
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let c = &self.counters;
+        writeln!(
+            f,
+            "rendered {}x{} at {} spp{} in {:.2} s on {} thread{}",
+            self.width,
+            self.height,
+            self.spp,
+            if self.frames > 1 {
+                format!(", {} frames,", self.frames)
+            } else {
+                String::new()
+            },
+            self.seconds,
+            self.threads,
+            if self.threads == 1 { "" } else { "s" }
+        )?;
+        writeln!(
+            f,
+            "rays          {} ({:.3} M/s)",
+            grouped(c.rays()),
+            self.rays_per_second() / 1e6
+        )?;
+        writeln!(f, "  primary     {}", grouped(c.primary_rays))?;
+        writeln!(f, "  secondary   {}", grouped(c.secondary_rays))?;
+        writeln!(f, "  shadow      {}", grouped(c.shadow_rays))?;
+        writeln!(
+            f,
+            "path length   {:.3} rays on average",
+            self.average_path_length()
+        )?;
+        writeln!(
+            f,
+            "intersection  {:.2} tests per ray",
+            self.intersection_tests_per_ray()
+        )?;
+        writeln!(
+            f,
+            "bvh           {} node visits, {:.2} per ray",
+            grouped(c.bvh_node_visits),
+            self.bvh_node_visits_per_ray()
+        )?;
+        match self.peak_memory {
+            Some(bytes) => write!(
+                f,
+                "peak memory   {:.1} MiB",
+                bytes as f64 / (1024.0 * 1024.0)
+            ),
+            None => write!(f, "peak memory   unknown"),
+        }
+    }
+}
+
+/// `h:mm:ss`, or `m:ss` under an hour.
+fn clock(d: Duration) -> String {
+    let s = d.as_secs();
+    if s >= 3600 {
+        format!("{}:{:02}:{:02}", s / 3600, s / 60 % 60, s % 60)
+    } else {
+        format!("{}:{:02}", s / 60, s % 60)
+    }
+}
+
+const BAR_WIDTH: usize = 30;
+
+/// Progress bar with an ETA on standard error, safe to advance from several threads.
+/// A hidden one only keeps count.
+pub struct Progress {
+    total: u64,
+    done: AtomicU64,
+    start: Instant,
+    /// When the bar was last drawn, or `None` when it is hidden.
+    drawn: Option<Mutex<Instant>>,
+}
+
+impl Progress {
+    /// `total` is in whatever unit `advance` is called with, e.g. samples.
+    pub fn new(total: u64, visible: bool) -> Progress {
+        let start = Instant::now();
+        Progress {
+            total,
+            done: AtomicU64::new(0),
+            start,
+            drawn: if visible {
+                Some(Mutex::new(start - Duration::from_secs(1)))
+            } else {
+                None
+            },
+        }
+    }
+
+    pub fn advance(&self, n: u64) {
+        let done = self.done.fetch_add(n, Ordering::Relaxed) + n;
+        if let Some(drawn) = &self.drawn {
+            // a thread drawing already is reason enough to skip
+            if let Ok(mut last) = drawn.try_lock() {
+                if last.elapsed() >= Duration::from_millis(100) || done >= self.total {
+                    *last = Instant::now();
+                    self.draw(done);
+                }
+            }
+        }
+    }
+
+    /// Remaining time extrapolated from the rate so far.
+    pub fn eta(&self) -> Option<Duration> {
+        let done = self.done.load(Ordering::Relaxed);
+        if done == 0 {
+            return None;
+        }
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let left = self.total.saturating_sub(done) as f64;
+        Some(Duration::from_secs_f64(elapsed * left / done as f64))
+    }
+
+    fn draw(&self, done: u64) {
+        let fraction = ratio(done.min(self.total), self.total);
+        let filled = (fraction * BAR_WIDTH as f64) as usize;
+        let eta = self.eta().map_or("--:--".to_string(), clock);
+        eprint!(
+            "\r[{}{}] {:3.0}%  {} elapsed, ETA {} ",
+            "#".repeat(filled),
+            "-".repeat(BAR_WIDTH - filled),
+            fraction * 100.0,
+            clock(self.start.elapsed()),
+            eta
+        );
+        let _ = std::io::stderr().flush();
+    }
+
+    /// Prints a line of its own above the bar.
+    pub fn message(&self, text: &str) {
+        if self.drawn.is_some() {
+            eprint!("\r\x1b[2K");
+        }
+        eprintln!("{}", text);
+    }
+
+    /// Leaves the finished bar on its own line.
+    pub fn finish(&self) {
+        if self.drawn.is_some() {
+            self.draw(self.done.load(Ordering::Relaxed));
+            eprintln!();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_per_thread() {
+        Counters::take();
+        count(Counter::PrimaryRays);
+        count(Counter::SecondaryRays);
+        count(Counter::SecondaryRays);
+        std::thread::spawn(|| count(Counter::ShadowRays))
+            .join()
+            .unwrap();
+        let counters = Counters::take();
+        assert_eq!(counters.rays(), 3);
+        assert_eq!(counters.shadow_rays, 0);
+        assert_eq!(Counters::take(), Counters::default());
+    }
+
+    #[test]
+    fn reports_ratios() {
+        let report = Report {
+            width: 4,
+            height: 2,
+            spp: 1,
+            frames: 1,
+            threads: 1,
+            seconds: 2.0,
+            counters: Counters {
+                primary_rays: 8,
+                secondary_rays: 12,
+                shadow_rays: 0,
+                intersection_tests: 50,
+                bvh_node_visits: 1234567,
+            },
+            peak_memory: None,
+        };
+        assert_eq!(report.rays_per_second(), 10.0);
+        assert_eq!(report.average_path_length(), 2.5);
+        assert_eq!(report.intersection_tests_per_ray(), 2.5);
+        let json = report.to_json();
+        assert!(json.starts_with('{') && json.ends_with('}'));
+        assert!(json.contains("\"average_path_length\":2.5000"));
+        assert!(json.contains("\"peak_memory_bytes\":null"));
+        assert!(report.to_string().contains("1,234,567 node visits"));
+        assert_eq!(grouped(999), "999");
+        assert_eq!(grouped(1000), "1,000");
+    }
+
+    #[test]
+    fn estimates_the_time_left() {
+        let progress = Progress::new(100, false);
+        assert_eq!(progress.eta(), None);
+        std::thread::sleep(Duration::from_millis(20));
+        progress.advance(50);
+        let eta = progress.eta().unwrap();
+        assert!(eta >= Duration::from_millis(15) && eta < Duration::from_secs(1));
+        assert_eq!(clock(Duration::from_secs(3725)), "1:02:05");
+        assert_eq!(clock(Duration::from_secs(65)), "1:05");
+    }
+}
@@ -0,0 +1,155 @@
+use crate::color::Color;
+use std::fmt::Write;
+
+/// Columns and rows of the terminal, from `COLUMNS` and `LINES` or else `stty`.
+pub fn size() -> Option<(usize, usize)> {
+    let variable = |name| std::env::var(name).ok()?.trim().parse::<usize>().ok();
+    if let (Some(columns), Some(rows)) = (variable("COLUMNS"), variable("LINES")) {
+        return Some((columns, rows));
+    }
+    let tty = std::fs::File::open("/dev/tty").ok()?;
+    let output = std::process::Command::new("stty")
+        .arg("size")
+        .stdin(tty)
+        .output()
+        .ok()?;
+    let text = String::from_utf8(output.stdout).ok()?;
+    let mut words = text.split_whitespace().map(|w| w.parse::<usize>().ok());
+    let (rows, columns) = (words.next()??, words.next()??);
+    if rows > 0 && columns > 0 {
+        Some((columns, rows))
+    } else {
+        None
+    }
+}
+
+/// Largest size of an image fitting into `columns` x `rows` character cells, which hold two
+/// pixels each, one above the other.
+pub fn fit(width: usize, height: usize, columns: usize, rows: usize) -> (usize, usize) {
+    let scale = (columns as f32 / width as f32).min(2.0 * rows as f32 / height as f32);
+    let scale = scale.min(1.0);
+    (
+        ((width as f32 * scale).round() as usize).clamp(1, columns.max(1)),
+        ((height as f32 * scale).round() as usize).clamp(1, 2 * rows.max(1)),
+    )
+}
+
+/// Box-filtered to `to_width` x `to_height`; every source pixel lands in exactly one target.
+pub fn downsample(
+    pixels: &[Color],
+    width: usize,
+    height: usize,
+    to_width: usize,
+    to_height: usize,
+) -> Vec<Color> {
+    let mut sums = vec![(0, 0, 0, 0); to_width * to_height];
+    for y in 0..height {
+        let ty = y * to_height / height;
+        for x in 0..width {
+            let tx = x * to_width / width;
+            let c = &pixels[y * width + x];
+            let s = &mut sums[ty * to_width + tx];
+            *s = (s.0 + c.r, s.1 + c.g, s.2 + c.b, s.3 + 1);
+        }
+    }
+    sums.into_iter()
+        .map(|(r, g, b, n)| {
+            let n = n.max(1);
+            Color::new((r + n / 2) / n, (g + n / 2) / n, (b + n / 2) / n)
+        })
+        .collect()
+}
+
+/// Pixels as lines of `▀`, the upper pixel in the foreground color and the lower one in the
+/// background, using 24-bit ANSI colors. A last odd row leaves the background alone.
+pub fn half_blocks(pixels: &[Color], width: usize, height: usize) -> String {
+    let mut out = String::new();
+    for y in (0..height).step_by(2) {
+        for x in 0..width {
+            let top = &pixels[y * width + x];
+            let _ = write!(out, "\x1b[38;2;{};{};{}m", top.r, top.g, top.b);
+            if y + 1 < height {
+                let bottom = &pixels[(y + 1) * width + x];
+                let _ = write!(out, "\x1b[48;2;{};{};{}m", bottom.r, bottom.g, bottom.b);
+            } else {
+                out.push_str("\x1b[49m");
+            }
+            out.push('▀');
+        }
+        out.push_str("\x1b[0m\n");
+    }
+    out
+}
+
+/// Redraws an image in place, moving the cursor back over the previous drawing.
+#[derive(Default)]
+pub struct Canvas {
+    lines: usize,
+}
+
+impl Canvas {
+    pub fn new() -> Canvas {
+        Canvas::default()
+    }
+
+    /// `status` goes on a line under the image.
+    pub fn frame(&mut self, pixels: &[Color], width: usize, height: usize, status: &str) -> String {
+        let mut out = String::new();
+        if self.lines > 0 {
+            let _ = write!(out, "\x1b[{}F", self.lines);
+        }
+        out.push_str(&half_blocks(pixels, width, height));
+        let _ = writeln!(out, "\x1b[2K{}", status);
+        self.lines = height.div_ceil(2) + 1;
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fits_into_the_terminal() {
+        // cells are two pixels high
+        assert_eq!(fit(400, 225, 80, 24), (80, 45));
+        assert_eq!(fit(400, 400, 80, 24), (48, 48));
+        // never enlarged
+        assert_eq!(fit(10, 6, 80, 24), (10, 6));
+        assert_eq!(fit(1000, 1, 80, 24), (80, 1));
+    }
+
+    #[test]
+    fn averages_blocks() {
+        let pixels = vec![
+            Color::new(0, 0, 0),
+            Color::new(100, 0, 0),
+            Color::new(255, 255, 255),
+            Color::new(0, 50, 0),
+            Color::new(0, 150, 0),
+            Color::new(255, 255, 255),
+        ];
+        let small = downsample(&pixels, 3, 2, 2, 1);
+        assert_eq!(
+            small,
+            vec![Color::new(25, 50, 0), Color::new(255, 255, 255)]
+        );
+    }
+
+    #[test]
+    fn pairs_rows_into_half_blocks() {
+        let pixels = vec![
+            Color::new(1, 2, 3),
+            Color::new(4, 5, 6),
+            Color::new(7, 8, 9),
+        ];
+        let text = half_blocks(&pixels, 1, 3);
+        assert_eq!(
+            text,
+            "\x1b[38;2;1;2;3m\x1b[48;2;4;5;6m▀\x1b[0m\n\x1b[38;2;7;8;9m\x1b[49m▀\x1b[0m\n"
+        );
+        let mut canvas = Canvas::new();
+        assert!(!canvas.frame(&pixels, 1, 3, "").starts_with("\x1b[3F"));
+        assert!(canvas.frame(&pixels, 1, 3, "").starts_with("\x1b[3F"));
+    }
+}
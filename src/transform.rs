@@ -0,0 +1,274 @@
+use crate::aabb::Aabb;
+use crate::hitable::{HitRecord, Hitable, Interval};
+use crate::misc::degree_to_radian;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+
+/// Rotation stored as a unit quaternion `w + xi + yj + zk`.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Quaternion {
+    pub w: f32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Quaternion {
+    pub fn identity() -> Quaternion {
+        Quaternion {
+            w: 1.0,
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        }
+    }
+
+    /// Counter-clockwise rotation by `degrees` when looking down `axis`.
+    pub fn from_axis_angle(axis: Vec3, degrees: f32) -> Quaternion {
+        let half = degree_to_radian(degrees) / 2.0;
+        let a = axis.unit_vector() * half.sin();
+        Quaternion {
+            w: half.cos(),
+            x: a.x,
+            y: a.y,
+            z: a.z,
+        }
+    }
+
+    fn vector(&self) -> Vec3 {
+        Vec3::new(self.x, self.y, self.z)
+    }
+
+    pub fn conjugate(&self) -> Quaternion {
+        Quaternion {
+            w: self.w,
+            x: -self.x,
+            y: -self.y,
+            z: -self.z,
+        }
+    }
+
+    pub fn dot(&self, other: &Quaternion) -> f32 {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn normalized(&self) -> Quaternion {
+        self.scale(1.0 / self.dot(self).sqrt())
+    }
+
+    pub fn scale(&self, f: f32) -> Quaternion {
+        Quaternion {
+            w: self.w * f,
+            x: self.x * f,
+            y: self.y * f,
+            z: self.z * f,
+        }
+    }
+
+    pub fn add(&self, other: &Quaternion) -> Quaternion {
+        Quaternion {
+            w: self.w + other.w,
+            x: self.x + other.x,
+            y: self.y + other.y,
+            z: self.z + other.z,
+        }
+    }
+
+    pub fn rotate(&self, v: &Vec3) -> Vec3 {
+        let q = self.vector();
+        let t = 2.0 * q.cross(v);
+        *v + self.w * t + q.cross(&t)
+    }
+
+    /// Constant angular velocity interpolation along the shorter arc.
+    pub fn slerp(&self, other: &Quaternion, t: f32) -> Quaternion {
+        let mut cos = self.dot(other);
+        let mut other = *other;
+        if cos < 0.0 {
+            cos = -cos;
+            other = other.scale(-1.0);
+        }
+        if cos > 0.9995 {
+            // nearly parallel: the arc is indistinguishable from the chord
+            return self.scale(1.0 - t).add(&other.scale(t)).normalized();
+        }
+        let theta = cos.acos();
+        let sin = theta.sin();
+        self.scale(((1.0 - t) * theta).sin() / sin)
+            .add(&other.scale((t * theta).sin() / sin))
+    }
+}
+
+/// Scale, then rotation, then translation.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quaternion,
+    /// Uniform, so normals only need rotating.
+    pub scale: f32,
+}
+
+impl Default for Transform {
+    fn default() -> Transform {
+        Transform {
+            translation: Vec3::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::identity(),
+            scale: 1.0,
+        }
+    }
+}
+
+impl Transform {
+    pub fn apply(&self, p: &Vec3) -> Vec3 {
+        self.rotation.rotate(&(*p * self.scale)) + self.translation
+    }
+
+    /// The ray in object space; its parameter `t` still measures the same points.
+    fn object_ray(&self, r: &Ray) -> Ray {
+        let inverse = self.rotation.conjugate();
+        Ray::new(
+            inverse.rotate(&(*r.origin() - self.translation)) / self.scale,
+            inverse.rotate(r.direction()) / self.scale,
+        )
+    }
+
+    fn world_hit(&self, r: &Ray, mut rec: HitRecord) -> HitRecord {
+        rec.p = r.point_at_parameter(rec.t);
+        rec.normal = self.rotation.rotate(&rec.normal);
+        rec
+    }
+}
+
+/// Places a hitable defined in its own object space into the world.
+pub struct Transformed {
+    pub inner: Box<dyn Hitable>,
+    pub transform: Transform,
+}
+
+impl Hitable for Transformed {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        self.inner
+            .hit(&self.transform.object_ray(r), t_min, t_max)
+            .map(|rec| self.transform.world_hit(r, rec))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let b = self.inner.bounding_box()?;
+        // built up front: folding the lazily mapped corners loses some of them at opt-level 2
+        // and above with rustc 1.95
+        let corners: [Vec3; 8] = std::array::from_fn(|i| {
+            self.transform.apply(&Vec3::new(
+                if i & 1 == 0 { b.min.x } else { b.max.x },
+                if i & 2 == 0 { b.min.y } else { b.max.y },
+                if i & 4 == 0 { b.min.z } else { b.max.z },
+            ))
+        });
+        corners
+            .iter()
+            .map(|&c| Aabb::from_points(c, c))
+            .reduce(|a, b| a.surrounding(&b))
+    }
+
+    fn intervals(&self, r: &Ray) -> Vec<Interval> {
+        self.inner
+            .intervals(&self.transform.object_ray(r))
+            .into_iter()
+            .map(|i| Interval {
+                enter: self.transform.world_hit(r, i.enter),
+                exit: self.transform.world_hit(r, i.exit),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hitable::Sphere;
+    use crate::material::Lambertian;
+    use crate::shapes::AxisAlignedBox;
+    use std::rc::Rc;
+
+    fn close(a: &Vec3, b: &Vec3) -> bool {
+        (*a - *b).length() < 1e-5
+    }
+
+    #[test]
+    fn rotates_vectors() {
+        let q = Quaternion::from_axis_angle(Vec3::new(0.0, 0.0, 1.0), 90.0);
+        assert!(close(
+            &q.rotate(&Vec3::new(1.0, 0.0, 0.0)),
+            &Vec3::new(0.0, 1.0, 0.0)
+        ));
+        assert!(close(
+            &q.conjugate().rotate(&Vec3::new(0.0, 1.0, 0.0)),
+            &Vec3::new(1.0, 0.0, 0.0)
+        ));
+    }
+
+    #[test]
+    fn slerp_keeps_constant_speed() {
+        let axis = Vec3::new(0.0, 1.0, 0.0);
+        let a = Quaternion::identity();
+        let b = Quaternion::from_axis_angle(axis, 120.0);
+        for &t in &[0.0, 0.25, 0.5, 1.0] {
+            let expected = Quaternion::from_axis_angle(axis, 120.0 * t);
+            assert!((a.slerp(&b, t).dot(&expected).abs() - 1.0).abs() < 1e-5);
+        }
+        // the shorter way round even when the signs disagree
+        let c = Quaternion::from_axis_angle(axis, 10.0).scale(-1.0);
+        let mid = a.slerp(&c, 0.5);
+        assert!((mid.dot(&Quaternion::from_axis_angle(axis, 5.0)).abs() - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn transformed_shapes_hit_in_world_space() {
+        let material: Rc<Box<dyn crate::material::Material>> =
+            Rc::new(Box::new(Lambertian::new((128, 128, 128).into())));
+        let cube = Transformed {
+            inner: Box::new(AxisAlignedBox::new(
+                Vec3::new(-1.0, -1.0, -1.0),
+                Vec3::new(1.0, 1.0, 1.0),
+                material.clone(),
+            )),
+            transform: Transform {
+                translation: Vec3::new(0.0, 0.0, -10.0),
+                rotation: Quaternion::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), 45.0),
+                scale: 2.0,
+            },
+        };
+        // the rotated cube shows its edge to the camera, sqrt(2) * 2 in front of its center
+        let r = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let rec = cube.hit(&r, 0.0, f32::MAX).unwrap();
+        assert!((rec.t - (10.0 - 2.0 * 2f32.sqrt())).abs() < 1e-4);
+        assert!((rec.normal.length() - 1.0).abs() < 1e-5);
+        assert!(rec.normal.z > 0.0);
+        // rotating the corners before bounding them rounds differently in release builds
+        let bounds = cube.bounding_box().unwrap();
+        let near = |a: f32, b: f32| (a - b).abs() < 1e-3 * b.abs();
+        assert!(near(bounds.max.x, 2.0 * 2f32.sqrt()), "{:?}", bounds);
+        assert!(
+            near(bounds.min.z, -10.0 - 2.0 * 2f32.sqrt()),
+            "{:?}",
+            bounds
+        );
+
+        let ball = Transformed {
+            inner: Box::new(Sphere {
+                center: Vec3::new(0.0, 0.0, 0.0),
+                radius: 1.0,
+                material,
+            }),
+            transform: Transform {
+                translation: Vec3::new(0.0, 0.0, -5.0),
+                scale: 0.5,
+                ..Transform::default()
+            },
+        };
+        let intervals = ball.intervals(&r);
+        assert_eq!(intervals.len(), 1);
+        assert!((intervals[0].enter.t - 4.5).abs() < 1e-4);
+        assert!((intervals[0].exit.t - 5.5).abs() < 1e-4);
+        assert!(close(&intervals[0].exit.p, &Vec3::new(0.0, 0.0, -5.5)));
+    }
+}
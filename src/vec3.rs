@@ -25,11 +25,11 @@ impl Vec3 {
         Vec3::new(x as f32, y as f32, z as f32)
     }
 
-    pub fn dot(self: &Vec3, other: &Vec3) -> f32 {
+    pub fn dot(&self, other: &Vec3) -> f32 {
         self.x * other.x + self.y * other.y + self.z * other.z
     }
 
-    pub fn cross(self: &Vec3, other: &Vec3) -> Vec3 {
+    pub fn cross(&self, other: &Vec3) -> Vec3 {
         Vec3::new(
             self.y * other.z - self.z * other.y,
             self.z * other.x - self.x * other.z,
@@ -37,19 +37,19 @@ impl Vec3 {
         )
     }
 
-    pub fn length(self: &Vec3) -> f32 {
+    pub fn length(&self) -> f32 {
         self.squared_length().sqrt()
     }
 
-    pub fn squared_length(self: &Vec3) -> f32 {
+    pub fn squared_length(&self) -> f32 {
         self.x * self.x + self.y * self.y + self.z * self.z
     }
 
-    pub fn unit_vector(self: &Vec3) -> Vec3 {
-        self.clone() / self.length()
+    pub fn unit_vector(&self) -> Vec3 {
+        *self / self.length()
     }
 
-    pub fn as_color(self: &Vec3) -> Color {
+    pub fn as_color(&self) -> Color {
         (self.x, self.y, self.z).into()
     }
 }
@@ -64,7 +64,7 @@ impl ops::Sub for &Vec3 {
 impl ops::Sub for Vec3 {
     type Output = Vec3;
     fn sub(self, other: Vec3) -> Vec3 {
-        self - &other
+        Vec3::new(self.x - other.x, self.y - other.y, self.z - other.z)
     }
 }
 
@@ -92,7 +92,7 @@ impl ops::Mul<Vec3> for &Vec3 {
 impl ops::Mul<f32> for Vec3 {
     type Output = Vec3;
     fn mul(self, f: f32) -> Vec3 {
-        &self * f
+        Vec3::new(self.x * f, self.y * f, self.z * f)
     }
 }
 
@@ -106,7 +106,7 @@ impl ops::Mul<f32> for &Vec3 {
 impl ops::Div<f32> for Vec3 {
     type Output = Vec3;
     fn div(self, f: f32) -> Vec3 {
-        &self / f
+        self * (1.0 / f)
     }
 }
 
@@ -138,6 +138,18 @@ impl ops::Add for Vec3 {
     }
 }
 
+impl ops::Index<usize> for Vec3 {
+    type Output = f32;
+    fn index(&self, axis: usize) -> &f32 {
+        match axis {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("Vec3 axis out of range: {}", axis),
+        }
+    }
+}
+
 impl From<(f32, f32, f32)> for Vec3 {
     fn from(pos: (f32, f32, f32)) -> Vec3 {
         Vec3::new(pos.0, pos.1, pos.2)
@@ -158,3 +170,25 @@ pub fn random_in_unit_sphere() -> Vec3 {
         }
     }
 }
+
+/// Uniformly distributed over the surface of the unit sphere.
+pub fn random_unit_vector() -> Vec3 {
+    loop {
+        let p = random_in_unit_sphere();
+        if p.squared_length() > 1e-12 {
+            return p.unit_vector();
+        }
+    }
+}
+
+/// Two unit vectors that complete `n` (assumed unit length) to a right-handed orthonormal basis.
+pub fn orthonormal_basis(n: &Vec3) -> (Vec3, Vec3) {
+    // Duff et al., "Building an Orthonormal Basis, Revisited"
+    let sign = 1.0f32.copysign(n.z);
+    let a = -1.0 / (sign + n.z);
+    let b = n.x * n.y * a;
+    (
+        Vec3::new(1.0 + sign * n.x * n.x * a, sign * b, -sign * n.x),
+        Vec3::new(b, sign + n.y * n.y * a, -n.y),
+    )
+}
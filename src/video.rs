@@ -0,0 +1,670 @@
+use crate::color::Color;
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+/// Destination of an animation's frames, in playback order.
+pub trait FrameSink {
+    /// `pixels` hold 8-bit color stored top to bottom.
+    fn push(&mut self, width: usize, height: usize, pixels: &[Color]) -> io::Result<()>;
+    /// Writes whatever trails the last frame; nothing may be pushed afterwards.
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+fn channel(c: usize) -> u8 {
+    c.min(255) as u8
+}
+
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// `fps` as a reduced fraction, exact to a thousandth of a frame.
+fn frame_rate(fps: f32) -> (u32, u32) {
+    let num = (fps * 1000.0).round().max(1.0) as u32;
+    let d = gcd(num, 1000);
+    (num / d, 1000 / d)
+}
+
+fn check_size(
+    expected: &mut Option<(usize, usize)>,
+    width: usize,
+    height: usize,
+    pixels: &[Color],
+) -> io::Result<()> {
+    if pixels.len() != width * height {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} pixels for a {}x{} frame", pixels.len(), width, height),
+        ));
+    }
+    match *expected {
+        Some(size) if size != (width, height) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "frame is {}x{} but the stream is {}x{}",
+                width, height, size.0, size.1
+            ),
+        )),
+        _ => {
+            *expected = Some((width, height));
+            Ok(())
+        }
+    }
+}
+
+/// Uncompressed YUV4MPEG2 stream in 4:2:0, the way `ffmpeg -i in.y4m` and most encoders
+/// expect it. Colors are converted with BT.601 coefficients to limited (16-235) range.
+pub struct Y4mWriter<W: Write> {
+    out: W,
+    fps: f32,
+    size: Option<(usize, usize)>,
+}
+
+impl<W: Write> Y4mWriter<W> {
+    pub fn new(out: W, fps: f32) -> Y4mWriter<W> {
+        Y4mWriter {
+            out,
+            fps,
+            size: None,
+        }
+    }
+}
+
+fn ycbcr(c: &Color) -> (f32, f32, f32) {
+    let (r, g, b) = (
+        channel(c.r) as f32 / 255.0,
+        channel(c.g) as f32 / 255.0,
+        channel(c.b) as f32 / 255.0,
+    );
+    let y = 0.299 * r + 0.587 * g + 0.114 * b;
+    (y, (b - y) / 1.772, (r - y) / 1.402)
+}
+
+impl<W: Write> FrameSink for Y4mWriter<W> {
+    fn push(&mut self, width: usize, height: usize, pixels: &[Color]) -> io::Result<()> {
+        let first = self.size.is_none();
+        check_size(&mut self.size, width, height, pixels)?;
+        if first {
+            let (num, den) = frame_rate(self.fps);
+            writeln!(
+                self.out,
+                "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C420jpeg",
+                width, height, num, den
+            )?;
+        }
+        let converted: Vec<(f32, f32, f32)> = pixels.iter().map(ycbcr).collect();
+        let mut frame = Vec::with_capacity(width * height * 3 / 2 + 6);
+        frame.extend_from_slice(b"FRAME\n");
+        frame.extend(
+            converted
+                .iter()
+                .map(|&(y, _, _)| (16.0 + 219.0 * y).round() as u8),
+        );
+        // chroma is averaged over 2x2 blocks, the last row or column alone when odd
+        let (cw, ch) = (width.div_ceil(2), height.div_ceil(2));
+        for plane in 0..2 {
+            for by in 0..ch {
+                for bx in 0..cw {
+                    let (mut sum, mut n) = (0.0, 0.0);
+                    for y in 2 * by..(2 * by + 2).min(height) {
+                        for x in 2 * bx..(2 * bx + 2).min(width) {
+                            let (_, cb, cr) = converted[y * width + x];
+                            sum += if plane == 0 { cb } else { cr };
+                            n += 1.0;
+                        }
+                    }
+                    frame.push((128.0 + 224.0 * sum / n).round() as u8);
+                }
+            }
+        }
+        self.out.write_all(&frame)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+pub(crate) fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in bytes {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+pub(crate) fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in bytes.chunks(5552) {
+        for &x in chunk {
+            a += x as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+/// zlib stream made of stored deflate blocks: larger than a compressed one, but any PNG
+/// reader accepts it and it needs no compressor.
+pub(crate) fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(65535).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let len = block.len() as u16;
+        out.push(if blocks.peek().is_none() { 1 } else { 0 });
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+pub(crate) fn png_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+pub(crate) const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+fn png_header(out: &mut Vec<u8>, width: usize, height: usize) {
+    out.extend_from_slice(&PNG_SIGNATURE);
+    let mut ihdr = vec![];
+    ihdr.extend_from_slice(&(width as u32).to_be_bytes());
+    ihdr.extend_from_slice(&(height as u32).to_be_bytes());
+    // 8 bits per channel, truecolor, deflate, adaptive filtering, no interlace
+    ihdr.extend_from_slice(&[8, 2, 0, 0, 0]);
+    png_chunk(out, b"IHDR", &ihdr);
+}
+
+/// Image data as PNG scanlines, each with filter type 0, zlib-wrapped.
+fn png_image_data(width: usize, pixels: &[Color]) -> Vec<u8> {
+    let mut raw = Vec::with_capacity(pixels.len() * 3 + pixels.len() / width.max(1));
+    for row in pixels.chunks(width.max(1)) {
+        raw.push(0);
+        for c in row {
+            raw.extend_from_slice(&[channel(c.r), channel(c.g), channel(c.b)]);
+        }
+    }
+    zlib_stored(&raw)
+}
+
+/// A still PNG.
+pub fn encode_png(width: usize, height: usize, pixels: &[Color]) -> Vec<u8> {
+    let mut out = vec![];
+    png_header(&mut out, width, height);
+    png_chunk(&mut out, b"IDAT", &png_image_data(width, pixels));
+    png_chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+/// Animated PNG that loops forever. APNG declares its frame count up front, so it has to be
+/// known when the writer is made; readers without APNG support show the first frame.
+pub struct ApngWriter<W: Write> {
+    out: W,
+    fps: f32,
+    frames: u32,
+    written: u32,
+    sequence: u32,
+    size: Option<(usize, usize)>,
+}
+
+impl<W: Write> ApngWriter<W> {
+    pub fn new(out: W, fps: f32, frames: u32) -> ApngWriter<W> {
+        ApngWriter {
+            out,
+            fps,
+            frames,
+            written: 0,
+            sequence: 0,
+            size: None,
+        }
+    }
+}
+
+impl<W: Write> FrameSink for ApngWriter<W> {
+    fn push(&mut self, width: usize, height: usize, pixels: &[Color]) -> io::Result<()> {
+        if self.written == self.frames {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("the animation was declared with {} frames", self.frames),
+            ));
+        }
+        check_size(&mut self.size, width, height, pixels)?;
+        let mut out = vec![];
+        if self.written == 0 {
+            png_header(&mut out, width, height);
+            let mut actl = self.frames.to_be_bytes().to_vec();
+            actl.extend_from_slice(&0u32.to_be_bytes());
+            png_chunk(&mut out, b"acTL", &actl);
+        }
+        // delay in hundredths of a second keeps the fraction within 16 bits
+        let delay_den = ((self.fps * 100.0).round() as u32).clamp(1, 65535) as u16;
+        let mut fctl = self.sequence.to_be_bytes().to_vec();
+        fctl.extend_from_slice(&(width as u32).to_be_bytes());
+        fctl.extend_from_slice(&(height as u32).to_be_bytes());
+        fctl.extend_from_slice(&[0; 8]);
+        fctl.extend_from_slice(&100u16.to_be_bytes());
+        fctl.extend_from_slice(&delay_den.to_be_bytes());
+        fctl.extend_from_slice(&[0, 0]);
+        png_chunk(&mut out, b"fcTL", &fctl);
+        self.sequence += 1;
+        let data = png_image_data(width, pixels);
+        if self.written == 0 {
+            png_chunk(&mut out, b"IDAT", &data);
+        } else {
+            let mut fdat = self.sequence.to_be_bytes().to_vec();
+            fdat.extend_from_slice(&data);
+            png_chunk(&mut out, b"fdAT", &fdat);
+            self.sequence += 1;
+        }
+        self.written += 1;
+        self.out.write_all(&out)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if self.written != self.frames {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "the animation was declared with {} frames but got {}",
+                    self.frames, self.written
+                ),
+            ));
+        }
+        let mut out = vec![];
+        png_chunk(&mut out, b"IEND", &[]);
+        self.out.write_all(&out)?;
+        self.out.flush()
+    }
+}
+
+/// 4x4 Bayer matrix for ordered dithering, in sixteenths.
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// Levels of red, green and blue in the fixed GIF palette; green gets the extra level
+/// because the eye is most sensitive to it.
+const LEVELS: [usize; 3] = [6, 7, 6];
+
+fn gif_palette() -> Vec<u8> {
+    let mut palette = vec![0; 256 * 3];
+    let mut i = 0;
+    for r in 0..LEVELS[0] {
+        for g in 0..LEVELS[1] {
+            for b in 0..LEVELS[2] {
+                let level = |v: usize, n: usize| (v * 255 / (n - 1)) as u8;
+                palette[i * 3..i * 3 + 3].copy_from_slice(&[
+                    level(r, LEVELS[0]),
+                    level(g, LEVELS[1]),
+                    level(b, LEVELS[2]),
+                ]);
+                i += 1;
+            }
+        }
+    }
+    palette
+}
+
+fn gif_index(c: &Color, x: usize, y: usize) -> u8 {
+    let threshold = (BAYER[y % 4][x % 4] as f32 + 0.5) / 16.0;
+    let quantize = |v: usize, n: usize| {
+        let scaled = channel(v) as f32 / 255.0 * (n - 1) as f32;
+        ((scaled + threshold).floor() as usize).min(n - 1)
+    };
+    let (r, g, b) = (
+        quantize(c.r, LEVELS[0]),
+        quantize(c.g, LEVELS[1]),
+        quantize(c.b, LEVELS[2]),
+    );
+    ((r * LEVELS[1] + g) * LEVELS[2] + b) as u8
+}
+
+/// Variable-width LZW as used by GIF, codes packed least significant bit first.
+fn lzw_encode(indices: &[u8], min_code_size: u8) -> Vec<u8> {
+    let clear = 1u16 << min_code_size;
+    let end = clear + 1;
+    let mut out = vec![];
+    let (mut buffer, mut bits) = (0u32, 0u32);
+    let mut emit = |code: u16, size: u32, out: &mut Vec<u8>| {
+        buffer |= (code as u32) << bits;
+        bits += size;
+        while bits >= 8 {
+            out.push(buffer as u8);
+            buffer >>= 8;
+            bits -= 8;
+        }
+    };
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut size = min_code_size as u32 + 1;
+    let mut next = end + 1;
+    emit(clear, size, &mut out);
+    let mut prefix: Option<u16> = None;
+    for &k in indices {
+        let p = match prefix {
+            None => {
+                prefix = Some(k as u16);
+                continue;
+            }
+            Some(p) => p,
+        };
+        if let Some(&code) = table.get(&(p, k)) {
+            prefix = Some(code);
+            continue;
+        }
+        emit(p, size, &mut out);
+        if next == 4096 {
+            emit(clear, size, &mut out);
+            table.clear();
+            size = min_code_size as u32 + 1;
+            next = end + 1;
+        } else {
+            table.insert((p, k), next);
+            // the decoder widens codes one step behind the encoder
+            if next == 1 << size && size < 12 {
+                size += 1;
+            }
+            next += 1;
+        }
+        prefix = Some(k as u16);
+    }
+    if let Some(p) = prefix {
+        emit(p, size, &mut out);
+        if next == 1 << size && size < 12 {
+            size += 1;
+        }
+    }
+    emit(end, size, &mut out);
+    if bits > 0 {
+        out.push(buffer as u8);
+    }
+    out
+}
+
+/// Looping GIF with a fixed 252-color palette and ordered dithering.
+pub struct GifWriter<W: Write> {
+    out: W,
+    fps: f32,
+    size: Option<(usize, usize)>,
+}
+
+impl<W: Write> GifWriter<W> {
+    pub fn new(out: W, fps: f32) -> GifWriter<W> {
+        GifWriter {
+            out,
+            fps,
+            size: None,
+        }
+    }
+}
+
+impl<W: Write> FrameSink for GifWriter<W> {
+    fn push(&mut self, width: usize, height: usize, pixels: &[Color]) -> io::Result<()> {
+        let first = self.size.is_none();
+        check_size(&mut self.size, width, height, pixels)?;
+        if width > 65535 || height > 65535 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "GIF frames are at most 65535 pixels wide and high",
+            ));
+        }
+        let mut out = vec![];
+        if first {
+            out.extend_from_slice(b"GIF89a");
+            out.extend_from_slice(&(width as u16).to_le_bytes());
+            out.extend_from_slice(&(height as u16).to_le_bytes());
+            // global color table of 2^(7+1) entries
+            out.extend_from_slice(&[0xf7, 0, 0]);
+            out.extend_from_slice(&gif_palette());
+            // loop forever
+            out.extend_from_slice(b"\x21\xff\x0bNETSCAPE2.0\x03\x01\x00\x00\x00");
+        }
+        let delay = (100.0 / self.fps).round().clamp(1.0, 65535.0) as u16;
+        out.extend_from_slice(&[0x21, 0xf9, 4, 0]);
+        out.extend_from_slice(&delay.to_le_bytes());
+        out.extend_from_slice(&[0, 0]);
+        out.push(0x2c);
+        out.extend_from_slice(&[0, 0, 0, 0]);
+        out.extend_from_slice(&(width as u16).to_le_bytes());
+        out.extend_from_slice(&(height as u16).to_le_bytes());
+        out.push(0);
+        let indices: Vec<u8> = pixels
+            .iter()
+            .enumerate()
+            .map(|(i, c)| gif_index(c, i % width, i / width))
+            .collect();
+        out.push(8);
+        for block in lzw_encode(&indices, 8).chunks(255) {
+            out.push(block.len() as u8);
+            out.extend_from_slice(block);
+        }
+        out.push(0);
+        self.out.write_all(&out)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.out.write_all(&[0x3b])?;
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(width: usize, height: usize, seed: usize) -> Vec<Color> {
+        (0..width * height)
+            .map(|i| Color::new((i * 37 + seed) % 256, (i * 11) % 256, (seed * 90) % 256))
+            .collect()
+    }
+
+    /// Splits a PNG into its chunks, checking every CRC on the way.
+    fn chunks(png: &[u8]) -> Vec<(String, Vec<u8>)> {
+        assert_eq!(png[..8], PNG_SIGNATURE);
+        let mut chunks = vec![];
+        let mut pos = 8;
+        while pos < png.len() {
+            let len = u32::from_be_bytes([png[pos], png[pos + 1], png[pos + 2], png[pos + 3]]);
+            let body = &png[pos + 4..pos + 8 + len as usize];
+            let crc = &png[pos + 8 + len as usize..pos + 12 + len as usize];
+            assert_eq!(crc, crc32(body).to_be_bytes());
+            chunks.push((
+                String::from_utf8_lossy(&body[..4]).into_owned(),
+                body[4..].to_vec(),
+            ));
+            pos += 12 + len as usize;
+        }
+        chunks
+    }
+
+    fn inflate_stored(zlib: &[u8]) -> Vec<u8> {
+        let mut data = vec![];
+        let mut pos = 2;
+        loop {
+            let last = zlib[pos] & 1 == 1;
+            let len = u16::from_le_bytes([zlib[pos + 1], zlib[pos + 2]]) as usize;
+            data.extend_from_slice(&zlib[pos + 5..pos + 5 + len]);
+            pos += 5 + len;
+            if last {
+                break;
+            }
+        }
+        assert_eq!(zlib[pos..], adler32(&data).to_be_bytes());
+        data
+    }
+
+    fn lzw_decode(data: &[u8], min_code_size: u8) -> Vec<u8> {
+        let clear = 1usize << min_code_size;
+        let mut table: Vec<Vec<u8>> = vec![];
+        let mut size = 0;
+        let reset = |table: &mut Vec<Vec<u8>>, size: &mut u32| {
+            *table = (0..clear + 2).map(|i| vec![i as u8]).collect();
+            *size = min_code_size as u32 + 1;
+        };
+        reset(&mut table, &mut size);
+        let (mut pos, mut out, mut previous) = (0usize, vec![], None::<Vec<u8>>);
+        loop {
+            let mut code = 0usize;
+            for i in 0..size as usize {
+                let bit = (data[(pos + i) / 8] >> ((pos + i) % 8)) & 1;
+                code |= (bit as usize) << i;
+            }
+            pos += size as usize;
+            if code == clear {
+                reset(&mut table, &mut size);
+                previous = None;
+                continue;
+            }
+            if code == clear + 1 {
+                return out;
+            }
+            let entry = match (&previous, table.get(code)) {
+                (_, Some(e)) => e.clone(),
+                (Some(p), None) => {
+                    let mut e = p.clone();
+                    e.push(p[0]);
+                    e
+                }
+                (None, None) => panic!("bad code"),
+            };
+            if let Some(mut p) = previous.take() {
+                p.push(entry[0]);
+                table.push(p);
+                if table.len() == 1 << size && size < 12 {
+                    size += 1;
+                }
+            }
+            out.extend_from_slice(&entry);
+            previous = Some(entry);
+        }
+    }
+
+    #[test]
+    fn y4m_frames_are_420() {
+        let mut bytes = vec![];
+        let mut writer = Y4mWriter::new(&mut bytes, 29.97);
+        let mut pixels = vec![Color::new(255, 255, 255); 15];
+        pixels[0] = Color::new(0, 0, 0);
+        writer.push(5, 3, &pixels).unwrap();
+        writer.push(5, 3, &pixels).unwrap();
+        assert!(writer.push(3, 5, &pixels).is_err());
+        writer.finish().unwrap();
+
+        let header = b"YUV4MPEG2 W5 H3 F2997:100 Ip A1:1 C420jpeg\n";
+        assert_eq!(&bytes[..header.len()], header);
+        let frame = &bytes[header.len()..];
+        // luma for every pixel, then 3x2 samples of each chroma plane
+        let size = 6 + 15 + 2 * 6;
+        assert_eq!(frame.len(), 2 * size);
+        assert_eq!(&frame[..6], b"FRAME\n");
+        assert_eq!(frame[6], 16);
+        assert_eq!(frame[7], 235);
+        assert!(frame[21..size].iter().all(|&c| c == 128));
+    }
+
+    #[test]
+    fn png_stills_hold_their_pixels() {
+        let pixels = frame(4, 3, 1);
+        let chunks = chunks(&encode_png(4, 3, &pixels));
+        let names: Vec<&str> = chunks.iter().map(|c| c.0.as_str()).collect();
+        assert_eq!(names, ["IHDR", "IDAT", "IEND"]);
+        let raw = inflate_stored(&chunks[1].1);
+        assert_eq!(raw.len(), 3 * (1 + 4 * 3));
+        assert_eq!(raw[0], 0);
+        assert_eq!(
+            raw[1..4],
+            [pixels[0].r as u8, pixels[0].g as u8, pixels[0].b as u8]
+        );
+    }
+
+    #[test]
+    fn apng_sequences_frames() {
+        let mut bytes = vec![];
+        let mut writer = ApngWriter::new(&mut bytes, 24.0, 3);
+        for i in 0..3 {
+            writer.push(2, 2, &frame(2, 2, i)).unwrap();
+        }
+        assert!(writer.push(2, 2, &frame(2, 2, 3)).is_err());
+        writer.finish().unwrap();
+
+        let chunks = chunks(&bytes);
+        let names: Vec<&str> = chunks.iter().map(|c| c.0.as_str()).collect();
+        assert_eq!(
+            names,
+            ["IHDR", "acTL", "fcTL", "IDAT", "fcTL", "fdAT", "fcTL", "fdAT", "IEND"]
+        );
+        assert_eq!(chunks[1].1[..4], 3u32.to_be_bytes());
+        // sequence numbers run across fcTL and fdAT without gaps
+        let sequence: Vec<u32> = chunks
+            .iter()
+            .filter(|c| c.0 == "fcTL" || c.0 == "fdAT")
+            .map(|c| u32::from_be_bytes([c.1[0], c.1[1], c.1[2], c.1[3]]))
+            .collect();
+        assert_eq!(sequence, [0, 1, 2, 3, 4]);
+        let last = inflate_stored(&chunks[7].1[4..]);
+        let expected = frame(2, 2, 2);
+        assert_eq!(
+            last[1..4],
+            [
+                expected[0].r as u8,
+                expected[0].g as u8,
+                expected[0].b as u8
+            ]
+        );
+
+        let mut short = ApngWriter::new(vec![], 24.0, 2);
+        short.push(2, 2, &frame(2, 2, 0)).unwrap();
+        assert!(short.finish().is_err());
+    }
+
+    #[test]
+    fn lzw_round_trips() {
+        let mut data: Vec<u8> = (0..20000).map(|i| ((i * i) % 251) as u8).collect();
+        data.extend([7; 5000]);
+        assert_eq!(lzw_decode(&lzw_encode(&data, 8), 8), data);
+        assert_eq!(lzw_decode(&lzw_encode(&[3], 8), 8), [3]);
+    }
+
+    #[test]
+    fn gif_frames_use_the_palette() {
+        let mut bytes = vec![];
+        let mut writer = GifWriter::new(&mut bytes, 25.0);
+        let pixels = vec![Color::new(255, 0, 0), Color::new(0, 0, 255)];
+        writer.push(2, 1, &pixels).unwrap();
+        writer.push(2, 1, &pixels).unwrap();
+        writer.finish().unwrap();
+        assert_eq!(&bytes[..6], b"GIF89a");
+        assert_eq!(bytes[bytes.len() - 1], 0x3b);
+        // pure primaries need no dithering
+        let palette = gif_palette();
+        for c in &pixels {
+            let i = gif_index(c, 1, 3) as usize;
+            assert_eq!(palette[i * 3..i * 3 + 3], [c.r as u8, c.g as u8, c.b as u8]);
+        }
+        // two frames, each 4 hundredths of a second
+        let controls: Vec<usize> = (0..bytes.len() - 1)
+            .filter(|&i| bytes[i] == 0x21 && bytes[i + 1] == 0xf9)
+            .collect();
+        assert_eq!(controls.len(), 2);
+        assert_eq!(bytes[controls[0] + 4], 4);
+    }
+}
@@ -0,0 +1,144 @@
+//! Small canonical scenes rendered at a low resolution with fixed seeds and checked against
+//! the references in `tests/golden`, so that changes to materials or shapes that alter how a
+//! scene looks get noticed. `UPDATE_GOLDEN=1 cargo test --release --test golden` rewrites the
+//! references after a deliberate change.
+
+use ray_tracing_001::image::{encode_pfm, Image};
+use ray_tracing_001::integrator::BounceLimits;
+use ray_tracing_001::misc;
+use ray_tracing_001::render::Renderer;
+use ray_tracing_001::scene::Scene;
+use ray_tracing_001::vec3::Vec3;
+
+const SCENES: [(&str, &str); 4] = [
+    ("random", "scenes/random.scene"),
+    ("cornell", "tests/golden/cornell.scene"),
+    ("glass", "tests/golden/glass.scene"),
+    ("textured", "tests/golden/textured.scene"),
+];
+const SIZE: (usize, usize) = (48, 36);
+const SPP: u32 = 64;
+const MAX_DEPTH: u32 = 16;
+/// Seed the scene is built from, that of the command line.
+const SCENE_SEED: u64 = 1234;
+/// References are rendered with many more samples from another seed, so that they are
+/// close to the expected image rather than a copy of one noisy estimate.
+const REFERENCE_SPP: u32 = 1024;
+const REFERENCE_SEED: u64 = 7;
+/// Side of the pixel blocks whose means are compared, which averages most noise away.
+const BLOCK: usize = 4;
+/// Largest relative MSE of the block means. Noise alone leaves at most a third of it on
+/// these scenes, whatever the seed, while darkening a wall of the Cornell box by a fifth
+/// exceeds it.
+const TOLERANCE: f64 = 0.0015;
+/// Largest relative difference of the mean of any channel over the whole image, which
+/// catches slight changes that span the image.
+const MEAN_TOLERANCE: f32 = 0.01;
+
+fn path(relative: &str) -> String {
+    format!("{}/{}", env!("CARGO_MANIFEST_DIR"), relative)
+}
+
+fn render_scene(scene: &str, seed: u64, spp: u32) -> Image {
+    // the scene's own seed stays the default so the random spheres do not move
+    let load = || {
+        misc::seed(SCENE_SEED);
+        Scene::load(scene).map_err(|e| format!("{}: {}", scene, e))
+    };
+    let integrator = load().unwrap().integrator;
+    Renderer::new(load)
+        .integrator(integrator.build(MAX_DEPTH, BounceLimits::default()))
+        .size(SIZE.0, SIZE.1)
+        .spp(spp)
+        .seed(seed)
+        .render()
+        .unwrap()
+        .image
+}
+
+fn block_means(image: &Image) -> Vec<Vec3> {
+    let (columns, rows) = (image.width / BLOCK, image.height / BLOCK);
+    let mut means = vec![];
+    for by in 0..rows {
+        for bx in 0..columns {
+            let mut sum = Vec3::new(0.0, 0.0, 0.0);
+            for y in by * BLOCK..(by + 1) * BLOCK {
+                for x in bx * BLOCK..(bx + 1) * BLOCK {
+                    sum = sum + image.get(x, y);
+                }
+            }
+            means.push(sum / (BLOCK * BLOCK) as f32);
+        }
+    }
+    means
+}
+
+/// Relative MSE of the block means and the largest relative difference of a channel mean.
+fn difference(reference: &Image, test: &Image) -> (f64, f32) {
+    let (r, t) = (block_means(reference), block_means(test));
+    let mut error = 0.0;
+    for (r, t) in r.iter().zip(&t) {
+        for (r, t) in [(r.x, t.x), (r.y, t.y), (r.z, t.z)] {
+            error += ((t - r) as f64).powi(2) / ((r as f64).powi(2) + 0.01);
+        }
+    }
+    let mean = |blocks: &[Vec3]| {
+        blocks
+            .iter()
+            .fold(Vec3::new(0.0, 0.0, 0.0), |sum, b| sum + *b)
+            / blocks.len() as f32
+    };
+    let (mr, mt) = (mean(&r), mean(&t));
+    let drift = [(mr.x, mt.x), (mr.y, mt.y), (mr.z, mt.z)]
+        .iter()
+        .map(|(r, t)| (t - r).abs() / r.max(0.01))
+        .fold(0.0, f32::max);
+    (error / (r.len() * 3) as f64, drift)
+}
+
+fn matches(name: &str, reference: &Image, test: &Image) -> bool {
+    let (error, drift) = difference(reference, test);
+    println!(
+        "{}: block relMSE {:.5}, mean drift {:.4}",
+        name, error, drift
+    );
+    error <= TOLERANCE && drift <= MEAN_TOLERANCE
+}
+
+fn reference(name: &str) -> Image {
+    Image::load(&path(&format!("tests/golden/{}.pfm", name))).unwrap()
+}
+
+#[test]
+fn scenes_match_their_references() {
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let mut failures = vec![];
+    for (name, scene) in SCENES {
+        if update {
+            let reference = render_scene(&path(scene), REFERENCE_SEED, REFERENCE_SPP);
+            let file = path(&format!("tests/golden/{}.pfm", name));
+            std::fs::write(file, encode_pfm(&reference)).unwrap();
+        }
+        let test = render_scene(&path(scene), SCENE_SEED, SPP);
+        if !matches(name, &reference(name), &test) {
+            failures.push(name);
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "differ from their references: {:?}",
+        failures
+    );
+}
+
+#[test]
+fn changed_materials_are_caught() {
+    let text = std::fs::read_to_string(path("tests/golden/cornell.scene")).unwrap();
+    let changed = text.replace("red lambertian 0.65", "red lambertian 0.5");
+    assert_ne!(text, changed);
+    let scene = std::env::temp_dir().join(format!("golden-{}.scene", std::process::id()));
+    std::fs::write(&scene, changed).unwrap();
+    let test = render_scene(scene.to_str().unwrap(), SCENE_SEED, SPP);
+    let _ = std::fs::remove_file(&scene);
+    assert!(!matches("cornell", &reference("cornell"), &test));
+}